@@ -0,0 +1,84 @@
+//! Time taken to recover the state of a run from its write-ahead log, by number of segments. Segments are replayed
+//! in parallel, one thread per segment, so recovery should get faster with more segments, up to the number of cores.
+//!
+//! Run with `cargo bench --bench recovery`.
+
+use std::{
+    num::NonZeroUsize,
+    time::{Duration, Instant},
+};
+
+use transactions_simulator::business_logic::engine::{
+    apply_transaction, recover_wal, ProcessingOptions,
+};
+
+/// Input records of the logged run
+const RECORDS: u32 = 400_000;
+/// Recoveries timed for every number of segments, the fastest being reported
+const ROUNDS: usize = 3;
+
+fn main() {
+    let dir = std::env::temp_dir().join("transactions_simulator_bench_recovery");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = input();
+    let cores = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    println!("{RECORDS} input records, {cores} core(s)");
+    println!("{:>8} {:>12} {:>8}", "segments", "recovery", "speedup");
+
+    let mut single = None;
+    for segments in [1, 2, 4, 8, 16] {
+        let wal_dir = dir.join(format!("wal-{segments}"));
+        let options = ProcessingOptions {
+            threads: NonZeroUsize::new(segments),
+            wal_dir: Some(wal_dir.clone()),
+            ..Default::default()
+        };
+        apply_transaction(input.as_bytes(), &options, std::io::sink()).unwrap();
+
+        let checkpoint_file = dir.join("recovered.snap");
+        let fastest = (0..ROUNDS)
+            .map(|_| {
+                let start = Instant::now();
+                let position = recover_wal(&wal_dir, &checkpoint_file).unwrap();
+                assert_eq!(position, u64::from(RECORDS));
+                start.elapsed()
+            })
+            .min()
+            .unwrap_or(Duration::ZERO);
+        let single = *single.get_or_insert(fastest);
+        println!(
+            "{segments:>8} {:>10.1}ms {:>7.2}x",
+            fastest.as_secs_f64() * 1000.0,
+            single.as_secs_f64() / fastest.as_secs_f64()
+        );
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Deposits, withdrawals and disputes over 10000 clients, generated by a linear congruential generator so that
+/// every run of the benchmark replays the same log
+fn input() -> String {
+    let mut state = 0x5eed_u64;
+    let mut next = |bound: u64| {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (state >> 33) % bound
+    };
+    let mut input = String::from("type,client,tx,amount\n");
+    for tx in 1..=RECORDS {
+        let client = next(10_000) + 1;
+        let line = match next(10) {
+            0..=5 => format!("deposit,{client},{tx},{}.{:04}\n", next(1000), next(10_000)),
+            6..=8 => format!(
+                "withdrawal,{client},{tx},{}.{:04}\n",
+                next(100),
+                next(10_000)
+            ),
+            _ => format!("dispute,{client},{},\n", next(u64::from(tx)) + 1),
+        };
+        input.push_str(&line);
+    }
+    input
+}
@@ -0,0 +1,55 @@
+#!/bin/sh
+# Mutation testing gate: runs `cargo mutants` and fails when a mutant survives that is not listed in the
+# baseline of accepted survivors. `scripts/mutants-gate.sh --update` rewrites the baseline from the run instead.
+# Any other argument is passed to `cargo mutants` (e.g. `--in-diff changes.diff`, `--jobs 4`).
+set -eu
+
+cd "$(dirname "$0")/.."
+baseline=.cargo/mutants-baseline.txt
+
+update=false
+if [ "${1:-}" = "--update" ]; then
+    update=true
+    shift
+fi
+
+# 0: every mutant caught, 2: mutants survived, anything else (timeouts, failing unmutated tree) fails the gate
+status=0
+cargo mutants "$@" || status=$?
+if [ "$status" -ne 0 ] && [ "$status" -ne 2 ]; then
+    echo "cargo mutants failed with status $status" >&2
+    exit "$status"
+fi
+
+# mutants are compared without their line and column, so that unrelated edits do not change them
+normalize() {
+    grep -v '^#' "$1" | grep -v '^$' | sed -E 's/^([^:]+):[0-9]+:[0-9]+: /\1: /' | LC_ALL=C sort -u
+}
+missed=$(mktemp)
+accepted=$(mktemp)
+trap 'rm -f "$missed" "$accepted"' EXIT
+normalize mutants.out/missed.txt > "$missed"
+normalize "$baseline" > "$accepted"
+
+if $update; then
+    {
+        echo "# Surviving mutants accepted by scripts/mutants-gate.sh, one per line without line and column."
+        echo "# Regenerate with \`scripts/mutants-gate.sh --update\` after a full run, and review the diff."
+        cat "$missed"
+    } > "$baseline"
+    echo "$baseline updated with $(wc -l < "$missed") surviving mutants"
+    exit 0
+fi
+
+new=$(LC_ALL=C comm -23 "$missed" "$accepted")
+caught=$(LC_ALL=C comm -13 "$missed" "$accepted")
+if [ -n "$caught" ]; then
+    echo "Mutants of the baseline now caught (remove them with --update after a full run):"
+    echo "$caught"
+fi
+if [ -n "$new" ]; then
+    echo "New surviving mutants, not in $baseline:" >&2
+    echo "$new" >&2
+    exit 1
+fi
+echo "No surviving mutant outside $baseline"
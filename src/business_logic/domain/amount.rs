@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+use crate::shared::decimal::Decimal;
+
+/// Amount of funds moved by a deposit, withdrawal, dispute, resolve or chargeback: never negative.
+/// Signed corrections (adjustments) are plain [Decimal] values, applied with [Balance::checked_adjust]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Amount(Decimal);
+
+impl Amount {
+    /// Amount of `value`, `None` if negative
+    pub(crate) fn new(value: Decimal) -> Option<Self> {
+        match value.is_negative() {
+            true => None,
+            false => Some(Self(value)),
+        }
+    }
+
+    pub(crate) fn value(self) -> Decimal {
+        self.0
+    }
+}
+
+/// Funds of a client account (available, held or total). Balances may be negative (e.g. available funds
+/// after the dispute of a deposit already withdrawn), and change only by amounts, with checked arithmetic:
+/// operations return `None` instead of overflowing
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Balance(Decimal);
+
+impl Balance {
+    pub(crate) const ZERO: Self = Self(Decimal::ZERO);
+
+    pub(crate) fn new(value: Decimal) -> Self {
+        Self(value)
+    }
+
+    pub(crate) fn value(self) -> Decimal {
+        self.0
+    }
+
+    pub(crate) fn is_negative(self) -> bool {
+        self.0.is_negative()
+    }
+
+    /// Whether the balance is enough to take `amount` from it without going negative
+    pub(crate) fn covers(self, amount: Amount) -> bool {
+        self.0 >= amount.0
+    }
+
+    /// Balance increased by `amount`
+    pub(crate) fn checked_credit(self, amount: Amount) -> Option<Self> {
+        self.0.checked_add(amount.0).map(Self)
+    }
+
+    /// Balance decreased by `amount`
+    pub(crate) fn checked_debit(self, amount: Amount) -> Option<Self> {
+        self.0.checked_sub(amount.0).map(Self)
+    }
+
+    /// Balance corrected by a signed `amount`
+    pub(crate) fn checked_adjust(self, amount: Decimal) -> Option<Self> {
+        self.0.checked_add(amount).map(Self)
+    }
+}
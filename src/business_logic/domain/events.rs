@@ -0,0 +1,375 @@
+use crate::{
+    business_logic::domain::{
+        amount::{Amount, Balance},
+        transactions_logic::Rejection,
+        Client, DisputeDetails, DisputeEffect, DisputeOutcomes, DisputeStatus, Transaction,
+        TransferLeg,
+    },
+    shared::decimal::Decimal,
+};
+
+/// Domain events emitted by a client account once a transaction has been accepted.
+/// Account state is never modified directly: it is always the fold of the events applied so far.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Event {
+    /// Funds credited to the account by a deposit
+    Deposited {
+        tx: u32,
+        amount: Amount,
+        channel: Option<String>,
+    },
+    /// Funds debited from the account by a withdrawal
+    Withdrawn {
+        tx: u32,
+        amount: Amount,
+        channel: Option<String>,
+    },
+    /// Funds debited from the account by a transfer to another client
+    TransferredOut {
+        tx: u32,
+        amount: Amount,
+        to: u16,
+        channel: Option<String>,
+    },
+    /// Funds credited to the account by a transfer from another client
+    TransferredIn {
+        tx: u32,
+        amount: Amount,
+        from: u16,
+        channel: Option<String>,
+    },
+    /// Dispute of a transfer opened by its source: the funds are held by the destination, which records the
+    /// dispute as its own
+    TransferDisputed { tx: u32, details: DisputeDetails },
+    /// Dispute of a transfer resolved by its source: the funds are released by the destination
+    TransferResolved { tx: u32 },
+    /// Funds of a transfer charged back to its destination, credited back to its source
+    TransferReversed { tx: u32, amount: Amount },
+    /// Chargeback received on a transaction not under dispute, as allowed by policy: audit record of the dispute
+    /// opened on its behalf, which follows
+    DisputeImplied { tx: u32 },
+    /// Amount of the referenced transaction moved from available to held funds, or credited back as held funds
+    /// depending on the effect of the dispute
+    DisputeOpened {
+        tx: u32,
+        amount: Amount,
+        details: DisputeDetails,
+        effect: DisputeEffect,
+    },
+    /// Amount of the referenced transaction released from held to available funds, or removed from held and total
+    /// funds if credited back by the dispute
+    DisputeResolved {
+        tx: u32,
+        amount: Amount,
+        effect: DisputeEffect,
+    },
+    /// Amount of the referenced transaction removed from held and total funds, or released to available funds if
+    /// credited back by the dispute, with the details of its dispute
+    ChargedBack {
+        tx: u32,
+        amount: Amount,
+        details: DisputeDetails,
+        effect: DisputeEffect,
+    },
+    /// Signed amount added to available and total funds by an operations correction
+    Adjusted { amount: Decimal, reason: String },
+    /// Account frozen: every following transaction is ignored
+    Locked,
+    /// Account unlocked by an administrative unlock, with its optional reason code. Audited unlocks are recorded
+    /// as an operator note of the account
+    Unlocked {
+        tx: u32,
+        reason: Option<String>,
+        audited: bool,
+    },
+    /// Transaction removed from the history by the retention policy: it can no longer be disputed
+    Pruned { tx: u32 },
+}
+
+/// Balances of an account, changed by events with checked arithmetic
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Balances {
+    pub(crate) available: Balance,
+    pub(crate) held: Balance,
+    pub(crate) total: Balance,
+}
+
+impl Balances {
+    /// Balances after the event, `None` if any of them would overflow
+    pub(crate) fn after(self, event: &Event) -> Option<Self> {
+        let Balances {
+            available,
+            held,
+            total,
+        } = self;
+        match event {
+            Event::Deposited { amount, .. }
+            | Event::TransferredIn { amount, .. }
+            | Event::TransferReversed { amount, .. } => Some(Self {
+                available: available.checked_credit(*amount)?,
+                total: total.checked_credit(*amount)?,
+                ..self
+            }),
+            Event::Withdrawn { amount, .. } | Event::TransferredOut { amount, .. } => Some(Self {
+                available: available.checked_debit(*amount)?,
+                total: total.checked_debit(*amount)?,
+                ..self
+            }),
+            Event::DisputeOpened {
+                amount,
+                effect: DisputeEffect::Debit,
+                ..
+            } => Some(Self {
+                available: available.checked_debit(*amount)?,
+                held: held.checked_credit(*amount)?,
+                ..self
+            }),
+            Event::DisputeOpened {
+                amount,
+                effect: DisputeEffect::Credit,
+                ..
+            } => Some(Self {
+                held: held.checked_credit(*amount)?,
+                total: total.checked_credit(*amount)?,
+                ..self
+            }),
+            Event::DisputeResolved {
+                amount,
+                effect: DisputeEffect::Debit,
+                ..
+            }
+            | Event::ChargedBack {
+                amount,
+                effect: DisputeEffect::Credit,
+                ..
+            } => Some(Self {
+                available: available.checked_credit(*amount)?,
+                held: held.checked_debit(*amount)?,
+                ..self
+            }),
+            Event::DisputeResolved {
+                amount,
+                effect: DisputeEffect::Credit,
+                ..
+            }
+            | Event::ChargedBack {
+                amount,
+                effect: DisputeEffect::Debit,
+                ..
+            } => Some(Self {
+                held: held.checked_debit(*amount)?,
+                total: total.checked_debit(*amount)?,
+                ..self
+            }),
+            Event::Adjusted { amount, .. } => Some(Self {
+                available: available.checked_adjust(*amount)?,
+                total: total.checked_adjust(*amount)?,
+                ..self
+            }),
+            Event::DisputeImplied { .. }
+            | Event::TransferDisputed { .. }
+            | Event::TransferResolved { .. }
+            | Event::Locked
+            | Event::Unlocked { .. }
+            | Event::Pruned { .. } => Some(self),
+        }
+    }
+}
+
+impl Client {
+    pub(crate) fn balances(&self) -> Balances {
+        Balances {
+            available: self.available,
+            held: self.held,
+            total: self.total,
+        }
+    }
+
+    /// Fold a single event into the account state. Fails, leaving the account untouched,
+    /// if the event would overflow a balance or a dispute total
+    pub(crate) fn apply_event(&mut self, event: &Event) -> Result<(), Rejection> {
+        let balances = self.balances().after(event).ok_or(Rejection::Overflow)?;
+        let dispute_outcomes = self
+            .dispute_outcomes
+            .after(event)
+            .ok_or(Rejection::Overflow)?;
+        self.available = balances.available;
+        self.held = balances.held;
+        self.total = balances.total;
+        self.dispute_outcomes = dispute_outcomes;
+        self.record_event(event);
+        Ok(())
+    }
+
+    /// Fold the effects of an event other than on balances and dispute outcomes into the account state:
+    /// transactions history, lock, pruned transactions and operator notes
+    pub(crate) fn record_event(&mut self, event: &Event) {
+        match event {
+            Event::Deposited {
+                tx,
+                amount,
+                channel,
+            } => self.record_transaction(*tx, *amount, channel, None, false),
+            Event::Withdrawn {
+                tx,
+                amount,
+                channel,
+            } => self.record_transaction(*tx, *amount, channel, None, true),
+            Event::TransferredOut {
+                tx,
+                amount,
+                to,
+                channel,
+            } => self.record_transaction(*tx, *amount, channel, Some(TransferLeg::To(*to)), false),
+            Event::TransferredIn {
+                tx,
+                amount,
+                from,
+                channel,
+            } => self.record_transaction(
+                *tx,
+                *amount,
+                channel,
+                Some(TransferLeg::From(*from)),
+                false,
+            ),
+            Event::DisputeOpened {
+                tx,
+                details,
+                effect,
+                ..
+            } => {
+                if let Some(tx) = self.transations_history.get_mut(tx) {
+                    tx.dispute_status = DisputeStatus::Disputed;
+                    tx.dispute = details.clone();
+                    tx.dispute_effect = *effect;
+                }
+            }
+            Event::TransferDisputed { tx, details } => {
+                if let Some(tx) = self.transations_history.get_mut(tx) {
+                    tx.dispute_status = DisputeStatus::Disputed;
+                    tx.dispute = details.clone();
+                }
+            }
+            Event::DisputeResolved { tx, .. } | Event::TransferResolved { tx } => {
+                self.close_dispute(*tx, DisputeStatus::Resolved)
+            }
+            Event::ChargedBack { tx, .. } | Event::TransferReversed { tx, .. } => {
+                self.close_dispute(*tx, DisputeStatus::ChargedBack)
+            }
+            Event::DisputeImplied { .. } | Event::Adjusted { .. } => {}
+            Event::Locked => self.locked = true,
+            Event::Unlocked {
+                tx,
+                reason,
+                audited,
+            } => {
+                self.locked = false;
+                if *audited {
+                    let note = match reason {
+                        Some(reason) => format!("unlocked by tx {tx}: {reason}"),
+                        None => format!("unlocked by tx {tx}"),
+                    };
+                    // notes are single line
+                    self.annotations.push(note.replace(['\r', '\n'], " "));
+                }
+            }
+            Event::Pruned { tx } => {
+                self.transations_history.remove(tx);
+                // the oldest transaction is usually the one pruned
+                match self.history_order.front() == Some(tx) {
+                    true => {
+                        self.history_order.pop_front();
+                    }
+                    false => self.history_order.retain(|kept| kept != tx),
+                }
+                self.pruned_transactions.insert(*tx);
+            }
+        }
+    }
+
+    /// End the dispute of transaction `tx`, which can no longer be disputed, resolved nor charged back
+    fn close_dispute(&mut self, tx: u32, status: DisputeStatus) {
+        if let Some(tx) = self.transations_history.get_mut(&tx) {
+            tx.dispute_status = status;
+        }
+    }
+
+    /// Add a deposit, withdrawal or transfer leg to the history, as the newest transaction
+    fn record_transaction(
+        &mut self,
+        tx: u32,
+        amount: Amount,
+        channel: &Option<String>,
+        transfer: Option<TransferLeg>,
+        withdrawal: bool,
+    ) {
+        self.history_order.push_back(tx);
+        self.transations_history.insert(
+            tx,
+            Transaction {
+                amount,
+                dispute_status: DisputeStatus::Undisputed,
+                channel: channel.clone(),
+                dispute: DisputeDetails::default(),
+                transfer,
+                withdrawal,
+                dispute_effect: DisputeEffect::default(),
+            },
+        );
+    }
+}
+
+impl DisputeOutcomes {
+    /// Outcomes after the event, `None` if a count or a total would overflow
+    pub(crate) fn after(self, event: &Event) -> Option<Self> {
+        match event {
+            Event::DisputeOpened { amount, .. } => Some(Self {
+                opened: self.opened.checked_add(1)?,
+                opened_amount: self.opened_amount.checked_add(amount.value())?,
+                ..self
+            }),
+            Event::DisputeResolved { amount, .. } => Some(Self {
+                resolved: self.resolved.checked_add(1)?,
+                resolved_amount: self.resolved_amount.checked_add(amount.value())?,
+                ..self
+            }),
+            Event::ChargedBack { amount, .. } => Some(Self {
+                charged_back: self.charged_back.checked_add(1)?,
+                charged_back_amount: self.charged_back_amount.checked_add(amount.value())?,
+                ..self
+            }),
+            _ => Some(self),
+        }
+    }
+
+    /// Number of disputes neither resolved nor charged back
+    pub(crate) fn pending(&self) -> u32 {
+        // a saturated sum exceeds the disputes opened anyway
+        self.opened
+            .saturating_sub(self.resolved.saturating_add(self.charged_back))
+    }
+
+    /// Amount of the disputes neither resolved nor charged back
+    pub(crate) fn pending_amount(&self) -> Decimal {
+        // amounts are never negative: a difference out of range is below zero anyway
+        self.opened_amount
+            .checked_sub(self.resolved_amount)
+            .and_then(|amount| amount.checked_sub(self.charged_back_amount))
+            .map_or(Decimal::ZERO, |amount| amount.max(Decimal::ZERO))
+    }
+
+    /// Outcomes accumulated with the ones of another client, `None` if a count or a total would overflow
+    pub(crate) fn checked_add(self, other: &DisputeOutcomes) -> Option<Self> {
+        Some(Self {
+            opened: self.opened.checked_add(other.opened)?,
+            opened_amount: self.opened_amount.checked_add(other.opened_amount)?,
+            resolved: self.resolved.checked_add(other.resolved)?,
+            resolved_amount: self.resolved_amount.checked_add(other.resolved_amount)?,
+            charged_back: self.charged_back.checked_add(other.charged_back)?,
+            charged_back_amount: self
+                .charged_back_amount
+                .checked_add(other.charged_back_amount)?,
+        })
+    }
+}
@@ -0,0 +1,98 @@
+use crate::business_logic::{
+    domain::{
+        amount::{Amount, Balance},
+        events::Event,
+        handlers::TransactionHandler,
+        Client, ClientTransaction, Rejection,
+    },
+    policy::EnginePolicy,
+};
+
+/// Correct the available funds by a signed amount, with a mandatory reason code. Applied to locked accounts if
+/// allowed by policy
+pub(crate) struct AdjustmentHandler;
+
+impl TransactionHandler for AdjustmentHandler {
+    fn takes_amount(&self) -> bool {
+        false
+    }
+
+    fn applies_to_locked(&self, policy: &EnginePolicy) -> bool {
+        policy.adjust_locked_accounts
+    }
+
+    fn events(
+        &self,
+        client: &Client,
+        transaction: &ClientTransaction,
+        _amount: Option<Amount>,
+        policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        let amount = transaction.amount.ok_or(Rejection::InvalidAmount)?;
+        // reason code is mandatory
+        let reason = transaction
+            .reason
+            .clone()
+            .filter(|reason| !reason.is_empty())
+            .ok_or(Rejection::MissingReason)?;
+        // ignore adjustment driving available funds negative, unless allowed for the client
+        if client
+            .available
+            .checked_adjust(amount)
+            .is_some_and(Balance::is_negative)
+            && !policy.negative_adjustments.allows(client.id)
+        {
+            return Err(Rejection::NegativeBalance);
+        }
+        Ok(vec![Event::Adjusted { amount, reason }])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::business_logic::{
+        domain::{
+            events::Event,
+            handlers::{account, events, handler, transaction},
+            Rejection, Type,
+        },
+        policy::EnginePolicy,
+    };
+
+    #[test]
+    fn test_adjustment() {
+        let policy = EnginePolicy::default();
+        let client = account(&[transaction(Type::Deposit, 1, Some("2.0"))], &policy);
+        let adjustment = |amount| transaction(Type::Adjustment, 2, Some(amount));
+        assert_eq!(
+            events(&client, &adjustment("-2.0").with_reason("FEE"), &policy),
+            Ok(vec![Event::Adjusted {
+                amount: "-2.0".parse().unwrap(),
+                reason: "FEE".to_owned(),
+            }])
+        );
+        assert_eq!(
+            events(&client, &adjustment("-2.0001").with_reason("FEE"), &policy),
+            Err(Rejection::NegativeBalance)
+        );
+        assert_eq!(
+            events(&client, &adjustment("1.0"), &policy),
+            Err(Rejection::MissingReason)
+        );
+        assert_eq!(
+            events(&client, &adjustment("1.0").with_reason(""), &policy),
+            Err(Rejection::MissingReason)
+        );
+    }
+
+    #[test]
+    fn test_adjustment_of_locked_account() {
+        let handler = handler(Type::Adjustment);
+        assert!(!handler.takes_amount());
+        assert!(!handler.applies_to_locked(&EnginePolicy::default()));
+        assert!(handler.applies_to_locked(&EnginePolicy {
+            adjust_locked_accounts: true,
+            ..Default::default()
+        }));
+    }
+}
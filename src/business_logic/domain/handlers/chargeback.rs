@@ -0,0 +1,144 @@
+use crate::business_logic::{
+    domain::{
+        amount::Amount, events::Event, handlers::TransactionHandler,
+        transactions_logic::dispute_details, Client, ClientTransaction, Rejection,
+    },
+    policy::EnginePolicy,
+};
+
+/// Remove the funds held by the dispute of a previous transaction and lock the account
+pub(crate) struct ChargeBackHandler;
+
+impl TransactionHandler for ChargeBackHandler {
+    fn events(
+        &self,
+        client: &Client,
+        transaction: &ClientTransaction,
+        _amount: Option<Amount>,
+        policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        let tx = client.referenced(transaction.tx)?;
+        let charged_back = |details, effect| Event::ChargedBack {
+            tx: transaction.tx,
+            amount: tx.amount,
+            details,
+            effect,
+        };
+        match tx.dispute_status.check_closing()? {
+            true => Ok(vec![
+                charged_back(tx.dispute.clone(), tx.dispute_effect),
+                Event::Locked,
+            ]),
+            // feeds may lack the dispute preceding a chargeback: if allowed by policy, the dispute is
+            // opened with the details of the chargeback and charged back right away
+            false if policy.charge_back_undisputed => {
+                let effect = policy
+                    .dispute_effect(tx)
+                    .ok_or(Rejection::RejectedByPolicy)?;
+                if !policy.allows_dispute(client.available, tx.amount, effect) {
+                    return Err(Rejection::InsufficientFunds);
+                }
+                let details = dispute_details(transaction);
+                Ok(vec![
+                    Event::DisputeImplied { tx: transaction.tx },
+                    Event::DisputeOpened {
+                        tx: transaction.tx,
+                        amount: tx.amount,
+                        details: details.clone(),
+                        effect,
+                    },
+                    charged_back(details, effect),
+                    Event::Locked,
+                ])
+            }
+            false => Err(Rejection::NotDisputed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::business_logic::{
+        domain::{
+            amount::Amount,
+            events::Event,
+            handlers::{account, events, handler, transaction},
+            DisputeEffect, Rejection, Type,
+        },
+        policy::EnginePolicy,
+    };
+
+    #[test]
+    fn test_chargeback() {
+        let policy = EnginePolicy::default();
+        let client = account(
+            &[
+                transaction(Type::Deposit, 1, Some("2.0")),
+                transaction(Type::Dispute, 1, None),
+            ],
+            &policy,
+        );
+        assert_eq!(
+            events(&client, &transaction(Type::ChargeBack, 1, None), &policy),
+            Ok(vec![
+                Event::ChargedBack {
+                    tx: 1,
+                    amount: Amount::new("2.0".parse().unwrap()).unwrap(),
+                    details: Default::default(),
+                    effect: DisputeEffect::Debit,
+                },
+                Event::Locked,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_chargeback_undisputed() {
+        let policy = EnginePolicy::default();
+        let client = account(&[transaction(Type::Deposit, 1, Some("2.0"))], &policy);
+        assert_eq!(
+            events(&client, &transaction(Type::ChargeBack, 1, None), &policy),
+            Err(Rejection::NotDisputed)
+        );
+
+        // feeds lacking the dispute preceding a chargeback, if allowed by policy
+        let policy = EnginePolicy {
+            charge_back_undisputed: true,
+            ..Default::default()
+        };
+        let events = events(&client, &transaction(Type::ChargeBack, 1, None), &policy).unwrap();
+        assert!(
+            matches!(
+                events.as_slice(),
+                [
+                    Event::DisputeImplied { tx: 1 },
+                    Event::DisputeOpened { tx: 1, .. },
+                    Event::ChargedBack { tx: 1, .. },
+                    Event::Locked
+                ]
+            ),
+            "{events:?}"
+        );
+        assert!(!handler(Type::ChargeBack).applies_to_locked(&policy));
+    }
+
+    #[test]
+    fn test_chargeback_resolved() {
+        let policy = EnginePolicy {
+            charge_back_undisputed: true,
+            ..Default::default()
+        };
+        let client = account(
+            &[
+                transaction(Type::Deposit, 1, Some("2.0")),
+                transaction(Type::Dispute, 1, None),
+                transaction(Type::Resolve, 1, None),
+            ],
+            &policy,
+        );
+        assert_eq!(
+            events(&client, &transaction(Type::ChargeBack, 1, None), &policy),
+            Err(Rejection::AlreadyResolved)
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use crate::business_logic::{
+    domain::{
+        amount::Amount, events::Event, handlers::TransactionHandler, Client, ClientTransaction,
+        Rejection,
+    },
+    policy::{EnginePolicy, CHANNEL_ATTRIBUTE},
+};
+
+/// Credit the amount to the available funds, under a transaction ID never used by the client
+pub(crate) struct DepositHandler;
+
+impl TransactionHandler for DepositHandler {
+    fn events(
+        &self,
+        client: &Client,
+        transaction: &ClientTransaction,
+        amount: Option<Amount>,
+        policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        client.check_new(transaction.tx)?;
+        let amount = amount.ok_or(Rejection::InvalidAmount)?;
+        let deposited = Event::Deposited {
+            tx: transaction.tx,
+            amount,
+            channel: transaction.attributes.get(CHANNEL_ATTRIBUTE).cloned(),
+        };
+        Ok(client.with_pruning(deposited, transaction.tx, policy))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::business_logic::{
+        domain::{
+            amount::Amount,
+            events::Event,
+            handlers::{account, events, handler, transaction},
+            Rejection, Type,
+        },
+        policy::EnginePolicy,
+    };
+
+    #[test]
+    fn test_deposit() {
+        let policy = EnginePolicy::default();
+        let client = account(&[transaction(Type::Deposit, 1, Some("2.0"))], &policy);
+        assert_eq!(
+            events(
+                &client,
+                &transaction(Type::Deposit, 2, Some("1.5")),
+                &policy
+            ),
+            Ok(vec![Event::Deposited {
+                tx: 2,
+                amount: Amount::new("1.5".parse().unwrap()).unwrap(),
+                channel: None,
+            }])
+        );
+        assert_eq!(
+            events(
+                &client,
+                &transaction(Type::Deposit, 1, Some("1.5")),
+                &policy
+            ),
+            Err(Rejection::DuplicateTransaction)
+        );
+        assert_eq!(
+            events(&client, &transaction(Type::Deposit, 2, None), &policy),
+            Err(Rejection::InvalidAmount)
+        );
+    }
+
+    #[test]
+    fn test_deposit_on_locked_account() {
+        let policy = EnginePolicy::default();
+        let mut client = account(
+            &[
+                transaction(Type::Deposit, 1, Some("2.0")),
+                transaction(Type::Dispute, 1, None),
+                transaction(Type::ChargeBack, 1, None),
+            ],
+            &policy,
+        );
+        assert!(client.locked && !handler(Type::Deposit).applies_to_locked(&policy));
+        assert_eq!(
+            client.apply_transaction(&transaction(Type::Deposit, 2, Some("1.0")), &policy),
+            Err(Rejection::LockedAccount)
+        );
+    }
+}
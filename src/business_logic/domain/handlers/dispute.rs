@@ -0,0 +1,129 @@
+use crate::business_logic::{
+    domain::{
+        amount::Amount, events::Event, handlers::TransactionHandler,
+        transactions_logic::dispute_details, Client, ClientTransaction, Rejection,
+    },
+    policy::EnginePolicy,
+};
+
+/// Hold the funds of a previous transaction of the client, moving them as told by the policy. Disputes with
+/// reason codes resolved by policy (e.g. friendly fraud) release them right away
+pub(crate) struct DisputeHandler;
+
+impl TransactionHandler for DisputeHandler {
+    fn events(
+        &self,
+        client: &Client,
+        transaction: &ClientTransaction,
+        _amount: Option<Amount>,
+        policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        // ignore non existing tx IDs and do not modify tx reference
+        let tx = client.referenced(transaction.tx)?;
+        tx.dispute_status.check_dispute()?;
+        let effect = policy
+            .dispute_effect(tx)
+            .ok_or(Rejection::RejectedByPolicy)?;
+        if !policy.allows_dispute(client.available, tx.amount, effect) {
+            return Err(Rejection::InsufficientFunds);
+        }
+        let details = dispute_details(transaction);
+        let auto_resolved = policy.auto_resolves(&details);
+        let mut events = vec![Event::DisputeOpened {
+            tx: transaction.tx,
+            amount: tx.amount,
+            details,
+            effect,
+        }];
+        if auto_resolved {
+            events.push(Event::DisputeResolved {
+                tx: transaction.tx,
+                amount: tx.amount,
+                effect,
+            });
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::business_logic::{
+        domain::{
+            amount::Amount,
+            events::Event,
+            handlers::{account, events, handler, transaction},
+            DisputeEffect, Rejection, Type,
+        },
+        policy::EnginePolicy,
+    };
+
+    #[test]
+    fn test_dispute() {
+        let policy = EnginePolicy::default();
+        let client = account(&[transaction(Type::Deposit, 1, Some("2.0"))], &policy);
+        assert_eq!(
+            events(&client, &transaction(Type::Dispute, 1, None), &policy),
+            Ok(vec![Event::DisputeOpened {
+                tx: 1,
+                amount: Amount::new("2.0".parse().unwrap()).unwrap(),
+                details: Default::default(),
+                effect: DisputeEffect::Debit,
+            }])
+        );
+        assert_eq!(
+            events(&client, &transaction(Type::Dispute, 2, None), &policy),
+            Err(Rejection::UnknownTransaction)
+        );
+    }
+
+    #[test]
+    fn test_dispute_closed() {
+        let policy = EnginePolicy::default();
+        let client = account(
+            &[
+                transaction(Type::Deposit, 1, Some("2.0")),
+                transaction(Type::Deposit, 2, Some("1.0")),
+                transaction(Type::Deposit, 3, Some("1.0")),
+                transaction(Type::Dispute, 1, None),
+                transaction(Type::Dispute, 2, None),
+                transaction(Type::Resolve, 2, None),
+                transaction(Type::Dispute, 3, None),
+                transaction(Type::ChargeBack, 3, None),
+            ],
+            &policy,
+        );
+        for (tx, rejection) in [
+            (1, Rejection::AlreadyDisputed),
+            (2, Rejection::AlreadyResolved),
+            (3, Rejection::AlreadyChargedBack),
+        ] {
+            assert_eq!(
+                events(&client, &transaction(Type::Dispute, tx, None), &policy),
+                Err(rejection)
+            );
+        }
+    }
+
+    #[test]
+    fn test_dispute_auto_resolved() {
+        let policy = EnginePolicy {
+            auto_resolve_reasons: vec!["FRIENDLY".to_owned()],
+            ..Default::default()
+        };
+        let client = account(&[transaction(Type::Deposit, 1, Some("2.0"))], &policy);
+        let dispute = transaction(Type::Dispute, 1, None).with_reason("FRIENDLY");
+        let events = events(&client, &dispute, &policy).unwrap();
+        assert!(
+            matches!(
+                events.as_slice(),
+                [
+                    Event::DisputeOpened { tx: 1, .. },
+                    Event::DisputeResolved { tx: 1, .. }
+                ]
+            ),
+            "{events:?}"
+        );
+        assert!(!handler(Type::Dispute).applies_to_locked(&policy));
+    }
+}
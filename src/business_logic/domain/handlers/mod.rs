@@ -0,0 +1,85 @@
+use crate::business_logic::{
+    domain::{amount::Amount, events::Event, Client, ClientTransaction, Rejection, Type},
+    policy::EnginePolicy,
+};
+
+mod adjustment;
+mod chargeback;
+mod deposit;
+mod dispute;
+mod resolve;
+mod transfer;
+mod unlock;
+mod withdrawal;
+
+/// Business rules of one transaction type, turning its transactions into the events applied to the client account.
+/// The checks shared by every type (erased and locked accounts, policy rules, amount) are made beforehand by
+/// `Client::apply_transaction`, as told by the handler
+pub(crate) trait TransactionHandler: Sync {
+    /// Whether the amount of the transaction, if any, must be a non negative [Amount], checked before anything else.
+    /// Types with a signed amount or no amount at all check it themselves
+    fn takes_amount(&self) -> bool {
+        true
+    }
+
+    /// Whether the transaction applies also to locked accounts
+    fn applies_to_locked(&self, _policy: &EnginePolicy) -> bool {
+        false
+    }
+
+    /// Events produced by the transaction, checked against the account without modifying it, or why it is ignored.
+    /// `amount` is the amount checked beforehand if the handler [takes it](TransactionHandler::takes_amount)
+    fn events(
+        &self,
+        client: &Client,
+        transaction: &ClientTransaction,
+        amount: Option<Amount>,
+        policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection>;
+}
+
+/// Handler registered for each transaction type. A new type is added as a module of its own, registered here
+pub(crate) fn handler(transaction_type: Type) -> &'static dyn TransactionHandler {
+    match transaction_type {
+        Type::Deposit => &deposit::DepositHandler,
+        Type::Withdrawal => &withdrawal::WithdrawalHandler,
+        Type::Dispute => &dispute::DisputeHandler,
+        Type::Resolve => &resolve::ResolveHandler,
+        Type::ChargeBack => &chargeback::ChargeBackHandler,
+        Type::Adjustment => &adjustment::AdjustmentHandler,
+        Type::Transfer => &transfer::TransferHandler,
+        Type::Unlock => &unlock::UnlockHandler,
+    }
+}
+
+/// Transaction of client 1, for the tests of the handlers
+#[cfg(test)]
+fn transaction(transaction_type: Type, tx: u32, amount: Option<&str>) -> ClientTransaction {
+    let amount = amount.map(|amount| amount.parse().unwrap());
+    ClientTransaction::new(transaction_type, 1, tx, amount)
+}
+
+/// Account of client 1 after applying `transactions`, for the tests of the handlers
+#[cfg(test)]
+fn account(transactions: &[ClientTransaction], policy: &EnginePolicy) -> Client {
+    let mut client = Client::new(1);
+    for transaction in transactions {
+        client.apply_transaction(transaction, policy).unwrap();
+    }
+    client
+}
+
+/// Events of a transaction from the handler registered for its type, given its amount as checked beforehand
+#[cfg(test)]
+fn events(
+    client: &Client,
+    transaction: &ClientTransaction,
+    policy: &EnginePolicy,
+) -> Result<Vec<Event>, Rejection> {
+    let handler = handler(transaction.transaction_type);
+    let amount = transaction
+        .amount
+        .filter(|_| handler.takes_amount())
+        .and_then(Amount::new);
+    handler.events(client, transaction, amount, policy)
+}
@@ -0,0 +1,90 @@
+use crate::business_logic::{
+    domain::{
+        amount::Amount, events::Event, handlers::TransactionHandler, Client, ClientTransaction,
+        Rejection,
+    },
+    policy::EnginePolicy,
+};
+
+/// Release the funds held by the dispute of a previous transaction, undoing the dispute as it was opened
+pub(crate) struct ResolveHandler;
+
+impl TransactionHandler for ResolveHandler {
+    fn events(
+        &self,
+        client: &Client,
+        transaction: &ClientTransaction,
+        _amount: Option<Amount>,
+        _policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        let tx = client.referenced(transaction.tx)?;
+        match tx.dispute_status.check_closing()? {
+            true => Ok(vec![Event::DisputeResolved {
+                tx: transaction.tx,
+                amount: tx.amount,
+                effect: tx.dispute_effect,
+            }]),
+            false => Err(Rejection::NotDisputed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::business_logic::{
+        domain::{
+            amount::Amount,
+            events::Event,
+            handlers::{account, events, handler, transaction},
+            DisputeEffect, Rejection, Type,
+        },
+        policy::EnginePolicy,
+    };
+
+    #[test]
+    fn test_resolve() {
+        let policy = EnginePolicy::default();
+        let client = account(
+            &[
+                transaction(Type::Deposit, 1, Some("2.0")),
+                transaction(Type::Dispute, 1, None),
+            ],
+            &policy,
+        );
+        assert_eq!(
+            events(&client, &transaction(Type::Resolve, 1, None), &policy),
+            Ok(vec![Event::DisputeResolved {
+                tx: 1,
+                amount: Amount::new("2.0".parse().unwrap()).unwrap(),
+                effect: DisputeEffect::Debit,
+            }])
+        );
+        assert_eq!(
+            events(&client, &transaction(Type::Resolve, 2, None), &policy),
+            Err(Rejection::UnknownTransaction)
+        );
+    }
+
+    #[test]
+    fn test_resolve_not_disputed() {
+        let policy = EnginePolicy::default();
+        let client = account(
+            &[
+                transaction(Type::Deposit, 1, Some("2.0")),
+                transaction(Type::Deposit, 2, Some("1.0")),
+                transaction(Type::Dispute, 2, None),
+                transaction(Type::Resolve, 2, None),
+            ],
+            &policy,
+        );
+        assert_eq!(
+            events(&client, &transaction(Type::Resolve, 1, None), &policy),
+            Err(Rejection::NotDisputed)
+        );
+        assert_eq!(
+            events(&client, &transaction(Type::Resolve, 2, None), &policy),
+            Err(Rejection::AlreadyResolved)
+        );
+        assert!(!handler(Type::Resolve).applies_to_locked(&policy));
+    }
+}
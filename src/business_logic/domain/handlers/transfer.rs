@@ -0,0 +1,45 @@
+use crate::business_logic::{
+    domain::{
+        amount::Amount, events::Event, handlers::TransactionHandler, Client, ClientTransaction,
+        Rejection,
+    },
+    policy::EnginePolicy,
+};
+
+/// Transfers are applied to both clients by the engine (see `Client::plan_transfer`), never to one alone
+pub(crate) struct TransferHandler;
+
+impl TransactionHandler for TransferHandler {
+    fn events(
+        &self,
+        _client: &Client,
+        _transaction: &ClientTransaction,
+        _amount: Option<Amount>,
+        _policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        Err(Rejection::InvalidTransfer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::business_logic::{
+        domain::{
+            handlers::{account, events, handler, transaction},
+            Rejection, Type,
+        },
+        policy::EnginePolicy,
+    };
+
+    #[test]
+    fn test_transfer_alone() {
+        let policy = EnginePolicy::default();
+        let client = account(&[transaction(Type::Deposit, 1, Some("2.0"))], &policy);
+        let transfer = transaction(Type::Transfer, 2, Some("1.0")).with_destination(2);
+        assert_eq!(
+            events(&client, &transfer, &policy),
+            Err(Rejection::InvalidTransfer)
+        );
+        assert!(!handler(Type::Transfer).applies_to_locked(&policy));
+    }
+}
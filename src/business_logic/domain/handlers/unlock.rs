@@ -0,0 +1,95 @@
+use crate::business_logic::{
+    domain::{
+        amount::Amount, events::Event, handlers::TransactionHandler, Client, ClientTransaction,
+        Rejection,
+    },
+    policy::EnginePolicy,
+};
+
+/// Lift the lock of an account charged back. Unlocks carry no amount, and are accepted only if allowed by policy
+pub(crate) struct UnlockHandler;
+
+impl TransactionHandler for UnlockHandler {
+    fn takes_amount(&self) -> bool {
+        false
+    }
+
+    fn applies_to_locked(&self, _policy: &EnginePolicy) -> bool {
+        true
+    }
+
+    fn events(
+        &self,
+        client: &Client,
+        transaction: &ClientTransaction,
+        _amount: Option<Amount>,
+        policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        match client.locked {
+            true => Ok(vec![Event::Unlocked {
+                tx: transaction.tx,
+                reason: transaction
+                    .reason
+                    .clone()
+                    .filter(|reason| !reason.is_empty()),
+                audited: policy.audit_unlocks,
+            }]),
+            false => Err(Rejection::NotLocked),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::business_logic::{
+        domain::{
+            events::Event,
+            handlers::{account, events, handler, transaction},
+            Rejection, Type,
+        },
+        policy::EnginePolicy,
+    };
+
+    #[test]
+    fn test_unlock() {
+        let policy = EnginePolicy {
+            allow_unlock: true,
+            audit_unlocks: true,
+            ..Default::default()
+        };
+        let client = account(
+            &[
+                transaction(Type::Deposit, 1, Some("2.0")),
+                transaction(Type::Dispute, 1, None),
+                transaction(Type::ChargeBack, 1, None),
+            ],
+            &policy,
+        );
+        assert!(handler(Type::Unlock).applies_to_locked(&policy));
+        assert_eq!(
+            events(
+                &client,
+                &transaction(Type::Unlock, 2, None).with_reason("REVIEWED"),
+                &policy
+            ),
+            Ok(vec![Event::Unlocked {
+                tx: 2,
+                reason: Some("REVIEWED".to_owned()),
+                audited: true,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_unlock_not_locked() {
+        let policy = EnginePolicy {
+            allow_unlock: true,
+            ..Default::default()
+        };
+        let client = account(&[transaction(Type::Deposit, 1, Some("2.0"))], &policy);
+        assert_eq!(
+            events(&client, &transaction(Type::Unlock, 2, None), &policy),
+            Err(Rejection::NotLocked)
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use crate::business_logic::{
+    domain::{
+        amount::Amount, events::Event, handlers::TransactionHandler, Client, ClientTransaction,
+        Rejection,
+    },
+    policy::{EnginePolicy, CHANNEL_ATTRIBUTE},
+};
+
+/// Debit the amount from the available funds, if they cover it with the overdraft allowed by policy
+pub(crate) struct WithdrawalHandler;
+
+impl TransactionHandler for WithdrawalHandler {
+    fn events(
+        &self,
+        client: &Client,
+        transaction: &ClientTransaction,
+        amount: Option<Amount>,
+        policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        client.check_new(transaction.tx)?;
+        let amount = amount.ok_or(Rejection::InvalidAmount)?;
+        if !policy.covers(client.available, amount) {
+            return Err(Rejection::InsufficientFunds);
+        }
+        let withdrawn = Event::Withdrawn {
+            tx: transaction.tx,
+            amount,
+            channel: transaction.attributes.get(CHANNEL_ATTRIBUTE).cloned(),
+        };
+        Ok(client.with_pruning(withdrawn, transaction.tx, policy))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::business_logic::{
+        domain::{
+            amount::Amount,
+            events::Event,
+            handlers::{account, events, handler, transaction},
+            Rejection, Type,
+        },
+        policy::EnginePolicy,
+    };
+
+    #[test]
+    fn test_withdrawal() {
+        let policy = EnginePolicy::default();
+        let client = account(&[transaction(Type::Deposit, 1, Some("2.0"))], &policy);
+        assert_eq!(
+            events(
+                &client,
+                &transaction(Type::Withdrawal, 2, Some("2.0")),
+                &policy
+            ),
+            Ok(vec![Event::Withdrawn {
+                tx: 2,
+                amount: Amount::new("2.0".parse().unwrap()).unwrap(),
+                channel: None,
+            }])
+        );
+        assert_eq!(
+            events(
+                &client,
+                &transaction(Type::Withdrawal, 2, Some("2.0001")),
+                &policy
+            ),
+            Err(Rejection::InsufficientFunds)
+        );
+        assert_eq!(
+            events(
+                &client,
+                &transaction(Type::Withdrawal, 1, Some("1.0")),
+                &policy
+            ),
+            Err(Rejection::DuplicateTransaction)
+        );
+        assert!(!handler(Type::Withdrawal).applies_to_locked(&policy));
+    }
+
+    #[test]
+    fn test_withdrawal_overdraft() {
+        let policy = EnginePolicy {
+            overdraft_limit: Some("1.0".parse().unwrap()),
+            ..Default::default()
+        };
+        let client = account(&[transaction(Type::Deposit, 1, Some("2.0"))], &policy);
+        assert!(events(
+            &client,
+            &transaction(Type::Withdrawal, 2, Some("3.0")),
+            &policy
+        )
+        .is_ok());
+        assert_eq!(
+            events(
+                &client,
+                &transaction(Type::Withdrawal, 2, Some("3.0001")),
+                &policy
+            ),
+            Err(Rejection::InsufficientFunds)
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    business_logic::{
+        domain::{
+            amount::{Amount, Balance},
+            trait_impl::from_str,
+        },
+        io::enrichment::Attributes,
+    },
+    shared::decimal::Decimal,
+};
+
+pub(crate) mod amount;
+pub(crate) mod events;
+pub(crate) mod handlers;
+pub(crate) mod trait_impl;
+pub(crate) mod transactions_logic;
+pub(crate) mod transfers;
+
+pub use transactions_logic::Rejection;
+
+/// Transaction read from the input, or submitted to a [TransactionEngine](crate::TransactionEngine)
+#[derive(Debug, Deserialize)]
+pub struct ClientTransaction {
+    /// Client ID, UUID
+    #[serde(rename = "client")]
+    pub(crate) id: u16,
+    /// Type of transaction
+    #[serde(rename = "type", deserialize_with = "from_str")]
+    pub(crate) transaction_type: Type,
+    /// Transaction ID
+    pub(crate) tx: u32,
+    /// Transaction amount. Present only for Deposit, Withdrawal and Adjustment (signed)
+    pub(crate) amount: Option<Decimal>,
+    /// Reason code. Mandatory for Adjustment, optional for Dispute
+    pub(crate) reason: Option<String>,
+    /// Reference of the evidence supporting a Dispute (e.g. a case or document ID)
+    pub(crate) evidence: Option<String>,
+    /// Client credited by a Transfer, debiting the client of the transaction
+    pub(crate) to_client: Option<u16>,
+    #[serde(skip)]
+    /// Additional attributes merged from enrichment files
+    pub(crate) attributes: Attributes,
+}
+
+/// Account of a client: balances, transactions history and dispute outcomes. Opaque to other programs, which can
+/// keep it in a [ClientStore](crate::business_logic::engine::store::ClientStore) of their own
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Client {
+    /// Client ID, UUID
+    #[serde(rename = "client")]
+    pub(crate) id: u16,
+    /// Available founds = total - held
+    pub(crate) available: Balance,
+    /// Held founds = total - available
+    pub(crate) held: Balance,
+    /// Total founds = available + held
+    pub(crate) total: Balance,
+    /// Identify if client account is locked
+    pub(crate) locked: bool,
+    #[serde(skip)]
+    /// History of transactions of client identified by ID
+    pub(crate) transations_history: HashMap<u32, Transaction>,
+    #[serde(skip)]
+    /// IDs of the transactions in history, from the oldest to the newest
+    pub(crate) history_order: VecDeque<u32>,
+    #[serde(skip)]
+    /// IDs of the transactions removed from history by the retention policy
+    pub(crate) pruned_transactions: HashSet<u32>,
+    #[serde(skip)]
+    /// How disputes opened on client transactions ended
+    pub(crate) dispute_outcomes: DisputeOutcomes,
+    #[serde(skip)]
+    /// Risk score computed by the scoring pass, reported by the extended output format
+    pub(crate) risk_score: f64,
+    #[serde(skip)]
+    /// Number of transactions applied to the account, bumped on every applied transaction
+    pub(crate) version: u64,
+    #[serde(skip)]
+    /// Identify if the personal data of the client were erased, leaving only balances and dispute outcomes
+    pub(crate) erased: bool,
+    #[serde(skip)]
+    /// Notes attached to the account by operators, in the order they were added
+    pub(crate) annotations: Vec<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Transaction {
+    /// The found amount linked to this transaction
+    pub(crate) amount: Amount,
+    /// Stage of the dispute lifecycle the transaction is at
+    pub(crate) dispute_status: DisputeStatus,
+    /// Channel the transaction was made on, if known from enrichment
+    pub(crate) channel: Option<String>,
+    /// Reason code and evidence of the dispute opened on the transaction, if any
+    pub(crate) dispute: DisputeDetails,
+    /// Side of the transfer and client on the other side, if the transaction is a transfer
+    pub(crate) transfer: Option<TransferLeg>,
+    /// Identify if the transaction is a withdrawal
+    pub(crate) withdrawal: bool,
+    /// How the funds moved when the last dispute was opened on the transaction
+    pub(crate) dispute_effect: DisputeEffect,
+}
+
+/// Stage of the dispute lifecycle of a transaction. A dispute can be opened once, and ends either resolved or
+/// charged back: both are final, so that the funds of a transaction are never released nor removed twice
+///
+/// | From         | Dispute                | Resolve                | Chargeback                                  |
+/// |--------------|------------------------|------------------------|---------------------------------------------|
+/// | undisputed   | disputed               | `not_disputed`         | `not_disputed`, or charged back if allowed  |
+/// |              |                        |                        | by policy (`--charge-back-undisputed`)      |
+/// | disputed     | `already_disputed`     | resolved               | charged back                                |
+/// | resolved     | `already_resolved`     | `already_resolved`     | `already_resolved`                          |
+/// | charged back | `already_charged_back` | `already_charged_back` | `already_charged_back`                      |
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) enum DisputeStatus {
+    /// Never disputed
+    #[default]
+    Undisputed,
+    /// Under dispute, its funds being held
+    Disputed,
+    /// Dispute resolved, its funds released
+    Resolved,
+    /// Dispute charged back, its funds removed
+    ChargedBack,
+}
+
+impl DisputeStatus {
+    /// Check that a dispute can be opened on the transaction
+    pub(crate) fn check_dispute(self) -> Result<(), Rejection> {
+        match self {
+            DisputeStatus::Undisputed => Ok(()),
+            DisputeStatus::Disputed => Err(Rejection::AlreadyDisputed),
+            DisputeStatus::Resolved => Err(Rejection::AlreadyResolved),
+            DisputeStatus::ChargedBack => Err(Rejection::AlreadyChargedBack),
+        }
+    }
+
+    /// Check that the dispute of the transaction can be resolved or charged back. Returns `false` if the
+    /// transaction was never disputed, in which case only a chargeback opening the dispute may apply
+    pub(crate) fn check_closing(self) -> Result<bool, Rejection> {
+        match self {
+            DisputeStatus::Undisputed => Ok(false),
+            DisputeStatus::Disputed => Ok(true),
+            DisputeStatus::Resolved => Err(Rejection::AlreadyResolved),
+            DisputeStatus::ChargedBack => Err(Rejection::AlreadyChargedBack),
+        }
+    }
+}
+
+/// How the funds of a disputed transaction move while the dispute is open, fixed when it is opened so that
+/// resolve and chargeback undo it whatever the policy in force by then
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) enum DisputeEffect {
+    /// Amount moved from available to held funds, removed from held and total funds if charged back
+    #[default]
+    Debit,
+    /// Amount of a withdrawal credited back as held funds, released to available funds if charged back and
+    /// removed from held and total funds if resolved
+    Credit,
+}
+
+/// Side of a transfer recorded in the history of a client, with the client on the other side
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TransferLeg {
+    /// Funds sent to the client
+    To(u16),
+    /// Funds received from the client
+    From(u16),
+}
+
+/// Reason code and evidence reference given when a dispute is opened, both optional
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct DisputeDetails {
+    pub(crate) reason: Option<String>,
+    pub(crate) evidence: Option<String>,
+}
+
+impl DisputeDetails {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.reason.is_none() && self.evidence.is_none()
+    }
+}
+
+/// Number and amount of disputes by outcome. Pending disputes are the ones neither resolved nor charged back
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct DisputeOutcomes {
+    /// Number of disputes opened
+    pub(crate) opened: u32,
+    /// Total amount of the disputed transactions
+    pub(crate) opened_amount: Decimal,
+    /// Number of disputes resolved
+    pub(crate) resolved: u32,
+    /// Total amount released by resolved disputes
+    pub(crate) resolved_amount: Decimal,
+    /// Number of disputes charged back
+    pub(crate) charged_back: u32,
+    /// Total amount removed by chargebacks
+    pub(crate) charged_back_amount: Decimal,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    ChargeBack,
+    Adjustment,
+    Transfer,
+    Unlock,
+}
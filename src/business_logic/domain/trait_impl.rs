@@ -0,0 +1,122 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    business_logic::domain::{
+        amount::{Amount, Balance},
+        DisputeDetails, DisputeStatus, Rejection, Type,
+    },
+    shared::errors::Error,
+};
+
+impl From<Type> for String {
+    fn from(value: Type) -> Self {
+        match value {
+            Type::Deposit => "deposit".to_owned(),
+            Type::Withdrawal => "withdrawal".to_owned(),
+            Type::Dispute => "dispute".to_owned(),
+            Type::Resolve => "resolve".to_owned(),
+            Type::ChargeBack => "chargeback".to_owned(),
+            Type::Adjustment => "adjustment".to_owned(),
+            Type::Transfer => "transfer".to_owned(),
+            Type::Unlock => "unlock".to_owned(),
+        }
+    }
+}
+
+impl FromStr for Type {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposit" => Ok(Self::Deposit),
+            "withdrawal" => Ok(Self::Withdrawal),
+            "dispute" => Ok(Self::Dispute),
+            "resolve" => Ok(Self::Resolve),
+            "chargeback" => Ok(Self::ChargeBack),
+            "adjustment" => Ok(Self::Adjustment),
+            "transfer" => Ok(Self::Transfer),
+            "unlock" => Ok(Self::Unlock),
+            _ => Err(Error::InvalidTransactionType(s.to_owned())),
+        }
+    }
+}
+
+pub(crate) fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let s = String::deserialize(deserializer)?;
+    T::from_str(&s).map_err(serde::de::Error::custom)
+}
+
+pub(crate) fn four_decimals<S>(value: &f64, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&format!("{:.4}", value))
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            Rejection::InvalidAmount => "invalid amount",
+            Rejection::LockedAccount => "account locked",
+            Rejection::RejectedByPolicy => "rejected by policy",
+            Rejection::DuplicateTransaction => "duplicate transaction ID",
+            Rejection::InsufficientFunds => "insufficient funds",
+            Rejection::UnknownTransaction => "unknown transaction",
+            Rejection::PrunedTransaction => "transaction pruned from history",
+            Rejection::AlreadyDisputed => "transaction already under dispute",
+            Rejection::NotDisputed => "transaction not under dispute",
+            Rejection::AlreadyResolved => "dispute of the transaction already resolved",
+            Rejection::AlreadyChargedBack => "dispute of the transaction already charged back",
+            Rejection::MissingReason => "missing reason code",
+            Rejection::NegativeBalance => "negative balance not allowed",
+            Rejection::Overflow => "balance overflow",
+            Rejection::ErasedAccount => "account erased",
+            Rejection::InvalidTransfer => "invalid transfer",
+            Rejection::IncomingTransfer => "transfer disputed by its destination",
+            Rejection::NotLocked => "account not locked",
+        };
+        f.pad(description)
+    }
+}
+
+impl Display for DisputeDetails {
+    /// Given details only, e.g. `reason fraud, evidence CASE-1`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let details = [("reason", &self.reason), ("evidence", &self.evidence)]
+            .into_iter()
+            .filter_map(|(name, value)| value.as_ref().map(|value| format!("{name} {value}")))
+            .collect::<Vec<_>>();
+        f.pad(&details.join(", "))
+    }
+}
+
+impl Display for DisputeStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            DisputeStatus::Undisputed => "undisputed",
+            DisputeStatus::Disputed => "disputed",
+            DisputeStatus::Resolved => "resolved",
+            DisputeStatus::ChargedBack => "charged_back",
+        };
+        f.pad(status)
+    }
+}
+
+impl Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.value().fmt(f)
+    }
+}
+
+impl Display for Balance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.value().fmt(f)
+    }
+}
@@ -0,0 +1,332 @@
+use crate::{
+    business_logic::{
+        domain::{
+            amount::{Amount, Balance},
+            events::{Balances, Event},
+            handlers, Client, ClientTransaction, DisputeDetails, DisputeStatus, Transaction,
+            TransferLeg,
+        },
+        policy::EnginePolicy,
+    },
+    shared::errors::Error,
+};
+
+/// Reason why a transaction is ignored instead of being applied to the client account
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rejection {
+    /// Amount missing, or negative for a type other than adjustment
+    InvalidAmount,
+    /// Client account locked by a chargeback
+    LockedAccount,
+    /// Transaction rejected by a policy rule (attributes, channels)
+    RejectedByPolicy,
+    /// Deposit or withdrawal reusing the ID of a previous transaction of the client
+    DuplicateTransaction,
+    /// Withdrawal or transfer exceeding the available funds and overdraft, or dispute exceeding them if refused by
+    /// policy
+    InsufficientFunds,
+    /// Dispute, resolve or chargeback referring to a transaction never seen for the client
+    UnknownTransaction,
+    /// Dispute, resolve or chargeback referring to a transaction pruned from the history by the retention policy
+    PrunedTransaction,
+    /// Dispute referring to a transaction already under dispute
+    AlreadyDisputed,
+    /// Resolve or chargeback referring to a transaction never disputed
+    NotDisputed,
+    /// Dispute, resolve or chargeback referring to a transaction whose dispute was resolved
+    AlreadyResolved,
+    /// Dispute, resolve or chargeback referring to a transaction whose dispute was charged back
+    AlreadyChargedBack,
+    /// Adjustment without reason code
+    MissingReason,
+    /// Adjustment driving available funds negative, not allowed for the client
+    NegativeBalance,
+    /// Amount driving a balance beyond the range of representable amounts
+    Overflow,
+    /// Client account erased on request, only kept as a tombstone
+    ErasedAccount,
+    /// Transfer without destination, or to its own source
+    InvalidTransfer,
+    /// Dispute, resolve or chargeback of a transfer filed by its destination instead of its source
+    IncomingTransfer,
+    /// Unlock of an account which is not locked
+    NotLocked,
+}
+
+impl Rejection {
+    /// Stable machine-readable code of the rejection, in snake case
+    pub fn code(&self) -> &'static str {
+        match self {
+            Rejection::InvalidAmount => "invalid_amount",
+            Rejection::LockedAccount => "locked_account",
+            Rejection::RejectedByPolicy => "rejected_by_policy",
+            Rejection::DuplicateTransaction => "duplicate_transaction",
+            Rejection::InsufficientFunds => "insufficient_funds",
+            Rejection::UnknownTransaction => "unknown_transaction",
+            Rejection::PrunedTransaction => "pruned_transaction",
+            Rejection::AlreadyDisputed => "already_disputed",
+            Rejection::NotDisputed => "not_disputed",
+            Rejection::AlreadyResolved => "already_resolved",
+            Rejection::AlreadyChargedBack => "already_charged_back",
+            Rejection::MissingReason => "missing_reason",
+            Rejection::NegativeBalance => "negative_balance",
+            Rejection::Overflow => "overflow",
+            Rejection::ErasedAccount => "erased_account",
+            Rejection::InvalidTransfer => "invalid_transfer",
+            Rejection::IncomingTransfer => "incoming_transfer",
+            Rejection::NotLocked => "not_locked",
+        }
+    }
+}
+
+impl Client {
+    pub(crate) fn new(id: u16) -> Self {
+        Self {
+            id,
+            ..Default::default()
+        }
+    }
+
+    /// Apply a transaction to the client account: the transaction is first turned into domain events,
+    /// which are then folded into the account state. Returns the applied events, or why the transaction was ignored.
+    pub(crate) fn apply_transaction(
+        &mut self,
+        transaction: &ClientTransaction,
+        policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        let events = self.handle(transaction, policy)?;
+        for event in &events {
+            self.apply_event(event)?;
+        }
+        self.version += 1;
+        Ok(events)
+    }
+
+    /// Apply a run of deposits to the client account, leaving it as [Client::apply_transaction] would, applying
+    /// them one after the other, but writing the balances once: deposits depend on the history and the lock of the
+    /// account, not on its balances, so every deposit is checked and recorded in the history as it comes, and only
+    /// its overflow check is made against the balances left by the previous ones. Returns the balances before and
+    /// after every deposit, with its applied events or why it was ignored
+    pub(crate) fn apply_deposits<'a>(
+        &mut self,
+        deposits: impl IntoIterator<Item = &'a ClientTransaction>,
+        policy: &EnginePolicy,
+    ) -> Vec<(Balances, Balances, Result<Vec<Event>, Rejection>)> {
+        let mut balances = self.balances();
+        let results = deposits
+            .into_iter()
+            .map(|deposit| {
+                let before = balances;
+                let result = self.events(deposit, policy).and_then(|events| {
+                    let (after, outcomes) = events
+                        .iter()
+                        .try_fold(
+                            (balances, self.dispute_outcomes),
+                            |(balances, outcomes), event| {
+                                Some((balances.after(event)?, outcomes.after(event)?))
+                            },
+                        )
+                        .ok_or(Rejection::Overflow)?;
+                    events.iter().for_each(|event| self.record_event(event));
+                    self.dispute_outcomes = outcomes;
+                    self.version += 1;
+                    balances = after;
+                    Ok(events)
+                });
+                (before, balances, result)
+            })
+            .collect();
+
+        self.available = balances.available;
+        self.held = balances.held;
+        self.total = balances.total;
+        results
+    }
+
+    /// Apply again the events of a transaction applied before, as recorded by a write-ahead log, leaving the
+    /// account as [Client::apply_transaction] left it
+    pub(crate) fn replay_transaction(&mut self, events: &[Event]) -> Result<(), Rejection> {
+        for event in events {
+            self.apply_event(event)?;
+        }
+        self.version += 1;
+        Ok(())
+    }
+
+    /// Erase the personal data of the account: transactions history, with channels and dispute details,
+    /// pruned transaction IDs and annotations. The account is left as a tombstone keeping balances and dispute outcomes,
+    /// so that aggregate totals do not change, and rejecting any later transaction. Refused while funds are held
+    /// by pending disputes, which could no longer be resolved nor charged back
+    pub(crate) fn erase(&mut self) -> Result<(), Error> {
+        if self.held != Balance::ZERO {
+            return Err(Error::ErasureRefused(
+                self.id,
+                format!("{} held by pending disputes", self.held),
+            ));
+        }
+        self.transations_history.clear();
+        self.history_order.clear();
+        self.pruned_transactions.clear();
+        self.risk_score = 0.0;
+        self.annotations.clear();
+        self.erased = true;
+        Ok(())
+    }
+
+    /// Attach an operator note to the account, after the ones already attached. Empty notes and notes spanning
+    /// several lines, which statements could not render, are refused
+    pub(crate) fn annotate(&mut self, note: &str) -> Result<(), Error> {
+        let note = note.trim();
+        if note.is_empty() {
+            return Err(Error::AnnotationRefused(self.id, "empty note".to_owned()));
+        }
+        if note.contains(['\r', '\n']) {
+            return Err(Error::AnnotationRefused(
+                self.id,
+                "note spanning several lines".to_owned(),
+            ));
+        }
+        self.annotations.push(note.to_owned());
+        Ok(())
+    }
+
+    /// Balances the account would have after a transaction, or why the transaction would be ignored,
+    /// without modifying the account. The preview has no transactions history, which balances do not depend on
+    pub(crate) fn preview_transaction(
+        &self,
+        transaction: &ClientTransaction,
+        policy: &EnginePolicy,
+    ) -> Result<Client, Rejection> {
+        self.preview_events(&self.handle(transaction, policy)?)
+    }
+
+    /// Balances the account would have after the events of a transaction, checked beforehand, as
+    /// [Client::preview_transaction]
+    pub(crate) fn preview_events(&self, events: &[Event]) -> Result<Client, Rejection> {
+        let mut preview = Client {
+            id: self.id,
+            available: self.available,
+            held: self.held,
+            total: self.total,
+            locked: self.locked,
+            ..Default::default()
+        };
+        for event in events {
+            preview.apply_event(event)?;
+        }
+        Ok(preview)
+    }
+
+    /// Check a transaction against the current account state and return the events it produces,
+    /// without modifying the account. Events are returned only if none of them overflows a balance
+    /// or a dispute total
+    fn handle(
+        &self,
+        transaction: &ClientTransaction,
+        policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        let events = self.events(transaction, policy)?;
+        self.checked(events)
+    }
+
+    /// Events returned only if none of them overflows a balance or a dispute total of the account
+    pub(crate) fn checked(&self, events: Vec<Event>) -> Result<Vec<Event>, Rejection> {
+        events
+            .iter()
+            .try_fold(
+                (self.balances(), self.dispute_outcomes),
+                |(balances, outcomes), event| {
+                    Some((balances.after(event)?, outcomes.after(event)?))
+                },
+            )
+            .ok_or(Rejection::Overflow)?;
+        Ok(events)
+    }
+
+    /// Events of a transaction, produced by the handler of its type once the checks shared by every type are made
+    fn events(
+        &self,
+        transaction: &ClientTransaction,
+        policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        if self.erased {
+            return Err(Rejection::ErasedAccount);
+        }
+        let handler = handlers::handler(transaction.transaction_type);
+        let amount = match handler.takes_amount() {
+            true => transaction
+                .amount
+                .map(|amount| Amount::new(amount).ok_or(Rejection::InvalidAmount))
+                .transpose()?,
+            false => None,
+        };
+        if self.locked && !handler.applies_to_locked(policy) {
+            return Err(Rejection::LockedAccount);
+        }
+        if !policy.allows(transaction, self) {
+            return Err(Rejection::RejectedByPolicy);
+        }
+
+        handler.events(self, transaction, amount, policy)
+    }
+
+    /// Check that a deposit or withdrawal ID was never used by the client, pruned transactions included
+    pub(crate) fn check_new(&self, tx: u32) -> Result<(), Rejection> {
+        match self.transations_history.contains_key(&tx) || self.pruned_transactions.contains(&tx) {
+            true => Err(Rejection::DuplicateTransaction),
+            false => Ok(()),
+        }
+    }
+
+    /// Transaction of the history referred to by a dispute, resolve or chargeback of the client alone: transfers
+    /// are disputed by their source together with their destination (see `Client::plan_transfer`)
+    pub(crate) fn referenced(&self, tx: u32) -> Result<&Transaction, Rejection> {
+        match self.history_entry(tx)?.transfer {
+            Some(TransferLeg::From(_)) => Err(Rejection::IncomingTransfer),
+            Some(TransferLeg::To(_)) => Err(Rejection::InvalidTransfer),
+            None => self.history_entry(tx),
+        }
+    }
+
+    /// Transaction `tx` of the history, or why it is missing
+    pub(crate) fn history_entry(&self, tx: u32) -> Result<&Transaction, Rejection> {
+        self.transations_history.get(&tx).ok_or_else(|| {
+            match self.pruned_transactions.contains(&tx) {
+                true => Rejection::PrunedTransaction,
+                false => Rejection::UnknownTransaction,
+            }
+        })
+    }
+
+    /// Follow the event adding transaction `tx` to the history with the pruning of the oldest transactions
+    /// exceeding the history limit, if any. Transactions under dispute are never pruned.
+    pub(crate) fn with_pruning(&self, event: Event, tx: u32, policy: &EnginePolicy) -> Vec<Event> {
+        let excess = policy.history_limit.map_or(0, |limit| {
+            (self.transations_history.len() + 1).saturating_sub(limit)
+        });
+
+        let pruned = self
+            .history_order
+            .iter()
+            .copied()
+            .filter(|tx| {
+                self.transations_history
+                    .get(tx)
+                    .is_some_and(|tx| tx.dispute_status != DisputeStatus::Disputed)
+            })
+            .chain(std::iter::once(tx))
+            .take(excess)
+            .map(|tx| Event::Pruned { tx });
+
+        std::iter::once(event).chain(pruned).collect()
+    }
+}
+
+/// Dispute details given by a dispute, or by a chargeback opening its dispute, empty values being missing
+pub(crate) fn dispute_details(transaction: &ClientTransaction) -> DisputeDetails {
+    let non_empty = |value: &Option<String>| value.clone().filter(|value| !value.is_empty());
+    DisputeDetails {
+        reason: non_empty(&transaction.reason),
+        evidence: non_empty(&transaction.evidence),
+    }
+}
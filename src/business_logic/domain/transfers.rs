@@ -0,0 +1,290 @@
+use crate::business_logic::{
+    domain::{
+        amount::Amount,
+        events::Event,
+        transactions_logic::{dispute_details, Rejection},
+        Client, ClientTransaction, DisputeDetails, DisputeEffect, Transaction, TransferLeg, Type,
+    },
+    policy::{EnginePolicy, CHANNEL_ATTRIBUTE},
+};
+
+/// Transaction moving funds between two clients, as checked against the account of its source: a transfer, or a
+/// dispute, resolve or chargeback of a transfer, filed by its source. Both legs are applied together, once the
+/// destination accepts its own (see [Client::receive_transfer]), or none is
+#[derive(Debug)]
+pub(crate) struct TransferPlan {
+    /// Client on the other side of the transfer
+    pub(crate) destination: u16,
+    tx: u32,
+    amount: Amount,
+    step: Step,
+    /// Events of the leg of the source
+    pub(crate) source_events: Vec<Event>,
+}
+
+/// What the transaction does to the leg of the destination
+#[derive(Debug)]
+enum Step {
+    Send {
+        channel: Option<String>,
+    },
+    Dispute {
+        details: DisputeDetails,
+        auto_resolved: bool,
+    },
+    Resolve,
+    ChargeBack {
+        details: DisputeDetails,
+        /// Chargeback of a transfer not under dispute, as allowed by policy
+        implied: bool,
+    },
+}
+
+impl Client {
+    /// Whether the transactions history of the client holds a leg of a transfer
+    pub(crate) fn has_transfers(&self) -> bool {
+        self.transations_history
+            .values()
+            .any(|transaction| transaction.transfer.is_some())
+    }
+
+    /// Destination of transfer `tx`, if sent by the client
+    pub(crate) fn sent_transfer(&self, tx: u32) -> Option<u16> {
+        match self.transations_history.get(&tx)?.transfer? {
+            TransferLeg::To(destination) => Some(destination),
+            TransferLeg::From(_) => None,
+        }
+    }
+
+    /// Check a transfer, or a dispute, resolve or chargeback of a transfer sent by the client, against the
+    /// account of the client, its source, without modifying it. Disputes of a transfer hold the funds where they
+    /// went, on the destination, and its chargeback credits them back to the source, whose account is locked
+    pub(crate) fn plan_transfer(
+        &self,
+        transaction: &ClientTransaction,
+        policy: &EnginePolicy,
+    ) -> Result<TransferPlan, Rejection> {
+        if self.erased {
+            return Err(Rejection::ErasedAccount);
+        }
+        if self.locked {
+            return Err(Rejection::LockedAccount);
+        }
+        if !policy.allows(transaction, self) {
+            return Err(Rejection::RejectedByPolicy);
+        }
+
+        let tx = transaction.tx;
+        let plan = match transaction.transaction_type {
+            Type::Transfer => {
+                let destination = transaction
+                    .to_client
+                    .filter(|destination| *destination != self.id)
+                    .ok_or(Rejection::InvalidTransfer)?;
+                self.check_new(tx)?;
+                let amount = transaction
+                    .amount
+                    .and_then(Amount::new)
+                    .ok_or(Rejection::InvalidAmount)?;
+                if !policy.covers(self.available, amount) {
+                    return Err(Rejection::InsufficientFunds);
+                }
+                let channel = transaction.attributes.get(CHANNEL_ATTRIBUTE).cloned();
+                let sent = Event::TransferredOut {
+                    tx,
+                    amount,
+                    to: destination,
+                    channel: channel.clone(),
+                };
+                TransferPlan {
+                    destination,
+                    tx,
+                    amount,
+                    step: Step::Send { channel },
+                    source_events: self.with_pruning(sent, tx, policy),
+                }
+            }
+            Type::Dispute => {
+                let (sent, destination) = self.sent(tx)?;
+                sent.dispute_status.check_dispute()?;
+                let details = dispute_details(transaction);
+                let auto_resolved = policy.auto_resolves(&details);
+                let mut source_events = vec![Event::TransferDisputed {
+                    tx,
+                    details: details.clone(),
+                }];
+                if auto_resolved {
+                    source_events.push(Event::TransferResolved { tx });
+                }
+                TransferPlan {
+                    destination,
+                    tx,
+                    amount: sent.amount,
+                    step: Step::Dispute {
+                        auto_resolved,
+                        details,
+                    },
+                    source_events,
+                }
+            }
+            Type::Resolve => {
+                let (sent, destination) = self.sent(tx)?;
+                if !sent.dispute_status.check_closing()? {
+                    return Err(Rejection::NotDisputed);
+                }
+                TransferPlan {
+                    destination,
+                    tx,
+                    amount: sent.amount,
+                    step: Step::Resolve,
+                    source_events: vec![Event::TransferResolved { tx }],
+                }
+            }
+            Type::ChargeBack => {
+                let (sent, destination) = self.sent(tx)?;
+                let reversed = Event::TransferReversed {
+                    tx,
+                    amount: sent.amount,
+                };
+                let (details, implied, source_events) = match sent.dispute_status.check_closing()? {
+                    true => (sent.dispute.clone(), false, vec![reversed, Event::Locked]),
+                    // as for other transactions, the dispute is opened with the details of the chargeback
+                    false if policy.charge_back_undisputed => {
+                        let details = dispute_details(transaction);
+                        let disputed = Event::TransferDisputed {
+                            tx,
+                            details: details.clone(),
+                        };
+                        (details, true, vec![disputed, reversed, Event::Locked])
+                    }
+                    false => return Err(Rejection::NotDisputed),
+                };
+                TransferPlan {
+                    destination,
+                    tx,
+                    amount: sent.amount,
+                    step: Step::ChargeBack { details, implied },
+                    source_events,
+                }
+            }
+            Type::Deposit | Type::Withdrawal | Type::Adjustment | Type::Unlock => {
+                return Err(Rejection::InvalidTransfer)
+            }
+        };
+
+        Ok(TransferPlan {
+            source_events: self.checked(plan.source_events)?,
+            ..plan
+        })
+    }
+
+    /// Check the leg of a transfer planned by client `source` against the account of the client, its destination,
+    /// without modifying it. Returns the events of the leg. A locked destination receives no transfer, but its
+    /// funds can still be held and charged back by the disputes of the transfers it received
+    pub(crate) fn receive_transfer(
+        &self,
+        source: u16,
+        plan: &TransferPlan,
+        policy: &EnginePolicy,
+    ) -> Result<Vec<Event>, Rejection> {
+        if self.erased {
+            return Err(Rejection::ErasedAccount);
+        }
+
+        let (tx, amount) = (plan.tx, plan.amount);
+        let events = match &plan.step {
+            Step::Send { channel } => {
+                if self.locked {
+                    return Err(Rejection::LockedAccount);
+                }
+                self.check_new(tx)?;
+                let received = Event::TransferredIn {
+                    tx,
+                    amount,
+                    from: source,
+                    channel: channel.clone(),
+                };
+                self.with_pruning(received, tx, policy)
+            }
+            Step::Dispute {
+                details,
+                auto_resolved,
+            } => {
+                self.received(tx, source)?;
+                if !policy.allows_dispute(self.available, amount, DisputeEffect::Debit) {
+                    return Err(Rejection::InsufficientFunds);
+                }
+                // the funds of a transfer are on the destination: they are held there as for a deposit
+                let mut events = vec![Event::DisputeOpened {
+                    tx,
+                    amount,
+                    details: details.clone(),
+                    effect: DisputeEffect::Debit,
+                }];
+                if *auto_resolved {
+                    events.push(Event::DisputeResolved {
+                        tx,
+                        amount,
+                        effect: DisputeEffect::Debit,
+                    });
+                }
+                events
+            }
+            Step::Resolve => {
+                self.received(tx, source)?;
+                vec![Event::DisputeResolved {
+                    tx,
+                    amount,
+                    effect: DisputeEffect::Debit,
+                }]
+            }
+            Step::ChargeBack { details, implied } => {
+                self.received(tx, source)?;
+                if *implied && !policy.allows_dispute(self.available, amount, DisputeEffect::Debit)
+                {
+                    return Err(Rejection::InsufficientFunds);
+                }
+                let charged_back = Event::ChargedBack {
+                    tx,
+                    amount,
+                    details: details.clone(),
+                    effect: DisputeEffect::Debit,
+                };
+                match implied {
+                    true => vec![
+                        Event::DisputeImplied { tx },
+                        Event::DisputeOpened {
+                            tx,
+                            amount,
+                            details: details.clone(),
+                            effect: DisputeEffect::Debit,
+                        },
+                        charged_back,
+                    ],
+                    false => vec![charged_back],
+                }
+            }
+        };
+
+        self.checked(events)
+    }
+
+    /// Transfer `tx` sent by the client, with its destination
+    fn sent(&self, tx: u32) -> Result<(&Transaction, u16), Rejection> {
+        let sent = self.history_entry(tx)?;
+        match sent.transfer {
+            Some(TransferLeg::To(destination)) => Ok((sent, destination)),
+            Some(TransferLeg::From(_)) => Err(Rejection::IncomingTransfer),
+            None => Err(Rejection::UnknownTransaction),
+        }
+    }
+
+    /// Transfer `tx` received by the client from `source`
+    fn received(&self, tx: u32, source: u16) -> Result<&Transaction, Rejection> {
+        let received = self.history_entry(tx)?;
+        match received.transfer {
+            Some(TransferLeg::From(from)) if from == source => Ok(received),
+            _ => Err(Rejection::UnknownTransaction),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::{collections::HashMap, num::NonZeroUsize};
+
+use crate::{
+    business_logic::{
+        domain::{events::Event, Client, ClientTransaction, Type},
+        engine::outcomes::{self, OutcomeSink},
+        policy::EnginePolicy,
+    },
+    shared::errors::Error,
+};
+
+/// Consecutive deposits of the same client held back to be applied together, with a single update of the client
+/// balances, once the run ends: on a record which is not a deposit of the client, or once the run holds `window`
+/// deposits. Their outcomes are the ones of the deposits applied one by one, handed to the side outputs in input order
+pub(crate) struct DepositRun {
+    window: NonZeroUsize,
+    /// Sequence number, line and transaction of the deposits of the run
+    deposits: Vec<(u64, u64, ClientTransaction)>,
+}
+
+impl DepositRun {
+    pub(crate) fn new(window: NonZeroUsize) -> Self {
+        Self {
+            window,
+            deposits: Vec::with_capacity(window.get()),
+        }
+    }
+
+    /// Whether the transaction is a deposit going on with the run, which must be applied first otherwise
+    pub(crate) fn accepts(&self, transaction: &ClientTransaction) -> bool {
+        transaction.transaction_type == Type::Deposit
+            && self.deposits.first().is_none_or(|(_, _, first)| {
+                first.id == transaction.id && self.deposits.len() < self.window.get()
+            })
+    }
+
+    /// Add deposit `seq` to the run, which must accept it
+    pub(crate) fn push(&mut self, seq: u64, line: u64, deposit: ClientTransaction) {
+        self.deposits.push((seq, line, deposit));
+    }
+
+    /// Apply the deposits of the run to their client, handing their outcomes to `sink`, and start a new run
+    pub(crate) fn flush<F>(
+        &mut self,
+        clients: &mut HashMap<u16, Client>,
+        policy: &EnginePolicy,
+        sink: &mut OutcomeSink<F>,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(u16, Vec<Event>),
+    {
+        let (sequence, deposits): (Vec<_>, Vec<_>) = self
+            .deposits
+            .drain(..)
+            .map(|(seq, line, deposit)| (seq, (line, deposit)))
+            .unzip();
+        let outcomes = outcomes::apply_deposits(clients, deposits, policy);
+        sequence
+            .into_iter()
+            .zip(outcomes)
+            .try_for_each(|(seq, outcome)| sink.push(seq, outcome))
+    }
+}
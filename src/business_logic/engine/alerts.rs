@@ -0,0 +1,138 @@
+use std::{fs::OpenOptions, path::Path};
+
+use csv::{Writer, WriterBuilder};
+use serde::Serialize;
+
+use crate::{
+    business_logic::domain::{amount::Balance, events::Balances, Client},
+    shared::{decimal::Decimal, errors::Error},
+};
+
+/// Thresholds raising an alert when crossed by a client. Unset thresholds are not checked
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AlertThresholds {
+    /// Alert when the total funds of a client rise above this amount
+    pub balance_above: Option<Decimal>,
+    /// Alert when the chargebacks of a client exceed this number
+    pub chargebacks_above: Option<u32>,
+}
+
+/// Single row of the alerts file
+#[derive(Debug, Serialize)]
+struct Alert {
+    /// Sequence number of the triggering input record
+    seq: u64,
+    client: u16,
+    /// Transaction that made the client cross the threshold
+    tx: u32,
+    /// Name of the crossed threshold
+    alert: &'static str,
+    /// Value of the client metric after the transaction
+    value: String,
+}
+
+/// Alerts file, written while transactions are processed
+pub(crate) struct AlertWriter {
+    writer: Writer<std::fs::File>,
+    thresholds: AlertThresholds,
+}
+
+/// Client metrics taken before and after each transaction, checked against the thresholds, and written with the
+/// balances to the audit log
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct AlertMetrics {
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+    chargebacks: u32,
+}
+
+impl From<&Client> for AlertMetrics {
+    fn from(client: &Client) -> Self {
+        Self::new(
+            client.balances(),
+            client.locked,
+            client.dispute_outcomes.charged_back,
+        )
+    }
+}
+
+impl AlertMetrics {
+    pub(crate) fn new(balances: Balances, locked: bool, chargebacks: u32) -> Self {
+        Self {
+            available: balances.available.value(),
+            held: balances.held.value(),
+            total: balances.total.value(),
+            locked,
+            chargebacks,
+        }
+    }
+
+    pub(crate) fn total(&self) -> Decimal {
+        self.total
+    }
+
+    pub(crate) fn balances(&self) -> Balances {
+        Balances {
+            available: Balance::new(self.available),
+            held: Balance::new(self.held),
+            total: Balance::new(self.total),
+        }
+    }
+
+    pub(crate) fn locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl AlertWriter {
+    /// Open the alerts file in append mode, writing the header only if the file is new or empty
+    pub(crate) fn open(path: &Path, thresholds: AlertThresholds) -> Result<Self, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+        let writer = WriterBuilder::new()
+            .has_headers(is_empty)
+            .terminator(csv::Terminator::Any(b'\n'))
+            .from_writer(file);
+
+        Ok(Self { writer, thresholds })
+    }
+
+    /// Append an alert for every threshold crossed by the client because of transaction `tx`, read as input
+    /// record `seq`, i.e. not exceeded before the transaction and exceeded after it. Alerts are flushed right away
+    pub(crate) fn check(
+        &mut self,
+        seq: u64,
+        client: u16,
+        tx: u32,
+        before: AlertMetrics,
+        after: AlertMetrics,
+    ) -> Result<(), Error> {
+        if let Some(threshold) = self.thresholds.balance_above {
+            if before.total <= threshold && after.total > threshold {
+                self.writer.serialize(Alert {
+                    seq,
+                    client,
+                    tx,
+                    alert: "balance_above",
+                    value: after.total.to_string(),
+                })?;
+            }
+        }
+        if let Some(threshold) = self.thresholds.chargebacks_above {
+            if before.chargebacks <= threshold && after.chargebacks > threshold {
+                self.writer.serialize(Alert {
+                    seq,
+                    client,
+                    tx,
+                    alert: "chargebacks_above",
+                    value: after.chargebacks.to_string(),
+                })?;
+            }
+        }
+
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use std::{collections::VecDeque, fmt::Display};
+
+use crate::shared::{decimal::Decimal, errors::Error};
+
+/// Maximum change of the system-wide total funds accepted over a window of input records.
+/// Faster changes are flagged as anomalies, as they may come from corrupted data or a duplicated batch
+#[derive(Debug, Clone, Copy)]
+pub struct AnomalyRate {
+    /// Maximum absolute change of the total funds of all clients within the window
+    pub max_change: Decimal,
+    /// Number of consecutive input records in the window, malformed records included
+    pub window: u64,
+}
+
+/// Window of input records where the system total changed faster than the accepted rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Anomaly {
+    /// Sequence numbers of the first and the last record of the window
+    pub(crate) from_seq: u64,
+    pub(crate) to_seq: u64,
+    /// Signed change of the system total over the window
+    pub(crate) change: Decimal,
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "system total changed by {} ", self.change)?;
+        match self.from_seq == self.to_seq {
+            true => write!(f, "at record {}", self.to_seq),
+            false => write!(f, "between records {} and {}", self.from_seq, self.to_seq),
+        }
+    }
+}
+
+/// Time series of the system total, sampled after every applied transaction, checked against the accepted rate
+#[derive(Debug)]
+pub(crate) struct AnomalyDetector {
+    rate: AnomalyRate,
+    /// Current system total
+    total: Decimal,
+    /// (sequence number, system total after it) samples, the oldest being the baseline of the current window
+    samples: VecDeque<(u64, Decimal)>,
+    anomalies: Vec<Anomaly>,
+}
+
+impl AnomalyDetector {
+    /// Start the time series from the system total before the first record, the sum of `totals`
+    pub(crate) fn new(
+        rate: AnomalyRate,
+        totals: impl IntoIterator<Item = Decimal>,
+    ) -> Result<Self, Error> {
+        let initial_total = totals
+            .into_iter()
+            .try_fold(Decimal::ZERO, Decimal::checked_add)
+            .ok_or_else(Self::overflow)?;
+        Ok(Self {
+            rate,
+            total: initial_total,
+            samples: VecDeque::from([(0, initial_total)]),
+            anomalies: Vec::new(),
+        })
+    }
+
+    fn overflow() -> Error {
+        Error::AmountOverflow("System total of all clients".to_owned())
+    }
+
+    /// Add to the system total the change caused by record `seq`, flagging an anomaly if the total moved
+    /// more than the accepted rate since the beginning of the window ending with the record.
+    /// Once a window is flagged the series restarts from the record, so every anomaly is reported once.
+    /// Fails if the system total, or its change over the window, overflows
+    pub(crate) fn record(&mut self, seq: u64, change: Decimal) -> Result<(), Error> {
+        self.total = self.total.checked_add(change).ok_or_else(Self::overflow)?;
+
+        // the baseline is the latest sample taken before the window
+        let window_start = seq.saturating_sub(self.rate.window);
+        while self
+            .samples
+            .get(1)
+            .is_some_and(|&(sample_seq, _)| sample_seq <= window_start)
+        {
+            self.samples.pop_front();
+        }
+        let (baseline_seq, baseline) = self.samples[0];
+        let total_change = self
+            .total
+            .checked_sub(baseline)
+            .ok_or_else(Self::overflow)?;
+
+        if total_change.abs() > self.rate.max_change {
+            self.anomalies.push(Anomaly {
+                from_seq: baseline_seq + 1,
+                to_seq: seq,
+                change: total_change,
+            });
+            self.samples.clear();
+        }
+        self.samples.push_back((seq, self.total));
+        Ok(())
+    }
+
+    pub(crate) fn anomalies(&self) -> &[Anomaly] {
+        &self.anomalies
+    }
+}
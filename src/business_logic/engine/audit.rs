@@ -0,0 +1,302 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    path::Path,
+};
+
+use csv::{ReaderBuilder, StringRecord, Writer, WriterBuilder};
+
+use crate::{
+    business_logic::{
+        domain::{
+            events::{Balances, Event},
+            ClientTransaction,
+        },
+        engine::{demo, outcomes::Outcome},
+    },
+    shared::{
+        decimal::Decimal,
+        errors::Error,
+        sha256::{self, Digest, Sha256},
+    },
+};
+
+/*
+ * Audit log written by `--audit`, a CSV file appended with a row per event of every account changed by a
+ * transaction, and a row per rejected transaction:
+ *
+ * | Column                         | Content                                                                    |
+ * |--------------------------------|----------------------------------------------------------------------------|
+ * | `seq`                          | sequence number of the input record, or rank of the transaction received   |
+ * |                                | by a server                                                                |
+ * | `line`                         | line of the record in its input, empty for a server                        |
+ * | `client`                       | account: the client of the transaction, or the destination of a transfer   |
+ * | `tx`, `type`                   | transaction                                                                |
+ * | `event`                        | `deposited`, `withdrawn`, `transferred_out`, `transferred_in`,             |
+ * |                                | `transfer_disputed`, `transfer_resolved`, `transfer_reversed`,             |
+ * |                                | `dispute_implied`, `funds_held`, `resolved`, `charged_back`, `adjusted`,   |
+ * |                                | `account_locked`, `account_unlocked`, `pruned`, or `rejected`              |
+ * | `amount`                       | funds moved by the event, or amount of the rejected transaction            |
+ * | `reason`                       | reason code of a rejected transaction, as listed by `--rejects`            |
+ * | `detail`                       | human-readable explanation of the event or of the rejection                |
+ * | `available`, `held`, `total`,  | account after the event, unchanged by a rejection                          |
+ * | `locked`                       |                                                                            |
+ * | `prev_sha256`                  | `sha256` of the previous row of the account, 64 zeros for its first row    |
+ * | `sha256`                       | SHA-256 of the fields of the row before it, `prev_sha256` included         |
+ *
+ * The rows of every account make a hash chain: a field edited afterwards no longer matches the `sha256` of its
+ * row, and a row edited along with its `sha256`, removed or inserted no longer matches the `prev_sha256` of the
+ * next row of the account. Every field is hashed as its length [u64 little endian] followed by its bytes, so
+ * that no two rows hash the same fields.
+ */
+
+/// Columns of the audit log, the last two chaining the rows of each account
+const COLUMNS: [&str; 15] = [
+    "seq",
+    "line",
+    "client",
+    "tx",
+    "type",
+    "event",
+    "amount",
+    "reason",
+    "detail",
+    "available",
+    "held",
+    "total",
+    "locked",
+    "prev_sha256",
+    "sha256",
+];
+
+/// Position of the `client` column
+const CLIENT: usize = 2;
+
+/// `prev_sha256` of the first row of every account
+const GENESIS: Digest = [0; 32];
+
+/// Audit log, appended with the events of the transactions applied to each account and with the rejected
+/// transactions, chained by account
+pub(crate) struct AuditLog {
+    writer: Writer<File>,
+    /// `sha256` of the last row of every account
+    heads: HashMap<u16, Digest>,
+}
+
+/// Row of the audit log, before it is chained
+struct Row<'a> {
+    seq: u64,
+    line: Option<u64>,
+    client: u16,
+    transaction: &'a ClientTransaction,
+    event: &'static str,
+    amount: Option<Decimal>,
+    reason: &'static str,
+    detail: String,
+    balances: Balances,
+    locked: bool,
+}
+
+impl AuditLog {
+    /// Open the audit log in append mode, writing the header only if the file is new or empty. The chains of an
+    /// existing log are checked first, and go on from its last row of every account: a broken log is refused
+    pub(crate) fn open(path: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+        let heads = match is_empty {
+            true => HashMap::new(),
+            false => verify_chain(path)?.heads,
+        };
+        let mut writer = WriterBuilder::new()
+            .terminator(csv::Terminator::Any(b'\n'))
+            .from_writer(file);
+        if is_empty {
+            writer.write_record(COLUMNS)?;
+        }
+
+        Ok(Self { writer, heads })
+    }
+
+    /// Append the rows of the outcome of input record `seq`, read at `line`: the events of every account changed
+    /// by the transaction, with the account after each of them, or the rejection of the transaction. Malformed
+    /// records, which reach no account, are not logged
+    pub(crate) fn record(
+        &mut self,
+        seq: u64,
+        line: Option<u64>,
+        outcome: &Outcome,
+    ) -> Result<(), Error> {
+        let Outcome::Applied {
+            transaction,
+            before,
+            after,
+            result,
+            counterparty,
+            ..
+        } = outcome
+        else {
+            return Ok(());
+        };
+        let events = match result {
+            Ok(events) => events,
+            Err(rejection) => {
+                return self.append(Row {
+                    seq,
+                    line,
+                    client: transaction.id,
+                    transaction,
+                    event: "rejected",
+                    amount: transaction.amount,
+                    reason: rejection.code(),
+                    detail: rejection.to_string(),
+                    balances: after.balances(),
+                    locked: after.locked(),
+                })
+            }
+        };
+        let legs = std::iter::once((transaction.id, before, events)).chain(
+            counterparty
+                .iter()
+                .map(|other| (other.client, &other.before, &other.events)),
+        );
+        for (client, before, events) in legs {
+            let (mut balances, mut locked) = (before.balances(), before.locked());
+            for event in events {
+                // events are checked before being applied, none of them overflows
+                balances = balances.after(event).unwrap_or(balances);
+                locked = match event {
+                    Event::Locked => true,
+                    Event::Unlocked { .. } => false,
+                    _ => locked,
+                };
+                let (name, amount) = event_entry(event);
+                self.append(Row {
+                    seq,
+                    line,
+                    client,
+                    transaction,
+                    event: name,
+                    amount,
+                    reason: "",
+                    detail: demo::describe(event),
+                    balances,
+                    locked,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the rows appended so far to the file
+    pub(crate) fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Append a row, chained to the previous row of its account
+    fn append(&mut self, row: Row) -> Result<(), Error> {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let prev = self.heads.get(&row.client).unwrap_or(&GENESIS);
+        let mut fields = vec![
+            row.seq.to_string(),
+            optional(row.line.map(|line| line.to_string())),
+            row.client.to_string(),
+            row.transaction.tx.to_string(),
+            String::from(row.transaction.transaction_type),
+            row.event.to_owned(),
+            optional(row.amount.map(|amount| amount.to_string())),
+            row.reason.to_owned(),
+            row.detail,
+            row.balances.available.value().to_string(),
+            row.balances.held.value().to_string(),
+            row.balances.total.value().to_string(),
+            row.locked.to_string(),
+            sha256::to_hex(prev),
+        ];
+        let digest = chain_digest(fields.iter().map(String::as_str));
+        fields.push(sha256::to_hex(&digest));
+        self.writer.write_record(&fields)?;
+        self.heads.insert(row.client, digest);
+        Ok(())
+    }
+}
+
+/// Name of an event in the audit log, with the funds it moves
+fn event_entry(event: &Event) -> (&'static str, Option<Decimal>) {
+    match event {
+        Event::Deposited { amount, .. } => ("deposited", Some(amount.value())),
+        Event::Withdrawn { amount, .. } => ("withdrawn", Some(amount.value())),
+        Event::TransferredOut { amount, .. } => ("transferred_out", Some(amount.value())),
+        Event::TransferredIn { amount, .. } => ("transferred_in", Some(amount.value())),
+        Event::TransferDisputed { .. } => ("transfer_disputed", None),
+        Event::TransferResolved { .. } => ("transfer_resolved", None),
+        Event::TransferReversed { amount, .. } => ("transfer_reversed", Some(amount.value())),
+        Event::DisputeImplied { .. } => ("dispute_implied", None),
+        Event::DisputeOpened { amount, .. } => ("funds_held", Some(amount.value())),
+        Event::DisputeResolved { amount, .. } => ("resolved", Some(amount.value())),
+        Event::ChargedBack { amount, .. } => ("charged_back", Some(amount.value())),
+        Event::Adjusted { amount, .. } => ("adjusted", Some(*amount)),
+        Event::Locked => ("account_locked", None),
+        Event::Unlocked { .. } => ("account_unlocked", None),
+        Event::Pruned { .. } => ("pruned", None),
+    }
+}
+
+/// Accounts of an audit log whose chains are checked, with the number of rows and the last `sha256` of each
+#[derive(Debug, Default)]
+pub(crate) struct VerifiedChains {
+    pub(crate) rows: HashMap<u16, u64>,
+    pub(crate) heads: HashMap<u16, Digest>,
+}
+
+/// Check the chain of every account of an audit log, failing on the first row not matching its `sha256` or not
+/// following the previous row of its account
+pub(crate) fn verify_chain(path: &Path) -> Result<VerifiedChains, Error> {
+    let broken = |line: u64, detail: String| {
+        Error::BrokenAuditChain(path.display().to_string(), line, detail)
+    };
+    let mut reader = ReaderBuilder::new().has_headers(true).from_path(path)?;
+    if reader.headers()? != &StringRecord::from(COLUMNS.to_vec()) {
+        return Err(broken(
+            1,
+            format!("columns are not `{}`", COLUMNS.join(",")),
+        ));
+    }
+
+    let mut chains = VerifiedChains::default();
+    let mut row = StringRecord::new();
+    while reader.read_record(&mut row)? {
+        let line = row.position().map_or(0, |position| position.line());
+        let client = row[CLIENT]
+            .parse::<u16>()
+            .map_err(|_| broken(line, format!("invalid client {}", &row[CLIENT])))?;
+        let prev = sha256::to_hex(chains.heads.get(&client).unwrap_or(&GENESIS));
+        if row[COLUMNS.len() - 2] != prev {
+            return Err(broken(
+                line,
+                format!("row of client {client} does not follow its previous row, whose sha256 is {prev}"),
+            ));
+        }
+        let digest = chain_digest(row.iter().take(COLUMNS.len() - 1));
+        if row[COLUMNS.len() - 1] != sha256::to_hex(&digest) {
+            return Err(broken(
+                line,
+                format!("row of client {client} modified, its fields do not match its sha256"),
+            ));
+        }
+        chains.heads.insert(client, digest);
+        *chains.rows.entry(client).or_default() += 1;
+    }
+
+    Ok(chains)
+}
+
+/// SHA-256 of the fields of a row, each as its length followed by its bytes
+fn chain_digest<'a>(fields: impl Iterator<Item = &'a str>) -> Digest {
+    let mut hasher = Sha256::default();
+    for field in fields {
+        hasher.update(&(field.len() as u64).to_le_bytes());
+        hasher.update(field.as_bytes());
+    }
+    hasher.finalize()
+}
@@ -0,0 +1,54 @@
+use std::{collections::HashMap, path::Path};
+
+use csv::ReaderBuilder;
+use serde::Deserialize;
+
+use crate::{
+    business_logic::domain::{events::Event, Client},
+    shared::{decimal::Decimal, errors::Error},
+};
+
+/// Single row of the correction file
+#[derive(Debug, Deserialize)]
+struct Correction {
+    /// Client ID, UUID
+    client: u16,
+    /// Signed amount added to the client available and total funds
+    amount: Decimal,
+    /// Reason code of the correction, mandatory
+    reason: Option<String>,
+}
+
+/// Apply every correction of the file to the accounts, as adjustments. Clients missing from the accounts are created.
+/// Corrections are validated up front: if any row is malformed or lacks a reason code, nothing is applied.
+/// A correction overflowing a balance fails the whole backfill.
+pub(crate) fn apply_corrections(
+    clients: &mut HashMap<u16, Client>,
+    corrections_file: &Path,
+) -> Result<(), Error> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_path(corrections_file)?;
+
+    let mut corrections = Vec::new();
+    for (row, correction) in reader.deserialize::<Correction>().enumerate() {
+        let correction = correction?;
+        let reason = correction
+            .reason
+            .filter(|reason| !reason.is_empty())
+            // first data row is line 2, after headers
+            .ok_or(Error::MissingReasonCode(row + 2))?;
+        corrections.push((row + 2, correction.client, correction.amount, reason));
+    }
+
+    for (line, client, amount, reason) in corrections {
+        let client = clients.entry(client).or_insert_with(|| Client::new(client));
+        client
+            .apply_event(&Event::Adjusted { amount, reason })
+            .map_err(|rejection| Error::RejectedCorrection(line, rejection.to_string()))?;
+        client.version += 1;
+    }
+
+    Ok(())
+}
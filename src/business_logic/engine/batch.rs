@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+
+use csv::ReaderBuilder;
+use serde::Deserialize;
+
+use crate::{
+    business_logic::engine::{read_transactions, ProcessingOptions},
+    shared::{
+        errors::Error,
+        sha256::{self, Digest},
+    },
+};
+
+/// Single row of a batch manifest
+#[derive(Debug, Deserialize)]
+struct ManifestEntry {
+    /// Member input file, relative to the directory of the manifest
+    file: PathBuf,
+    /// Expected number of records, header excluded
+    rows: u64,
+    /// Expected SHA-256 of the file, hex encoded
+    sha256: String,
+}
+
+/// Input file of a batch, read in memory once verified, so that the processed content is the verified one
+pub(crate) struct BatchMember {
+    pub(crate) content: Vec<u8>,
+    pub(crate) digest: Digest,
+}
+
+/// Read every member of the batch listed in `manifest_file` (a `file,rows,sha256` CSV file), checking that
+/// it exists and matches the expected number of records and checksum. Every member is checked, and all the
+/// problems found are reported together: the batch is accepted only if no member is missing, truncated or altered.
+pub(crate) fn load_batch(
+    manifest_file: &Path,
+    options: &ProcessingOptions,
+) -> Result<Vec<BatchMember>, Error> {
+    let base_dir = manifest_file.parent().unwrap_or(Path::new(""));
+    let entries = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_path(manifest_file)?
+        .deserialize::<ManifestEntry>()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut members = Vec::new();
+    let mut problems = Vec::new();
+    for entry in entries {
+        let path = base_dir.join(&entry.file);
+        let content = match std::fs::read(&path) {
+            Ok(content) => content,
+            Err(error) => {
+                problems.push(format!("{} cannot be read ({error})", entry.file.display()));
+                continue;
+            }
+        };
+
+        let mut hasher = sha256::Sha256::default();
+        hasher.update(&content);
+        let digest = hasher.finalize();
+        if !sha256::to_hex(&digest).eq_ignore_ascii_case(&entry.sha256) {
+            problems.push(format!(
+                "{} has SHA-256 {}, expected {}",
+                entry.file.display(),
+                sha256::to_hex(&digest),
+                entry.sha256
+            ));
+        }
+        // records are counted the way they are read when processed, malformed ones included
+        let rows = read_transactions(content.as_slice(), options)?.0.count() as u64;
+        if rows != entry.rows {
+            problems.push(format!(
+                "{} has {rows} rows, expected {}",
+                entry.file.display(),
+                entry.rows
+            ));
+        }
+
+        members.push(BatchMember { content, digest });
+    }
+
+    match problems.is_empty() {
+        true => Ok(members),
+        false => Err(Error::InvalidBatch(problems.join("; "))),
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    business_logic::{
+        domain::{events::Event, Client, DisputeEffect},
+        engine::ProcessingOptions,
+        io::{config, dump::json_string, snapshot},
+    },
+    shared::{
+        decimal::Decimal,
+        errors::Error,
+        sha256::{self, Sha256},
+    },
+};
+
+/*
+ * Certificate written by `--certify`, indented JSON:
+ *
+ * | Member             | Content                                                                              |
+ * |--------------------|--------------------------------------------------------------------------------------|
+ * | `simulator`        | version of the simulator                                                             |
+ * | `snapshot_version` | version of the snapshot layout the state digest is computed with                     |
+ * | `config_sha256`    | SHA-256 of the effective configuration, as printed by `--print-config`               |
+ * | `state_sha256`     | SHA-256 of the final accounts, sorted by client ID, encoded as in a snapshot         |
+ * | `clients`          | number of accounts                                                                   |
+ * | `totals`           | sums of the available, held and total funds of every account                         |
+ * | `locked`           | number of locked accounts                                                            |
+ * | `rejections`       | number of ignored input records by reason code, as listed by `--rejects`             |
+ * | `policy`           | policy settings of the effective configuration, keys and TOML values as strings      |
+ * | `checks`           | every invariant check: `name`, `passed`, `failures` count and the first failures     |
+ * | `passed`           | whether every check passed                                                           |
+ * | `sha256`           | SHA-256 of the certificate text preceding its line, so that edits can be detected    |
+ */
+
+/// Most failures listed by a check, the others being only counted
+const LISTED_FAILURES: usize = 20;
+
+/// State of an account before the run, and the funds moved by the transactions applied to it since
+#[derive(Debug)]
+struct Trail {
+    /// Total funds before the run
+    initial_total: Decimal,
+    /// Held funds not explained by pending disputes before the run, such as the held funds of an account
+    /// resumed from a previous output, which has no dispute history
+    unexplained_held: Decimal,
+    /// Deposits minus withdrawals and chargebacks, plus adjustments, `None` once it overflows
+    net_flow: Option<Decimal>,
+}
+
+impl Default for Trail {
+    /// Trail of an account opened during the run
+    fn default() -> Self {
+        Self {
+            initial_total: Decimal::ZERO,
+            unexplained_held: Decimal::ZERO,
+            net_flow: Some(Decimal::ZERO),
+        }
+    }
+}
+
+/// Keeper of the trail of every account during a run, checking the invariants of the final accounts against it
+#[derive(Debug, Default)]
+pub(crate) struct Auditor {
+    trails: HashMap<u16, Trail>,
+}
+
+/// Result of an invariant check
+struct Check {
+    name: &'static str,
+    failures: Vec<String>,
+}
+
+impl Auditor {
+    /// Auditor of a run starting from the given accounts
+    pub(crate) fn new<'a>(clients: impl Iterator<Item = &'a Client>) -> Self {
+        let trails = clients
+            .map(|client| {
+                let trail = Trail {
+                    initial_total: client.total.value(),
+                    // held funds are never that far below zero
+                    unexplained_held: client
+                        .held
+                        .value()
+                        .checked_sub(client.dispute_outcomes.pending_amount())
+                        .unwrap_or_default(),
+                    ..Default::default()
+                };
+                (client.id, trail)
+            })
+            .collect();
+        Self { trails }
+    }
+
+    /// Record the funds moved by the events of a transaction applied to `client`
+    pub(crate) fn record(&mut self, client: u16, events: &[Event]) {
+        let trail = self.trails.entry(client).or_default();
+        for event in events {
+            trail.net_flow = trail.net_flow.and_then(|net_flow| match event {
+                Event::Deposited { amount, .. }
+                | Event::TransferredIn { amount, .. }
+                | Event::TransferReversed { amount, .. }
+                | Event::DisputeOpened {
+                    amount,
+                    effect: DisputeEffect::Credit,
+                    ..
+                } => net_flow.checked_add(amount.value()),
+                Event::Withdrawn { amount, .. }
+                | Event::TransferredOut { amount, .. }
+                | Event::DisputeResolved {
+                    amount,
+                    effect: DisputeEffect::Credit,
+                    ..
+                }
+                | Event::ChargedBack {
+                    amount,
+                    effect: DisputeEffect::Debit,
+                    ..
+                } => net_flow.checked_sub(amount.value()),
+                Event::Adjusted { amount, .. } => net_flow.checked_add(*amount),
+                _ => Some(net_flow),
+            });
+        }
+    }
+
+    /// Check the invariants of the final accounts and write the certificate in `path`. Fails if any check
+    /// failed, once the certificate listing the failures is written
+    pub(crate) fn certify(
+        &self,
+        clients: &HashMap<u16, Client>,
+        rejections: &BTreeMap<&'static str, u64>,
+        options: &ProcessingOptions,
+        path: &Path,
+    ) -> Result<(), Error> {
+        let mut sorted = clients.values().collect::<Vec<_>>();
+        sorted.sort_by_key(|client| client.id);
+        let checks = [
+            self.check_accounts("account_identity", &sorted, account_identity),
+            self.check_accounts("account_reconciliation", &sorted, account_reconciliation),
+            self.check_accounts("dispute_consistency", &sorted, dispute_consistency),
+            self.global_reconciliation(&sorted),
+        ];
+
+        let sum = |balance: fn(&Client) -> Decimal| {
+            checked_sum(sorted.iter().map(|client| Some(balance(client))))
+                .map_or_else(|| json_string("overflow"), |sum| sum.to_string())
+        };
+        let mut certificate = String::new();
+        // writing on a String never fails
+        let _ = write_body(
+            &mut certificate,
+            &[
+                ("simulator", json_string(env!("CARGO_PKG_VERSION"))),
+                ("snapshot_version", snapshot::VERSION.to_string()),
+                (
+                    "config_sha256",
+                    json_string(&sha256::to_hex(&config::config_digest(options))),
+                ),
+                (
+                    "state_sha256",
+                    json_string(&sha256::to_hex(&snapshot::state_digest(
+                        sorted.iter().copied(),
+                    )?)),
+                ),
+                ("clients", sorted.len().to_string()),
+                (
+                    "totals",
+                    format!(
+                        "{{\"available\": {}, \"held\": {}, \"total\": {}}}",
+                        sum(|client| client.available.value()),
+                        sum(|client| client.held.value()),
+                        sum(|client| client.total.value())
+                    ),
+                ),
+                (
+                    "locked",
+                    sorted
+                        .iter()
+                        .filter(|client| client.locked)
+                        .count()
+                        .to_string(),
+                ),
+                (
+                    "rejections",
+                    object(
+                        rejections
+                            .iter()
+                            .map(|(reason, count)| (*reason, count.to_string())),
+                    ),
+                ),
+                (
+                    "policy",
+                    object(
+                        config::policy_settings(&options.policy)
+                            .iter()
+                            .map(|(key, value)| (key.as_str(), json_string(value))),
+                    ),
+                ),
+            ],
+            &checks,
+        );
+        let mut hasher = Sha256::default();
+        hasher.update(certificate.as_bytes());
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "{certificate}")?;
+        writeln!(
+            writer,
+            "  \"sha256\": {}",
+            json_string(&sha256::to_hex(&hasher.finalize()))
+        )?;
+        writeln!(writer, "}}")?;
+        writer.flush()?;
+
+        match checks
+            .iter()
+            .filter(|check| !check.failures.is_empty())
+            .count()
+        {
+            0 => Ok(()),
+            failed => Err(Error::CertificationFailed(
+                failed,
+                path.display().to_string(),
+            )),
+        }
+    }
+
+    /// Check every account with `check`, given the trail of the account
+    fn check_accounts(
+        &self,
+        name: &'static str,
+        clients: &[&Client],
+        check: fn(&Client, &Trail) -> Option<String>,
+    ) -> Check {
+        // accounts opened during the run by transactions that were all rejected
+        let unchanged = Trail::default();
+        let failures = clients
+            .iter()
+            .filter_map(|client| {
+                let trail = self.trails.get(&client.id).unwrap_or(&unchanged);
+                check(client, trail).map(|failure| format!("client {}: {failure}", client.id))
+            })
+            .collect();
+        Check { name, failures }
+    }
+
+    /// Total funds of all the accounts equal to their total before the run plus the funds moved during the run
+    fn global_reconciliation(&self, clients: &[&Client]) -> Check {
+        let total = checked_sum(clients.iter().map(|client| Some(client.total.value())));
+        let initial = checked_sum(self.trails.values().map(|trail| Some(trail.initial_total)));
+        let net_flow = checked_sum(self.trails.values().map(|trail| trail.net_flow));
+        let expected = initial
+            .zip(net_flow)
+            .and_then(|(initial, net_flow)| initial.checked_add(net_flow));
+
+        let failures = match (total, initial, net_flow, expected) {
+            (Some(total), _, _, Some(expected)) if total == expected => Vec::new(),
+            (Some(total), Some(initial), Some(net_flow), Some(expected)) => vec![format!(
+                "total funds {total}, expected {expected} from {initial} before the run and {net_flow} moved by transactions"
+            )],
+            _ => vec!["sum of the funds out of range".to_owned()],
+        };
+        Check {
+            name: "global_reconciliation",
+            failures,
+        }
+    }
+}
+
+/// JSON object on a single line, of already rendered member values
+fn object<'a>(members: impl Iterator<Item = (&'a str, String)>) -> String {
+    let members = members
+        .map(|(name, value)| format!("{}: {value}", json_string(name)))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", members.join(", "))
+}
+
+/// Sum of the values, `None` if any of them is missing or the sum overflows
+fn checked_sum(mut values: impl Iterator<Item = Option<Decimal>>) -> Option<Decimal> {
+    values.try_fold(Decimal::ZERO, |sum, value| sum.checked_add(value?))
+}
+
+/// Available plus held funds equal to total funds
+fn account_identity(client: &Client, _: &Trail) -> Option<String> {
+    match client.available.value().checked_add(client.held.value()) {
+        Some(sum) if sum == client.total.value() => None,
+        Some(sum) => Some(format!(
+            "available {} + held {} = {sum}, but total is {}",
+            client.available, client.held, client.total
+        )),
+        None => Some("available + held out of range".to_owned()),
+    }
+}
+
+/// Total funds equal to the total before the run plus the funds moved by the transactions applied since
+fn account_reconciliation(client: &Client, trail: &Trail) -> Option<String> {
+    let expected = trail
+        .net_flow
+        .and_then(|net_flow| trail.initial_total.checked_add(net_flow));
+    match expected {
+        Some(expected) if expected == client.total.value() => None,
+        Some(expected) => Some(format!(
+            "total {}, expected {expected} from {} before the run and {} moved by transactions",
+            client.total,
+            trail.initial_total,
+            trail.net_flow.unwrap_or_default()
+        )),
+        None => Some("funds moved by transactions out of range".to_owned()),
+    }
+}
+
+/// No more disputes ended than opened, and held funds equal to the amount of the pending disputes
+/// (plus the held funds of the account before the run not explained by its disputes)
+fn dispute_consistency(client: &Client, trail: &Trail) -> Option<String> {
+    let outcomes = &client.dispute_outcomes;
+    let ended = outcomes.resolved.checked_add(outcomes.charged_back);
+    let ended_amount = outcomes
+        .resolved_amount
+        .checked_add(outcomes.charged_back_amount);
+    if ended.is_none_or(|ended| ended > outcomes.opened)
+        || ended_amount.is_none_or(|ended| ended > outcomes.opened_amount)
+    {
+        return Some(format!(
+            "{} dispute(s) resolved and {} charged back, but only {} opened",
+            outcomes.resolved, outcomes.charged_back, outcomes.opened
+        ));
+    }
+
+    match outcomes
+        .pending_amount()
+        .checked_add(trail.unexplained_held)
+    {
+        Some(expected) if expected == client.held.value() => None,
+        Some(_) => Some(format!(
+            "held {}, but pending disputes amount to {}",
+            client.held,
+            outcomes.pending_amount()
+        )),
+        None => Some("pending disputes amount out of range".to_owned()),
+    }
+}
+
+/// Write the certificate up to its `sha256` member, excluded
+fn write_body(
+    certificate: &mut String,
+    members: &[(&str, String)],
+    checks: &[Check],
+) -> std::fmt::Result {
+    use std::fmt::Write;
+
+    writeln!(certificate, "{{")?;
+    for (name, value) in members {
+        writeln!(certificate, "  {}: {value},", json_string(name))?;
+    }
+    writeln!(certificate, "  \"checks\": [")?;
+    for (index, check) in checks.iter().enumerate() {
+        let failures = check
+            .failures
+            .iter()
+            .take(LISTED_FAILURES)
+            .map(|failure| json_string(failure))
+            .collect::<Vec<_>>();
+        let separator = if index + 1 < checks.len() { "," } else { "" };
+        writeln!(
+            certificate,
+            "    {{\"name\": {}, \"passed\": {}, \"failures\": {}, \"first_failures\": [{}]}}{separator}",
+            json_string(check.name),
+            check.failures.is_empty(),
+            check.failures.len(),
+            failures.join(", ")
+        )?;
+    }
+    writeln!(certificate, "  ],")?;
+    writeln!(
+        certificate,
+        "  \"passed\": {},",
+        checks.iter().all(|check| check.failures.is_empty())
+    )
+}
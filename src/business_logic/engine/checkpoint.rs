@@ -0,0 +1,40 @@
+use std::{collections::HashMap, num::NonZeroU64, path::PathBuf};
+
+use crate::{
+    business_logic::{domain::Client, io::snapshot},
+    shared::{errors::Error, retry::RetryPolicy, sha256::Digest},
+};
+
+/// Periodic save of the state of the accounts while the input is processed, so that an interrupted run can go on
+/// from the last checkpoint instead of starting over
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    /// Snapshot file rewritten at every checkpoint, recording how many input records were applied
+    pub file: PathBuf,
+    /// Number of input records between two checkpoints
+    pub interval: NonZeroU64,
+}
+
+impl Checkpoint {
+    /// Whether a checkpoint is due after input record `seq`
+    pub(crate) fn is_due(&self, seq: u64) -> bool {
+        seq.is_multiple_of(self.interval.get())
+    }
+
+    /// Save the state of the accounts after input record `seq`, if a checkpoint is due. The ledger lists the inputs
+    /// applied before the run
+    pub(crate) fn save_if_due(
+        &self,
+        clients: &HashMap<u16, Client>,
+        ledger: &[Digest],
+        seq: u64,
+        retry: &RetryPolicy,
+    ) -> Result<(), Error> {
+        if !self.is_due(seq) {
+            return Ok(());
+        }
+        retry.run("write checkpoint", || {
+            snapshot::write_checkpoint(clients.values(), ledger, seq, &self.file)
+        })
+    }
+}
@@ -0,0 +1,259 @@
+use std::{collections::HashMap, io::Write, str::FromStr};
+
+use csv::ReaderBuilder;
+
+use crate::{
+    business_logic::{
+        domain::{events::Event, Client, ClientTransaction, DisputeDetails, DisputeEffect},
+        io::output::{self, OutputFormat},
+        policy::EnginePolicy,
+    },
+    shared::errors::Error,
+};
+
+/// Example dataset bundled in the binary, run by the `demo` subcommand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DemoDataset {
+    /// Deposits and withdrawals only
+    Basic,
+    /// Disputes ending in every possible way
+    DisputeHeavy,
+    /// Malformed, duplicate and out of place transactions
+    Adversarial,
+}
+
+impl DemoDataset {
+    /// Every dataset, in the order they are run when none is chosen
+    pub const ALL: [Self; 3] = [Self::Basic, Self::DisputeHeavy, Self::Adversarial];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Basic => "basic",
+            Self::DisputeHeavy => "dispute-heavy",
+            Self::Adversarial => "adversarial",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::Basic => "deposits and withdrawals, including one exceeding the available funds",
+            Self::DisputeHeavy => {
+                "disputes that are resolved, charged back, or left pending on spent funds"
+            }
+            Self::Adversarial => {
+                "duplicate IDs, invalid amounts, unknown references and malformed records"
+            }
+        }
+    }
+
+    fn content(self) -> &'static str {
+        match self {
+            Self::Basic => include_str!("../../../datasets/basic.csv"),
+            Self::DisputeHeavy => include_str!("../../../datasets/dispute_heavy.csv"),
+            Self::Adversarial => include_str!("../../../datasets/adversarial.csv"),
+        }
+    }
+}
+
+impl FromStr for DemoDataset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|dataset| dataset.name() == s)
+            .ok_or_else(|| Error::InvalidDemoDataset(s.to_owned()))
+    }
+}
+
+/// Run a dataset through the engine, writing every record followed by the state changes it caused
+/// (or why it was ignored) and the resulting balances of its client, then the final accounts
+pub(crate) fn write_demo<W>(
+    dataset: DemoDataset,
+    policy: &EnginePolicy,
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    writeln!(
+        writer,
+        "== {}: {} ==",
+        dataset.name(),
+        dataset.description()
+    )?;
+
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(dataset.content().as_bytes());
+    let headers = reader.headers()?.clone();
+    let mut clients = HashMap::<u16, Client>::new();
+    for (sequence, record) in (1..).zip(reader.records()) {
+        let record = record?;
+        writeln!(
+            writer,
+            "#{sequence} {}",
+            record.iter().collect::<Vec<_>>().join(",")
+        )?;
+        let transaction = match record.deserialize::<ClientTransaction>(Some(&headers)) {
+            Ok(transaction) => transaction,
+            Err(error) => {
+                writeln!(writer, "   ignored: malformed record ({error})")?;
+                continue;
+            }
+        };
+
+        let client = clients
+            .entry(transaction.id)
+            .or_insert_with(|| Client::new(transaction.id));
+        match client.apply_transaction(&transaction, policy) {
+            Ok(events) => {
+                for event in &events {
+                    writeln!(writer, "   {}", describe(event))?;
+                }
+            }
+            Err(rejection) => writeln!(writer, "   ignored: {rejection}")?,
+        }
+        writeln!(
+            writer,
+            "   client {}: available {}, held {}, total {}, locked {}",
+            client.id, client.available, client.held, client.total, client.locked
+        )?;
+    }
+
+    let mut clients = clients.values().collect::<Vec<_>>();
+    clients.sort_by_key(|client| client.id);
+    writeln!(writer, "Final accounts:")?;
+    output::write_clients(clients.into_iter(), OutputFormat::V1, &mut writer)
+}
+
+/// Human-readable explanation of the state change made by an event
+pub(crate) fn describe(event: &Event) -> String {
+    match event {
+        Event::Deposited { amount, .. } => {
+            format!("deposited: {amount} credited to available and total funds")
+        }
+        Event::Withdrawn { amount, .. } => {
+            format!("withdrawn: {amount} debited from available and total funds")
+        }
+        Event::TransferredOut { amount, to, .. } => {
+            format!("transferred to client {to}: {amount} debited from available and total funds")
+        }
+        Event::TransferredIn { amount, from, .. } => {
+            format!(
+                "transferred from client {from}: {amount} credited to available and total funds"
+            )
+        }
+        Event::TransferDisputed { tx, details } => {
+            format!(
+                "dispute opened on transfer {tx}{}: the funds are held by its destination",
+                annotation(details)
+            )
+        }
+        Event::TransferResolved { tx } => {
+            format!("dispute on transfer {tx} resolved: the funds are released by its destination")
+        }
+        Event::TransferReversed { tx, amount } => {
+            format!(
+                "transfer {tx} charged back: {amount} credited back to available and total funds"
+            )
+        }
+        Event::DisputeImplied { tx } => {
+            format!(
+                "chargeback of tx {tx} without dispute: the dispute is opened by the chargeback"
+            )
+        }
+        Event::DisputeOpened {
+            tx,
+            amount,
+            details,
+            effect: DisputeEffect::Debit,
+        } => {
+            format!(
+                "dispute opened on tx {tx}{}: {amount} moved from available to held funds",
+                annotation(details)
+            )
+        }
+        Event::DisputeOpened {
+            tx,
+            amount,
+            details,
+            effect: DisputeEffect::Credit,
+        } => {
+            format!(
+                "dispute opened on withdrawal {tx}{}: {amount} credited back to held and total funds",
+                annotation(details)
+            )
+        }
+        Event::DisputeResolved {
+            tx,
+            amount,
+            effect: DisputeEffect::Debit,
+        } => {
+            format!("dispute on tx {tx} resolved: {amount} released from held to available funds")
+        }
+        Event::DisputeResolved {
+            tx,
+            amount,
+            effect: DisputeEffect::Credit,
+        } => {
+            format!(
+                "dispute on withdrawal {tx} resolved: {amount} removed from held and total funds"
+            )
+        }
+        Event::ChargedBack {
+            tx,
+            amount,
+            details,
+            effect: DisputeEffect::Debit,
+        } => {
+            format!(
+                "tx {tx} charged back{}: {amount} removed from held and total funds",
+                annotation(details)
+            )
+        }
+        Event::ChargedBack {
+            tx,
+            amount,
+            details,
+            effect: DisputeEffect::Credit,
+        } => {
+            format!(
+                "withdrawal {tx} charged back{}: {amount} released from held to available funds",
+                annotation(details)
+            )
+        }
+        Event::Adjusted { amount, reason } => {
+            format!("adjusted ({reason}): {amount} added to available and total funds")
+        }
+        Event::Locked => {
+            "account locked: every following transaction of the client is ignored".to_owned()
+        }
+        Event::Unlocked {
+            tx,
+            reason,
+            audited,
+        } => {
+            let reason = reason
+                .as_ref()
+                .map_or_else(String::new, |reason| format!(" ({reason})"));
+            let audit = match audited {
+                true => ", recorded as an operator note",
+                false => "",
+            };
+            format!("account unlocked by tx {tx}{reason}: transactions of the client are applied again{audit}")
+        }
+        Event::Pruned { tx } => {
+            format!("tx {tx} pruned from history: it can no longer be disputed")
+        }
+    }
+}
+
+/// Dispute details in parentheses, if any
+fn annotation(details: &DisputeDetails) -> String {
+    match details.is_empty() {
+        true => String::new(),
+        false => format!(" ({details})"),
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    business_logic::{
+        domain::Client,
+        engine::{
+            malformation,
+            server::{self, Reply, RequestReader, SharedClients},
+            transaction_engine, Account, ProcessingOptions,
+        },
+        io::{dump::json_string, query},
+    },
+    shared::errors::Error,
+};
+
+/*
+ * HTTP API of `--http`, a minimal HTTP/1.1 server answering one request per connection, with JSON bodies:
+ *
+ * | Request                               | Response                                                                 |
+ * |---------------------------------------|--------------------------------------------------------------------------|
+ * | `POST /transactions`                  | `200`, what became of every transaction of the body (CSV rows or JSON    |
+ * |                                       | Lines), in order: `{"result":"applied"}`,                                |
+ * |                                       | `{"result":"rejected","reason":"<reject code>"}`,                        |
+ * |                                       | `{"result":"malformed","detail":"…"}`, if not appended to the `--audit`  |
+ * |                                       | log `{"result":"unaudited","detail":"…"}` or, if the account is not at   |
+ * |                                       | the `expected_version` of the transaction,                               |
+ * |                                       | `{"result":"conflict","version":<current version>}`, answered with `409` |
+ * | `POST /transactions:validate`         | `200`, what would become of every transaction of the body if it were     |
+ * |                                       | applied now, each checked against the current accounts, which are left   |
+ * |                                       | untouched: `{"result":"applied","account":{<balances after it>}}`,       |
+ * |                                       | `{"result":"rejected","reason":"<reject code>"}`,                        |
+ * |                                       | `{"result":"malformed","detail":"…"}` or                                 |
+ * |                                       | `{"result":"conflict","version":<current version>}`                      |
+ * | `GET /accounts`                       | `200`, every account sorted by client ID, as printed by `query --format  |
+ * |                                       | json`                                                                    |
+ * | `GET /accounts/<client>`              | `200` with the account of the client, `404` if unknown                   |
+ * | `POST /accounts/<client>/annotations` | `200` with the account of the client, after attaching the body to it as  |
+ * |                                       | an operator note; `404` if the client is unknown, `400` if the body is   |
+ * |                                       | empty                                                                    |
+ *
+ * The body of `POST /transactions` and `POST /transactions:validate` is read like a `serve` connection: one transaction per line, CSV rows having the
+ * `type,client,tx,amount` columns unless a header is given, which may add an `expected_version` column. Errors are
+ * answered with `{"error":"…"}`.
+ */
+
+/// Longest request line or header line accepted
+const MAX_LINE: usize = 8 * 1024;
+/// Most headers accepted in a request
+const MAX_HEADERS: usize = 100;
+/// Largest request body accepted
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+/// Response to a request
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn ok(body: String) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            body: format!("{{\"error\":{}}}", json_string(message)),
+        }
+    }
+}
+
+/// Serve the HTTP API on `listener` forever, applying the transactions received to `clients`
+pub(crate) fn serve(
+    listener: TcpListener,
+    clients: HashMap<u16, Client>,
+    options: &ProcessingOptions,
+) -> Result<(), Error> {
+    let clients = SharedClients::new(clients, options)?;
+    server::accept(listener, |stream| {
+        serve_connection(stream, &clients, options)
+    })
+}
+
+/// Answer the request of a connection, then close it
+fn serve_connection(
+    stream: TcpStream,
+    clients: &SharedClients,
+    options: &ProcessingOptions,
+) -> Result<(), Error> {
+    let mut writer = stream.try_clone()?;
+    let response = match read_request(&mut BufReader::new(stream))? {
+        Ok((method, path, body)) => route(&method, &path, &body, clients, options),
+        Err(response) => response,
+    };
+
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        _ => "Error",
+    };
+    write!(
+        writer,
+        "HTTP/1.1 {} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.body.len(),
+        response.body
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Method, path (without query string) and body of a request, or the error response to an invalid request
+fn read_request<R: BufRead>(
+    reader: &mut R,
+) -> Result<Result<(String, String, String), Response>, Error> {
+    let bad_request = |message: &str| Ok(Err(Response::error(400, message)));
+
+    let Some(request_line) = read_line(reader)? else {
+        return bad_request("invalid request line");
+    };
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return bad_request("invalid request line");
+    };
+    if !version.starts_with("HTTP/1.") {
+        return bad_request("unsupported HTTP version");
+    }
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+
+    let mut length = None;
+    for _ in 0..=MAX_HEADERS {
+        let Some(header) = read_line(reader)? else {
+            return bad_request("invalid header");
+        };
+        if header.is_empty() {
+            let body = match length {
+                None => String::new(),
+                Some(length) if length > MAX_BODY => {
+                    return Ok(Err(Response::error(413, "request body too large")))
+                }
+                Some(length) => {
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body)?;
+                    match String::from_utf8(body) {
+                        Ok(body) => body,
+                        Err(_) => return bad_request("request body is not valid UTF-8"),
+                    }
+                }
+            };
+            return Ok(Ok((method.to_owned(), path.to_owned(), body)));
+        }
+
+        let Some((name, value)) = header.split_once(':') else {
+            return bad_request("invalid header");
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            match value.parse() {
+                Ok(value) => length = Some(value),
+                Err(_) => return bad_request("invalid Content-Length"),
+            }
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Ok(Err(Response::error(
+                411,
+                "chunked bodies are not supported",
+            )));
+        }
+    }
+
+    bad_request("too many headers")
+}
+
+/// Line of the request head without its line ending, `None` if too long, not UTF-8 or missing
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<String>, Error> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if line.len() > MAX_LINE || !line.ends_with(b"\n") {
+        return Ok(None);
+    }
+    Ok(String::from_utf8(line)
+        .ok()
+        .map(|line| line.trim_end_matches(['\r', '\n']).to_owned()))
+}
+
+/// Response to a request, according to its method and path
+fn route(
+    method: &str,
+    path: &str,
+    body: &str,
+    clients: &SharedClients,
+    options: &ProcessingOptions,
+) -> Response {
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    match (method, segments.as_slice()) {
+        ("POST", ["transactions"]) => post_transactions(body, clients, options),
+        ("POST", ["transactions:validate"]) => validate_transactions(body, clients, options),
+        ("GET", ["accounts"]) => {
+            let clients = clients.lock();
+            let mut accounts = clients.values().collect::<Vec<_>>();
+            accounts.sort_by_key(|client| client.id);
+            let accounts = accounts
+                .into_iter()
+                .map(query::account_json)
+                .collect::<Vec<_>>();
+            Response::ok(format!("[{}]", accounts.join(",")))
+        }
+        ("GET", ["accounts", client]) => {
+            let account = client
+                .parse::<u16>()
+                .ok()
+                .and_then(|client| clients.lock().get(&client).map(query::account_json));
+            match account {
+                Some(account) => Response::ok(account),
+                None => Response::error(404, &format!("unknown client {client}")),
+            }
+        }
+        ("POST", ["accounts", client, "annotations"]) => {
+            let mut accounts = clients.lock();
+            match client
+                .parse::<u16>()
+                .ok()
+                .and_then(|client| accounts.get_mut(&client))
+            {
+                Some(account) => match account.annotate(body) {
+                    Ok(()) => Response::ok(query::account_json(account)),
+                    Err(error) => Response::error(400, &error.to_string()),
+                },
+                None => Response::error(404, &format!("unknown client {client}")),
+            }
+        }
+        (
+            _,
+            ["transactions"]
+            | ["transactions:validate"]
+            | ["accounts"]
+            | ["accounts", _]
+            | ["accounts", _, "annotations"],
+        ) => Response::error(405, &format!("method {method} not allowed on {path}")),
+        _ => Response::error(404, &format!("no resource {path}")),
+    }
+}
+
+/// Apply the transactions of the body, one per line, in order. A transaction whose account is not at the version it
+/// expects is not applied, and makes the response a conflict, the other transactions being applied anyway
+fn post_transactions(body: &str, clients: &SharedClients, options: &ProcessingOptions) -> Response {
+    let mut requests = RequestReader::default();
+    let mut conflict = false;
+    let results = body
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| requests.read(line, &options.amount_units))
+        .map(|request| match clients.apply(request, options) {
+            Reply::Applied => "{\"result\":\"applied\"}".to_owned(),
+            Reply::Rejected(rejection) => format!(
+                "{{\"result\":\"rejected\",\"reason\":{}}}",
+                json_string(rejection.code())
+            ),
+            Reply::Malformed(detail) => format!(
+                "{{\"result\":\"malformed\",\"detail\":{}}}",
+                json_string(&detail)
+            ),
+            Reply::Unaudited(detail) => format!(
+                "{{\"result\":\"unaudited\",\"detail\":{}}}",
+                json_string(&detail)
+            ),
+            Reply::Conflict(version) => {
+                conflict = true;
+                conflict_json(version)
+            }
+        })
+        .collect::<Vec<_>>();
+    Response {
+        status: if conflict { 409 } else { 200 },
+        body: format!("[{}]", results.join(",")),
+    }
+}
+
+/// Outcome of every transaction of the body, one per line, if it were applied now, without applying any
+fn validate_transactions(
+    body: &str,
+    clients: &SharedClients,
+    options: &ProcessingOptions,
+) -> Response {
+    let mut requests = RequestReader::default();
+    let results = body
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| requests.read(line, &options.amount_units))
+        .map(|request| match request {
+            Ok(request) => {
+                let clients = clients.lock();
+                if let Some(version) = request.conflict(&clients) {
+                    return conflict_json(version);
+                }
+                let mut transaction = request.transaction;
+                options.enrichment.merge_into(&mut transaction);
+                match transaction_engine::validate(&*clients, &transaction, &options.policy) {
+                    Ok(account) => format!(
+                        "{{\"result\":\"applied\",\"account\":{}}}",
+                        balances_json(&account)
+                    ),
+                    Err(rejection) => format!(
+                        "{{\"result\":\"rejected\",\"reason\":{}}}",
+                        json_string(rejection.code())
+                    ),
+                }
+            }
+            Err(error) => format!(
+                "{{\"result\":\"malformed\",\"detail\":{}}}",
+                json_string(&malformation(error))
+            ),
+        })
+        .collect::<Vec<_>>();
+    Response::ok(format!("[{}]", results.join(",")))
+}
+
+/// Result of a transaction whose account is at another version than the expected one
+fn conflict_json(version: u64) -> String {
+    format!("{{\"result\":\"conflict\",\"version\":{version}}}")
+}
+
+/// Balances of an account as a JSON object
+fn balances_json(account: &Account) -> String {
+    format!(
+        "{{\"client\":{},\"available\":{},\"held\":{},\"total\":{},\"locked\":{}}}",
+        account.client, account.available, account.held, account.total, account.locked
+    )
+}
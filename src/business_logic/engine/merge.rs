@@ -0,0 +1,187 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use crate::{
+    business_logic::domain::{Client, DisputeOutcomes},
+    shared::errors::Error,
+};
+
+/// Treatment of a client found more than once in the account sets combined into one: the shards of the worker
+/// threads, or the rows of an accounts file seeding the run (e.g. the outputs of several tenants concatenated),
+/// parsed from `error`, `merge` or `prefer-latest`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DuplicateClients {
+    /// The combination fails
+    #[default]
+    Error,
+    /// The entries are combined into one account: balances, dispute outcomes and versions are summed, histories
+    /// and notes joined, and the account is locked if any entry is. Fails if both entries know a transaction ID
+    Merge,
+    /// The latest entry replaces the previous ones
+    PreferLatest,
+}
+
+impl FromStr for DuplicateClients {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "error" => Ok(Self::Error),
+            "merge" => Ok(Self::Merge),
+            "prefer-latest" => Ok(Self::PreferLatest),
+            _ => Err(Error::InvalidDuplicateClients(s.to_owned())),
+        }
+    }
+}
+
+/// Clients found more than once while combining account sets, by resolution
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct MergeReport {
+    pub(crate) merged: Vec<u16>,
+    pub(crate) replaced: Vec<u16>,
+}
+
+impl MergeReport {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.merged.is_empty() && self.replaced.is_empty()
+    }
+}
+
+impl Display for MergeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |clients: &[u16]| {
+            let mut clients = clients.to_vec();
+            clients.sort();
+            clients.dedup();
+            let ids = clients.iter().map(u16::to_string).collect::<Vec<_>>();
+            format!("{} client(s) ({})", clients.len(), ids.join(", "))
+        };
+        let mut parts = Vec::new();
+        if !self.merged.is_empty() {
+            parts.push(format!("{} merged", list(&self.merged)));
+        }
+        if !self.replaced.is_empty() {
+            parts.push(format!(
+                "{} replaced by their latest entry",
+                list(&self.replaced)
+            ));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Combines account sets into one, resolving the clients found more than once as told by [DuplicateClients]
+/// instead of letting the last entry silently win
+pub(crate) struct AccountMerger {
+    clients: HashMap<u16, Client>,
+    duplicates: DuplicateClients,
+    /// Where the entry kept for each client comes from, for error messages
+    sources: HashMap<u16, String>,
+    report: MergeReport,
+}
+
+impl AccountMerger {
+    pub(crate) fn new(duplicates: DuplicateClients) -> Self {
+        Self {
+            clients: HashMap::new(),
+            duplicates,
+            sources: HashMap::new(),
+            report: MergeReport::default(),
+        }
+    }
+
+    /// Add the entry of a client coming from `source` (e.g. `shard 2`, `row 7`)
+    pub(crate) fn add(&mut self, client: Client, source: impl Into<String>) -> Result<(), Error> {
+        let source = source.into();
+        let Some(previous) = self.clients.get_mut(&client.id) else {
+            self.sources.insert(client.id, source);
+            self.clients.insert(client.id, client);
+            return Ok(());
+        };
+        let id = client.id;
+        match self.duplicates {
+            DuplicateClients::Error => Err(Error::DuplicateClient(
+                id,
+                format!("{} and {source}", self.sources[&id]),
+            )),
+            DuplicateClients::Merge => {
+                merge_entries(previous, client).map_err(|detail| {
+                    Error::DuplicateClient(
+                        id,
+                        format!("{} and {source}, not merged: {detail}", self.sources[&id]),
+                    )
+                })?;
+                self.report.merged.push(id);
+                Ok(())
+            }
+            DuplicateClients::PreferLatest => {
+                *previous = client;
+                self.sources.insert(id, source);
+                self.report.replaced.push(id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Add every account of a set coming from `source`
+    pub(crate) fn add_all(
+        &mut self,
+        clients: HashMap<u16, Client>,
+        source: &str,
+    ) -> Result<(), Error> {
+        clients
+            .into_values()
+            .try_for_each(|client| self.add(client, source))
+    }
+
+    /// Combined accounts, with the clients found more than once
+    pub(crate) fn finish(self) -> (HashMap<u16, Client>, MergeReport) {
+        (self.clients, self.report)
+    }
+}
+
+/// Combine the entry of a client into a previous one of the same client, or tell why they cannot be
+fn merge_entries(into: &mut Client, other: Client) -> Result<(), String> {
+    if let Some(tx) = other
+        .transations_history
+        .keys()
+        .chain(&other.pruned_transactions)
+        .find(|tx| into.check_new(**tx).is_err())
+    {
+        return Err(format!("transaction {tx} found in both entries"));
+    }
+    let overflow = || "balance overflow".to_owned();
+    into.available = into
+        .available
+        .checked_adjust(other.available.value())
+        .ok_or_else(overflow)?;
+    into.held = into
+        .held
+        .checked_adjust(other.held.value())
+        .ok_or_else(overflow)?;
+    into.total = into
+        .total
+        .checked_adjust(other.total.value())
+        .ok_or_else(overflow)?;
+    into.dispute_outcomes =
+        merge_outcomes(into.dispute_outcomes, other.dispute_outcomes).ok_or_else(overflow)?;
+    into.locked |= other.locked;
+    into.erased &= other.erased;
+    into.transations_history.extend(other.transations_history);
+    into.history_order.extend(other.history_order);
+    into.pruned_transactions.extend(other.pruned_transactions);
+    into.risk_score = into.risk_score.max(other.risk_score);
+    into.version += other.version;
+    into.annotations.extend(other.annotations);
+    Ok(())
+}
+
+fn merge_outcomes(a: DisputeOutcomes, b: DisputeOutcomes) -> Option<DisputeOutcomes> {
+    Some(DisputeOutcomes {
+        opened: a.opened.checked_add(b.opened)?,
+        opened_amount: a.opened_amount.checked_add(b.opened_amount)?,
+        resolved: a.resolved.checked_add(b.resolved)?,
+        resolved_amount: a.resolved_amount.checked_add(b.resolved_amount)?,
+        charged_back: a.charged_back.checked_add(b.charged_back)?,
+        charged_back_amount: a.charged_back_amount.checked_add(b.charged_back_amount)?,
+    })
+}
@@ -0,0 +1,1353 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    io::{Read, Write},
+    net::TcpListener,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Mutex, PoisonError},
+};
+
+use csv::{Position, ReaderBuilder, StringRecord};
+
+use crate::{
+    business_logic::{
+        domain::{events::Event, Client, ClientTransaction, Type},
+        engine::{
+            aggregate::DepositRun,
+            alerts::{AlertThresholds, AlertWriter},
+            anomaly::{AnomalyDetector, AnomalyRate},
+            audit::AuditLog,
+            certify::Auditor,
+            checkpoint::Checkpoint,
+            demo::DemoDataset,
+            merge::{AccountMerger, DuplicateClients},
+            outcomes::{Outcome, OutcomeSink},
+            rejects::RejectWriter,
+            routing::DisputeRouter,
+            spill::{HistorySpill, MemoryLimit},
+            tx_index::TransactionIndex,
+        },
+        io::{
+            dump,
+            enrichment::Enrichment,
+            export::{self, ExportFormat},
+            input::{AmountUnits, InputFormat, MinorUnitsTransaction},
+            iso8583, json_lines,
+            normalize::{NormalizationReport, Normalizations, NormalizingReader},
+            output::{self, OutputFormat, PageLimits, SortKey},
+            proto,
+            query::{self, AccountQuery, QueryFormat},
+            snapshot, wal, xlsx,
+        },
+        policy::{
+            filter::Filter,
+            risk::{self, RiskWeights},
+            EnginePolicy,
+        },
+    },
+    shared::{
+        decimal::Decimal,
+        errors::Error,
+        memory::{self, Subsystem},
+        retry::RetryPolicy,
+        sha256::{self, Digest, DigestReader},
+    },
+};
+
+mod aggregate;
+pub mod alerts;
+pub mod anomaly;
+mod audit;
+mod backfill;
+mod batch;
+mod certify;
+pub mod checkpoint;
+pub mod demo;
+mod http;
+pub mod merge;
+pub mod observer;
+mod outcomes;
+mod pipeline;
+mod policy_diff;
+mod rejects;
+pub(crate) mod report;
+mod routing;
+pub(crate) mod server;
+mod shards;
+pub mod spill;
+pub mod store;
+mod transaction_engine;
+pub mod tx_index;
+mod verify;
+
+pub use transaction_engine::{Account, TransactionEngine};
+
+/// Transactions applied between two progress reports of the replay of `serve --replay`
+const REPLAY_PROGRESS_EVERY: u64 = 100_000;
+
+/// Options driving how transactions are processed and how the resulting accounts are written
+#[derive(Debug, Default)]
+pub struct ProcessingOptions {
+    /// Layout of the input file
+    pub input_format: InputFormat,
+    /// Unit of the amounts found in the input file, for CSV input
+    pub amount_units: AmountUnits,
+    /// Accounts file, in the v1 output format, whose balances are the initial state (without transactions history)
+    pub seed_file: Option<PathBuf>,
+    /// Snapshot file whose full state, transactions history included, is the initial state. Takes precedence
+    /// over `seed_file`
+    pub restore_file: Option<PathBuf>,
+    /// Checkpoint written by an interrupted run (see `checkpoint`), whose state is the initial state: the input
+    /// records it applied are read again but skipped. Takes precedence over `restore_file` and `seed_file`
+    pub resume_file: Option<PathBuf>,
+    /// Business rules applied to every transaction
+    pub policy: EnginePolicy,
+    /// Lookup tables merged into each transaction before it is applied
+    pub enrichment: Enrichment,
+    /// Format used to write the resulting accounts
+    pub output_format: OutputFormat,
+    /// Weights used to compute the client risk score
+    pub risk_weights: RiskWeights,
+    /// Column the accounts are sorted by when written, client ID by default
+    pub sort_by: SortKey,
+    /// File where the final state of the accounts, transactions history included, is saved as binary snapshot
+    pub snapshot_file: Option<PathBuf>,
+    /// Snapshot file where closed accounts are moved, instead of being kept in `snapshot_file`
+    pub archive_file: Option<PathBuf>,
+    /// Refuse to process an input already listed in the ledger of `snapshot_file`
+    pub refuse_applied_inputs: bool,
+    /// Retries of the snapshot and archive file operations failing with transient errors
+    pub storage_retry: RetryPolicy,
+    /// File where an alert is appended whenever a client crosses one of `alert_thresholds`
+    pub alerts_file: Option<PathBuf>,
+    /// Thresholds checked after every transaction, if `alerts_file` is set
+    pub alert_thresholds: AlertThresholds,
+    /// Write only the accounts satisfying the filter expression
+    pub filter: Option<Filter>,
+    /// Abort on the first malformed input record, instead of skipping it
+    pub strict: bool,
+    /// File listing every ignored input record, with the reason why it was ignored
+    pub rejects_file: Option<PathBuf>,
+    /// Append-only log of the events of the transactions applied to each account and of the rejected ones, with
+    /// the balances after each of them, chained by account so that later changes can be detected (see
+    /// [verify_audit_chain])
+    pub audit_file: Option<PathBuf>,
+    /// Flag the windows of records where the system total changes faster than the given rate
+    pub anomaly_rate: Option<AnomalyRate>,
+    /// Number of worker threads applying the transactions, sharded by client ID, instead of the calling thread.
+    /// Inputs are then read whole upfront, refused if they hold a transfer, parsed by a thread of their own, and side
+    /// outputs written by another one
+    pub threads: Option<NonZeroUsize>,
+    /// Number of input records parsed ahead by a reader thread of their own while the previous ones are applied
+    /// on the calling thread. Ignored with `threads`, whose reader already runs ahead
+    pub read_ahead: Option<NonZeroUsize>,
+    /// Treatment of a client found in more than one worker shard, or in more than one row of the seed file
+    pub duplicate_clients: DuplicateClients,
+    /// Directory of the write-ahead log where the workers log the transactions they apply, so that the state of
+    /// an interrupted run can be rebuilt (see [recover_wal]). Only written with `threads`
+    pub wal_dir: Option<PathBuf>,
+    /// Memory the transactions history may take while processing: beyond it, the oldest transactions not under
+    /// dispute are spilled to a temporary file and reloaded when a transaction refers to them. Not supported with
+    /// `threads`, `checkpoint` or a history limit
+    pub max_memory: Option<MemoryLimit>,
+    /// Apply runs of up to N consecutive deposits of the same client together, writing the client balances once
+    /// per run, with the same outcomes as applying them one by one. Not supported with `threads` or `max_memory`
+    pub aggregate_deposits: Option<NonZeroUsize>,
+    /// Apply the disputes, resolves and chargebacks filed by a client other than the one owning the transaction
+    /// they refer to, found by a global index of the transaction IDs, to the owner, listing them in the rejects
+    /// file. Not supported with `threads` or `max_memory`
+    pub route_disputes_by_tx: bool,
+    /// Save the state of the accounts periodically while processing, so that an interrupted run can be resumed.
+    /// Not supported with `threads`
+    pub checkpoint: Option<Checkpoint>,
+    /// File where a certificate of the final accounts is written: digests of the configuration and of the state,
+    /// and the result of the invariant checks (balance identities, reconciliation, dispute consistency)
+    pub certificate_file: Option<PathBuf>,
+}
+
+/**
+ * Having CSV input line, here data are processed as follow:
+ * | type [String] | client [UUID - u16] | tx [u32] | amount [decimal] |
+ *
+ * type can be one the following operations:
+ *     - deposit:
+ *         * available += amount
+ *         * total += amount
+ *     - withdrawal:
+ *         * available -= amount
+ *         * total -= amount
+ *     - dispute: [Request to revert a transaction. It refers to a specific transaction ID and does not have an amount]
+ *         * held += tx_hisotry[tx_id]
+ *         * available -= tx_hisotry[tx_id]
+ *         * set tx_id attribute is_dispute to true
+ *     - resolve: [Request to resolve a dispute. It refers to a specific transaction ID and does not have an amount]
+ *         * held -= tx_hisotry[tx_id]
+ *         * available += tx_hisotry[tx_id]
+ *         * Previous operations should be taken into account iff tx_id exists in history_tx and if tx_id is under dispute
+ *     - chargeback: [Final state of a dispute. It refers to a specific transaction ID and does not have an amount]
+ *         * held -= tx_hisotry[tx_id]
+ *         * total -= tx_hisotry[tx_id]
+ *         * Previous operations should be taken into account iff tx_id exists in history_tx and if tx_id is under dispute
+ *         * This operation immediately freeze the client acount.
+ *
+ *  Assumptions to very:
+ *  While implementing the solution I identified a few edge cases that are not fully specified. I made conservative assumptions and I’d like to confirm they align with your expectations.
+ *  1. I assume accounts start with zero balance and cannot go negative. Withdrawals with insufficient available funds are ignored.
+ *  2. I assume disputes can only target previous monetary transactions (deposit/withdrawal), and that disputes themselves cannot be disputed.
+ *  3. After a chargeback I treat the account as frozen and ignore all subsequent transactions, considering it a terminal state.
+ *  4. If a transaction with an already-seen transaction ID is received, I ignore it and keep the original transaction unchanged.
+ *  5. Malformed input data are simply ignored
+ *
+ *
+ *
+ *  OUTPUT file will contain
+ *  | client [UUID - u16] | available [decimal {.4}] | held [decimal {.4}] | total [decimal {.4}] | locked [bool]|
+ *
+ */
+pub fn apply_transaction<R, W>(
+    input: R,
+    options: &ProcessingOptions,
+    writer: W,
+) -> Result<(), Error>
+where
+    R: Read + Send,
+    W: Write,
+{
+    apply_transactions([input], options, writer)
+}
+
+/// Same processing as [apply_transaction], for several inputs applied in order into the same engine state,
+/// as a single stream (e.g. month-split exports replayed together): sequence numbers go on from one input
+/// to the next, and the snapshot ledger lists the digest of every input
+pub fn apply_transactions<R, W>(
+    inputs: impl IntoIterator<Item = R>,
+    options: &ProcessingOptions,
+    writer: W,
+) -> Result<(), Error>
+where
+    R: Read + Send,
+    W: Write,
+{
+    let (mut client_out, ledger) = process_inputs(inputs, options)?;
+
+    output::write_clients(
+        ordered_clients(&client_out, options).into_iter(),
+        options.output_format,
+        writer,
+    )?;
+
+    write_state(&mut client_out, &ledger, options)
+}
+
+/// Same processing as [apply_transaction], but the output is split into `partitions` CSV files
+/// by client id range and written in `output_dir` together with a manifest describing each partition.
+/// Inputs are applied in order into the same engine state, as in [apply_transactions]
+pub fn apply_transaction_partitioned<R>(
+    inputs: impl IntoIterator<Item = R>,
+    options: &ProcessingOptions,
+    partitions: u16,
+    output_dir: PathBuf,
+) -> Result<(), Error>
+where
+    R: Read + Send,
+{
+    if partitions == 0 {
+        return Err(Error::InvalidPartitionCount);
+    }
+    let (mut client_out, ledger) = process_inputs(inputs, options)?;
+
+    output::write_partitions(
+        ordered_clients(&client_out, options),
+        options.output_format,
+        partitions,
+        &output_dir,
+    )?;
+
+    write_state(&mut client_out, &ledger, options)
+}
+
+/// Same processing as [apply_transaction], but the output is split into numbered CSV files, each holding at most
+/// the accounts and bytes allowed by `limits` and repeating the header, written in `output_dir` together with a
+/// manifest describing each page. Inputs are applied in order into the same engine state, as in [apply_transactions]
+pub fn apply_transaction_paged<R>(
+    inputs: impl IntoIterator<Item = R>,
+    options: &ProcessingOptions,
+    limits: PageLimits,
+    output_dir: PathBuf,
+) -> Result<(), Error>
+where
+    R: Read + Send,
+{
+    let (mut client_out, ledger) = process_inputs(inputs, options)?;
+
+    output::write_pages(
+        ordered_clients(&client_out, options),
+        options.output_format,
+        limits,
+        &output_dir,
+    )?;
+
+    write_state(&mut client_out, &ledger, options)
+}
+
+/// Same processing as [apply_transaction], for the input files listed in the batch manifest `manifest_file`
+/// (a `file,rows,sha256` CSV file, paths relative to its directory) applied in order as a single atomic batch:
+/// if any member is missing, or its number of records or checksum does not match the manifest, nothing is
+/// processed, written or saved. The snapshot ledger lists the digest of every member.
+pub fn apply_batch<W>(
+    manifest_file: PathBuf,
+    options: &ProcessingOptions,
+    writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let members = batch::load_batch(&manifest_file, options)?;
+    let ledger = members
+        .iter()
+        .map(|member| member.digest)
+        .collect::<Vec<_>>();
+    if let Some(snapshot_file) = &options.snapshot_file {
+        if options.refuse_applied_inputs && snapshot_file.exists() {
+            let applied = options.storage_retry.run("read snapshot ledger", || {
+                snapshot::read_ledger(snapshot_file)
+            })?;
+            if let Some(digest) = ledger.iter().find(|digest| applied.contains(digest)) {
+                return Err(Error::InputAlreadyApplied(sha256::to_hex(digest)));
+            }
+        }
+    }
+
+    let mut client_out = process_transactions_with(
+        members.iter().map(|member| member.content.as_slice()),
+        options,
+        |_, _| {},
+    )?;
+
+    output::write_clients(
+        ordered_clients(&client_out, options).into_iter(),
+        options.output_format,
+        writer,
+    )?;
+
+    write_state(&mut client_out, &ledger, options)
+}
+
+/// Process the inputs in order, together with their SHA-256, computed while reading only if a snapshot is
+/// requested, to be recorded in the snapshot ledger. Fails if any input is already listed in the ledger of the
+/// existing snapshot and re-applying inputs is refused: in that case the whole inputs are read upfront, so that
+/// nothing is applied (e.g. no alert is written) before the check.
+fn process_inputs<R>(
+    inputs: impl IntoIterator<Item = R>,
+    options: &ProcessingOptions,
+) -> Result<(HashMap<u16, Client>, Vec<Digest>), Error>
+where
+    R: Read + Send,
+{
+    let Some(snapshot_file) = &options.snapshot_file else {
+        let client_out = process_transactions_with(inputs, options, |_, _| {})?;
+        return Ok((client_out, Vec::new()));
+    };
+    let mut inputs = inputs
+        .into_iter()
+        .map(DigestReader::new)
+        .collect::<Vec<_>>();
+    if options.refuse_applied_inputs && snapshot_file.exists() {
+        let mut contents = Vec::new();
+        for input in &mut inputs {
+            let mut content = Vec::new();
+            input.read_to_end(&mut content)?;
+            contents.push(content);
+        }
+        let ledger = inputs
+            .into_iter()
+            .map(DigestReader::finalize)
+            .collect::<Vec<_>>();
+        let applied = options.storage_retry.run("read snapshot ledger", || {
+            snapshot::read_ledger(snapshot_file)
+        })?;
+        if let Some(digest) = ledger.iter().find(|digest| applied.contains(digest)) {
+            return Err(Error::InputAlreadyApplied(sha256::to_hex(digest)));
+        }
+        let client_out =
+            process_transactions_with(contents.iter().map(Vec::as_slice), options, |_, _| {})?;
+        return Ok((client_out, ledger));
+    }
+
+    let client_out = process_transactions_with(inputs.iter_mut(), options, |_, _| {})?;
+    let ledger = inputs.into_iter().map(DigestReader::finalize).collect();
+    Ok((client_out, ledger))
+}
+
+/// Save the final state as snapshot, if requested, after moving closed accounts into the archive (if any).
+/// The snapshot ledger lists the digests of the processed inputs, following the ledger of the restored snapshot.
+fn write_state(
+    client_out: &mut HashMap<u16, Client>,
+    ledger: &[Digest],
+    options: &ProcessingOptions,
+) -> Result<(), Error> {
+    let Some(snapshot_file) = &options.snapshot_file else {
+        return Ok(());
+    };
+    let mut full_ledger = restored_ledger(options)?;
+    full_ledger.extend_from_slice(ledger);
+    if let Some(archive_file) = &options.archive_file {
+        snapshot::archive_closed_accounts(
+            client_out,
+            &full_ledger,
+            archive_file,
+            &options.storage_retry,
+        )?;
+    }
+
+    options.storage_retry.run("write snapshot", || {
+        snapshot::write_snapshot(client_out.values(), &full_ledger, snapshot_file)
+    })
+}
+
+/// Clients to write, in output order: sorted by the requested column, then by client ID.
+/// Clients not satisfying the output filter, if any, are left out.
+fn ordered_clients<'a>(
+    clients: &'a HashMap<u16, Client>,
+    options: &ProcessingOptions,
+) -> Vec<&'a Client> {
+    let mut ordered = clients
+        .values()
+        .filter(|client| {
+            options
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(client))
+        })
+        .collect::<Vec<_>>();
+    output::sort_clients(&mut ordered, options.sort_by);
+    ordered
+}
+
+/// Process the input and compare the resulting accounts against the expected output file.
+/// Clients are matched by ID, so row ordering is not significant, and monetary values are considered equal
+/// if their absolute difference does not exceed `tolerance`.
+/// Every difference found is written on `writer`, and an error is returned if there is at least one.
+pub fn verify_transaction<R, W>(
+    input: R,
+    options: &ProcessingOptions,
+    expected_file: PathBuf,
+    tolerance: Decimal,
+    writer: W,
+) -> Result<(), Error>
+where
+    R: Read + Send,
+    W: Write,
+{
+    let client_out = process_transactions(input, options)?;
+    let expected = load_accounts(expected_file, options.duplicate_clients)?;
+
+    verify::report_mismatches(&expected, &client_out, tolerance, writer)
+}
+
+/// Apply the corrections of `corrections_file` (a `client,amount,reason` CSV file of signed adjustments
+/// with mandatory reason code) to the accounts of an existing output file, and write the adjusted accounts
+pub fn backfill_accounts<W>(
+    accounts_file: PathBuf,
+    corrections_file: PathBuf,
+    options: &ProcessingOptions,
+    writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut client_out = load_accounts(accounts_file, options.duplicate_clients)?;
+    backfill::apply_corrections(&mut client_out, &corrections_file)?;
+
+    output::write_clients(
+        ordered_clients(&client_out, options).into_iter(),
+        options.output_format,
+        writer,
+    )
+}
+
+/// Load accounts (without transactions history) from a CSV file in the v1 output format. Clients found in more
+/// than one row are treated as told by `duplicates`
+fn load_accounts(
+    accounts_file: PathBuf,
+    duplicates: DuplicateClients,
+) -> Result<HashMap<u16, Client>, Error> {
+    let mut merger = AccountMerger::new(duplicates);
+    for (row, client) in ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_path(&accounts_file)?
+        .deserialize::<Client>()
+        .enumerate()
+    {
+        merger.add(client?, format!("row {}", row + 1))?;
+    }
+    let (clients, report) = merger.finish();
+    if !report.is_empty() {
+        eprintln!("Duplicate clients in {}: {report}", accounts_file.display());
+    }
+    Ok(clients)
+}
+
+/// Load the accounts of a snapshot file, together with the archived ones if an archive file is given,
+/// and write on `writer` those selected by `query`, sorted by client ID
+pub fn query_snapshot<W>(
+    snapshot_file: PathBuf,
+    archive_file: Option<PathBuf>,
+    query: &AccountQuery,
+    format: QueryFormat,
+    writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let clients = read_snapshot_accounts(&snapshot_file, archive_file.as_deref())?;
+    let mut selected = clients
+        .values()
+        .filter(|client| query.matches(client))
+        .collect::<Vec<_>>();
+    selected.sort_by_key(|client| client.id);
+
+    query::write_accounts(&selected, format, writer)
+}
+
+/// Load the accounts of a snapshot file, together with the archived ones if an archive file is given, and index
+/// their transactions by ID, so that the owner and the dispute state of any transaction can be looked up
+pub fn index_snapshot(
+    snapshot_file: &Path,
+    archive_file: Option<&Path>,
+) -> Result<TransactionIndex, Error> {
+    let clients = read_snapshot_accounts(snapshot_file, archive_file)?;
+    Ok(TransactionIndex::new(clients.values()))
+}
+
+/// Write on `writer` the clients owning transaction `tx` in a snapshot file, or in the archive file if given,
+/// with the dispute state of the transaction. Nothing but the header is written if the transaction is unknown
+pub fn query_snapshot_transaction<W>(
+    snapshot_file: PathBuf,
+    archive_file: Option<PathBuf>,
+    tx: u32,
+    format: QueryFormat,
+    writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let index = index_snapshot(&snapshot_file, archive_file.as_deref())?;
+    query::write_transactions(index.get(tx), format, writer)
+}
+
+/// Accounts of a snapshot file, together with the archived ones if an archive file is given
+fn read_snapshot_accounts(
+    snapshot_file: &Path,
+    archive_file: Option<&Path>,
+) -> Result<HashMap<u16, Client>, Error> {
+    let mut clients = snapshot::read_snapshot(snapshot_file)?;
+    if let Some(archive_file) = archive_file {
+        // accounts are archived only once closed, so live accounts never overlap archived ones
+        clients.extend(snapshot::read_snapshot(archive_file)?);
+    }
+    Ok(clients)
+}
+
+/// Write on `writer` the full internal state of a snapshot as indented JSON, for debugging: the ledger of applied
+/// inputs and every client, sorted by ID, including the fields not present in the output schema such as
+/// the transactions history with the dispute state of each transaction and the pending disputes.
+pub fn dump_snapshot<W>(snapshot_file: PathBuf, writer: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    let ledger = snapshot::read_ledger(&snapshot_file)?;
+    let clients = snapshot::read_snapshot(&snapshot_file)?;
+
+    let mut clients = clients.values().collect::<Vec<_>>();
+    clients.sort_by_key(|client| client.id);
+
+    dump::write_state(&ledger, &clients, writer)
+}
+
+/// Write on `writer` the accounts of a snapshot file, together with the archived ones if an archive file is given,
+/// sorted by client ID, as length-delimited `AccountSnapshot` Protocol Buffers messages (schema in
+/// `proto/simulator.proto`), transactions history included, for tools written in other languages
+pub fn export_snapshot_proto<W>(
+    snapshot_file: &Path,
+    archive_file: Option<&Path>,
+    writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let clients = read_snapshot_accounts(snapshot_file, archive_file)?;
+    let mut clients = clients.values().collect::<Vec<_>>();
+    clients.sort_by_key(|client| client.id);
+
+    proto::write_accounts(&clients, writer)
+}
+
+/// Read accounts exported by [export_snapshot_proto], or by another tool following the same schema, and save them
+/// as a snapshot file with an empty ledger, to be restored by later runs. Returns the number of accounts imported
+pub fn import_snapshot_proto<R>(reader: R, snapshot_file: &Path) -> Result<usize, Error>
+where
+    R: Read,
+{
+    let clients = proto::read_accounts(reader)?;
+    snapshot::write_snapshot(clients.values(), &[], snapshot_file)?;
+    Ok(clients.len())
+}
+
+/// Write on `writer` which policy settings differ between the runs certified by `before` and `after`, as
+/// written by `--certify`, and which outputs differ between them, each with the differing settings that can
+/// explain it. Fails if either certificate was edited since written, or does not embed its policy
+pub fn diff_policies<W>(before: &Path, after: &Path, writer: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    let before = policy_diff::RunReport::read(before)?;
+    let after = policy_diff::RunReport::read(after)?;
+    policy_diff::write_policy_diff(&before, &after, writer)
+}
+
+/// Erase the personal data of a client from a snapshot, rewritten in place with the same ledger. The account
+/// is kept as a tombstone with the same balances, as done by [TransactionEngine::erase_client]
+pub fn erase_snapshot_client(snapshot_file: PathBuf, client: u16) -> Result<(), Error> {
+    let ledger = snapshot::read_ledger(&snapshot_file)?;
+    let mut clients = snapshot::read_snapshot(&snapshot_file)?;
+    clients
+        .get_mut(&client)
+        .ok_or_else(|| Error::ErasureRefused(client, "unknown client".to_owned()))?
+        .erase()?;
+
+    snapshot::write_snapshot(clients.values(), &ledger, &snapshot_file)
+}
+
+/// Rebuild the accounts of a run with worker threads interrupted while writing the write-ahead log in `wal_dir`,
+/// replaying the segments of the workers in parallel, and save them as a checkpoint, to be resumed (see
+/// [ProcessingOptions::resume_file]) with the same inputs. Returns the number of input records applied to the
+/// accounts of the checkpoint
+pub fn recover_wal(wal_dir: &Path, checkpoint_file: &Path) -> Result<u64, Error> {
+    let recovered = wal::recover(wal_dir)?;
+    snapshot::write_checkpoint(
+        recovered.clients.values(),
+        &recovered.ledger,
+        recovered.position,
+        checkpoint_file,
+    )?;
+    Ok(recovered.position)
+}
+
+/// Check the hash chain of every account of an audit log written by [ProcessingOptions::audit_file], failing on the
+/// first row edited, removed or inserted afterwards, and write on `writer` the number of rows and the last
+/// `sha256` of every account (`client,rows,sha256`), sorted by client ID. Archived, they tell rows removed from the
+/// end of the log, which no later row can tell
+pub fn verify_audit_chain<W>(audit_file: &Path, writer: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    let chains = audit::verify_chain(audit_file)?;
+    let mut clients = chains.heads.keys().copied().collect::<Vec<_>>();
+    clients.sort();
+
+    let mut writer = csv::WriterBuilder::new()
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(writer);
+    writer.write_record(["client", "rows", "sha256"])?;
+    for client in &clients {
+        writer.write_record([
+            client.to_string(),
+            chains.rows[client].to_string(),
+            sha256::to_hex(&chains.heads[client]),
+        ])?;
+    }
+    writer.flush()?;
+    eprintln!(
+        "Audit log verified: {} row(s) of {} account(s)",
+        chains.rows.values().sum::<u64>(),
+        clients.len()
+    );
+    Ok(())
+}
+
+/// Attach operator notes, given as `(client, note)` pairs, to the clients of a snapshot, rewritten in place
+/// with the same ledger. Notes are kept in snapshots and shown by `query`, `dump-state` and statements. Nothing
+/// is written if any client is unknown or any note is empty
+pub fn annotate_snapshot_clients(
+    snapshot_file: PathBuf,
+    annotations: &[(u16, String)],
+) -> Result<(), Error> {
+    let ledger = snapshot::read_ledger(&snapshot_file)?;
+    let mut clients = snapshot::read_snapshot(&snapshot_file)?;
+    for (client, note) in annotations {
+        clients
+            .get_mut(client)
+            .ok_or_else(|| Error::AnnotationRefused(*client, "unknown client".to_owned()))?
+            .annotate(note)?;
+    }
+
+    snapshot::write_snapshot(clients.values(), &ledger, &snapshot_file)
+}
+
+/// Load operator notes from a `client,note` CSV file, in file order
+pub fn read_annotations(path: &Path) -> Result<Vec<(u16, String)>, Error> {
+    ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_path(path)?
+        .deserialize::<(u16, String)>()
+        .collect::<Result<_, _>>()
+        .map_err(Error::Csv)
+}
+
+/// Kind of human-readable report produced by [report_transaction]
+#[derive(Debug)]
+pub enum ReportKind {
+    /// Metrics aggregated by the cohorts listed in the given `client,cohort` CSV file. Clients not listed
+    /// in the file, or every client if no file is given, are aggregated in a default cohort.
+    Cohorts(Option<PathBuf>),
+    /// Outcome of disputes per client and overall
+    DisputeOutcomes,
+    /// Activity breakdown: transactions per client percentiles, most disputed clients and busiest
+    /// transaction ID ranges
+    Activity,
+}
+
+/// Process the input and write on `writer` a human-readable report of the resulting accounts
+pub fn report_transaction<R, W>(
+    input: R,
+    options: &ProcessingOptions,
+    kind: ReportKind,
+    writer: W,
+) -> Result<(), Error>
+where
+    R: Read + Send,
+    W: Write,
+{
+    // reports count the transactions in the history
+    if options.max_memory.is_some() {
+        return Err(Error::SpillNotSupported("reports"));
+    }
+    let mut tx_ids = Vec::new();
+    let client_out = process_transactions_with([input], options, |_, events| {
+        tx_ids.extend(events.iter().filter_map(|event| match event {
+            Event::Deposited { tx, .. }
+            | Event::Withdrawn { tx, .. }
+            | Event::TransferredOut { tx, .. }
+            | Event::TransferredIn { tx, .. } => Some(*tx),
+            _ => None,
+        }))
+    })?;
+    let cohorts_file = match kind {
+        ReportKind::Cohorts(cohorts_file) => cohorts_file,
+        ReportKind::DisputeOutcomes => {
+            return report::write_dispute_outcomes_report(&client_out, writer)
+        }
+        ReportKind::Activity => return report::write_activity_report(&client_out, &tx_ids, writer),
+    };
+    let cohorts = match cohorts_file {
+        Some(cohorts_file) => report::load_cohorts(&cohorts_file)?,
+        None => HashMap::new(),
+    };
+
+    report::write_cohort_report(&client_out, &cohorts, writer)
+}
+
+/// Process the input and write on `writer` the statement of every client, sorted by client ID,
+/// listing the movements of its funds in the requested interchange format. `date` (`MM/DD/YYYY`) is used
+/// by the formats requiring a date on every entry, since input transactions carry no timestamp.
+pub fn export_transaction<R, W>(
+    input: R,
+    options: &ProcessingOptions,
+    format: ExportFormat,
+    date: &str,
+    writer: W,
+) -> Result<(), Error>
+where
+    R: Read + Send,
+    W: Write,
+{
+    let mut activity = HashMap::<u16, Vec<Event>>::new();
+    let client_out = process_transactions_with([input], options, |client, events| {
+        activity.entry(client).or_default().extend(events)
+    })?;
+
+    let mut clients = client_out.values().collect::<Vec<_>>();
+    clients.sort_by_key(|client| client.id);
+
+    export::write_statements(&clients, &activity, format, date, writer)
+}
+
+/// Run the bundled example datasets (every one, if `dataset` is not given) through the engine, writing on `writer`
+/// every transaction annotated with the state changes it caused, or why it was ignored, and the final accounts
+pub fn run_demo<W>(
+    dataset: Option<DemoDataset>,
+    options: &ProcessingOptions,
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let datasets = match dataset {
+        Some(dataset) => vec![dataset],
+        None => DemoDataset::ALL.to_vec(),
+    };
+    for (index, dataset) in datasets.into_iter().enumerate() {
+        if index > 0 {
+            writeln!(writer)?;
+        }
+        demo::write_demo(dataset, &options.policy, &mut writer)?;
+    }
+
+    Ok(())
+}
+
+/// Listen for transactions on `listener` (line protocol documented in `engine/server.rs`), applying them as they are received
+/// from any number of concurrent connections to the accounts of the initial state, which can be queried at
+/// any time. Runs until accepting connections fails
+pub fn serve_transactions(listener: TcpListener, options: &ProcessingOptions) -> Result<(), Error> {
+    let clients = load_initial_state(options)?;
+    server::serve(listener, clients, options)
+}
+
+/// Same as [serve_transactions], after replaying every transaction of `replay` on the initial state as a batch run
+/// would, side outputs included, reporting the progress on stderr. Connections made to `listener` during the replay
+/// wait to be accepted until it is over, so that live transactions are only applied after every replayed one
+pub fn serve_transactions_replaying<R>(
+    listener: TcpListener,
+    replay: Vec<R>,
+    options: &ProcessingOptions,
+) -> Result<(), Error>
+where
+    R: Read + Send,
+{
+    let mut applied = 0_u64;
+    let clients = process_transactions_with(replay, options, |_, _| {
+        applied += 1;
+        if applied.is_multiple_of(REPLAY_PROGRESS_EVERY) {
+            eprintln!("Replayed {applied} transaction(s)");
+        }
+    })?;
+    eprintln!(
+        "Replay over: {applied} transaction(s) applied to {} account(s), accepting connections",
+        clients.len()
+    );
+    server::serve(listener, clients, options)
+}
+
+/// Serve the HTTP API (documented in `engine/http.rs`) on `listener`, applying the transactions posted to the
+/// accounts of the initial state, which can be fetched at any time. Runs until accepting connections fails
+pub fn serve_http(listener: TcpListener, options: &ProcessingOptions) -> Result<(), Error> {
+    let clients = load_initial_state(options)?;
+    http::serve(listener, clients, options)
+}
+
+/// Read every transaction of the input and apply it to the related client
+fn process_transactions<R>(
+    input: R,
+    options: &ProcessingOptions,
+) -> Result<HashMap<u16, Client>, Error>
+where
+    R: Read + Send,
+{
+    process_transactions_with([input], options, |_, _| {})
+}
+
+/// Same as [process_transactions], for a sequence of inputs applied one after the other as a single stream
+/// (sequence numbers go on across inputs), calling `on_applied` with the client ID and the events of every
+/// applied transaction
+fn process_transactions_with<R>(
+    inputs: impl IntoIterator<Item = R>,
+    options: &ProcessingOptions,
+    mut on_applied: impl FnMut(u16, Vec<Event>) + Send,
+) -> Result<HashMap<u16, Client>, Error>
+where
+    R: Read + Send,
+{
+    if options.checkpoint.is_some() && options.threads.is_some() {
+        return Err(Error::CheckpointWithThreads);
+    }
+    if options.wal_dir.is_some() && options.threads.is_none() {
+        return Err(Error::WalWithoutThreads);
+    }
+    if options.aggregate_deposits.is_some() {
+        match (&options.threads, &options.max_memory) {
+            (Some(_), _) => return Err(Error::AggregationNotSupported("worker threads")),
+            (_, Some(_)) => return Err(Error::AggregationNotSupported("a history spill")),
+            (None, None) => {}
+        }
+    }
+    if options.max_memory.is_some() {
+        match (
+            &options.threads,
+            &options.checkpoint,
+            &options.policy.history_limit,
+        ) {
+            (Some(_), _, _) => return Err(Error::SpillNotSupported("worker threads")),
+            (_, Some(_), _) => return Err(Error::SpillNotSupported("checkpoints")),
+            (_, _, Some(_)) => return Err(Error::SpillNotSupported("a history limit")),
+            (None, None, None) => {}
+        }
+    }
+    if options.route_disputes_by_tx {
+        match (&options.threads, &options.max_memory) {
+            (Some(_), _) => return Err(Error::RoutingNotSupported("worker threads")),
+            (_, Some(_)) => return Err(Error::RoutingNotSupported("a history spill")),
+            (None, None) => {}
+        }
+    }
+    let client_out = load_initial_state(options)?;
+    let mut routed = 0_u64;
+    let resumed = match &options.resume_file {
+        Some(resume_file) => options.storage_retry.run("read checkpoint position", || {
+            snapshot::read_position(resume_file)
+        })?,
+        None => 0,
+    };
+    let mut inputs = inputs.into_iter();
+    let contents = match options.threads {
+        Some(_) => read_without_transfers(&mut inputs, &client_out, options, resumed)?,
+        None => Vec::new(),
+    };
+    let ledger = match options.checkpoint.is_some() || options.wal_dir.is_some() {
+        true => restored_ledger(options)?,
+        false => Vec::new(),
+    };
+    let mut auditor = options
+        .certificate_file
+        .as_ref()
+        .map(|_| Auditor::new(client_out.values()));
+    let alerts = options
+        .alerts_file
+        .as_ref()
+        .map(|alerts_file| AlertWriter::open(alerts_file, options.alert_thresholds))
+        .transpose()?;
+    let rejects = options
+        .rejects_file
+        .as_deref()
+        .map(RejectWriter::create)
+        .transpose()?;
+    let anomalies = options
+        .anomaly_rate
+        .map(|rate| {
+            AnomalyDetector::new(rate, client_out.values().map(|client| client.total.value()))
+        })
+        .transpose()?;
+    // updated by the sink as transactions are applied, read before applying the next one. Never contended, as
+    // routing is not supported with worker threads
+    let router = options
+        .route_disputes_by_tx
+        .then(|| Mutex::new(DisputeRouter::new(client_out.values())));
+    let audit = options
+        .audit_file
+        .as_deref()
+        .map(AuditLog::open)
+        .transpose()?;
+    let mut sink = OutcomeSink::new(
+        alerts,
+        rejects,
+        anomalies,
+        audit,
+        |client, events: Vec<Event>| {
+            if let Some(auditor) = &mut auditor {
+                auditor.record(client, &events);
+            }
+            if let Some(router) = &router {
+                router
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .record(client, &events);
+            }
+            on_applied(client, events)
+        },
+    );
+    sink.resume_after(resumed);
+
+    let (mut client_out, normalizations) = match options.threads {
+        Some(threads) => {
+            if let Some(wal_dir) = &options.wal_dir {
+                wal::start(wal_dir, client_out.values(), &ledger, resumed)?;
+            }
+            let inputs = contents.iter().map(Vec::as_slice).collect();
+            pipeline::run(inputs, options, threads, resumed, client_out, &mut sink)?
+        }
+        None => {
+            let mut client_out = client_out;
+            let mut spill = options
+                .max_memory
+                .map(|limit| HistorySpill::create(limit, &mut client_out))
+                .transpose()?;
+            let mut deposits = options.aggregate_deposits.map(DepositRun::new);
+            let on_record = |seq, line, result| {
+                let mut admitted = admit_record(line, result, options);
+                if let Some(deposits) = &mut deposits {
+                    // the run is over on any record it does not accept, failures included. It is applied before
+                    // the record is routed, so that the router knows the deposits held back
+                    if !matches!(&admitted, Ok(Ok(transaction)) if deposits.accepts(transaction)) {
+                        deposits.flush(&mut client_out, &options.policy, &mut sink)?;
+                    }
+                }
+                if let (Some(router), Ok(Ok(transaction))) = (&router, &mut admitted) {
+                    let owner = router
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .route(transaction);
+                    if let Some(owner) = owner {
+                        if let Some(rejects) = &mut sink.rejects {
+                            rejects.mismatched(seq, line, transaction, owner)?;
+                        }
+                        transaction.id = owner;
+                        routed += 1;
+                    }
+                }
+                let outcome = match (admitted?, &mut spill, &mut deposits) {
+                    (Ok(transaction), _, Some(deposits))
+                        if transaction.transaction_type == Type::Deposit =>
+                    {
+                        deposits.push(seq, line, transaction);
+                        None
+                    }
+                    (Ok(transaction), Some(spill), _) => {
+                        Some(spill.apply(&mut client_out, line, transaction, &options.policy)?)
+                    }
+                    (Ok(transaction), None, _) => Some(outcomes::apply(
+                        &mut client_out,
+                        line,
+                        transaction,
+                        &options.policy,
+                    )),
+                    (Err(detail), _, _) => Some(Outcome::Malformed { line, detail }),
+                };
+                if let Some(outcome) = outcome {
+                    sink.push(seq, outcome)?;
+                }
+                match &options.checkpoint {
+                    Some(checkpoint) => {
+                        // the checkpoint records the deposits held back too
+                        if let Some(deposits) = deposits.as_mut().filter(|_| checkpoint.is_due(seq))
+                        {
+                            deposits.flush(&mut client_out, &options.policy, &mut sink)?;
+                        }
+                        checkpoint.save_if_due(&client_out, &ledger, seq, &options.storage_retry)
+                    }
+                    None => Ok(()),
+                }
+            };
+            let read = match options.read_ahead {
+                Some(batch_size) => {
+                    let inputs = inputs.collect();
+                    pipeline::read_ahead(inputs, options, batch_size, resumed, on_record)
+                }
+                None => read_inputs(inputs, options, resumed, on_record),
+            };
+            // the deposits of the last run are applied even if processing stops, as the previous records are
+            let flushed = deposits.as_mut().map_or(Ok(()), |deposits| {
+                deposits.flush(&mut client_out, &options.policy, &mut sink)
+            });
+            let normalizations = read?;
+            flushed?;
+            // only the snapshot keeps the transactions history
+            if let (Some(spill), Some(_)) = (spill, &options.snapshot_file) {
+                spill.restore(&mut client_out)?;
+            }
+            (client_out, normalizations)
+        }
+    };
+
+    if !normalizations.is_empty() {
+        eprintln!("Input normalized: {normalizations}");
+    }
+    if routed > 0 {
+        eprintln!("Routed {routed} dispute(s) filed by another client than the owner of their transaction");
+    }
+    if sink.skipped > 0 {
+        eprintln!(
+            "Skipped {} malformed record(s), use --strict to abort on the first one",
+            sink.skipped
+        );
+    }
+    if let Some(rejects) = sink.rejects {
+        rejects.finish()?;
+    }
+    if let Some(audit) = &mut sink.audit {
+        audit.flush()?;
+    }
+    let rejections = std::mem::take(&mut sink.rejections);
+    if let Some(anomalies) = &sink.anomalies {
+        anomalies
+            .anomalies()
+            .iter()
+            .for_each(|anomaly| eprintln!("Anomaly: {anomaly}"));
+    }
+
+    risk::score_clients(&mut client_out, &options.risk_weights);
+    if let (Some(auditor), Some(certificate_file)) = (auditor, &options.certificate_file) {
+        auditor.certify(&client_out, &rejections, options, certificate_file)?;
+    }
+
+    Ok(client_out)
+}
+
+/// Accounts before any transaction is applied: resumed from a checkpoint, restored from a snapshot, resumed from
+/// a previous output or none. A checkpoint cannot be restored, as the records it applied would be applied again
+fn load_initial_state(options: &ProcessingOptions) -> Result<HashMap<u16, Client>, Error> {
+    match (
+        &options.resume_file,
+        &options.restore_file,
+        &options.seed_file,
+    ) {
+        (Some(resume_file), _, _) => options
+            .storage_retry
+            .run("read checkpoint", || snapshot::read_snapshot(resume_file)),
+        (None, Some(restore_file), _) => {
+            let position = options.storage_retry.run("read restored position", || {
+                snapshot::read_position(restore_file)
+            })?;
+            if position > 0 {
+                return Err(Error::InvalidSnapshot(format!(
+                    "checkpoint of a run interrupted after record {position}, to be resumed instead of restored"
+                )));
+            }
+            options.storage_retry.run("read restored snapshot", || {
+                snapshot::read_snapshot(restore_file)
+            })
+        }
+        (None, None, Some(seed_file)) => {
+            load_accounts(seed_file.clone(), options.duplicate_clients)
+        }
+        (None, None, None) => Ok(HashMap::new()),
+    }
+}
+
+/// Digests of the inputs applied to build the initial state, listed by the resumed or restored snapshot
+fn restored_ledger(options: &ProcessingOptions) -> Result<Vec<Digest>, Error> {
+    match options
+        .resume_file
+        .as_ref()
+        .or(options.restore_file.as_ref())
+    {
+        Some(snapshot_file) => options.storage_retry.run("read restored ledger", || {
+            snapshot::read_ledger(snapshot_file)
+        }),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Read the records of the inputs one after the other, calling `on_record` with the sequence number, the line
+/// number in its input and the transaction of every record, or why it cannot be read. Sequence numbers start
+/// from 1 and count malformed records too. The first `resumed` records, applied by the run that wrote the resumed
+/// checkpoint, are skipped. Returns what was normalized to read the inputs
+fn read_inputs<R>(
+    inputs: impl IntoIterator<Item = R>,
+    options: &ProcessingOptions,
+    resumed: u64,
+    mut on_record: impl FnMut(u64, u64, Result<ClientTransaction, Error>) -> Result<(), Error>,
+) -> Result<Normalizations, Error>
+where
+    R: Read,
+{
+    let mut normalizations = Vec::new();
+    let mut sequence = 0;
+    for input in inputs {
+        let (mut transactions, report) = memory::track(Subsystem::ParseBuffers, || {
+            read_transactions(input, options)
+        })?;
+        normalizations.extend(report);
+        while let Some((line, result)) =
+            memory::track(Subsystem::ParseBuffers, || transactions.next())
+        {
+            sequence += 1;
+            if sequence > resumed {
+                on_record(sequence, line, result)?;
+            }
+        }
+    }
+    if sequence < resumed {
+        return Err(Error::ResumePastInput(resumed, sequence));
+    }
+
+    Ok(normalizations
+        .iter()
+        .fold(Normalizations::default(), |total, report| {
+            total + report.counts()
+        }))
+}
+
+/// Read the whole inputs of a run with worker threads, failing if they or the initial state hold a transfer, which
+/// would reach the shard of a single client, disputes included. The inputs are checked before the run starts, so
+/// that nothing is applied nor written (e.g. no rejected record) when they cannot be processed, or cannot be read
+fn read_without_transfers<R>(
+    inputs: impl Iterator<Item = R>,
+    clients: &HashMap<u16, Client>,
+    options: &ProcessingOptions,
+    resumed: u64,
+) -> Result<Vec<Vec<u8>>, Error>
+where
+    R: Read,
+{
+    if clients.values().any(Client::has_transfers) {
+        return Err(Error::TransfersWithThreads);
+    }
+    let mut contents = Vec::new();
+    for mut input in inputs {
+        let mut content = Vec::new();
+        input
+            .read_to_end(&mut content)
+            .map_err(Error::UnreadableInput)?;
+        contents.push(content);
+    }
+    read_inputs(
+        contents.iter().map(Vec::as_slice),
+        options,
+        resumed,
+        |_, _, result| match result {
+            Ok(transaction) if transaction.transaction_type == Type::Transfer => {
+                Err(Error::TransfersWithThreads)
+            }
+            // anything else wrong with the inputs is reported by the run, in input order
+            _ => Ok(()),
+        },
+    )?;
+    Ok(contents)
+}
+
+/// Screen a record read at `line` before its transaction is applied: an unreadable input, or a malformed record
+/// in strict mode, stops the processing, while other malformed records are skipped, with what is wrong with them.
+/// Transactions get the attributes of the enrichment files
+fn admit_record(
+    line: u64,
+    result: Result<ClientTransaction, Error>,
+    options: &ProcessingOptions,
+) -> Result<Result<ClientTransaction, String>, Error> {
+    match result {
+        Ok(mut transaction) => {
+            options.enrichment.merge_into(&mut transaction);
+            Ok(Ok(transaction))
+        }
+        Err(error @ Error::UnreadableInput(_)) => Err(error),
+        Err(error) if options.strict => Err(Error::MalformedRecord(line, malformation(error))),
+        Err(error) => Ok(Err(malformation(error))),
+    }
+}
+
+/// Records of an input, each with its line number in the input, and what was normalized to read them (CSV only)
+type Transactions<'a> = Box<dyn Iterator<Item = (u64, Result<ClientTransaction, Error>)> + 'a>;
+
+/// Start reading the transactions of an input, according to its format. If reading the input fails
+/// (e.g. a truncated compressed file), the record being read is returned as [Error::UnreadableInput]
+fn read_transactions<'a, R>(
+    input: R,
+    options: &'a ProcessingOptions,
+) -> Result<(Transactions<'a>, Option<Rc<NormalizationReport>>), Error>
+where
+    R: Read + 'a,
+{
+    let failure = Rc::new(Cell::new(None));
+    let input = FailureTrackingReader {
+        reader: input,
+        failure: Rc::clone(&failure),
+    };
+    let (transactions, report) = read_records(input, options)
+        .map_err(|error| failure.take().map_or(error, Error::UnreadableInput))?;
+    // the record is not malformed, the rest of the input is missing
+    let transactions = transactions.map(move |(line, transaction)| match failure.take() {
+        Some(error) => (line, Err(Error::UnreadableInput(error))),
+        None => (line, transaction),
+    });
+
+    Ok((Box::new(transactions), report))
+}
+
+/// Reader remembering the last error of the underlying input, which parsers report as a malformed record
+struct FailureTrackingReader<R> {
+    reader: R,
+    failure: Rc<Cell<Option<std::io::Error>>>,
+}
+
+impl<R: Read> Read for FailureTrackingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf).inspect_err(|error| {
+            self.failure
+                .set(Some(std::io::Error::new(error.kind(), error.to_string())))
+        })
+    }
+}
+
+/// Read the records of an input, according to its format
+fn read_records<'a, R>(
+    input: R,
+    options: &'a ProcessingOptions,
+) -> Result<(Transactions<'a>, Option<Rc<NormalizationReport>>), Error>
+where
+    R: Read + 'a,
+{
+    let amount_units = &options.amount_units;
+    match &options.input_format {
+        InputFormat::Iso8583(exponents) => {
+            return Ok((Box::new(iso8583::read_messages(input, exponents)), None))
+        }
+        InputFormat::JsonLines => {
+            let transactions = json_lines::read_objects(input).map(move |(line, object)| {
+                let transaction = object
+                    .and_then(|(headers, record)| parse_record(&record, &headers, amount_units));
+                (line, transaction)
+            });
+            return Ok((Box::new(transactions), None));
+        }
+        InputFormat::Xlsx => {
+            let (headers, rows) = xlsx::read_sheet(input)?;
+            let transactions = rows
+                .into_iter()
+                .map(move |(line, record)| (line, parse_record(&record, &headers, amount_units)));
+            return Ok((Box::new(transactions), None));
+        }
+        InputFormat::Csv => {}
+    }
+
+    let (input, report) = NormalizingReader::new(input);
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(input);
+    let headers = reader.headers()?.clone();
+    let line_report = Rc::clone(&report);
+    let transactions = reader.into_records().map(move |record| {
+        let position = match &record {
+            Ok(record) => record.position(),
+            Err(error) => error.position(),
+        };
+        // normalization skips blank lines, so the CSV line is mapped back to the source one
+        let line = line_report.source_line(position.map_or(0, Position::line));
+        let transaction = record
+            .map_err(Error::Csv)
+            .and_then(|record| parse_record(&record, &headers, amount_units));
+        (line, transaction)
+    });
+
+    Ok((Box::new(transactions), Some(report)))
+}
+
+/// Parse a CSV record (or a JSON object read as such) into a transaction, converting amounts given in minor units
+fn parse_record(
+    record: &StringRecord,
+    headers: &StringRecord,
+    amount_units: &AmountUnits,
+) -> Result<ClientTransaction, Error> {
+    match amount_units {
+        AmountUnits::Decimal => record.deserialize(Some(headers)).map_err(Error::Csv),
+        AmountUnits::Minor(exponents) => record
+            .deserialize::<MinorUnitsTransaction>(Some(headers))
+            .map_err(Error::Csv)?
+            .into_client_transaction(exponents),
+    }
+}
+
+/// What is wrong with a malformed record, without the CSV position already reported as line number
+fn malformation(error: Error) -> String {
+    match error {
+        Error::Csv(error) => match error.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+            _ => error.to_string(),
+        },
+        error => error.to_string(),
+    }
+}
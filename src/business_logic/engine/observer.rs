@@ -0,0 +1,68 @@
+use crate::business_logic::{
+    domain::{ClientTransaction, Rejection},
+    engine::transaction_engine::Account,
+};
+
+/// Callbacks run by a [TransactionEngine](crate::TransactionEngine) on the outcome of every transaction it is
+/// submitted, once the transaction is applied to the accounts or ignored, so that programs embedding the engine
+/// can attach metrics, logging or side effects of their own without changing the transaction rules. Every
+/// callback does nothing by default.
+///
+/// Observers are attached with [TransactionEngine::observe](crate::TransactionEngine::observe), and run in the
+/// order they were attached
+pub trait TransactionObserver: Send {
+    /// Transaction applied to the account of `account.client`, with the account after it. Called for the source,
+    /// then for the destination, of a transaction moving funds between two clients
+    fn on_applied(&mut self, transaction: &ClientTransaction, account: &Account) {
+        let _ = (transaction, account);
+    }
+
+    /// Transaction ignored with `rejection`, leaving every account untouched
+    fn on_rejected(&mut self, transaction: &ClientTransaction, rejection: Rejection) {
+        let _ = (transaction, rejection);
+    }
+
+    /// Account of `account.client` locked by the chargeback of a transaction, with the account after it. Called
+    /// after [on_applied](TransactionObserver::on_applied) for the same account
+    fn on_lock(&mut self, transaction: &ClientTransaction, account: &Account) {
+        let _ = (transaction, account);
+    }
+}
+
+/// Observers attached to an engine, in the order they were attached
+#[derive(Default)]
+pub(crate) struct Observers(Vec<Box<dyn TransactionObserver>>);
+
+impl std::fmt::Debug for Observers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+impl Observers {
+    pub(crate) fn attach(&mut self, observer: Box<dyn TransactionObserver>) {
+        self.0.push(observer);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn applied(&mut self, transaction: &ClientTransaction, account: &Account) {
+        self.0
+            .iter_mut()
+            .for_each(|observer| observer.on_applied(transaction, account));
+    }
+
+    pub(crate) fn rejected(&mut self, transaction: &ClientTransaction, rejection: Rejection) {
+        self.0
+            .iter_mut()
+            .for_each(|observer| observer.on_rejected(transaction, rejection));
+    }
+
+    pub(crate) fn locked(&mut self, transaction: &ClientTransaction, account: &Account) {
+        self.0
+            .iter_mut()
+            .for_each(|observer| observer.on_lock(transaction, account));
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    business_logic::{
+        domain::{events::Event, transactions_logic::Rejection, ClientTransaction, Type},
+        engine::{
+            alerts::{AlertMetrics, AlertWriter},
+            anomaly::AnomalyDetector,
+            audit::AuditLog,
+            rejects::{RejectWriter, MALFORMED_RECORD},
+            store::ClientStore,
+        },
+        policy::EnginePolicy,
+    },
+    shared::{decimal::Decimal, errors::Error},
+};
+
+/// What happened to an input record
+pub(crate) enum Outcome {
+    /// Record that could not be parsed, with what is wrong with it
+    Malformed { line: u64, detail: String },
+    /// Transaction applied to its client, or rejected, with the client metrics before and after it, and the other
+    /// client it was applied to, if it moves funds between two clients
+    Applied {
+        line: u64,
+        transaction: ClientTransaction,
+        before: AlertMetrics,
+        after: AlertMetrics,
+        result: Result<Vec<Event>, Rejection>,
+        counterparty: Option<Box<Counterparty>>,
+    },
+}
+
+/// Other client a transaction moving funds between two clients was applied to: the destination of a transfer,
+/// with its metrics before and after the transaction and the events of its leg
+pub(crate) struct Counterparty {
+    pub(crate) client: u16,
+    pub(crate) before: AlertMetrics,
+    pub(crate) after: AlertMetrics,
+    pub(crate) events: Vec<Event>,
+}
+
+/// Client and events of the leg of the destination of a transfer
+type Leg = (u16, Vec<Event>);
+
+/// Destination of the transfer a transaction moves funds to or back from, if any: the destination of a transfer,
+/// or of the transfer sent by the client a dispute, resolve or chargeback refers to
+pub(crate) fn counterparty(
+    clients: &impl ClientStore,
+    transaction: &ClientTransaction,
+) -> Option<u16> {
+    match transaction.transaction_type {
+        Type::Transfer => transaction.to_client,
+        Type::Dispute | Type::Resolve | Type::ChargeBack => clients
+            .get(transaction.id, |client| {
+                client.sent_transfer(transaction.tx)
+            })
+            .flatten(),
+        Type::Deposit | Type::Withdrawal | Type::Adjustment | Type::Unlock => None,
+    }
+}
+
+/// Whether the transaction moves funds between two clients, and must be applied by [apply_transaction]
+pub(crate) fn is_transfer(clients: &impl ClientStore, transaction: &ClientTransaction) -> bool {
+    transaction.transaction_type == Type::Transfer || counterparty(clients, transaction).is_some()
+}
+
+/// Apply a transaction to its client, created if missing, and to the destination of the transfer it moves funds
+/// to or back from, if any, created if missing too: both legs of a transfer are checked before any is applied
+/// (see `Client::plan_transfer`). Returns the events of the client, and the destination with the events of its leg
+pub(crate) fn apply_transaction(
+    clients: &mut impl ClientStore,
+    transaction: &ClientTransaction,
+    policy: &EnginePolicy,
+) -> Result<(Vec<Event>, Option<Leg>), Rejection> {
+    if !is_transfer(clients, transaction) {
+        return clients
+            .update(transaction.id, |client| {
+                client.apply_transaction(transaction, policy)
+            })
+            .map(|events| (events, None));
+    }
+
+    let source = transaction.id;
+    let plan = clients.update(source, |client| client.plan_transfer(transaction, policy))?;
+    let destination_events = clients.update(plan.destination, |client| {
+        client.receive_transfer(source, &plan, policy)
+    })?;
+    // both legs are checked, applying them cannot fail
+    clients.update(source, |client| {
+        client.replay_transaction(&plan.source_events)
+    })?;
+    clients.update(plan.destination, |client| {
+        client.replay_transaction(&destination_events)
+    })?;
+    Ok((
+        plan.source_events,
+        Some((plan.destination, destination_events)),
+    ))
+}
+
+/// Apply a transaction to its client, created if missing, and to the destination of the transfer it moves funds
+/// to or back from, if any (see [apply_transaction])
+pub(crate) fn apply(
+    clients: &mut impl ClientStore,
+    line: u64,
+    transaction: ClientTransaction,
+    policy: &EnginePolicy,
+) -> Outcome {
+    if is_transfer(clients, &transaction) {
+        return apply_transfer(clients, line, transaction, policy);
+    }
+    let (before, after, result) = clients.update(transaction.id, |client| {
+        let before = AlertMetrics::from(&*client);
+        let result = client.apply_transaction(&transaction, policy);
+        (before, AlertMetrics::from(&*client), result)
+    });
+
+    Outcome::Applied {
+        line,
+        before,
+        after,
+        transaction,
+        result,
+        counterparty: None,
+    }
+}
+
+/// Apply a transaction moving funds between two clients, taking the metrics of both before and after it
+fn apply_transfer(
+    clients: &mut impl ClientStore,
+    line: u64,
+    transaction: ClientTransaction,
+    policy: &EnginePolicy,
+) -> Outcome {
+    let destination_before =
+        counterparty(clients, &transaction).map(|client| metrics(clients, client));
+    let before = metrics(clients, transaction.id);
+    let result = apply_transaction(clients, &transaction, policy);
+    let after = metrics(clients, transaction.id);
+    let (result, counterparty) = match result {
+        Ok((events, Some((client, destination_events)))) => (
+            Ok(events),
+            Some(Box::new(Counterparty {
+                client,
+                before: destination_before.unwrap_or_default(),
+                after: metrics(clients, client),
+                events: destination_events,
+            })),
+        ),
+        Ok((events, None)) => (Ok(events), None),
+        Err(rejection) => (Err(rejection), None),
+    };
+
+    Outcome::Applied {
+        line,
+        before,
+        after,
+        transaction,
+        result,
+        counterparty,
+    }
+}
+
+/// Metrics of a client, the ones of a new client if missing
+fn metrics(clients: &impl ClientStore, client: u16) -> AlertMetrics {
+    clients
+        .get(client, |client| AlertMetrics::from(client))
+        .unwrap_or_default()
+}
+
+/// Apply a run of deposits of the same client, created if missing, writing its balances once (see
+/// `Client::apply_deposits`). Returns the outcome of every deposit, as [apply] would applying them one by one
+pub(crate) fn apply_deposits(
+    clients: &mut impl ClientStore,
+    deposits: Vec<(u64, ClientTransaction)>,
+    policy: &EnginePolicy,
+) -> Vec<Outcome> {
+    let Some((_, first)) = deposits.first() else {
+        return Vec::new();
+    };
+    let results = clients.update(first.id, |client| {
+        // deposits do not change the lock nor the chargebacks of the client
+        let (locked, chargebacks) = (client.locked, client.dispute_outcomes.charged_back);
+        client
+            .apply_deposits(deposits.iter().map(|(_, deposit)| deposit), policy)
+            .into_iter()
+            .map(|(before, after, result)| {
+                (
+                    AlertMetrics::new(before, locked, chargebacks),
+                    AlertMetrics::new(after, locked, chargebacks),
+                    result,
+                )
+            })
+            .collect::<Vec<_>>()
+    });
+
+    deposits
+        .into_iter()
+        .zip(results)
+        .map(
+            |((line, transaction), (before, after, result))| Outcome::Applied {
+                line,
+                before,
+                after,
+                transaction,
+                result,
+                counterparty: None,
+            },
+        )
+        .collect()
+}
+
+/// Side outputs of the processing (rejects, alerts, anomalies, audit log and the `on_applied` callback), fed with the
+/// outcome of every input record. Outcomes may come out of order when transactions are applied by several
+/// threads: they are held back until all the previous ones are handled, so that side outputs are written
+/// in input order however transactions are applied
+pub(crate) struct OutcomeSink<F> {
+    pub(crate) alerts: Option<AlertWriter>,
+    pub(crate) rejects: Option<RejectWriter>,
+    pub(crate) anomalies: Option<AnomalyDetector>,
+    pub(crate) audit: Option<AuditLog>,
+    pub(crate) on_applied: F,
+    /// Number of malformed records
+    pub(crate) skipped: u64,
+    /// Number of ignored records, malformed ones included, by reason code
+    pub(crate) rejections: BTreeMap<&'static str, u64>,
+    /// Sequence number of the next outcome to handle
+    next: u64,
+    /// Outcomes of the records following the next one, received ahead of it, by distance from it
+    pending: VecDeque<Option<Outcome>>,
+}
+
+impl<F> OutcomeSink<F>
+where
+    F: FnMut(u16, Vec<Event>),
+{
+    pub(crate) fn new(
+        alerts: Option<AlertWriter>,
+        rejects: Option<RejectWriter>,
+        anomalies: Option<AnomalyDetector>,
+        audit: Option<AuditLog>,
+        on_applied: F,
+    ) -> Self {
+        Self {
+            alerts,
+            rejects,
+            anomalies,
+            audit,
+            on_applied,
+            skipped: 0,
+            rejections: BTreeMap::new(),
+            // sequence numbers start from 1
+            next: 1,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Skip the outcomes of the first `position` input records, applied by the run that wrote the resumed checkpoint.
+    /// Called before any outcome is handled
+    pub(crate) fn resume_after(&mut self, position: u64) {
+        self.next = position + 1;
+    }
+
+    /// Handle the outcome of input record `seq`, once the outcomes of all the previous records are handled
+    pub(crate) fn push(&mut self, seq: u64, outcome: Outcome) -> Result<(), Error> {
+        let distance = (seq - self.next) as usize;
+        if self.pending.len() <= distance {
+            self.pending.resize_with(distance + 1, || None);
+        }
+        self.pending[distance] = Some(outcome);
+        while let Some(Some(outcome)) = self.pending.front_mut().map(Option::take) {
+            self.pending.pop_front();
+            self.handle(self.next, outcome)?;
+            self.next += 1;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, seq: u64, outcome: Outcome) -> Result<(), Error> {
+        if let (Some(audit), Outcome::Applied { line, .. }) = (&mut self.audit, &outcome) {
+            audit.record(seq, Some(*line), &outcome)?;
+        }
+        match outcome {
+            // malformed records are skipped, and only counted and listed as rejects
+            Outcome::Malformed { line, detail } => {
+                self.skipped += 1;
+                *self.rejections.entry(MALFORMED_RECORD).or_default() += 1;
+                if let Some(rejects) = &mut self.rejects {
+                    rejects.malformed(seq, line, detail)?;
+                }
+            }
+            // rejected transactions are ignored, and only listed as rejects
+            Outcome::Applied {
+                line,
+                transaction,
+                result: Err(rejection),
+                ..
+            } => {
+                *self.rejections.entry(rejection.code()).or_default() += 1;
+                if let Some(rejects) = &mut self.rejects {
+                    rejects.rejected(seq, line, &transaction, rejection)?;
+                }
+            }
+            Outcome::Applied {
+                transaction,
+                before,
+                after,
+                result: Ok(events),
+                counterparty,
+                ..
+            } => {
+                if let Some(alerts) = &mut self.alerts {
+                    alerts.check(seq, transaction.id, transaction.tx, before, after)?;
+                    if let Some(other) = &counterparty {
+                        alerts.check(
+                            seq,
+                            other.client,
+                            transaction.tx,
+                            other.before,
+                            other.after,
+                        )?;
+                    }
+                }
+                if let Some(anomalies) = &mut self.anomalies {
+                    // a transfer changes the totals by the sum of both legs
+                    let change = std::iter::once((transaction.id, before, after))
+                        .chain(
+                            counterparty
+                                .iter()
+                                .map(|other| (other.client, other.before, other.after)),
+                        )
+                        .try_fold(Decimal::ZERO, |change, (client, before, after)| {
+                            after
+                                .total()
+                                .checked_sub(before.total())
+                                .and_then(|leg| change.checked_add(leg))
+                                .ok_or_else(|| {
+                                    Error::AmountOverflow(format!(
+                                        "Change of the total of client {}",
+                                        client
+                                    ))
+                                })
+                        })?;
+                    anomalies.record(seq, change)?;
+                }
+                (self.on_applied)(transaction.id, events);
+                if let Some(other) = counterparty {
+                    (self.on_applied)(other.client, other.events);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    num::NonZeroUsize,
+    sync::mpsc::{self, Receiver, SyncSender},
+};
+
+use crate::{
+    business_logic::{
+        domain::{events::Event, Client, ClientTransaction, Type},
+        engine::{
+            admit_record,
+            outcomes::{Outcome, OutcomeSink},
+            read_inputs,
+            shards::ShardPool,
+            ProcessingOptions,
+        },
+        io::normalize::Normalizations,
+    },
+    shared::errors::Error,
+};
+
+/// Records exchanged at once between two stages, as exchanging every single record costs more than handling it.
+/// Every worker gets its share of the transactions at once, however small, so that no outcome is held back long
+pub(crate) const BATCH_SIZE: usize = 1024;
+/// Batches queued to a stage before the previous one waits for it, bounding the memory used when a stage
+/// falls behind
+pub(crate) const QUEUE_CAPACITY: usize = 16;
+
+/// Record of input record `seq`, read at `line`
+type Record = (u64, u64, Result<ClientTransaction, Error>);
+
+/// Apply the transactions of the inputs to `clients` as a pipeline of threads connected by bounded queues, so that
+/// reading and parsing the input, applying transactions and writing side outputs overlap:
+///
+/// * the reader parses the records of the inputs, in batches;
+/// * the dispatcher, on the calling thread, screens every record and routes its transaction to the worker owning
+///   its client;
+/// * `threads` workers apply transactions to their share of the clients (see [ShardPool]);
+/// * the collector hands the outcomes, put back in input order, to `sink`.
+///
+/// Returns the clients and what was normalized to read the inputs. Like sequential processing, stops
+/// on the first unreadable input, or malformed record in strict mode, and on the first failure of the sink
+pub(crate) fn run<R, F>(
+    inputs: Vec<R>,
+    options: &ProcessingOptions,
+    threads: NonZeroUsize,
+    resumed: u64,
+    clients: HashMap<u16, Client>,
+    sink: &mut OutcomeSink<F>,
+) -> Result<(HashMap<u16, Client>, Normalizations), Error>
+where
+    R: Read + Send,
+    F: FnMut(u16, Vec<Event>) + Send,
+{
+    std::thread::scope(|scope| {
+        let (records_sender, records) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let reader = scope.spawn(move || read(inputs, options, resumed, records_sender));
+
+        let (outcomes_sender, outcomes) = mpsc::sync_channel(QUEUE_CAPACITY * threads.get());
+        let collector = scope.spawn(move || {
+            for (seq, outcome) in outcomes.into_iter().flatten() {
+                sink.push(seq, outcome)?;
+            }
+            Ok::<_, Error>(())
+        });
+
+        let mut pool = ShardPool::start(
+            scope,
+            threads,
+            clients,
+            &options.policy,
+            &outcomes_sender,
+            options.wal_dir.as_deref(),
+        )?;
+        // on failure the pool is dropped, stopping every stage
+        dispatch(records, &mut pool, &outcomes_sender, options)?;
+        drop(outcomes_sender);
+        let (clients, duplicates) = pool.finish(options.duplicate_clients)?;
+        if !duplicates.is_empty() {
+            eprintln!("Duplicate clients across shards: {duplicates}");
+        }
+
+        collector
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+        let normalizations = reader
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+        Ok((clients, normalizations))
+    })
+}
+
+/// Hand the records of the inputs following the first `resumed` ones to `on_record`, on the calling thread, while a
+/// reader thread parses them ahead in batches of `batch_size` records. Batches are double-buffered: the reader fills
+/// the next batch while the records of the current one are handled, then waits for it to be taken, and the
+/// buffers of handled batches go back to the reader to be filled again, so that no batch is allocated after the
+/// first two. Returns what was normalized to read the inputs, like [read_inputs]
+pub(crate) fn read_ahead<R>(
+    inputs: Vec<R>,
+    options: &ProcessingOptions,
+    batch_size: NonZeroUsize,
+    resumed: u64,
+    mut on_record: impl FnMut(u64, u64, Result<ClientTransaction, Error>) -> Result<(), Error>,
+) -> Result<Normalizations, Error>
+where
+    R: Read + Send,
+{
+    std::thread::scope(|scope| {
+        // no batch is queued: the reader hands over the next one once the current one is handled
+        let (records_sender, records) = mpsc::sync_channel::<Vec<Record>>(0);
+        let (buffers_sender, buffers) = mpsc::channel::<Vec<Record>>();
+        let reader = scope.spawn(move || {
+            let mut batch = Vec::with_capacity(batch_size.get());
+            let normalizations = read_inputs(inputs, options, resumed, |seq, line, result| {
+                batch.push((seq, line, result));
+                if batch.len() == batch_size.get() {
+                    let next = buffers
+                        .try_recv()
+                        .unwrap_or_else(|_| Vec::with_capacity(batch_size.get()));
+                    // the records stopped being handled on an error, which is the one reported
+                    records_sender
+                        .send(std::mem::replace(&mut batch, next))
+                        .map_err(|_| Error::Io(std::io::ErrorKind::BrokenPipe.into()))?;
+                }
+                Ok(())
+            });
+            let _ = records_sender.send(batch);
+            normalizations
+        });
+
+        for mut batch in records.iter() {
+            for (seq, line, result) in batch.drain(..) {
+                // on failure the receiver is dropped, stopping the reader
+                on_record(seq, line, result)?;
+            }
+            // the reader is done once it cannot take buffers back
+            let _ = buffers_sender.send(batch);
+        }
+        reader
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Read the records of the inputs following the first `resumed` ones, sending them to the dispatcher in batches,
+/// until the inputs end or the dispatcher stops
+fn read<R>(
+    inputs: Vec<R>,
+    options: &ProcessingOptions,
+    resumed: u64,
+    records: SyncSender<Vec<Record>>,
+) -> Result<Normalizations, Error>
+where
+    R: Read,
+{
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let normalizations = read_inputs(inputs, options, resumed, |seq, line, result| {
+        batch.push((seq, line, result));
+        if batch.len() == BATCH_SIZE {
+            let batch = std::mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE));
+            // the dispatcher stopped on its own error, which is the one reported
+            records
+                .send(batch)
+                .map_err(|_| Error::Io(std::io::ErrorKind::BrokenPipe.into()))?;
+        }
+        Ok(())
+    });
+    // records read before a failure are applied, as they would be by sequential processing.
+    // The last batch is of no use if the dispatcher stopped
+    let _ = records.send(batch);
+    normalizations
+}
+
+/// Route every transaction read to the worker owning its client, reporting malformed records straight
+/// to the collector, until the records end or the workers stop
+fn dispatch(
+    records: Receiver<Vec<Record>>,
+    pool: &mut ShardPool,
+    outcomes: &SyncSender<Vec<(u64, Outcome)>>,
+    options: &ProcessingOptions,
+) -> Result<(), Error> {
+    for (seq, line, result) in records.into_iter().flatten() {
+        let submitted = match admit_record(line, result, options)? {
+            // shards apply transactions to their own clients only
+            Ok(transaction) if transaction.transaction_type == Type::Transfer => {
+                return Err(Error::TransfersWithThreads)
+            }
+            Ok(transaction) => pool.submit(seq, line, transaction),
+            Err(detail) => outcomes
+                .send(vec![(seq, Outcome::Malformed { line, detail })])
+                .is_ok(),
+        };
+        // the collector failed, its error is the one reported
+        if !submitted {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,366 @@
+use std::{io::Write, path::Path};
+
+use crate::{
+    business_logic::{
+        engine::report::write_table,
+        io::{config::CHANNEL_RULES, json_lines::parse_object},
+    },
+    shared::{
+        errors::Error,
+        sha256::{self, Sha256},
+    },
+};
+
+/// Outputs of a run each policy setting can change when it differs between two runs: the rejections it raises
+/// or lifts directly, and the balances, locks and further rejections of the transactions it lets through or holds
+/// back. Channel rules are keyed by their prefix
+const EFFECTS: [(&str, &[&str]); 12] = [
+    (
+        "rejected_attributes",
+        &[
+            "rejections.rejected_by_policy",
+            "rejections.insufficient_funds",
+            "totals.available",
+            "totals.held",
+            "totals.total",
+            "locked",
+            "state_sha256",
+        ],
+    ),
+    (
+        "negative_adjustments",
+        &[
+            "rejections.negative_balance",
+            "rejections.insufficient_funds",
+            "totals.available",
+            "totals.total",
+            "state_sha256",
+        ],
+    ),
+    (
+        "adjust_locked_accounts",
+        &[
+            "rejections.locked_account",
+            "totals.available",
+            "totals.total",
+            "state_sha256",
+        ],
+    ),
+    (
+        "history_limit",
+        &[
+            "rejections.pruned_transaction",
+            "totals.available",
+            "totals.held",
+            "totals.total",
+            "locked",
+            "state_sha256",
+        ],
+    ),
+    (
+        "auto_resolve_reasons",
+        &[
+            "rejections.not_disputed",
+            "totals.available",
+            "totals.held",
+            "totals.total",
+            "locked",
+            "state_sha256",
+        ],
+    ),
+    (
+        "charge_back_undisputed",
+        &[
+            "rejections.not_disputed",
+            "rejections.locked_account",
+            "totals.available",
+            "totals.total",
+            "locked",
+            "state_sha256",
+        ],
+    ),
+    (
+        "allow_unlock",
+        &[
+            "rejections.rejected_by_policy",
+            "rejections.locked_account",
+            "rejections.insufficient_funds",
+            "totals.available",
+            "totals.held",
+            "totals.total",
+            "locked",
+            "state_sha256",
+        ],
+    ),
+    ("audit_unlocks", &["state_sha256"]),
+    (
+        "withdrawal_dispute_mode",
+        &[
+            "rejections.rejected_by_policy",
+            "rejections.not_disputed",
+            "rejections.insufficient_funds",
+            "totals.available",
+            "totals.held",
+            "totals.total",
+            "locked",
+            "state_sha256",
+        ],
+    ),
+    (
+        "overdraft_limit",
+        &[
+            "rejections.insufficient_funds",
+            "totals.available",
+            "totals.held",
+            "totals.total",
+            "locked",
+            "state_sha256",
+        ],
+    ),
+    (
+        "dispute_shortfall",
+        &[
+            "rejections.insufficient_funds",
+            "rejections.not_disputed",
+            "totals.available",
+            "totals.held",
+            "totals.total",
+            "locked",
+            "state_sha256",
+        ],
+    ),
+    (
+        CHANNEL_RULES,
+        &[
+            "rejections.rejected_by_policy",
+            "rejections.insufficient_funds",
+            "totals.available",
+            "totals.held",
+            "totals.total",
+            "locked",
+            "state_sha256",
+        ],
+    ),
+];
+
+/// Value shown for a policy setting left unset, or a member missing from a report
+const UNSET: &str = "unset";
+
+/// Policy and outputs of a run, read from the certificate written by `--certify`
+#[derive(Debug)]
+pub(crate) struct RunReport {
+    config_sha256: String,
+    /// Policy settings, as `(key, TOML value)` pairs in configuration order
+    policy: Vec<(String, String)>,
+    /// Outputs of the run compared between runs, as `(name, value)` pairs in certificate order, the members of
+    /// the `totals` and `rejections` objects named after their object
+    outputs: Vec<(String, String)>,
+}
+
+impl RunReport {
+    /// Read a run certificate, checking its `sha256` member against its text
+    pub(crate) fn read(path: &Path) -> Result<Self, Error> {
+        let invalid =
+            |reason: &str| Error::InvalidRunReport(path.display().to_string(), reason.to_owned());
+        let text = std::fs::read_to_string(path)?;
+        let body_end = text
+            .find("\n  \"sha256\": ")
+            .ok_or_else(|| invalid("missing `sha256` member"))?
+            + 1;
+        let (body, trailer) = text.split_at(body_end);
+        let digest = trailer
+            .lines()
+            .next()
+            .and_then(|line| member(line).ok())
+            .and_then(|(_, value)| scalar(value).ok())
+            .ok_or_else(|| invalid("invalid `sha256` member"))?;
+        let mut hasher = Sha256::default();
+        hasher.update(body.as_bytes());
+        if sha256::to_hex(&hasher.finalize()) != digest {
+            return Err(invalid(
+                "`sha256` does not match the certificate, edited since written",
+            ));
+        }
+
+        let mut report = Self {
+            config_sha256: String::new(),
+            policy: Vec::new(),
+            outputs: Vec::new(),
+        };
+        let mut has_policy = false;
+        // top level members, one per line, indented by two spaces
+        for line in body.lines().filter(|line| line.starts_with("  \"")) {
+            let (name, value) = member(line).map_err(|reason| invalid(&reason))?;
+            match (name.as_str(), value) {
+                ("config_sha256", value) => {
+                    report.config_sha256 = scalar(value).map_err(|reason| invalid(&reason))?
+                }
+                ("policy", value) => {
+                    report.policy = members(value).map_err(|reason| invalid(&reason))?;
+                    has_policy = true;
+                }
+                ("totals" | "rejections", value) => {
+                    let members = members(value).map_err(|reason| invalid(&reason))?;
+                    report.outputs.extend(
+                        members
+                            .into_iter()
+                            .map(|(key, value)| (format!("{name}.{key}"), value)),
+                    );
+                }
+                ("clients" | "locked" | "state_sha256" | "passed", value) => {
+                    let value = scalar(value).map_err(|reason| invalid(&reason))?;
+                    report.outputs.push((name, value));
+                }
+                _ => {}
+            }
+        }
+        match has_policy {
+            true => Ok(report),
+            false => Err(invalid(
+                "no policy settings, written by a version not embedding them",
+            )),
+        }
+    }
+}
+
+/// Name and value of a member on its own line, trailing comma excluded
+fn member(line: &str) -> Result<(String, &str), String> {
+    let member = line.trim().trim_end_matches(',');
+    let (name, value) = member
+        .split_once(": ")
+        .ok_or_else(|| format!("invalid member `{member}`"))?;
+    let name = name
+        .strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+        .ok_or_else(|| format!("invalid member name {name}"))?;
+    Ok((name.to_owned(), value))
+}
+
+/// Scalar value, as text
+fn scalar(value: &str) -> Result<String, String> {
+    let (_, values) = parse_object(&format!("{{\"value\": {value}}}"))?;
+    Ok(values.get(0).unwrap_or(UNSET).to_owned())
+}
+
+/// Members of a flat object, as text
+fn members(value: &str) -> Result<Vec<(String, String)>, String> {
+    let (names, values) = parse_object(value)?;
+    Ok(names
+        .iter()
+        .zip(values.iter())
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect())
+}
+
+/// Keys of both lists of pairs, in the order of the first one followed by the keys only in the second one, with
+/// their value in each list
+fn union<'a>(
+    before: &'a [(String, String)],
+    after: &'a [(String, String)],
+) -> Vec<(&'a str, &'a str, &'a str)> {
+    let value = |pairs: &'a [(String, String)], key: &str| {
+        pairs
+            .iter()
+            .find(|(name, _)| name == key)
+            .map_or(UNSET, |(_, value)| value.as_str())
+    };
+    let mut keys = before
+        .iter()
+        .map(|(key, _)| key.as_str())
+        .collect::<Vec<_>>();
+    keys.extend(
+        after
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .filter(|key| before.iter().all(|(name, _)| name != key)),
+    );
+    keys.into_iter()
+        .map(|key| (key, value(before, key), value(after, key)))
+        .collect()
+}
+
+/// Whether a policy setting can change an output, according to [EFFECTS]
+fn affects(setting: &str, output: &str) -> bool {
+    EFFECTS.iter().any(|(key, outputs)| {
+        (setting == *key || (*key == CHANNEL_RULES && setting.starts_with(CHANNEL_RULES)))
+            && outputs.contains(&output)
+    })
+}
+
+/// Write the policy settings differing between two runs, and the outputs differing between them, each with the
+/// differing settings that can explain it. Rejection counts missing from a report are zero
+pub(crate) fn write_policy_diff<W>(
+    before: &RunReport,
+    after: &RunReport,
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let settings = union(&before.policy, &after.policy)
+        .into_iter()
+        .filter(|(_, before, after)| before != after)
+        .collect::<Vec<_>>();
+    writeln!(writer, "Policy settings")?;
+    match settings.is_empty() {
+        true => writeln!(writer, "identical")?,
+        false => {
+            let header = ["setting", "before", "after"].map(str::to_owned).to_vec();
+            let rows = settings.iter().map(|(key, before, after)| {
+                vec![key.to_string(), before.to_string(), after.to_string()]
+            });
+            write_table(
+                &std::iter::once(header).chain(rows).collect::<Vec<_>>(),
+                &mut writer,
+            )?;
+        }
+    }
+    if before.config_sha256 != after.config_sha256 && settings.is_empty() {
+        writeln!(
+            writer,
+            "The effective configurations differ outside the policy (config_sha256)"
+        )?;
+    }
+
+    writeln!(writer)?;
+    writeln!(writer, "Output differences")?;
+    let outputs = union(&before.outputs, &after.outputs)
+        .into_iter()
+        .map(|(output, before, after)| {
+            let count = |value: &str| match (output.starts_with("rejections."), value) {
+                (true, UNSET) => "0".to_owned(),
+                _ => value.to_owned(),
+            };
+            (output, count(before), count(after))
+        })
+        .filter(|(_, before, after)| before != after)
+        .collect::<Vec<_>>();
+    if outputs.is_empty() {
+        writeln!(writer, "none")?;
+        return Ok(());
+    }
+    let header = ["output", "before", "after", "possible causes"]
+        .map(str::to_owned)
+        .to_vec();
+    let rows = outputs.into_iter().map(|(output, before, after)| {
+        let causes = settings
+            .iter()
+            .map(|(setting, _, _)| *setting)
+            .filter(|setting| affects(setting, output))
+            .collect::<Vec<_>>();
+        let causes = match causes.is_empty() {
+            true => "none in the policy".to_owned(),
+            false => causes.join(", "),
+        };
+        // digests are told apart by their first characters
+        let shorten = |value: String| match output == "state_sha256" {
+            true => value.chars().take(12).collect(),
+            false => value,
+        };
+        vec![output.to_owned(), shorten(before), shorten(after), causes]
+    });
+    write_table(
+        &std::iter::once(header).chain(rows).collect::<Vec<_>>(),
+        &mut writer,
+    )
+}
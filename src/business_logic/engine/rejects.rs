@@ -0,0 +1,110 @@
+use std::{fs::File, path::Path};
+
+use csv::{Writer, WriterBuilder};
+use serde::Serialize;
+
+use crate::{
+    business_logic::domain::{transactions_logic::Rejection, ClientTransaction},
+    shared::errors::Error,
+};
+
+/// Reason code of the records that could not be parsed
+pub(crate) const MALFORMED_RECORD: &str = "malformed_record";
+
+/// Reason code of the disputes, resolves and chargebacks filed by another client than the owner of their
+/// transaction, applied to the owner
+pub(crate) const CLIENT_MISMATCH: &str = "client_mismatch";
+
+/// Single row of the rejects file. Fields unknown for malformed records are left empty
+#[derive(Debug, Serialize)]
+struct Reject {
+    /// Sequence number of the input record
+    seq: u64,
+    /// Line of the record in the input
+    line: u64,
+    #[serde(rename = "type")]
+    transaction_type: Option<String>,
+    client: Option<u16>,
+    tx: Option<u32>,
+    /// Machine-readable reason code, e.g. `insufficient_funds`
+    reason: &'static str,
+    /// Human-readable description of the reason
+    detail: String,
+}
+
+/// Rejects file, listing every input record ignored while transactions are processed, and the ones applied to
+/// another client than the one of the record
+pub(crate) struct RejectWriter {
+    writer: Writer<File>,
+}
+
+impl RejectWriter {
+    /// Create the rejects file, replacing any previous content
+    pub(crate) fn create(path: &Path) -> Result<Self, Error> {
+        let writer = WriterBuilder::new()
+            .terminator(csv::Terminator::Any(b'\n'))
+            .from_path(path)?;
+
+        Ok(Self { writer })
+    }
+
+    /// Write a record that could not be parsed, with what is wrong with it
+    pub(crate) fn malformed(&mut self, seq: u64, line: u64, detail: String) -> Result<(), Error> {
+        self.writer.serialize(Reject {
+            seq,
+            line,
+            transaction_type: None,
+            client: None,
+            tx: None,
+            reason: MALFORMED_RECORD,
+            detail,
+        })?;
+        Ok(())
+    }
+
+    /// Write a transaction rejected by the business logic
+    pub(crate) fn rejected(
+        &mut self,
+        seq: u64,
+        line: u64,
+        transaction: &ClientTransaction,
+        rejection: Rejection,
+    ) -> Result<(), Error> {
+        self.writer.serialize(Reject {
+            seq,
+            line,
+            transaction_type: Some(String::from(transaction.transaction_type)),
+            client: Some(transaction.id),
+            tx: Some(transaction.tx),
+            reason: rejection.code(),
+            detail: rejection.to_string(),
+        })?;
+        Ok(())
+    }
+
+    /// Write a transaction filed by another client than `owner`, the client owning the transaction it refers to,
+    /// to which it is applied instead
+    pub(crate) fn mismatched(
+        &mut self,
+        seq: u64,
+        line: u64,
+        transaction: &ClientTransaction,
+        owner: u16,
+    ) -> Result<(), Error> {
+        self.writer.serialize(Reject {
+            seq,
+            line,
+            transaction_type: Some(String::from(transaction.transaction_type)),
+            client: Some(transaction.id),
+            tx: Some(transaction.tx),
+            reason: CLIENT_MISMATCH,
+            detail: format!("transaction owned by client {owner}, applied to it instead"),
+        })?;
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+use std::{collections::HashMap, io::Write, path::Path};
+
+use csv::ReaderBuilder;
+use serde::Deserialize;
+
+use crate::{
+    business_logic::domain::{Client, DisputeOutcomes},
+    shared::{decimal::Decimal, errors::Error},
+};
+
+/// Cohort of clients not tagged by the cohorts file
+const DEFAULT_COHORT: &str = "untagged";
+/// Percentiles of the number of transactions per client in the activity report
+const PERCENTILES: [u32; 4] = [50, 90, 99, 100];
+/// Number of clients listed by the activity report as the most disputed
+const MOST_DISPUTED: usize = 10;
+/// Maximum number of ranges the transaction IDs are split into by the activity report
+const TX_RANGES: u64 = 20;
+/// Number of transaction ID ranges listed by the activity report as the busiest
+const BUSIEST_RANGES: usize = 5;
+
+/// Single row of the cohorts file
+#[derive(Debug, Deserialize)]
+struct CohortEntry {
+    /// Client ID, UUID
+    client: u16,
+    /// Name of the cohort the client belongs to
+    cohort: String,
+}
+
+/// Metrics aggregated over all the clients of a cohort
+#[derive(Debug, Default)]
+struct CohortMetrics {
+    clients: usize,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: usize,
+    /// Deposits and withdrawals applied
+    transactions: usize,
+    dispute_outcomes: DisputeOutcomes,
+}
+
+impl CohortMetrics {
+    /// Add the metrics of a client, failing if a sum of the cohort would overflow
+    fn add(&mut self, cohort: &str, client: &Client) -> Result<(), Error> {
+        let overflow = || Error::AmountOverflow(format!("Sum of the balances of cohort {cohort}"));
+        self.available = self
+            .available
+            .checked_add(client.available.value())
+            .ok_or_else(overflow)?;
+        self.held = self
+            .held
+            .checked_add(client.held.value())
+            .ok_or_else(overflow)?;
+        self.total = self
+            .total
+            .checked_add(client.total.value())
+            .ok_or_else(overflow)?;
+        self.dispute_outcomes = self
+            .dispute_outcomes
+            .checked_add(&client.dispute_outcomes)
+            .ok_or_else(overflow)?;
+        self.clients += 1;
+        self.locked += client.locked as usize;
+        self.transactions += client.transations_history.len();
+        Ok(())
+    }
+}
+
+/// Load the cohort of each client from a `client,cohort` CSV file
+pub(crate) fn load_cohorts(path: &Path) -> Result<HashMap<u16, String>, Error> {
+    ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_path(path)?
+        .deserialize::<CohortEntry>()
+        .map(|entry| entry.map(|entry| (entry.client, entry.cohort)))
+        .collect::<Result<_, _>>()
+        .map_err(Error::Csv)
+}
+
+/// Percentage of `part` over `whole`, formatted with two decimal places (0 if `whole` is 0)
+fn rate(part: u32, whole: usize) -> String {
+    match whole {
+        0 => "0.00%".to_owned(),
+        whole => format!("{:.2}%", part as f64 * 100.0 / whole as f64),
+    }
+}
+
+/// Write a table with the metrics of each cohort, sorted by cohort name.
+/// Dispute rate is computed over deposits and withdrawals, chargeback rate over disputes.
+pub(crate) fn write_cohort_report<W>(
+    clients: &HashMap<u16, Client>,
+    cohorts: &HashMap<u16, String>,
+    writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut metrics = HashMap::<&str, CohortMetrics>::new();
+    for client in clients.values() {
+        let cohort = cohorts
+            .get(&client.id)
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_COHORT);
+        metrics.entry(cohort).or_default().add(cohort, client)?;
+    }
+    let mut metrics = metrics.into_iter().collect::<Vec<_>>();
+    metrics.sort_by_key(|(cohort, _)| *cohort);
+
+    let header = [
+        "cohort",
+        "clients",
+        "available",
+        "held",
+        "total",
+        "locked",
+        "disputes",
+        "dispute_rate",
+        "chargebacks",
+        "chargeback_rate",
+    ]
+    .map(str::to_owned)
+    .to_vec();
+    let rows = metrics.into_iter().map(|(cohort, metrics)| {
+        vec![
+            cohort.to_owned(),
+            metrics.clients.to_string(),
+            metrics.available.to_string(),
+            metrics.held.to_string(),
+            metrics.total.to_string(),
+            metrics.locked.to_string(),
+            metrics.dispute_outcomes.opened.to_string(),
+            rate(metrics.dispute_outcomes.opened, metrics.transactions),
+            metrics.dispute_outcomes.charged_back.to_string(),
+            rate(
+                metrics.dispute_outcomes.charged_back,
+                metrics.dispute_outcomes.opened as usize,
+            ),
+        ]
+    });
+
+    write_table(
+        &std::iter::once(header).chain(rows).collect::<Vec<_>>(),
+        writer,
+    )
+}
+
+/// Write a table with the outcome of disputes (resolved, charged back, pending) and related amounts
+/// for each client having at least one dispute, sorted by client ID, followed by the overall outcomes
+pub(crate) fn write_dispute_outcomes_report<W>(
+    clients: &HashMap<u16, Client>,
+    writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut disputed = clients
+        .values()
+        .filter(|client| client.dispute_outcomes.opened > 0)
+        .collect::<Vec<_>>();
+    disputed.sort_by_key(|client| client.id);
+
+    let overall = disputed
+        .iter()
+        .try_fold(DisputeOutcomes::default(), |overall, client| {
+            overall.checked_add(&client.dispute_outcomes)
+        })
+        .ok_or_else(|| Error::AmountOverflow("Sum of the disputed amounts".to_owned()))?;
+
+    let header = [
+        "client",
+        "disputes",
+        "disputed_amount",
+        "resolved",
+        "resolved_amount",
+        "charged_back",
+        "charged_back_amount",
+        "pending",
+        "pending_amount",
+    ]
+    .map(str::to_owned)
+    .to_vec();
+    let row = |name: String, outcomes: &DisputeOutcomes| {
+        vec![
+            name,
+            outcomes.opened.to_string(),
+            outcomes.opened_amount.to_string(),
+            outcomes.resolved.to_string(),
+            outcomes.resolved_amount.to_string(),
+            outcomes.charged_back.to_string(),
+            outcomes.charged_back_amount.to_string(),
+            outcomes.pending().to_string(),
+            outcomes.pending_amount().to_string(),
+        ]
+    };
+    let rows = disputed
+        .iter()
+        .map(|client| row(client.id.to_string(), &client.dispute_outcomes))
+        .chain(std::iter::once(row("overall".to_owned(), &overall)));
+
+    write_table(
+        &std::iter::once(header).chain(rows).collect::<Vec<_>>(),
+        writer,
+    )
+}
+
+/// Write the activity of the clients, to check how skewed a dataset is: percentiles of the number of transactions
+/// applied per client, the clients with the most disputes, sorted by number of disputes then client ID, and the
+/// ranges of transaction IDs with the most deposits and withdrawals (`tx_ids`), sorted by number of transactions
+/// then range. Ranges have the same width, a power of ten, so that IDs are split into at most 20 ranges
+pub(crate) fn write_activity_report<W>(
+    clients: &HashMap<u16, Client>,
+    tx_ids: &[u32],
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut transactions = clients
+        .values()
+        .map(|client| client.version)
+        .collect::<Vec<_>>();
+    transactions.sort_unstable();
+    // nearest-rank percentile
+    let percentile = |percentile: u32| match transactions.len() {
+        0 => 0,
+        len => transactions[(len * percentile as usize).div_ceil(100).max(1) - 1],
+    };
+    let header = std::iter::once("clients".to_owned())
+        .chain(PERCENTILES.map(|percentile| match percentile {
+            100 => "max".to_owned(),
+            percentile => format!("p{percentile}"),
+        }))
+        .collect();
+    let row = std::iter::once(clients.len().to_string())
+        .chain(PERCENTILES.map(|p| percentile(p).to_string()))
+        .collect();
+    writeln!(writer, "Transactions per client")?;
+    write_table(&[header, row], &mut writer)?;
+
+    let mut disputed = clients
+        .values()
+        .filter(|client| client.dispute_outcomes.opened > 0)
+        .collect::<Vec<_>>();
+    disputed.sort_by_key(|client| (std::cmp::Reverse(client.dispute_outcomes.opened), client.id));
+    let header = ["client", "disputes", "charged_back", "dispute_rate"]
+        .map(str::to_owned)
+        .to_vec();
+    let rows = disputed.iter().take(MOST_DISPUTED).map(|client| {
+        let outcomes = &client.dispute_outcomes;
+        vec![
+            client.id.to_string(),
+            outcomes.opened.to_string(),
+            outcomes.charged_back.to_string(),
+            rate(outcomes.opened, client.transations_history.len()),
+        ]
+    });
+    writeln!(writer)?;
+    writeln!(writer, "Most disputed clients")?;
+    write_table(
+        &std::iter::once(header).chain(rows).collect::<Vec<_>>(),
+        &mut writer,
+    )?;
+
+    let max_tx = tx_ids.iter().max().map_or(0, |&tx| u64::from(tx));
+    let width = std::iter::successors(Some(1_u64), |width| Some(width * 10))
+        .find(|width| (max_tx + 1).div_ceil(*width) <= TX_RANGES)
+        .unwrap_or(1);
+    let mut ranges = HashMap::<u64, u32>::new();
+    for &tx in tx_ids {
+        *ranges.entry(u64::from(tx) / width).or_default() += 1;
+    }
+    let mut ranges = ranges.into_iter().collect::<Vec<_>>();
+    ranges.sort_by_key(|&(range, count)| (std::cmp::Reverse(count), range));
+    let header = ["tx_range", "transactions", "share"]
+        .map(str::to_owned)
+        .to_vec();
+    let rows = ranges.iter().take(BUSIEST_RANGES).map(|&(range, count)| {
+        vec![
+            format!("{}-{}", range * width, (range + 1) * width - 1),
+            count.to_string(),
+            rate(count, tx_ids.len()),
+        ]
+    });
+    writeln!(writer)?;
+    writeln!(writer, "Busiest transaction ID ranges")?;
+    write_table(
+        &std::iter::once(header).chain(rows).collect::<Vec<_>>(),
+        writer,
+    )
+}
+
+/// Write rows as a table with aligned columns: the first column is left aligned, the others right aligned
+pub(crate) fn write_table<W>(rows: &[Vec<String>], mut writer: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut widths = Vec::<usize>::new();
+    for row in rows {
+        widths.resize(widths.len().max(row.len()), 0);
+        row.iter()
+            .enumerate()
+            .for_each(|(column, cell)| widths[column] = widths[column].max(cell.chars().count()));
+    }
+
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(column, (cell, width))| match column {
+                0 => format!("{cell:<width$}"),
+                _ => format!("{cell:>width$}"),
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(writer, "{}", line.trim_end())?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use crate::business_logic::domain::{events::Event, Client, ClientTransaction, Type};
+
+/// Owner of a transaction ID
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Owner {
+    Client(u16),
+    /// ID used by several clients (e.g. both legs of a transfer): the disputes referring to it are never routed
+    Shared,
+}
+
+/// Global index from the ID of every deposit, withdrawal and transfer to the client owning it, routing the
+/// disputes, resolves and chargebacks filed by a client which does not know the transaction they refer to
+/// to the client owning it
+#[derive(Debug, Default)]
+pub(crate) struct DisputeRouter {
+    owners: HashMap<u32, Owner>,
+}
+
+impl DisputeRouter {
+    /// Router knowing the transactions of the given accounts, pruned ones included
+    pub(crate) fn new<'a>(clients: impl Iterator<Item = &'a Client>) -> Self {
+        let mut router = Self::default();
+        for client in clients {
+            let txs = client
+                .transations_history
+                .keys()
+                .chain(&client.pruned_transactions);
+            txs.for_each(|tx| router.own(*tx, client.id));
+        }
+        router
+    }
+
+    /// Record the transactions added to the history of `client` by the events of an applied transaction
+    pub(crate) fn record(&mut self, client: u16, events: &[Event]) {
+        for event in events {
+            match event {
+                Event::Deposited { tx, .. }
+                | Event::Withdrawn { tx, .. }
+                | Event::TransferredOut { tx, .. }
+                | Event::TransferredIn { tx, .. } => self.own(*tx, client),
+                _ => {}
+            }
+        }
+    }
+
+    /// Client owning the transaction referred to by a dispute, resolve or chargeback, if another client than the
+    /// one filing it owns it alone
+    pub(crate) fn route(&self, transaction: &ClientTransaction) -> Option<u16> {
+        if !matches!(
+            transaction.transaction_type,
+            Type::Dispute | Type::Resolve | Type::ChargeBack
+        ) {
+            return None;
+        }
+        match self.owners.get(&transaction.tx) {
+            Some(Owner::Client(owner)) if *owner != transaction.id => Some(*owner),
+            _ => None,
+        }
+    }
+
+    fn own(&mut self, tx: u32, client: u16) {
+        match self.owners.entry(tx) {
+            Entry::Vacant(entry) => {
+                entry.insert(Owner::Client(client));
+            }
+            Entry::Occupied(mut entry) if *entry.get() != Owner::Client(client) => {
+                entry.insert(Owner::Shared);
+            }
+            Entry::Occupied(_) => {}
+        }
+    }
+}
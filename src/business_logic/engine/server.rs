@@ -0,0 +1,378 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use csv::{ReaderBuilder, StringRecord};
+use serde::Deserialize;
+
+use crate::{
+    business_logic::{
+        domain::{Client, ClientTransaction, Rejection},
+        engine::{
+            audit::AuditLog,
+            malformation,
+            outcomes::{self, Outcome},
+            parse_record, Account, ProcessingOptions,
+        },
+        io::{input::AmountUnits, json_lines},
+    },
+    shared::errors::Error,
+};
+
+/*
+ * Line protocol of the `serve` subcommand. Every request is a line, answered by a single line, except `balances`:
+ *
+ * | Request                                  | Response                                                          |
+ * |------------------------------------------|-------------------------------------------------------------------|
+ * | CSV row, e.g. `deposit,1,1,2.5`          | `ok`, `rejected <reject code>` or `error <detail>` if malformed   |
+ * |                                          | (or not appended to the `--audit` log). `error conflict <version>` |
+ * |                                          | if the account is not at the `expected_version` of the row        |
+ * | JSON object, e.g. `{"type":"deposit",…}` | same as a CSV row                                                 |
+ * | CSV header, e.g. `type,client,tx,amount` | `ok`, the columns of the next CSV rows of the connection          |
+ * | `balance <client>`                       | `account <client>,<available>,<held>,<total>,<locked>`            |
+ * | `balances`                               | `accounts <count>`, followed by one `<client>,…` line each        |
+ * | `annotate <client> <note>`               | `ok`, `error <detail>` if the client is unknown or the note empty |
+ * | `quit`                                   | none, the connection is closed                                    |
+ *
+ * Empty lines are ignored. CSV rows have the `type,client,tx,amount` columns until a header is sent, which may add an
+ * `expected_version` column (a member of JSON objects), the account version the transaction is meant to apply to. A line longer
+ * than `MAX_LINE` bytes is answered by `error request longer than <MAX_LINE> bytes`, and the connection is closed.
+ */
+
+/// Columns of the CSV rows of a connection that sent no header
+const DEFAULT_COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+/// Longest request line accepted
+const MAX_LINE: usize = 8 * 1024;
+
+/// Accounts shared by the connections of a server, to which transactions are applied one at a time,
+/// in the order they are received, whatever the connection
+pub(crate) struct SharedClients {
+    clients: Mutex<HashMap<u16, Client>>,
+    /// Audit log of the applied transactions, appended while the accounts are locked, so that its rows are in the
+    /// order transactions are applied
+    audit: Option<Mutex<AuditLog>>,
+    /// Number of transactions received, numbering them in the audit log
+    received: AtomicU64,
+}
+
+/// What became of a transaction received by a server
+pub(crate) enum Reply {
+    Applied,
+    Rejected(Rejection),
+    /// The request is not a valid transaction
+    Malformed(String),
+    /// The transaction is applied or rejected, but could not be appended to the audit log
+    Unaudited(String),
+    /// The account is not at the version expected by the request, but at the given one. Nothing is applied
+    Conflict(u64),
+}
+
+/// Transaction received by a server, and the version its account is expected to be at, if any
+pub(crate) struct Request {
+    pub(crate) transaction: ClientTransaction,
+    pub(crate) expected_version: Option<u64>,
+}
+
+impl Request {
+    /// Version of the account of the client, if it is not the one expected by the request (an account not
+    /// opened yet being at version 0)
+    pub(crate) fn conflict(&self, clients: &HashMap<u16, Client>) -> Option<u64> {
+        let version = clients
+            .get(&self.transaction.id)
+            .map_or(0, |client| client.version);
+        self.expected_version
+            .filter(|expected| *expected != version)
+            .map(|_| version)
+    }
+}
+
+/// Columns of a request which are not part of its transaction
+#[derive(Deserialize)]
+struct Precondition {
+    expected_version: Option<u64>,
+}
+
+impl SharedClients {
+    /// Accounts shared by the connections, opening the audit log of the options, if any
+    pub(crate) fn new(
+        clients: HashMap<u16, Client>,
+        options: &ProcessingOptions,
+    ) -> Result<Self, Error> {
+        let audit = options
+            .audit_file
+            .as_deref()
+            .map(AuditLog::open)
+            .transpose()?;
+        Ok(Self {
+            clients: Mutex::new(clients),
+            audit: audit.map(Mutex::new),
+            received: AtomicU64::new(0),
+        })
+    }
+
+    /// Accounts, locked until the guard is dropped. A connection panicking while holding them cannot leave them
+    /// half updated, as transactions are checked before being applied
+    pub(crate) fn lock(&self) -> MutexGuard<'_, HashMap<u16, Client>> {
+        self.clients.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Apply the transaction read from a request to the account of its client, opened on its first transaction,
+    /// unless the account is not at the version expected by the request
+    pub(crate) fn apply(
+        &self,
+        request: Result<Request, Error>,
+        options: &ProcessingOptions,
+    ) -> Reply {
+        let request = match request {
+            Ok(request) => request,
+            Err(error) => return Reply::Malformed(malformation(error)),
+        };
+
+        let mut clients = self.lock();
+        // checked while the accounts are locked, so that no other transaction can be applied in between
+        if let Some(version) = request.conflict(&clients) {
+            return Reply::Conflict(version);
+        }
+        let mut transaction = request.transaction;
+        options.enrichment.merge_into(&mut transaction);
+        let seq = self.received.fetch_add(1, Ordering::Relaxed) + 1;
+        // transactions received by a server have no line
+        let outcome = outcomes::apply(&mut *clients, 0, transaction, &options.policy);
+        let audited = self.audit.as_ref().map_or(Ok(()), |audit| {
+            let mut audit = audit.lock().unwrap_or_else(PoisonError::into_inner);
+            audit
+                .record(seq, None, &outcome)
+                .and_then(|_| audit.flush())
+        });
+        match (outcome, audited) {
+            (_, Err(error)) => Reply::Unaudited(error.to_string()),
+            (Outcome::Applied { result, .. }, Ok(())) => match result {
+                Ok(_) => Reply::Applied,
+                Err(rejection) => Reply::Rejected(rejection),
+            },
+            (Outcome::Malformed { detail, .. }, Ok(())) => Reply::Malformed(detail),
+        }
+    }
+}
+
+/// Reader of the transactions sent one per line, as CSV rows or JSON objects, remembering the columns of the CSV
+/// rows given by the last header
+pub(crate) struct RequestReader {
+    columns: StringRecord,
+}
+
+impl Default for RequestReader {
+    fn default() -> Self {
+        Self {
+            columns: DEFAULT_COLUMNS.iter().collect(),
+        }
+    }
+}
+
+impl RequestReader {
+    /// Request of a line, `None` if the line is a header, setting the columns of the next rows
+    pub(crate) fn read(
+        &mut self,
+        line: &str,
+        amount_units: &AmountUnits,
+    ) -> Option<Result<Request, Error>> {
+        if line.starts_with('{') {
+            return Some(
+                json_lines::parse_object(line)
+                    .map_err(|reason| Error::InvalidJsonRecord(line.to_owned(), reason))
+                    .and_then(|(names, values)| parse_request(&values, &names, amount_units)),
+            );
+        }
+        match read_row(line) {
+            Ok(row) if row.get(0) == Some("type") => {
+                self.columns = row;
+                None
+            }
+            row => Some(row.and_then(|row| parse_request(&row, &self.columns, amount_units))),
+        }
+    }
+}
+
+/// Request of a CSV row or of the members of a JSON object
+fn parse_request(
+    record: &StringRecord,
+    headers: &StringRecord,
+    amount_units: &AmountUnits,
+) -> Result<Request, Error> {
+    let transaction = parse_record(record, headers, amount_units)?;
+    let precondition = record
+        .deserialize::<Precondition>(Some(headers))
+        .map_err(Error::Csv)?;
+    Ok(Request {
+        transaction,
+        expected_version: precondition.expected_version,
+    })
+}
+
+/// Accept connections on `listener` forever, each handled by a thread of its own. Failing connections are
+/// closed without stopping the server
+pub(crate) fn accept<F>(listener: TcpListener, handle: F) -> Result<(), Error>
+where
+    F: Fn(TcpStream) -> Result<(), Error> + Sync,
+{
+    accept_streams(listener.incoming(), handle)
+}
+
+/// Handle the connections of `streams`, each by a thread of its own, until there is none left. Connections
+/// failing to be accepted (e.g. aborted by the peer, or too many open files) are skipped
+pub(crate) fn accept_streams<I, F>(streams: I, handle: F) -> Result<(), Error>
+where
+    I: IntoIterator<Item = io::Result<TcpStream>>,
+    F: Fn(TcpStream) -> Result<(), Error> + Sync,
+{
+    std::thread::scope(|scope| {
+        for stream in streams {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    eprintln!("Connection not accepted: {error}");
+                    continue;
+                }
+            };
+            let handle = &handle;
+            scope.spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(error) = handle(stream) {
+                    eprintln!("Connection {peer:?} closed: {error}");
+                }
+            });
+        }
+        Ok(())
+    })
+}
+
+/// Serve the line protocol on `listener` forever, applying the transactions received to `clients`
+pub(crate) fn serve(
+    listener: TcpListener,
+    clients: HashMap<u16, Client>,
+    options: &ProcessingOptions,
+) -> Result<(), Error> {
+    let clients = SharedClients::new(clients, options)?;
+    accept(listener, |stream| {
+        serve_connection(stream, &clients, options)
+    })
+}
+
+/// Answer the requests of a connection until it is closed or it sends `quit`
+fn serve_connection(
+    stream: TcpStream,
+    clients: &SharedClients,
+    options: &ProcessingOptions,
+) -> Result<(), Error> {
+    let mut writer = BufWriter::new(stream.try_clone()?);
+    let mut requests = RequestReader::default();
+    let mut reader = BufReader::new(stream);
+    while let Some(line) = read_request(&mut reader)? {
+        let Some(line) = line else {
+            writeln!(writer, "error request longer than {MAX_LINE} bytes")?;
+            writer.flush()?;
+            break;
+        };
+        let request = line.trim();
+        let words = request.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] => continue,
+            [command] if command.eq_ignore_ascii_case("quit") => break,
+            [command] if command.eq_ignore_ascii_case("balances") => {
+                let mut accounts = clients
+                    .lock()
+                    .values()
+                    .map(Account::from)
+                    .collect::<Vec<_>>();
+                accounts.sort_by_key(|account| account.client);
+                writeln!(writer, "accounts {}", accounts.len())?;
+                for account in &accounts {
+                    writeln!(writer, "{}", account_row(account))?;
+                }
+            }
+            [command, client] if command.eq_ignore_ascii_case("balance") => {
+                let account = client
+                    .parse::<u16>()
+                    .ok()
+                    .and_then(|client| clients.lock().get(&client).map(Account::from));
+                match account {
+                    Some(account) => writeln!(writer, "account {}", account_row(&account))?,
+                    None => writeln!(writer, "error unknown client {client}")?,
+                }
+            }
+            [command, client, ..] if command.eq_ignore_ascii_case("annotate") => {
+                let note = request[command.len()..].trim_start()[client.len()..].trim();
+                let annotated = client.parse::<u16>().ok().and_then(|client| {
+                    clients
+                        .lock()
+                        .get_mut(&client)
+                        .map(|client| client.annotate(note))
+                });
+                match annotated {
+                    Some(Ok(())) => writeln!(writer, "ok")?,
+                    Some(Err(error)) => writeln!(writer, "error {error}")?,
+                    None => writeln!(writer, "error unknown client {client}")?,
+                }
+            }
+            _ => match requests.read(request, &options.amount_units) {
+                None => writeln!(writer, "ok")?,
+                Some(transaction) => match clients.apply(transaction, options) {
+                    Reply::Applied => writeln!(writer, "ok")?,
+                    Reply::Rejected(rejection) => {
+                        writeln!(writer, "rejected {}", rejection.code())?
+                    }
+                    Reply::Malformed(detail) => writeln!(writer, "error {detail}")?,
+                    Reply::Unaudited(detail) => writeln!(writer, "error not audited: {detail}")?,
+                    Reply::Conflict(version) => writeln!(writer, "error conflict {version}")?,
+                },
+            },
+        }
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Next request line without its line ending, `None` at the end of the connection, `Some(None)` if longer than
+/// [MAX_LINE], which is not read further
+fn read_request<R: BufRead>(reader: &mut R) -> Result<Option<Option<String>>, Error> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.len() > MAX_LINE && !line.ends_with(b"\n") {
+        return Ok(Some(None));
+    }
+    let line = String::from_utf8(line)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    Ok(Some(Some(line.trim_end_matches(['\r', '\n']).to_owned())))
+}
+
+/// Fields of a request read as a CSV row, trimmed like input files
+fn read_row(request: &str) -> Result<StringRecord, Error> {
+    let mut row = StringRecord::new();
+    ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(request.as_bytes())
+        .read_record(&mut row)?;
+    Ok(row)
+}
+
+/// Account as a row of the v1 output format
+fn account_row(account: &Account) -> String {
+    format!(
+        "{},{},{},{},{}",
+        account.client, account.available, account.held, account.total, account.locked
+    )
+}
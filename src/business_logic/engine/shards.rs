@@ -0,0 +1,171 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    path::Path,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread::{Scope, ScopedJoinHandle},
+};
+
+use crate::{
+    business_logic::{
+        domain::{Client, ClientTransaction},
+        engine::{
+            merge::{AccountMerger, DuplicateClients, MergeReport},
+            outcomes::{self, Outcome},
+            pipeline::{BATCH_SIZE, QUEUE_CAPACITY},
+        },
+        io::wal::SegmentWriter,
+        policy::EnginePolicy,
+    },
+    shared::errors::Error,
+};
+
+/// Transaction of input record `seq`, read at `line`
+type Job = (u64, u64, ClientTransaction);
+
+/// Transactions sent at once to a worker, with the sequence number of the last input record dispatched before
+/// them: every transaction of the worker up to it is in the batch or in a previous one
+type Batch = (u64, Vec<Job>);
+
+/// Worker threads applying transactions, each owning the clients whose ID modulo the number of workers
+/// is its index. Transactions of a client are always applied by the same worker, in input order.
+/// Workers send the outcomes of every batch to the collector, in the order they are applied, after appending them
+/// to their segment of the write-ahead log, if any
+pub(crate) struct ShardPool<'scope> {
+    jobs: Vec<SyncSender<Batch>>,
+    /// Transactions of every worker not sent yet
+    batches: Vec<Vec<Job>>,
+    /// Number of transactions submitted since the last dispatch
+    pending: usize,
+    /// Sequence number of the last transaction submitted
+    watermark: u64,
+    workers: Vec<ScopedJoinHandle<'scope, Result<HashMap<u16, Client>, Error>>>,
+}
+
+impl<'scope> ShardPool<'scope> {
+    /// Start `threads` workers in `scope`, splitting the initial clients among them. With `wal_dir`, every worker
+    /// writes its segment of the write-ahead log there
+    pub(crate) fn start<'env>(
+        scope: &'scope Scope<'scope, 'env>,
+        threads: NonZeroUsize,
+        clients: HashMap<u16, Client>,
+        policy: &'env EnginePolicy,
+        outcomes: &SyncSender<Vec<(u64, Outcome)>>,
+        wal_dir: Option<&Path>,
+    ) -> Result<Self, Error> {
+        let threads = threads.get();
+        let mut shards = (0..threads).map(|_| HashMap::new()).collect::<Vec<_>>();
+        for (id, client) in clients {
+            shards[id as usize % threads].insert(id, client);
+        }
+        let segments = (0..threads)
+            .map(|shard| {
+                wal_dir
+                    .map(|wal_dir| SegmentWriter::create(wal_dir, shard, threads))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let (jobs, workers) = shards
+            .into_iter()
+            .zip(segments)
+            .map(|(shard, segment)| {
+                let (jobs_sender, jobs) = mpsc::sync_channel(QUEUE_CAPACITY);
+                let outcomes = outcomes.clone();
+                let worker = scope.spawn(move || work(shard, jobs, outcomes, policy, segment));
+                (jobs_sender, worker)
+            })
+            .unzip();
+
+        Ok(Self {
+            jobs,
+            batches: (0..threads).map(|_| Vec::new()).collect(),
+            pending: 0,
+            watermark: 0,
+            workers,
+        })
+    }
+
+    /// Queue a transaction to the worker owning its client. Returns `false` if the workers stopped,
+    /// as the collector of their outcomes failed, so that nothing more can be applied
+    pub(crate) fn submit(&mut self, seq: u64, line: u64, transaction: ClientTransaction) -> bool {
+        let shard = transaction.id as usize % self.jobs.len();
+        self.batches[shard].push((seq, line, transaction));
+        self.pending += 1;
+        self.watermark = seq;
+        if self.pending < BATCH_SIZE {
+            return true;
+        }
+        self.pending = 0;
+        // idle workers get an empty batch too, so that their segment of the write-ahead log keeps up
+        self.jobs
+            .iter()
+            .zip(&mut self.batches)
+            .all(|(jobs, batch)| jobs.send((self.watermark, std::mem::take(batch))).is_ok())
+    }
+
+    /// Wait for every queued transaction to be applied, and merge the clients of all the workers, a client found
+    /// in more than one shard being treated as told by `duplicates`. Fails on the first failure to write the
+    /// write-ahead log
+    pub(crate) fn finish(
+        self,
+        duplicates: DuplicateClients,
+    ) -> Result<(HashMap<u16, Client>, MergeReport), Error> {
+        for (jobs, batch) in self.jobs.iter().zip(self.batches) {
+            let _ = jobs.send((self.watermark, batch));
+        }
+        // workers stop once their queue is closed and empty
+        drop(self.jobs);
+
+        let mut merger = AccountMerger::new(duplicates);
+        for (index, worker) in self.workers.into_iter().enumerate() {
+            let shard = worker
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+            merger.add_all(shard, &format!("shard {index}"))?;
+        }
+        Ok(merger.finish())
+    }
+}
+
+/// Apply every transaction received to the clients of the shard, logging them to the segment of the write-ahead log,
+/// if any, and sending back the outcomes
+fn work(
+    mut clients: HashMap<u16, Client>,
+    jobs: Receiver<Batch>,
+    outcomes: SyncSender<Vec<(u64, Outcome)>>,
+    policy: &EnginePolicy,
+    mut segment: Option<SegmentWriter>,
+) -> Result<HashMap<u16, Client>, Error> {
+    for (watermark, batch) in jobs {
+        let batch_outcomes = batch
+            .into_iter()
+            .map(|(seq, line, transaction)| {
+                let outcome = outcomes::apply(&mut clients, line, transaction, policy);
+                if let (
+                    Some(segment),
+                    Outcome::Applied {
+                        transaction,
+                        result,
+                        ..
+                    },
+                ) = (&mut segment, &outcome)
+                {
+                    segment.append(seq, transaction.id, result)?;
+                }
+                Ok((seq, outcome))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        if let Some(segment) = &mut segment {
+            segment.commit(watermark)?;
+        }
+        if batch_outcomes.is_empty() {
+            continue;
+        }
+        // the collector stopped, on a failure or as processing was aborted: the rest of the work is useless
+        if outcomes.send(batch_outcomes).is_err() {
+            break;
+        }
+    }
+    Ok(clients)
+}
@@ -0,0 +1,237 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    mem::size_of,
+    path::PathBuf,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{
+    business_logic::{
+        domain::{events::Event, Client, ClientTransaction, DisputeStatus, Transaction},
+        engine::outcomes::{self, Outcome},
+        io::snapshot,
+        policy::EnginePolicy,
+    },
+    shared::errors::Error,
+};
+
+/// Estimated memory taken by a transaction kept in the history: its entry in the history map, spare room of the
+/// map included, and its ID in the history order. Strings held by the transaction (channel, dispute details)
+/// are not counted
+const RESIDENT_SIZE: usize = size_of::<(u32, Transaction)>() * 8 / 7 + 2 * size_of::<u32>();
+
+/// Spill files created by the process, numbering the next one
+static SPILL_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Memory the transactions history may take while the input is processed, in bytes, given as a number of bytes
+/// with an optional `K`, `M` or `G` suffix (powers of 1024)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLimit {
+    pub bytes: u64,
+}
+
+impl FromStr for MemoryLimit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, unit) = match s.trim().to_ascii_uppercase() {
+            size if size.ends_with('K') => (size[..size.len() - 1].to_owned(), 1 << 10),
+            size if size.ends_with('M') => (size[..size.len() - 1].to_owned(), 1 << 20),
+            size if size.ends_with('G') => (size[..size.len() - 1].to_owned(), 1 << 30),
+            size => (size, 1),
+        };
+        digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|count| count.checked_mul(unit))
+            .map(|bytes| Self { bytes })
+            .ok_or_else(|| Error::InvalidMemoryLimit(s.to_owned()))
+    }
+}
+
+/*
+ * Spill file layout: transactions of the history evicted from memory, appended one after the other in the
+ * snapshot layout of a transaction (see `io/snapshot.rs`), without ID. A transaction reloaded and evicted again
+ * is appended again: the index in memory tells where the last copy of every spilled transaction starts and how
+ * long it is, older copies are never read.
+ */
+
+/// Transactions history of the clients kept under a memory limit while the input is processed: once the history
+/// holds more transactions than the limit allows, the oldest ones not under dispute are moved to a temporary spill
+/// file, and moved back into the history of their client as soon as a transaction refers to their ID again (a
+/// dispute, resolve or chargeback, or a deposit, withdrawal or transfer reusing it). Transactions under dispute are never
+/// spilled, so the limit is exceeded if they alone take more memory. The spill file is removed when dropped
+pub(crate) struct HistorySpill {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// Length of the spill file
+    end: u64,
+    /// Number of transactions the history may hold
+    capacity: usize,
+    /// Transactions in the history that may be spilled, by client ID, from the oldest to the newest
+    resident: VecDeque<(u16, u32)>,
+    /// Offset and length in the spill file of the transactions spilled, by client ID and transaction ID
+    spilled: HashMap<(u16, u32), (u64, usize)>,
+}
+
+impl HistorySpill {
+    /// Create the spill file in the temporary directory, spilling right away the transactions history of the
+    /// initial state exceeding the limit
+    pub(crate) fn create(
+        limit: MemoryLimit,
+        clients: &mut HashMap<u16, Client>,
+    ) -> Result<Self, Error> {
+        let path = std::env::temp_dir().join(format!(
+            "transactions-simulator-{}-{}.spill",
+            std::process::id(),
+            SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let mut spill = Self {
+            path,
+            writer: BufWriter::new(file),
+            end: 0,
+            capacity: usize::try_from(limit.bytes / RESIDENT_SIZE as u64).unwrap_or(usize::MAX),
+            resident: clients
+                .values()
+                .flat_map(|client| client.history_order.iter().map(|tx| (client.id, *tx)))
+                .collect(),
+            spilled: HashMap::new(),
+        };
+        spill.evict(clients)?;
+        Ok(spill)
+    }
+
+    /// Apply a transaction to its client, like [outcomes::apply], once the transaction of the history it refers to
+    /// is reloaded if spilled, then spill the oldest transactions exceeding the limit
+    pub(crate) fn apply(
+        &mut self,
+        clients: &mut HashMap<u16, Client>,
+        line: u64,
+        transaction: ClientTransaction,
+        policy: &EnginePolicy,
+    ) -> Result<Outcome, Error> {
+        if let Some(client) = clients.get_mut(&transaction.id) {
+            self.reload(client, transaction.tx)?;
+        }
+        // the leg of the destination of a transfer is checked too
+        let destination = outcomes::counterparty(clients, &transaction);
+        if let Some(client) = destination.and_then(|destination| clients.get_mut(&destination)) {
+            self.reload(client, transaction.tx)?;
+        }
+        let outcome = outcomes::apply(clients, line, transaction, policy);
+        if let Outcome::Applied {
+            transaction,
+            result: Ok(events),
+            counterparty,
+            ..
+        } = &outcome
+        {
+            let legs = std::iter::once((transaction.id, events)).chain(
+                counterparty
+                    .iter()
+                    .map(|other| (other.client, &other.events)),
+            );
+            for (client, events) in legs {
+                if events.iter().any(|event| {
+                    matches!(
+                        event,
+                        Event::Deposited { .. }
+                            | Event::Withdrawn { .. }
+                            | Event::TransferredOut { .. }
+                            | Event::TransferredIn { .. }
+                    )
+                }) {
+                    self.resident.push_back((client, transaction.tx));
+                }
+            }
+        }
+        self.evict(clients)?;
+        Ok(outcome)
+    }
+
+    /// Move every spilled transaction back into the history of its client, for the full state to be saved
+    pub(crate) fn restore(mut self, clients: &mut HashMap<u16, Client>) -> Result<(), Error> {
+        let mut spilled = std::mem::take(&mut self.spilled)
+            .into_iter()
+            .collect::<Vec<_>>();
+        // read the spill file from start to end
+        spilled.sort_by_key(|(_, (offset, _))| *offset);
+        for ((client, tx), location) in spilled {
+            let transaction = self.read(location, tx)?;
+            if let Some(client) = clients.get_mut(&client) {
+                client.transations_history.insert(tx, transaction);
+            }
+        }
+        Ok(())
+    }
+
+    /// Move transaction `tx` of the client back into its history, if spilled
+    fn reload(&mut self, client: &mut Client, tx: u32) -> Result<(), Error> {
+        let Some(location) = self.spilled.remove(&(client.id, tx)) else {
+            return Ok(());
+        };
+        let transaction = self.read(location, tx)?;
+        client.transations_history.insert(tx, transaction);
+        self.resident.push_back((client.id, tx));
+        Ok(())
+    }
+
+    /// Spill the oldest transactions not under dispute, until the history holds no more than the limit allows.
+    /// Transactions under dispute are moved behind the newest ones, to be spilled once the dispute is over
+    fn evict(&mut self, clients: &mut HashMap<u16, Client>) -> Result<(), Error> {
+        let mut kept = 0;
+        while self.resident.len() > self.capacity && kept < self.resident.len() {
+            let Some((client_id, tx)) = self.resident.pop_front() else {
+                break;
+            };
+            let Some(client) = clients.get_mut(&client_id) else {
+                continue;
+            };
+            match client.transations_history.remove(&tx) {
+                Some(transaction) if transaction.dispute_status == DisputeStatus::Disputed => {
+                    client.transations_history.insert(tx, transaction);
+                    self.resident.push_back((client_id, tx));
+                    kept += 1;
+                }
+                Some(transaction) => self.write(client_id, tx, &transaction)?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Append transaction `tx` of the client to the spill file
+    fn write(&mut self, client: u16, tx: u32, transaction: &Transaction) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        snapshot::encode_transaction(transaction, &mut bytes)?;
+        self.writer.write_all(&bytes)?;
+        self.spilled.insert((client, tx), (self.end, bytes.len()));
+        self.end += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Read transaction `tx` at `location` of the spill file, going back to its end for the next writes
+    fn read(&mut self, (offset, length): (u64, usize), tx: u32) -> Result<Transaction, Error> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = vec![0; length];
+        file.read_exact(&mut bytes)?;
+        file.seek(SeekFrom::End(0))?;
+        snapshot::decode_transaction(&mut bytes.as_slice(), snapshot::VERSION, tx)
+    }
+}
+
+impl Drop for HistorySpill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
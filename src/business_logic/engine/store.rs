@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use crate::{
+    business_logic::{domain::Client, io::snapshot},
+    shared::{
+        errors::Error,
+        memory::{self, Subsystem},
+    },
+};
+
+/// Storage of the client accounts transactions are applied to. Accounts are only reached through closures, so that
+/// a store keeping them out of memory (e.g. in an embedded database) can load an account before the closure runs
+/// and save it back after an update, while the in-memory store, a `HashMap` by client ID, lends them in place.
+///
+/// Stores keeping accounts as bytes can encode them with [Client::to_bytes] and decode them with [Client::from_bytes]
+pub trait ClientStore {
+    /// Run `read` on the account of a client, `None` if the client has no account
+    fn get<T>(&self, client: u16, read: impl FnOnce(&Client) -> T) -> Option<T>;
+
+    /// Run `update` on the account of a client, opened empty if missing, and keep the changes it made
+    fn update<T>(&mut self, client: u16, update: impl FnOnce(&mut Client) -> T) -> T;
+
+    /// Run `visit` on every account, in no particular order
+    fn iterate(&self, visit: impl FnMut(&Client));
+}
+
+impl ClientStore for HashMap<u16, Client> {
+    fn get<T>(&self, client: u16, read: impl FnOnce(&Client) -> T) -> Option<T> {
+        HashMap::get(self, &client).map(read)
+    }
+
+    fn update<T>(&mut self, client: u16, update: impl FnOnce(&mut Client) -> T) -> T {
+        let account = memory::track(Subsystem::ClientMap, || {
+            self.entry(client).or_insert_with(|| Client::new(client))
+        });
+        memory::track(Subsystem::Histories, || update(account))
+    }
+
+    fn iterate(&self, visit: impl FnMut(&Client)) {
+        self.values().for_each(visit)
+    }
+}
+
+impl Client {
+    /// Account encoded as in snapshots, transactions history included
+    pub fn to_bytes(&self) -> Vec<u8> {
+        snapshot::encode_account(self)
+    }
+
+    /// Account encoded by [Client::to_bytes]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        snapshot::decode_account(bytes)
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use crate::{
+    business_logic::{
+        domain::events::Event,
+        domain::{Client, ClientTransaction, Rejection, Type},
+        engine::{
+            observer::{Observers, TransactionObserver},
+            outcomes,
+            store::ClientStore,
+            tx_index::TransactionRef,
+        },
+        policy::EnginePolicy,
+    },
+    shared::{decimal::Decimal, errors::Error},
+};
+
+/// Payments engine embeddable by other programs: transactions are applied, in the order they are submitted,
+/// to the accounts of their clients, following the same rules as the CLI. Accounts are kept in memory, unless
+/// the engine is given another [ClientStore]
+#[derive(Debug)]
+pub struct TransactionEngine<S = HashMap<u16, Client>> {
+    clients: S,
+    policy: EnginePolicy,
+    /// Clients owning every deposit and withdrawal applied, by transaction ID
+    owners: HashMap<u32, Vec<u16>>,
+    observers: Observers,
+}
+
+impl Default for TransactionEngine {
+    fn default() -> Self {
+        Self::new(EnginePolicy::default())
+    }
+}
+
+/// Balances of a client account, as written by the v1 output format
+#[derive(Debug, Clone, PartialEq)]
+pub struct Account {
+    /// Client ID
+    pub client: u16,
+    /// Available funds = total - held
+    pub available: Decimal,
+    /// Held funds = total - available
+    pub held: Decimal,
+    /// Total funds = available + held
+    pub total: Decimal,
+    /// Whether the account is locked by a chargeback
+    pub locked: bool,
+}
+
+impl From<&Client> for Account {
+    fn from(client: &Client) -> Self {
+        Self {
+            client: client.id,
+            available: client.available.value(),
+            held: client.held.value(),
+            total: client.total.value(),
+            locked: client.locked,
+        }
+    }
+}
+
+impl ClientTransaction {
+    /// Transaction of the given type for a client. `amount` is needed only by deposits, withdrawals and adjustments
+    pub fn new(transaction_type: Type, client: u16, tx: u32, amount: Option<Decimal>) -> Self {
+        Self {
+            id: client,
+            transaction_type,
+            tx,
+            amount,
+            reason: None,
+            evidence: None,
+            to_client: None,
+            attributes: Default::default(),
+        }
+    }
+
+    /// Set the reason code, mandatory for adjustments and optional for disputes
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// Set the reference of the evidence supporting a dispute
+    pub fn with_evidence(mut self, evidence: impl Into<String>) -> Self {
+        self.evidence = Some(evidence.into());
+        self
+    }
+
+    /// Set the client credited by a transfer
+    pub fn with_destination(mut self, client: u16) -> Self {
+        self.to_client = Some(client);
+        self
+    }
+}
+
+impl TransactionEngine {
+    /// Engine applying the given business rules
+    pub fn new(policy: EnginePolicy) -> Self {
+        Self::with_store(HashMap::new(), policy)
+    }
+}
+
+impl<S: ClientStore> TransactionEngine<S> {
+    /// Engine applying the given business rules to the accounts of `store`
+    pub fn with_store(store: S, policy: EnginePolicy) -> Self {
+        Self {
+            clients: store,
+            policy,
+            owners: HashMap::new(),
+            observers: Observers::default(),
+        }
+    }
+
+    /// Attach an observer, told the outcome of every transaction applied from now on (see [TransactionObserver])
+    pub fn observe(&mut self, observer: impl TransactionObserver + 'static) {
+        self.observers.attach(Box::new(observer));
+    }
+
+    /// Apply a transaction to the account of its client, opened on its first transaction.
+    /// Returns why the transaction was ignored, leaving the account untouched, if it was not applied.
+    pub fn apply(&mut self, transaction: ClientTransaction) -> Result<(), Rejection> {
+        let (events, counterparty) =
+            match outcomes::apply_transaction(&mut self.clients, &transaction, &self.policy) {
+                Ok(legs) => legs,
+                Err(rejection) => {
+                    self.observers.rejected(&transaction, rejection);
+                    return Err(rejection);
+                }
+            };
+        let legs = std::iter::once((transaction.id, events)).chain(counterparty);
+        for (client, events) in legs {
+            let locked = events.contains(&Event::Locked);
+            for event in events {
+                if let Event::Deposited { tx, .. }
+                | Event::Withdrawn { tx, .. }
+                | Event::TransferredOut { tx, .. }
+                | Event::TransferredIn { tx, .. } = event
+                {
+                    self.owners.entry(tx).or_default().push(client);
+                }
+            }
+            if self.observers.is_empty() {
+                continue;
+            }
+            if let Some(account) = self.clients.get(client, |client| Account::from(client)) {
+                self.observers.applied(&transaction, &account);
+                if locked {
+                    self.observers.locked(&transaction, &account);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Clients owning transaction `tx`, sorted by client ID, with its current dispute state, found through an index
+    /// kept up to date as transactions are applied. Transaction IDs are only unique within the history of a
+    /// client, so an ID may be owned by several clients
+    pub fn transaction(&self, tx: u32) -> Vec<TransactionRef> {
+        let mut owners = self.owners.get(&tx).cloned().unwrap_or_default();
+        owners.sort_unstable();
+        owners
+            .into_iter()
+            .filter_map(|client| {
+                self.clients
+                    .get(client, |client| TransactionRef::find(client, tx))
+                    .flatten()
+            })
+            .collect()
+    }
+
+    /// Dry run of a transaction: the balances the account of its client would have if the transaction were
+    /// applied now, or why it would be ignored. Neither the account nor the engine are modified,
+    /// so that submissions can be checked beforehand.
+    pub fn validate(&self, transaction: &ClientTransaction) -> Result<Account, Rejection> {
+        validate(&self.clients, transaction, &self.policy)
+    }
+
+    /// Erase the personal data of a client (transactions history and dispute details), keeping its account
+    /// as a tombstone with the same balances, so that totals do not change. Later transactions of the client
+    /// are rejected. Fails if the client is unknown or has funds held by pending disputes.
+    pub fn erase_client(&mut self, client: u16) -> Result<(), Error> {
+        if self.clients.get(client, |_| ()).is_none() {
+            return Err(Error::ErasureRefused(client, "unknown client".to_owned()));
+        }
+        self.clients.update(client, Client::erase)
+    }
+
+    /// Attach an operator note to the account of a client, carried in snapshots and shown in statements and
+    /// queries. Fails if the client is unknown or the note is empty
+    pub fn annotate_client(&mut self, client: u16, note: &str) -> Result<(), Error> {
+        if self.clients.get(client, |_| ()).is_none() {
+            return Err(Error::AnnotationRefused(
+                client,
+                "unknown client".to_owned(),
+            ));
+        }
+        self.clients.update(client, |client| client.annotate(note))
+    }
+
+    /// Current balances of every account, sorted by client ID
+    pub fn accounts(&self) -> Vec<Account> {
+        let mut accounts = Vec::new();
+        self.clients
+            .iterate(|client| accounts.push(Account::from(client)));
+        accounts.sort_by_key(|account| account.client);
+        accounts
+    }
+
+    /// Stop the engine, returning the final balances of every account, sorted by client ID
+    pub fn finalize(self) -> Vec<Account> {
+        self.accounts()
+    }
+
+    /// Stop the engine, returning the store of the accounts
+    pub fn into_store(self) -> S {
+        self.clients
+    }
+}
+
+/// Dry run of a transaction on the accounts of `clients`, modifying none of them (see [TransactionEngine::validate])
+pub(crate) fn validate<S: ClientStore>(
+    clients: &S,
+    transaction: &ClientTransaction,
+    policy: &EnginePolicy,
+) -> Result<Account, Rejection> {
+    if outcomes::is_transfer(clients, transaction) {
+        return validate_transfer(clients, transaction, policy);
+    }
+    inspect(clients, transaction.id, |client| {
+        client
+            .preview_transaction(transaction, policy)
+            .map(|preview| Account::from(&preview))
+    })
+}
+
+/// Dry run of a transaction moving funds between two clients, checking the leg of the destination too
+fn validate_transfer<S: ClientStore>(
+    clients: &S,
+    transaction: &ClientTransaction,
+    policy: &EnginePolicy,
+) -> Result<Account, Rejection> {
+    let source = transaction.id;
+    let plan = inspect(clients, source, |client| {
+        client.plan_transfer(transaction, policy)
+    })?;
+    inspect(clients, plan.destination, |client| {
+        client.receive_transfer(source, &plan, policy)
+    })?;
+    inspect(clients, source, |client| {
+        client
+            .preview_events(&plan.source_events)
+            .map(|preview| Account::from(&preview))
+    })
+}
+
+/// Result of `f` on the account of a client, or on a new account if the client is unknown
+fn inspect<S: ClientStore, T>(clients: &S, client: u16, f: impl Fn(&Client) -> T) -> T {
+    clients
+        .get(client, &f)
+        .unwrap_or_else(|| f(&Client::new(client)))
+}
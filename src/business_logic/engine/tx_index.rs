@@ -0,0 +1,118 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    business_logic::domain::{Client, DisputeStatus},
+    shared::decimal::Decimal,
+};
+
+/// Dispute state of a transaction of the history of a client
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisputeState {
+    /// Not under dispute
+    Undisputed,
+    /// Under dispute, its amount being held
+    Disputed,
+    /// Dispute resolved, its amount released: it can no longer be disputed
+    Resolved,
+    /// Dispute charged back, its amount removed: it can no longer be disputed
+    ChargedBack,
+    /// Removed from the history by the retention policy, so that it can no longer be disputed
+    Pruned,
+}
+
+impl Display for DisputeState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match self {
+            DisputeState::Undisputed => "undisputed",
+            DisputeState::Disputed => "disputed",
+            DisputeState::Resolved => "resolved",
+            DisputeState::ChargedBack => "charged_back",
+            DisputeState::Pruned => "pruned",
+        };
+        write!(f, "{state}")
+    }
+}
+
+/// Deposit or withdrawal found in the history of a client
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionRef {
+    /// Transaction ID
+    pub tx: u32,
+    /// Client owning the transaction
+    pub client: u16,
+    /// Amount of the transaction, unknown once pruned
+    pub amount: Option<Decimal>,
+    /// Current dispute state
+    pub state: DisputeState,
+}
+
+impl TransactionRef {
+    /// Transaction `tx` of the history of `client`, pruned ones included, `None` if the client has no such
+    /// transaction
+    pub(crate) fn find(client: &Client, tx: u32) -> Option<Self> {
+        let (amount, state) = match client.transations_history.get(&tx) {
+            Some(transaction) => (
+                Some(transaction.amount.value()),
+                match transaction.dispute_status {
+                    DisputeStatus::Undisputed => DisputeState::Undisputed,
+                    DisputeStatus::Disputed => DisputeState::Disputed,
+                    DisputeStatus::Resolved => DisputeState::Resolved,
+                    DisputeStatus::ChargedBack => DisputeState::ChargedBack,
+                },
+            ),
+            None if client.pruned_transactions.contains(&tx) => (None, DisputeState::Pruned),
+            None => return None,
+        };
+        Some(Self {
+            tx,
+            client: client.id,
+            amount,
+            state,
+        })
+    }
+}
+
+/// Reverse index from transaction ID to the clients owning it, built once from the accounts, so that the owner
+/// and the dispute state of a transaction are found without going through every client. Transaction IDs are only
+/// unique within the history of a client, so an ID may be owned by several clients
+#[derive(Debug, Default)]
+pub struct TransactionIndex {
+    transactions: HashMap<u32, Vec<TransactionRef>>,
+}
+
+impl TransactionIndex {
+    /// Index of the transactions of every client, pruned ones included
+    pub(crate) fn new<'a>(clients: impl Iterator<Item = &'a Client>) -> Self {
+        let mut clients = clients.collect::<Vec<_>>();
+        clients.sort_by_key(|client| client.id);
+
+        let mut transactions = HashMap::<u32, Vec<TransactionRef>>::new();
+        for client in clients {
+            let txs = client
+                .transations_history
+                .keys()
+                .chain(&client.pruned_transactions);
+            for tx in txs {
+                if let Some(reference) = TransactionRef::find(client, *tx) {
+                    transactions.entry(*tx).or_default().push(reference);
+                }
+            }
+        }
+        Self { transactions }
+    }
+
+    /// Clients owning transaction `tx`, sorted by client ID, none if the transaction is unknown
+    pub fn get(&self, tx: u32) -> &[TransactionRef] {
+        self.transactions.get(&tx).map_or(&[], Vec::as_slice)
+    }
+
+    /// Number of transaction IDs indexed
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    /// Whether no transaction is indexed
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+}
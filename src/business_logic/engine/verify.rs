@@ -0,0 +1,109 @@
+use std::{collections::HashMap, fmt::Display, io::Write};
+
+use crate::{
+    business_logic::domain::Client,
+    shared::{decimal::Decimal, errors::Error},
+};
+
+/// Single difference between expected and actual output
+#[derive(Debug, PartialEq)]
+enum Mismatch {
+    /// Client present in expected output but not in actual output
+    Missing(u16),
+    /// Client present in actual output but not in expected output
+    Unexpected(u16),
+    /// Client present in both outputs with a different value for the given field
+    Field {
+        client: u16,
+        field: &'static str,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Missing(client) => write!(f, "client {client}: missing from output"),
+            Mismatch::Unexpected(client) => write!(f, "client {client}: not expected in output"),
+            Mismatch::Field {
+                client,
+                field,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "client {client}: {field} expected {expected}, got {actual}"
+            ),
+        }
+    }
+}
+
+/// Compare two states of the same client account field by field
+fn compare_client(expected: &Client, actual: &Client, tolerance: Decimal) -> Vec<Mismatch> {
+    let amount_mismatch = |field, expected_amount: Decimal, actual_amount: Decimal| {
+        // a difference out of range exceeds any tolerance
+        let difference = expected_amount.checked_sub(actual_amount);
+        difference
+            .is_none_or(|difference| difference.abs() > tolerance)
+            .then(|| Mismatch::Field {
+                client: expected.id,
+                field,
+                expected: expected_amount.to_string(),
+                actual: actual_amount.to_string(),
+            })
+    };
+
+    [
+        amount_mismatch(
+            "available",
+            expected.available.value(),
+            actual.available.value(),
+        ),
+        amount_mismatch("held", expected.held.value(), actual.held.value()),
+        amount_mismatch("total", expected.total.value(), actual.total.value()),
+        (expected.locked != actual.locked).then(|| Mismatch::Field {
+            client: expected.id,
+            field: "locked",
+            expected: expected.locked.to_string(),
+            actual: actual.locked.to_string(),
+        }),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Write on `writer` every difference between expected and actual accounts, sorted by client ID.
+/// Returns an error if at least one difference is found.
+pub(crate) fn report_mismatches<W>(
+    expected: &HashMap<u16, Client>,
+    actual: &HashMap<u16, Client>,
+    tolerance: Decimal,
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut clients = expected.keys().chain(actual.keys()).collect::<Vec<_>>();
+    clients.sort();
+    clients.dedup();
+
+    let mismatches = clients
+        .into_iter()
+        .flat_map(|id| match (expected.get(id), actual.get(id)) {
+            (Some(expected), Some(actual)) => compare_client(expected, actual, tolerance),
+            (Some(_), None) => vec![Mismatch::Missing(*id)],
+            (None, _) => vec![Mismatch::Unexpected(*id)],
+        })
+        .collect::<Vec<_>>();
+
+    mismatches
+        .iter()
+        .try_for_each(|mismatch| writeln!(writer, "{mismatch}"))?;
+
+    match mismatches.len() {
+        0 => Ok(()),
+        count => Err(Error::VerificationFailed(count)),
+    }
+}
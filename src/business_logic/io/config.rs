@@ -0,0 +1,336 @@
+use std::{fmt::Write as _, io::Write, path::Path};
+
+use crate::{
+    business_logic::{
+        engine::{merge::DuplicateClients, ProcessingOptions},
+        io::{
+            input::{AmountUnits, CurrencyExponents, InputFormat},
+            output::{OutputFormat, SortKey},
+        },
+        policy::{DisputeShortfall, EnginePolicy, NegativeAdjustments, WithdrawalDisputeMode},
+    },
+    shared::{errors::Error, sha256},
+};
+
+/// Prefix of the keys of the channel rules among the policy settings
+pub(crate) const CHANNEL_RULES: &str = "channel_rules.";
+
+/// Write the effective processing configuration as TOML, followed by a comment with its SHA-256,
+/// so that runs can be audited and reproduced. Unset optional values are omitted, as TOML has no null.
+pub fn write_config<W>(options: &ProcessingOptions, mut writer: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    write!(writer, "{}", render(options))?;
+    writeln!(
+        writer,
+        "# sha256 = {}",
+        sha256::to_hex(&config_digest(options))
+    )?;
+
+    Ok(())
+}
+
+/// SHA-256 of the effective configuration, as printed by [write_config]
+pub(crate) fn config_digest(options: &ProcessingOptions) -> sha256::Digest {
+    let mut hasher = sha256::Sha256::default();
+    hasher.update(render(options).as_bytes());
+    hasher.finalize()
+}
+
+fn render(options: &ProcessingOptions) -> String {
+    // writing on a String never fails
+    let mut toml = String::new();
+    let _ = write_sections(options, &mut toml);
+    toml
+}
+
+fn write_sections(options: &ProcessingOptions, toml: &mut String) -> std::fmt::Result {
+    let (input_format, iso8583_exponents) = match &options.input_format {
+        InputFormat::Csv => ("csv", None),
+        InputFormat::Iso8583(exponents) => ("iso8583", Some(exponents)),
+        InputFormat::JsonLines => ("json", None),
+        InputFormat::Xlsx => ("xlsx", None),
+    };
+    let (amount_units, minor_exponents) = match &options.amount_units {
+        AmountUnits::Decimal => ("decimal", None),
+        AmountUnits::Minor(exponents) => ("minor", Some(exponents)),
+    };
+    writeln!(toml, "[input]")?;
+    writeln!(toml, "format = {}", string(input_format))?;
+    writeln!(toml, "amount_units = {}", string(amount_units))?;
+    writeln!(toml, "strict = {}", options.strict)?;
+    writeln!(
+        toml,
+        "enrichment_files = {}",
+        array(options.enrichment.files().iter().map(|file| path(file)))
+    )?;
+    if let Some(seed_file) = &options.seed_file {
+        writeln!(toml, "resume_from_output = {}", path(seed_file))?;
+    }
+    if let Some(restore_file) = &options.restore_file {
+        writeln!(toml, "restore = {}", path(restore_file))?;
+    }
+    if let Some(resume_file) = &options.resume_file {
+        writeln!(toml, "resume = {}", path(resume_file))?;
+    }
+    if let Some(exponents) = iso8583_exponents.or(minor_exponents) {
+        write_exponents(exponents, toml)?;
+    }
+
+    writeln!(toml)?;
+    writeln!(toml, "[processing]")?;
+    writeln!(
+        toml,
+        "threads = {}",
+        options.threads.map_or(1, |threads| threads.get())
+    )?;
+    if let Some(read_ahead) = options.read_ahead {
+        writeln!(toml, "read_ahead = {read_ahead}")?;
+    }
+    if let Some(wal_dir) = &options.wal_dir {
+        writeln!(toml, "wal = {}", path(wal_dir))?;
+    }
+    let duplicate_clients = match options.duplicate_clients {
+        DuplicateClients::Error => "error",
+        DuplicateClients::Merge => "merge",
+        DuplicateClients::PreferLatest => "prefer-latest",
+    };
+    writeln!(toml, "duplicate_clients = {}", string(duplicate_clients))?;
+    writeln!(
+        toml,
+        "route_disputes_by_tx = {}",
+        options.route_disputes_by_tx
+    )?;
+    if let Some(window) = options.aggregate_deposits {
+        writeln!(toml, "aggregate_deposits = {window}")?;
+    }
+    if let Some(max_memory) = options.max_memory {
+        writeln!(toml, "max_memory = {}", max_memory.bytes)?;
+    }
+
+    writeln!(toml)?;
+    writeln!(toml, "[policy]")?;
+    let settings = policy_settings(&options.policy);
+    let (channel_rules, settings): (Vec<_>, Vec<_>) = settings
+        .iter()
+        .partition(|(key, _)| key.starts_with(CHANNEL_RULES));
+    for (key, value) in settings {
+        writeln!(toml, "{key} = {value}")?;
+    }
+    writeln!(toml)?;
+    writeln!(toml, "[policy.channel_rules]")?;
+    for (key, value) in channel_rules {
+        writeln!(toml, "{} = {value}", &key[CHANNEL_RULES.len()..])?;
+    }
+
+    writeln!(toml)?;
+    writeln!(toml, "[risk_weights]")?;
+    writeln!(toml, "chargeback = {:?}", options.risk_weights.chargeback)?;
+    writeln!(
+        toml,
+        "open_dispute = {:?}",
+        options.risk_weights.open_dispute
+    )?;
+
+    writeln!(toml)?;
+    writeln!(toml, "[output]")?;
+    let output_format = match options.output_format {
+        OutputFormat::V1 => "v1",
+        OutputFormat::Extended => "extended",
+    };
+    writeln!(toml, "format = {}", string(output_format))?;
+    let sort_by = match options.sort_by {
+        SortKey::Client => "client",
+        SortKey::Available => "available",
+        SortKey::Held => "held",
+        SortKey::Total => "total",
+        SortKey::Locked => "locked",
+    };
+    writeln!(toml, "sort_by = {}", string(sort_by))?;
+    if let Some(filter) = &options.filter {
+        writeln!(toml, "filter = {}", string(filter.source()))?;
+    }
+    if let Some(rejects_file) = &options.rejects_file {
+        writeln!(toml, "rejects = {}", path(rejects_file))?;
+    }
+    if let Some(audit_file) = &options.audit_file {
+        writeln!(toml, "audit = {}", path(audit_file))?;
+    }
+    if let Some(certificate_file) = &options.certificate_file {
+        writeln!(toml, "certificate = {}", path(certificate_file))?;
+    }
+
+    writeln!(toml)?;
+    writeln!(toml, "[snapshot]")?;
+    if let Some(snapshot_file) = &options.snapshot_file {
+        writeln!(toml, "file = {}", path(snapshot_file))?;
+    }
+    if let Some(archive_file) = &options.archive_file {
+        writeln!(toml, "archive = {}", path(archive_file))?;
+    }
+    if let Some(checkpoint) = &options.checkpoint {
+        writeln!(toml, "checkpoint = {}", path(&checkpoint.file))?;
+        writeln!(toml, "checkpoint_every = {}", checkpoint.interval)?;
+    }
+    writeln!(
+        toml,
+        "refuse_applied_inputs = {}",
+        options.refuse_applied_inputs
+    )?;
+    writeln!(toml, "attempts = {}", options.storage_retry.attempts)?;
+    writeln!(
+        toml,
+        "backoff_ms = {}",
+        options.storage_retry.initial_backoff.as_millis()
+    )?;
+    writeln!(
+        toml,
+        "max_backoff_ms = {}",
+        options.storage_retry.max_backoff.as_millis()
+    )?;
+
+    writeln!(toml)?;
+    writeln!(toml, "[alerts]")?;
+    if let Some(alerts_file) = &options.alerts_file {
+        writeln!(toml, "file = {}", path(alerts_file))?;
+    }
+    if let Some(balance_above) = options.alert_thresholds.balance_above {
+        writeln!(toml, "balance_above = {balance_above}")?;
+    }
+    if let Some(chargebacks_above) = options.alert_thresholds.chargebacks_above {
+        writeln!(toml, "chargebacks_above = {chargebacks_above}")?;
+    }
+
+    writeln!(toml)?;
+    writeln!(toml, "[anomalies]")?;
+    if let Some(rate) = &options.anomaly_rate {
+        writeln!(toml, "max_change = {}", rate.max_change)?;
+        writeln!(toml, "window = {}", rate.window)?;
+    }
+
+    Ok(())
+}
+
+/// Settings of the policy section of the configuration, as `(key, TOML value)` pairs in configuration order.
+/// Channel rules come last, keyed by transaction type prefixed with [CHANNEL_RULES]. Unset optional values are
+/// omitted
+pub(crate) fn policy_settings(policy: &EnginePolicy) -> Vec<(String, String)> {
+    let mut settings = vec![(
+        "rejected_attributes".to_owned(),
+        array(
+            policy
+                .rejected_attributes
+                .iter()
+                .map(|(name, value)| array([string(name), string(value)].into_iter())),
+        ),
+    )];
+    let negative_adjustments = match &policy.negative_adjustments {
+        NegativeAdjustments::None => string("none"),
+        NegativeAdjustments::All => string("all"),
+        NegativeAdjustments::Clients(clients) => {
+            let mut clients = clients.iter().collect::<Vec<_>>();
+            clients.sort();
+            array(clients.into_iter().map(u16::to_string))
+        }
+    };
+    settings.push(("negative_adjustments".to_owned(), negative_adjustments));
+    settings.push((
+        "adjust_locked_accounts".to_owned(),
+        policy.adjust_locked_accounts.to_string(),
+    ));
+    if let Some(history_limit) = policy.history_limit {
+        settings.push(("history_limit".to_owned(), history_limit.to_string()));
+    }
+    settings.push((
+        "auto_resolve_reasons".to_owned(),
+        array(
+            policy
+                .auto_resolve_reasons
+                .iter()
+                .map(|reason| string(reason)),
+        ),
+    ));
+    settings.push((
+        "charge_back_undisputed".to_owned(),
+        policy.charge_back_undisputed.to_string(),
+    ));
+    settings.push(("allow_unlock".to_owned(), policy.allow_unlock.to_string()));
+    settings.push(("audit_unlocks".to_owned(), policy.audit_unlocks.to_string()));
+    let withdrawal_dispute_mode = match policy.withdrawal_dispute_mode {
+        WithdrawalDisputeMode::Debit => "debit",
+        WithdrawalDisputeMode::Credit => "credit",
+        WithdrawalDisputeMode::Reject => "reject",
+    };
+    settings.push((
+        "withdrawal_dispute_mode".to_owned(),
+        string(withdrawal_dispute_mode),
+    ));
+    if let Some(overdraft_limit) = policy.overdraft_limit {
+        settings.push(("overdraft_limit".to_owned(), overdraft_limit.to_string()));
+    }
+    let dispute_shortfall = match policy.dispute_shortfall {
+        DisputeShortfall::Allow => "allow",
+        DisputeShortfall::Reject => "reject",
+    };
+    settings.push(("dispute_shortfall".to_owned(), string(dispute_shortfall)));
+    let mut channel_rules = policy
+        .channel_rules
+        .iter()
+        .map(|(transaction_type, channels)| (String::from(*transaction_type), channels))
+        .collect::<Vec<_>>();
+    channel_rules.sort();
+    settings.extend(
+        channel_rules
+            .into_iter()
+            .map(|(transaction_type, channels)| {
+                (
+                    format!("{CHANNEL_RULES}{transaction_type}"),
+                    array(channels.iter().map(|channel| string(channel))),
+                )
+            }),
+    );
+    settings
+}
+
+fn write_exponents(exponents: &CurrencyExponents, toml: &mut String) -> std::fmt::Result {
+    writeln!(toml, "default_exponent = {}", exponents.default)?;
+    writeln!(toml)?;
+    writeln!(toml, "[input.currency_exponents]")?;
+    let mut by_currency = exponents.by_currency.iter().collect::<Vec<_>>();
+    by_currency.sort();
+    for (currency, exponent) in by_currency {
+        writeln!(toml, "{} = {exponent}", string(currency))?;
+    }
+    Ok(())
+}
+
+/// TOML basic string
+fn string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04X}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn path(path: &Path) -> String {
+    string(&path.display().to_string())
+}
+
+/// TOML inline array of already rendered values
+fn array(values: impl Iterator<Item = String>) -> String {
+    format!("[{}]", values.collect::<Vec<_>>().join(", "))
+}
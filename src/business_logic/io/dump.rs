@@ -0,0 +1,146 @@
+use std::io::Write;
+
+use crate::{
+    business_logic::domain::Client,
+    shared::{
+        errors::Error,
+        sha256::{self, Digest},
+    },
+};
+
+/// Write the whole state of a snapshot as indented JSON, for debugging: the ledger of applied inputs and,
+/// for every client, already sorted, the balances along with the fields not present in the output schema
+/// (account version, annotations, dispute outcomes, pending disputes, transactions history and pruned transactions).
+pub(crate) fn write_state<W>(
+    ledger: &[Digest],
+    clients: &[&Client],
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let ledger = ledger
+        .iter()
+        .map(|digest| format!("\"{}\"", sha256::to_hex(digest)))
+        .collect::<Vec<_>>();
+
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"ledger\": [{}],", ledger.join(", "))?;
+    writeln!(writer, "  \"clients\": [")?;
+    for (index, client) in clients.iter().enumerate() {
+        write_client(client, &mut writer)?;
+        let separator = if index + 1 < clients.len() { "," } else { "" };
+        writeln!(writer, "{separator}")?;
+    }
+    writeln!(writer, "  ]")?;
+    writeln!(writer, "}}")?;
+
+    Ok(())
+}
+
+/// Write a client object, without trailing comma and newline
+fn write_client<W: Write>(client: &Client, writer: &mut W) -> Result<(), Error> {
+    let outcomes = &client.dispute_outcomes;
+
+    writeln!(writer, "    {{")?;
+    writeln!(writer, "      \"client\": {},", client.id)?;
+    writeln!(writer, "      \"available\": {},", client.available)?;
+    writeln!(writer, "      \"held\": {},", client.held)?;
+    writeln!(writer, "      \"total\": {},", client.total)?;
+    writeln!(writer, "      \"locked\": {},", client.locked)?;
+    writeln!(writer, "      \"erased\": {},", client.erased)?;
+    writeln!(writer, "      \"version\": {},", client.version)?;
+    let annotations = client
+        .annotations
+        .iter()
+        .map(|note| json_string(note))
+        .collect::<Vec<_>>();
+    writeln!(
+        writer,
+        "      \"annotations\": [{}],",
+        annotations.join(", ")
+    )?;
+    writeln!(writer, "      \"dispute_outcomes\": {{")?;
+    writeln!(writer, "        \"opened\": {},", outcomes.opened)?;
+    writeln!(
+        writer,
+        "        \"opened_amount\": {},",
+        outcomes.opened_amount
+    )?;
+    writeln!(writer, "        \"resolved\": {},", outcomes.resolved)?;
+    writeln!(
+        writer,
+        "        \"resolved_amount\": {},",
+        outcomes.resolved_amount
+    )?;
+    writeln!(
+        writer,
+        "        \"charged_back\": {},",
+        outcomes.charged_back
+    )?;
+    writeln!(
+        writer,
+        "        \"charged_back_amount\": {},",
+        outcomes.charged_back_amount
+    )?;
+    writeln!(writer, "        \"pending\": {},", outcomes.pending())?;
+    writeln!(
+        writer,
+        "        \"pending_amount\": {}",
+        outcomes.pending_amount()
+    )?;
+    writeln!(writer, "      }},")?;
+
+    let history = client
+        .history_order
+        .iter()
+        .filter_map(|tx| {
+            client.transations_history.get(tx).map(|transaction| {
+                let optional = |value: Option<&str>| value.map_or_else(|| "null".to_owned(), json_string);
+                format!(
+                    "        {{\"tx\": {tx}, \"amount\": {}, \"dispute_status\": \"{}\", \"channel\": {}, \"dispute_reason\": {}, \"evidence\": {}}}",
+                    transaction.amount,
+                    transaction.dispute_status,
+                    optional(transaction.channel.as_deref()),
+                    optional(transaction.dispute.reason.as_deref()),
+                    optional(transaction.dispute.evidence.as_deref())
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    match history.is_empty() {
+        true => writeln!(writer, "      \"transactions\": [],")?,
+        false => {
+            writeln!(writer, "      \"transactions\": [")?;
+            writeln!(writer, "{}", history.join(",\n"))?;
+            writeln!(writer, "      ],")?;
+        }
+    }
+
+    let mut pruned = client.pruned_transactions.iter().collect::<Vec<_>>();
+    pruned.sort();
+    let pruned = pruned.iter().map(|tx| tx.to_string()).collect::<Vec<_>>();
+    writeln!(writer, "      \"pruned\": [{}]", pruned.join(", "))?;
+    write!(writer, "    }}")?;
+
+    Ok(())
+}
+
+/// JSON string literal
+pub(crate) fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
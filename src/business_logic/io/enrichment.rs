@@ -0,0 +1,97 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use csv::ReaderBuilder;
+
+use crate::{business_logic::domain::ClientTransaction, shared::errors::Error};
+
+/// Additional attributes of a transaction (e.g. merchant category, channel), by attribute name
+pub type Attributes = HashMap<String, String>;
+
+/// Lookup tables of attributes merged into each transaction before it is applied
+#[derive(Debug, Default)]
+pub struct Enrichment {
+    /// Attributes of single transactions, by transaction ID
+    by_tx: HashMap<u32, Attributes>,
+    /// Attributes shared by every transaction of a client, by client ID
+    by_client: HashMap<u16, Attributes>,
+    /// Lookup files loaded, in load order
+    files: Vec<PathBuf>,
+}
+
+impl Enrichment {
+    /// Lookup files loaded, in load order
+    pub(crate) fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Load a lookup CSV file into the enrichment tables.
+    /// The first column must be either `tx` or `client` and identifies the key of each row,
+    /// while every other column is an attribute named after its header. Empty values are skipped.
+    pub fn load(&mut self, path: &Path) -> Result<(), Error> {
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_path(path)?;
+        let headers = reader.headers()?.clone();
+        self.files.push(path.to_owned());
+
+        for record in reader.records() {
+            let record = record?;
+            let attributes = headers
+                .iter()
+                .zip(record.iter())
+                .skip(1)
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect::<Attributes>();
+            let key = record.get(0).unwrap_or_default();
+
+            match headers.get(0) {
+                Some("tx") => self
+                    .by_tx
+                    .entry(parse_key(key, path)?)
+                    .or_default()
+                    .extend(attributes),
+                Some("client") => self
+                    .by_client
+                    .entry(parse_key(key, path)?)
+                    .or_default()
+                    .extend(attributes),
+                other => {
+                    return Err(Error::InvalidEnrichmentFile(
+                        path.display().to_string(),
+                        format!("unknown key column '{}'", other.unwrap_or_default()),
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Merge known attributes into the transaction. Transaction level attributes take precedence over client level ones.
+    pub(crate) fn merge_into(&self, transaction: &mut ClientTransaction) {
+        [
+            self.by_client.get(&transaction.id),
+            self.by_tx.get(&transaction.tx),
+        ]
+        .into_iter()
+        .flatten()
+        .for_each(|attributes| {
+            transaction.attributes.extend(
+                attributes
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone())),
+            )
+        });
+    }
+}
+
+fn parse_key<T: std::str::FromStr>(key: &str, path: &Path) -> Result<T, Error> {
+    key.parse().map_err(|_| {
+        Error::InvalidEnrichmentFile(path.display().to_string(), format!("invalid key '{key}'"))
+    })
+}
@@ -0,0 +1,390 @@
+use std::{collections::HashMap, io::Write, str::FromStr};
+
+use crate::{
+    business_logic::domain::{events::Event, Client, DisputeDetails, DisputeEffect},
+    shared::{decimal::Decimal, errors::Error},
+};
+
+/// Currency code of exported amounts: input amounts carry no currency
+const CURRENCY: &str = "XXX";
+
+/// Interchange format of the `export` subcommand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// ISO 20022 camt.053 bank-to-customer statement (simplified profile), one statement per client
+    Camt053,
+    /// Quicken Interchange Format, one bank account per client
+    Qif,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "camt053" => Ok(Self::Camt053),
+            "qif" => Ok(Self::Qif),
+            _ => Err(Error::InvalidExportFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Booked movement of the total funds of a client
+struct Entry {
+    /// Transaction ID, absent for adjustments
+    tx: Option<u32>,
+    /// Signed amount: positive for credits, negative for debits
+    amount: Decimal,
+    /// Human-readable description of the movement
+    description: &'static str,
+    /// Reason code of adjustments, reason code and evidence of the dispute of chargebacks
+    reason: Option<String>,
+}
+
+/// Movements of total funds among the events of a client. Disputes and resolves only move funds between
+/// available and held, so they are not booked entries, unless they credit back a disputed withdrawal, whose
+/// chargeback then only releases the funds already credited.
+fn entries(events: &[Event]) -> Vec<Entry> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Deposited { tx, amount, .. } => Some(Entry {
+                tx: Some(*tx),
+                amount: amount.value(),
+                description: "deposit",
+                reason: None,
+            }),
+            Event::Withdrawn { tx, amount, .. } => Some(Entry {
+                tx: Some(*tx),
+                amount: -amount.value(),
+                description: "withdrawal",
+                reason: None,
+            }),
+            Event::TransferredOut { tx, amount, .. } => Some(Entry {
+                tx: Some(*tx),
+                amount: -amount.value(),
+                description: "transfer",
+                reason: None,
+            }),
+            Event::TransferredIn { tx, amount, .. } => Some(Entry {
+                tx: Some(*tx),
+                amount: amount.value(),
+                description: "transfer",
+                reason: None,
+            }),
+            Event::TransferReversed { tx, amount } => Some(Entry {
+                tx: Some(*tx),
+                amount: amount.value(),
+                description: "transfer reversal",
+                reason: None,
+            }),
+            Event::ChargedBack {
+                tx,
+                amount,
+                details,
+                effect: DisputeEffect::Debit,
+            } => Some(Entry {
+                tx: Some(*tx),
+                amount: -amount.value(),
+                description: "chargeback",
+                reason: (!details.is_empty()).then(|| details.to_string()),
+            }),
+            Event::DisputeOpened {
+                tx,
+                amount,
+                details,
+                effect: DisputeEffect::Credit,
+            } => Some(Entry {
+                tx: Some(*tx),
+                amount: amount.value(),
+                description: "withdrawal dispute",
+                reason: (!details.is_empty()).then(|| details.to_string()),
+            }),
+            Event::DisputeResolved {
+                tx,
+                amount,
+                effect: DisputeEffect::Credit,
+            } => Some(Entry {
+                tx: Some(*tx),
+                amount: -amount.value(),
+                description: "withdrawal dispute resolved",
+                reason: None,
+            }),
+            Event::Adjusted { amount, reason } => Some(Entry {
+                tx: None,
+                amount: *amount,
+                description: "adjustment",
+                reason: Some(reason.clone()),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Write the statement of every client, in the given order, using the requested format.
+/// `activity` holds the events applied to each client, in input order, and `date` is the date of every QIF entry
+/// (`MM/DD/YYYY`), since input transactions carry no timestamp.
+pub(crate) fn write_statements<W>(
+    clients: &[&Client],
+    activity: &HashMap<u16, Vec<Event>>,
+    format: ExportFormat,
+    date: &str,
+    writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    match format {
+        ExportFormat::Camt053 => write_camt053(clients, activity, writer),
+        ExportFormat::Qif => write_qif(clients, activity, date, writer),
+    }
+}
+
+fn write_camt053<W>(
+    clients: &[&Client],
+    activity: &HashMap<u16, Vec<Event>>,
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let credit_debit = |amount: Decimal| match amount.is_negative() {
+        true => "DBIT",
+        false => "CRDT",
+    };
+    let amount = |amount: Decimal| format!("<Amt Ccy=\"{CURRENCY}\">{}</Amt>", amount.abs());
+
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        writer,
+        "<Document xmlns=\"urn:iso:std:iso:20022:tech:xsd:camt.053.001.02\">"
+    )?;
+    writeln!(writer, "  <BkToCstmrStmt>")?;
+    writeln!(writer, "    <GrpHdr>")?;
+    writeln!(writer, "      <MsgId>transactions-simulator</MsgId>")?;
+    writeln!(writer, "    </GrpHdr>")?;
+    for client in clients {
+        writeln!(writer, "    <Stmt>")?;
+        writeln!(writer, "      <Id>{}</Id>", client.id)?;
+        writeln!(
+            writer,
+            "      <Acct><Id><Othr><Id>{}</Id></Othr></Id></Acct>",
+            client.id
+        )?;
+        for (code, balance) in [
+            ("CLBD", client.total.value()),
+            ("CLAV", client.available.value()),
+        ] {
+            writeln!(
+                writer,
+                "      <Bal><Tp><CdOrPrtry><Cd>{code}</Cd></CdOrPrtry></Tp>{}<CdtDbtInd>{}</CdtDbtInd></Bal>",
+                amount(balance),
+                credit_debit(balance)
+            )?;
+        }
+        let events = activity.get(&client.id).map_or(&[][..], Vec::as_slice);
+        for entry in entries(events) {
+            let reference = entry
+                .tx
+                .map(|tx| format!("<NtryRef>{tx}</NtryRef>"))
+                .unwrap_or_default();
+            let information = match &entry.reason {
+                Some(reason) => format!("{}: {}", entry.description, escape_xml(reason)),
+                None => entry.description.to_owned(),
+            };
+            writeln!(
+                writer,
+                "      <Ntry>{reference}{}<CdtDbtInd>{}</CdtDbtInd><Sts>BOOK</Sts><AddtlNtryInf>{information}</AddtlNtryInf></Ntry>",
+                amount(entry.amount),
+                credit_debit(entry.amount)
+            )?;
+        }
+        if client.locked {
+            writeln!(writer, "      <AddtlStmtInf>locked</AddtlStmtInf>")?;
+        }
+        for note in &client.annotations {
+            writeln!(
+                writer,
+                "      <AddtlStmtInf>{}</AddtlStmtInf>",
+                escape_xml(note)
+            )?;
+        }
+        writeln!(writer, "    </Stmt>")?;
+    }
+    writeln!(writer, "  </BkToCstmrStmt>")?;
+    writeln!(writer, "</Document>")?;
+
+    Ok(())
+}
+
+/// Write every client as a QIF bank account. Besides the movements of total funds, disputes, resolves and
+/// unlocks are listed as zero-amount entries (unless they move total funds, as for withdrawals credited back),
+/// so the dispute flows can be followed in personal-finance tools.
+fn write_qif<W>(
+    clients: &[&Client],
+    activity: &HashMap<u16, Vec<Event>>,
+    date: &str,
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    for client in clients {
+        writeln!(writer, "!Account")?;
+        writeln!(writer, "NClient {}", client.id)?;
+        writeln!(writer, "TBank")?;
+        if !client.annotations.is_empty() {
+            writeln!(writer, "D{}", client.annotations.join(" | "))?;
+        }
+        writeln!(writer, "^")?;
+        writeln!(writer, "!Type:Bank")?;
+
+        let events = activity.get(&client.id).map_or(&[][..], Vec::as_slice);
+        for event in events {
+            let (tx, amount, payee, memo) = match event {
+                Event::Deposited { tx, amount, .. } => (Some(tx), amount.value(), "deposit", None),
+                Event::Withdrawn { tx, amount, .. } => {
+                    (Some(tx), -amount.value(), "withdrawal", None)
+                }
+                Event::TransferredOut { tx, amount, to, .. } => (
+                    Some(tx),
+                    -amount.value(),
+                    "transfer",
+                    Some(format!("to client {to}")),
+                ),
+                Event::TransferredIn {
+                    tx, amount, from, ..
+                } => (
+                    Some(tx),
+                    amount.value(),
+                    "transfer",
+                    Some(format!("from client {from}")),
+                ),
+                Event::TransferReversed { tx, amount } => (
+                    Some(tx),
+                    amount.value(),
+                    "chargeback",
+                    Some("transfer reversed, account locked".to_owned()),
+                ),
+                Event::DisputeOpened {
+                    tx,
+                    amount,
+                    details,
+                    effect: DisputeEffect::Debit,
+                } => (
+                    Some(tx),
+                    Decimal::ZERO,
+                    "dispute",
+                    Some(with_details(format!("{amount} held"), details)),
+                ),
+                Event::DisputeOpened {
+                    tx,
+                    amount,
+                    details,
+                    effect: DisputeEffect::Credit,
+                } => (
+                    Some(tx),
+                    amount.value(),
+                    "dispute",
+                    Some(with_details(
+                        "withdrawal credited back, held".to_owned(),
+                        details,
+                    )),
+                ),
+                Event::DisputeResolved {
+                    tx,
+                    amount,
+                    effect: DisputeEffect::Debit,
+                } => (
+                    Some(tx),
+                    Decimal::ZERO,
+                    "resolve",
+                    Some(format!("{amount} released")),
+                ),
+                Event::DisputeResolved {
+                    tx,
+                    amount,
+                    effect: DisputeEffect::Credit,
+                } => (
+                    Some(tx),
+                    -amount.value(),
+                    "resolve",
+                    Some("withdrawal upheld".to_owned()),
+                ),
+                Event::ChargedBack {
+                    tx,
+                    amount,
+                    details,
+                    effect: DisputeEffect::Debit,
+                } => (
+                    Some(tx),
+                    -amount.value(),
+                    "chargeback",
+                    Some(with_details("account locked".to_owned(), details)),
+                ),
+                Event::ChargedBack {
+                    tx,
+                    amount,
+                    details,
+                    effect: DisputeEffect::Credit,
+                } => (
+                    Some(tx),
+                    Decimal::ZERO,
+                    "chargeback",
+                    Some(with_details(
+                        format!("{amount} released, account locked"),
+                        details,
+                    )),
+                ),
+                Event::Adjusted { amount, reason } => {
+                    (None, *amount, "adjustment", Some(reason.clone()))
+                }
+                Event::Unlocked { tx, reason, .. } => (
+                    Some(tx),
+                    Decimal::ZERO,
+                    "unlock",
+                    Some(match reason {
+                        Some(reason) => format!("account unlocked, {reason}"),
+                        None => "account unlocked".to_owned(),
+                    }),
+                ),
+                // the funds of a disputed transfer are held by its destination
+                Event::DisputeImplied { .. }
+                | Event::TransferDisputed { .. }
+                | Event::TransferResolved { .. }
+                | Event::Locked
+                | Event::Pruned { .. } => continue,
+            };
+            writeln!(writer, "D{date}")?;
+            writeln!(writer, "T{amount}")?;
+            if let Some(tx) = tx {
+                writeln!(writer, "N{tx}")?;
+            }
+            writeln!(writer, "P{payee}")?;
+            if let Some(memo) = memo {
+                // QIF fields are line based
+                writeln!(writer, "M{}", memo.replace(['\r', '\n'], " "))?;
+            }
+            writeln!(writer, "^")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Memo followed by the dispute details, if any
+fn with_details(memo: String, details: &DisputeDetails) -> String {
+    match details.is_empty() {
+        true => memo,
+        false => format!("{memo}, {details}"),
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,201 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    business_logic::domain::{trait_impl::from_str, ClientTransaction, Type},
+    shared::{
+        decimal::Decimal,
+        errors::Error,
+        gzip::{GzipReader, GZIP_MAGIC},
+        zstd::{ZstdReader, ZSTD_MAGIC},
+    },
+};
+
+/// Unit of the amounts found in the input file
+#[derive(Debug, Default)]
+pub enum AmountUnits {
+    /// Decimal amounts in major units (e.g. `10.50`)
+    #[default]
+    Decimal,
+    /// Integer amounts in minor units (e.g. `1050` cents), converted to major units by the exponent table
+    Minor(CurrencyExponents),
+}
+
+/// Layout of the input file
+#[derive(Debug, Default)]
+pub enum InputFormat {
+    /// CSV rows with `type,client,tx,amount` columns (plus optional ones)
+    #[default]
+    Csv,
+    /// Simplified ISO 8583 message dump, whose amounts in minor units are converted by the exponent table
+    Iso8583(CurrencyExponents),
+    /// JSON Lines, one flat object per line with the same members as the CSV columns
+    JsonLines,
+    /// First sheet of an Excel workbook (.xlsx), whose first row holds the CSV column names
+    Xlsx,
+}
+
+/// Input reader decompressing the content of `reader` if it is compressed, as detected by its leading bytes
+/// whatever the file name, so that compressed stdin is detected too. Gzip and Zstandard are decompressed while read.
+pub fn decompressing_reader<'a, R>(reader: R) -> Result<Box<dyn Read + Send + 'a>, Error>
+where
+    R: Read + Send + 'a,
+{
+    let mut reader = BufReader::new(reader);
+    let leading_bytes = reader.fill_buf()?;
+    if leading_bytes.starts_with(&GZIP_MAGIC) {
+        return Ok(Box::new(GzipReader::new(reader)));
+    }
+    if leading_bytes.starts_with(&ZSTD_MAGIC) {
+        return Ok(Box::new(ZstdReader::new(reader)));
+    }
+
+    Ok(Box::new(reader))
+}
+
+/// Input files matching `pattern`, sorted by name so that e.g. `transactions-*.csv` month-split exports are
+/// listed in chronological order. `*` (any sequence of characters) and `?` (any single character) wildcards
+/// are supported in the file name only, and do not match hidden files. A path without wildcards is returned as is
+pub fn expand_input_pattern(pattern: &Path) -> Result<Vec<PathBuf>, Error> {
+    let Some(name) = pattern.file_name().and_then(|name| name.to_str()) else {
+        return Ok(vec![pattern.to_owned()]);
+    };
+    if !name.contains(['*', '?']) {
+        return Ok(vec![pattern.to_owned()]);
+    }
+    let dir = match pattern.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let wildcards = name.chars().collect::<Vec<_>>();
+    let mut matches = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let Some(file_name) = entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        let hidden = file_name.starts_with('.') && !name.starts_with('.');
+        let file_name_chars = file_name.chars().collect::<Vec<_>>();
+        if !hidden && entry.path().is_file() && matches_wildcards(&wildcards, &file_name_chars) {
+            matches.push(pattern.with_file_name(file_name));
+        }
+    }
+    if matches.is_empty() {
+        return Err(Error::NoMatchingInput(pattern.display().to_string()));
+    }
+    matches.sort();
+
+    Ok(matches)
+}
+
+fn matches_wildcards(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some(('*', rest)), _) => {
+            matches_wildcards(rest, name)
+                || (!name.is_empty() && matches_wildcards(pattern, &name[1..]))
+        }
+        (Some(('?', rest)), Some((_, name_rest))) => matches_wildcards(rest, name_rest),
+        (Some((expected, rest)), Some((c, name_rest))) if expected == c => {
+            matches_wildcards(rest, name_rest)
+        }
+        _ => false,
+    }
+}
+
+/// Number of decimal digits of the minor unit of each currency (e.g. USD 2, JPY 0, BHD 3)
+#[derive(Debug, Clone)]
+pub struct CurrencyExponents {
+    /// Exponent of transactions without currency or with a currency not listed in `by_currency`
+    pub default: u32,
+    /// Exponent by currency code
+    pub by_currency: HashMap<String, u32>,
+}
+
+/// Exponent of a single currency, parsed from `CURRENCY=exponent`
+#[derive(Debug)]
+pub struct CurrencyExponent {
+    pub currency: String,
+    pub exponent: u32,
+}
+
+impl FromStr for CurrencyExponent {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_once('=')
+            .and_then(|(currency, exponent)| {
+                Some(Self {
+                    currency: currency.trim().to_owned(),
+                    exponent: exponent.trim().parse().ok()?,
+                })
+            })
+            .ok_or_else(|| Error::InvalidCurrencyExponent(s.to_owned()))
+    }
+}
+
+impl CurrencyExponents {
+    pub(crate) fn exponent_of(&self, currency: Option<&str>) -> u32 {
+        currency
+            .and_then(|currency| self.by_currency.get(currency))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Input row whose amount is an integer number of minor units
+#[derive(Debug, Deserialize)]
+pub(crate) struct MinorUnitsTransaction {
+    /// Client ID, UUID
+    client: u16,
+    /// Type of transaction
+    #[serde(rename = "type", deserialize_with = "from_str")]
+    transaction_type: Type,
+    /// Transaction ID
+    tx: u32,
+    /// Transaction amount in minor units. Present only for Deposit and Withdrawal
+    amount: Option<i64>,
+    /// Currency code driving the conversion to major units
+    currency: Option<String>,
+    /// Reason code. Mandatory for Adjustment, optional for Dispute
+    reason: Option<String>,
+    /// Reference of the evidence supporting a Dispute
+    evidence: Option<String>,
+    /// Client credited by a Transfer
+    to_client: Option<u16>,
+}
+
+impl MinorUnitsTransaction {
+    /// Convert into a transaction with amount in major units, failing if the amount is out of range
+    pub(crate) fn into_client_transaction(
+        self,
+        exponents: &CurrencyExponents,
+    ) -> Result<ClientTransaction, Error> {
+        let exponent = exponents.exponent_of(self.currency.as_deref());
+        let amount = self
+            .amount
+            .map(|amount| {
+                Decimal::from_minor_units(amount, exponent)
+                    .ok_or_else(|| Error::InvalidDecimal(format!("{amount}e-{exponent}")))
+            })
+            .transpose()?;
+
+        Ok(ClientTransaction {
+            id: self.client,
+            transaction_type: self.transaction_type,
+            tx: self.tx,
+            amount,
+            reason: self.reason,
+            evidence: self.evidence,
+            to_client: self.to_client,
+            attributes: Default::default(),
+        })
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read},
+};
+
+use crate::{
+    business_logic::{
+        domain::{ClientTransaction, Type},
+        io::input::CurrencyExponents,
+    },
+    shared::{decimal::Decimal, errors::Error},
+};
+
+/*
+ * Simplified ISO 8583 message dump: one message per line, made of the message type indicator (MTI)
+ * followed by whitespace separated `field=value` pairs. Field numbers may carry a `DE` prefix
+ * and leading zeros (`DE004=1050`, `4=1050`). Empty lines and lines starting with `#` are skipped.
+ *
+ * Fields:
+ *  2   primary account number, holding the client ID
+ *  3   processing code, whose first two digits select the financial transaction type
+ *  4   amount, integer in minor units of the currency
+ *  11  system trace audit number (STAN), used as transaction ID
+ *  49  currency code, selecting the exponent of field 4 (default exponent if missing)
+ *
+ * MTI mapping:
+ *  0200, 0220  financial request / advice: deposit if processing code starts with 21,
+ *              withdrawal if it starts with 00 or 01
+ *  0420        reversal advice: dispute of the message with the same STAN
+ *  0430        reversal advice response: resolve of the message with the same STAN
+ *  0422        chargeback of the message with the same STAN
+ */
+
+/// Read every message of a dump, in order, with its line number. Malformed messages are returned as errors
+pub(crate) fn read_messages<'a, R>(
+    reader: R,
+    exponents: &'a CurrencyExponents,
+) -> impl Iterator<Item = (u64, Result<ClientTransaction, Error>)> + 'a
+where
+    R: Read + 'a,
+{
+    BufReader::new(reader)
+        .lines()
+        .zip(1..)
+        .filter(|(line, _)| {
+            line.as_ref().map_or(true, |line| {
+                !line.trim().is_empty() && !line.trim_start().starts_with('#')
+            })
+        })
+        .map(move |(line, number)| {
+            let message = line
+                .map_err(Error::Io)
+                .and_then(|line| parse_message(&line, exponents));
+            (number, message)
+        })
+}
+
+/// Parse a single message line into a transaction
+fn parse_message(line: &str, exponents: &CurrencyExponents) -> Result<ClientTransaction, Error> {
+    let invalid = |reason: &str| Error::InvalidIso8583Message(line.to_owned(), reason.to_owned());
+
+    let mut tokens = line.split_whitespace();
+    let mti = tokens.next().ok_or_else(|| invalid("missing MTI"))?;
+    let fields = tokens
+        .map(|token| {
+            let (field, value) = token.split_once('=')?;
+            let field = field
+                .strip_prefix("DE")
+                .unwrap_or(field)
+                .parse::<u8>()
+                .ok()?;
+            Some((field, value))
+        })
+        .collect::<Option<HashMap<_, _>>>()
+        .ok_or_else(|| invalid("expected `field=value` pairs"))?;
+
+    let transaction_type = match mti {
+        "0200" | "0220" => match fields.get(&3).map(|code| code.get(..2)) {
+            Some(Some("21")) => Type::Deposit,
+            Some(Some("00" | "01")) => Type::Withdrawal,
+            _ => return Err(invalid("unsupported processing code")),
+        },
+        "0420" => Type::Dispute,
+        "0430" => Type::Resolve,
+        "0422" => Type::ChargeBack,
+        _ => return Err(invalid("unsupported MTI")),
+    };
+    let client = fields
+        .get(&2)
+        .and_then(|client| client.parse().ok())
+        .ok_or_else(|| invalid("invalid client in field 2"))?;
+    let tx = fields
+        .get(&11)
+        .and_then(|tx| tx.parse().ok())
+        .ok_or_else(|| invalid("invalid STAN in field 11"))?;
+    let amount = match transaction_type {
+        Type::Deposit | Type::Withdrawal => {
+            let amount = fields
+                .get(&4)
+                .and_then(|amount| amount.parse::<i64>().ok())
+                .ok_or_else(|| invalid("invalid amount in field 4"))?;
+            let exponent = exponents.exponent_of(fields.get(&49).copied());
+            let amount = Decimal::from_minor_units(amount, exponent)
+                .ok_or_else(|| invalid("amount out of range in field 4"))?;
+            Some(amount)
+        }
+        _ => None,
+    };
+
+    Ok(ClientTransaction {
+        id: client,
+        transaction_type,
+        tx,
+        amount,
+        reason: None,
+        evidence: None,
+        to_client: None,
+        attributes: Default::default(),
+    })
+}
@@ -0,0 +1,136 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    iter::Peekable,
+    str::Chars,
+};
+
+use csv::StringRecord;
+
+use crate::shared::errors::Error;
+
+/*
+ * JSON Lines (NDJSON) input: one flat JSON object per line, whose members are the columns of a CSV row,
+ * e.g. `{"type":"deposit","client":1,"tx":1,"amount":1.0}`. Members may come in any order, `null` members
+ * are treated as missing columns, and numbers are kept as written so that amounts are parsed exactly.
+ * Nested objects and arrays are not supported. Empty lines are skipped.
+ */
+
+/// Read every object of a JSON Lines input, in order, with its line number, as a (header, record) pair
+/// deserializable like a CSV row. Malformed lines are returned as errors
+pub(crate) fn read_objects<R: Read>(
+    reader: R,
+) -> impl Iterator<Item = (u64, Result<(StringRecord, StringRecord), Error>)> {
+    BufReader::new(reader)
+        .lines()
+        .zip(1..)
+        .filter(|(line, _)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|(line, number)| {
+            let object = line.map_err(Error::Io).and_then(|line| {
+                parse_object(&line).map_err(|reason| Error::InvalidJsonRecord(line, reason))
+            });
+            (number, object)
+        })
+}
+
+/// Parse a flat JSON object into its member names and values
+pub(crate) fn parse_object(line: &str) -> Result<(StringRecord, StringRecord), String> {
+    let mut chars = line.trim().chars().peekable();
+    let mut names = StringRecord::new();
+    let mut values = StringRecord::new();
+
+    expect(&mut chars, '{')?;
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            skip_whitespace(&mut chars);
+            let name = parse_string(&mut chars)?;
+            skip_whitespace(&mut chars);
+            expect(&mut chars, ':')?;
+            skip_whitespace(&mut chars);
+            if let Some(value) = parse_value(&mut chars)? {
+                names.push_field(&name);
+                values.push_field(&value);
+            }
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err("expected `,` or `}`".to_owned()),
+            }
+        }
+    }
+
+    match chars.next() {
+        None => Ok((names, values)),
+        Some(_) => Err("unexpected content after the object".to_owned()),
+    }
+}
+
+/// Parse a scalar value as text, `None` for `null`
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Option<String>, String> {
+    match chars.peek() {
+        Some('"') => parse_string(chars).map(Some),
+        Some('{' | '[') => Err("nested values are not supported".to_owned()),
+        Some(_) => {
+            let mut literal = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c == '}' || c.is_whitespace() {
+                    break;
+                }
+                literal.push(c);
+                chars.next();
+            }
+            match literal.as_str() {
+                "null" => Ok(None),
+                "true" | "false" => Ok(Some(literal)),
+                number if number.parse::<f64>().is_ok() => Ok(Some(literal)),
+                _ => Err(format!("invalid value `{literal}`")),
+            }
+        }
+        None => Err("missing value".to_owned()),
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    expect(chars, '"')?;
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(string),
+            Some('\\') => match chars.next() {
+                Some('"') => string.push('"'),
+                Some('\\') => string.push('\\'),
+                Some('/') => string.push('/'),
+                Some('b') => string.push('\u{8}'),
+                Some('f') => string.push('\u{c}'),
+                Some('n') => string.push('\n'),
+                Some('r') => string.push('\r'),
+                Some('t') => string.push('\t'),
+                Some('u') => {
+                    let hex = chars.by_ref().take(4).collect::<String>();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("invalid escape `\\u{hex}`"))?;
+                    string.push(c);
+                }
+                _ => return Err("invalid escape".to_owned()),
+            },
+            Some(c) => string.push(c),
+            None => return Err("unterminated string".to_owned()),
+        }
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), String> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        _ => Err(format!("expected `{expected}`")),
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
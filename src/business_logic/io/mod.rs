@@ -0,0 +1,14 @@
+pub mod config;
+pub(crate) mod dump;
+pub mod enrichment;
+pub mod export;
+pub mod input;
+pub(crate) mod iso8583;
+pub(crate) mod json_lines;
+pub(crate) mod normalize;
+pub mod output;
+pub(crate) mod proto;
+pub mod query;
+pub(crate) mod snapshot;
+pub(crate) mod wal;
+pub(crate) mod xlsx;
@@ -0,0 +1,173 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Display,
+    io::{BufRead, BufReader, Read},
+    ops::Add,
+    rc::Rc,
+};
+
+/// Number of input irregularities accepted and normalized before parsing
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Normalizations {
+    /// Lines terminated by `\r\n`, as produced on Windows, rewritten as `\n`
+    pub(crate) crlf_line_endings: u64,
+    /// Empty or whitespace-only lines, skipped
+    pub(crate) blank_lines: u64,
+    /// Stray delimiters at the end of a line beyond the header columns, removed
+    pub(crate) trailing_delimiters: u64,
+}
+
+impl Normalizations {
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Add for Normalizations {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            crlf_line_endings: self.crlf_line_endings + other.crlf_line_endings,
+            blank_lines: self.blank_lines + other.blank_lines,
+            trailing_delimiters: self.trailing_delimiters + other.trailing_delimiters,
+        }
+    }
+}
+
+impl Display for Normalizations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} CRLF line endings, {} blank lines, {} trailing delimiters",
+            self.crlf_line_endings, self.blank_lines, self.trailing_delimiters
+        )
+    }
+}
+
+/// What the normalization did, shared with the caller, which can read it while or after the input is consumed
+#[derive(Debug, Default)]
+pub(crate) struct NormalizationReport {
+    counts: Cell<Normalizations>,
+    /// (normalized line, source line) pairs, one for every line following skipped blank lines,
+    /// so that source line numbers can be recovered for error messages
+    line_shifts: RefCell<Vec<(u64, u64)>>,
+}
+
+impl NormalizationReport {
+    pub(crate) fn counts(&self) -> Normalizations {
+        self.counts.get()
+    }
+
+    /// Line number in the source input of a line of the normalized input (both starting from 1)
+    pub(crate) fn source_line(&self, line: u64) -> u64 {
+        let line_shifts = self.line_shifts.borrow();
+        match line_shifts.partition_point(|&(normalized, _)| normalized <= line) {
+            0 => line,
+            index => {
+                let (normalized, source) = line_shifts[index - 1];
+                source + (line - normalized)
+            }
+        }
+    }
+}
+
+/// CSV input adapter normalizing line endings, blank lines and trailing delimiters, line by line, so that
+/// files produced on any platform are parsed the same way. The first non-blank line is the header: its own
+/// trailing delimiters are removed and its number of columns bounds the trailing delimiters removed from records.
+/// Delimiters are counted without considering quotes, so quoted fields containing commas are never shortened.
+pub(crate) struct NormalizingReader<R> {
+    reader: BufReader<R>,
+    /// Number of header columns, known once the header is read
+    columns: Option<usize>,
+    /// Normalized line being handed out, and position of the next byte to hand out
+    line: Vec<u8>,
+    position: usize,
+    /// Number of lines read from the source so far
+    source_lines: u64,
+    /// Number of normalized lines handed out so far
+    normalized_lines: u64,
+    report: Rc<NormalizationReport>,
+}
+
+impl<R: Read> NormalizingReader<R> {
+    pub(crate) fn new(reader: R) -> (Self, Rc<NormalizationReport>) {
+        let report = Rc::new(NormalizationReport::default());
+        let reader = Self {
+            reader: BufReader::new(reader),
+            columns: None,
+            line: Vec::new(),
+            position: 0,
+            source_lines: 0,
+            normalized_lines: 0,
+            report: Rc::clone(&report),
+        };
+
+        (reader, report)
+    }
+
+    /// Read and normalize the next non-blank line, returning false at the end of the input
+    fn next_line(&mut self) -> std::io::Result<bool> {
+        let mut counts = self.report.counts.get();
+        let mut skipped = false;
+        let found = loop {
+            self.line.clear();
+            self.position = 0;
+            if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                break false;
+            }
+            self.source_lines += 1;
+
+            if self.line.ends_with(b"\n") {
+                self.line.pop();
+                if self.line.ends_with(b"\r") {
+                    self.line.pop();
+                    counts.crlf_line_endings += 1;
+                }
+            }
+            if self.line.iter().all(u8::is_ascii_whitespace) {
+                counts.blank_lines += 1;
+                skipped = true;
+                continue;
+            }
+
+            let mut fields = self.line.iter().filter(|&&byte| byte == b',').count() + 1;
+            let columns = self.columns.unwrap_or(1);
+            while fields > columns && self.line.trim_ascii_end().ends_with(b",") {
+                let end = self.line.trim_ascii_end().len();
+                self.line.truncate(end - 1);
+                fields -= 1;
+                counts.trailing_delimiters += 1;
+            }
+            self.columns.get_or_insert(fields);
+
+            self.line.push(b'\n');
+            self.normalized_lines += 1;
+            if skipped {
+                self.report
+                    .line_shifts
+                    .borrow_mut()
+                    .push((self.normalized_lines, self.source_lines));
+            }
+            break true;
+        };
+
+        self.report.counts.set(counts);
+        Ok(found)
+    }
+}
+
+impl<R: Read> Read for NormalizingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.line.len() && !self.next_line()? {
+            return Ok(0);
+        }
+
+        let remaining = &self.line[self.position..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.position += len;
+
+        Ok(len)
+    }
+}
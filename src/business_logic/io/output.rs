@@ -0,0 +1,306 @@
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::Write,
+    num::{NonZeroU64, NonZeroUsize},
+    path::Path,
+    str::FromStr,
+};
+
+use csv::WriterBuilder;
+use serde::Serialize;
+
+use crate::{
+    business_logic::domain::{trait_impl::four_decimals, Client},
+    shared::{decimal::Decimal, errors::Error},
+};
+
+/// Name of the manifest file written alongside partitioned or paged output
+const MANIFEST_FILE: &str = "manifest.csv";
+
+/// Layout of the accounts output
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// `client,available,held,total,locked` with four decimal places and `\n` line terminator.
+    /// Guaranteed byte-identical to the output of the first release, except for row ordering.
+    #[default]
+    V1,
+    /// v1 columns followed by `risk_score` and `version`
+    Extended,
+}
+
+/// Row of the extended output format
+#[derive(Debug, Serialize)]
+struct ExtendedRow {
+    client: u16,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+    #[serde(serialize_with = "four_decimals")]
+    risk_score: f64,
+    version: u64,
+}
+
+impl From<&Client> for ExtendedRow {
+    fn from(client: &Client) -> Self {
+        Self {
+            client: client.id,
+            available: client.available.value(),
+            held: client.held.value(),
+            total: client.total.value(),
+            locked: client.locked,
+            risk_score: client.risk_score,
+            version: client.version,
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v1" => Ok(Self::V1),
+            "extended" => Ok(Self::Extended),
+            _ => Err(Error::InvalidOutputFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Column the accounts are sorted by when written. Ties are broken by client ID, so that the output
+/// is byte-identical across runs and platforms regardless of hash map ordering
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    #[default]
+    Client,
+    Available,
+    Held,
+    Total,
+    /// Unlocked accounts first
+    Locked,
+}
+
+impl FromStr for SortKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(Self::Client),
+            "available" => Ok(Self::Available),
+            "held" => Ok(Self::Held),
+            "total" => Ok(Self::Total),
+            "locked" => Ok(Self::Locked),
+            _ => Err(Error::InvalidSortKey(s.to_owned())),
+        }
+    }
+}
+
+/// Sort clients in ascending order of the given column, then of client ID
+pub(crate) fn sort_clients(clients: &mut [&Client], key: SortKey) {
+    clients.sort_by(|a, b| {
+        let by_key = match key {
+            SortKey::Client => Ordering::Equal,
+            SortKey::Available => a.available.cmp(&b.available),
+            SortKey::Held => a.held.cmp(&b.held),
+            SortKey::Total => a.total.cmp(&b.total),
+            SortKey::Locked => a.locked.cmp(&b.locked),
+        };
+        by_key.then(a.id.cmp(&b.id))
+    });
+}
+
+/// Serialize clients as CSV (with headers) on the given writer, using the requested format
+pub(crate) fn write_clients<'a, W>(
+    clients: impl Iterator<Item = &'a Client>,
+    format: OutputFormat,
+    writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut writer = WriterBuilder::new()
+        .has_headers(true)
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(writer);
+    clients.into_iter().try_for_each(|client| match format {
+        OutputFormat::V1 => writer.serialize(client),
+        OutputFormat::Extended => writer.serialize(ExtendedRow::from(client)),
+    })?;
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Single row of the partitions manifest
+#[derive(Debug, Serialize)]
+struct PartitionEntry {
+    /// Partition index, starting from 0
+    partition: u16,
+    /// Name of the file (relative to the output directory) holding this partition
+    file: String,
+    /// First client ID covered by this partition (inclusive)
+    first_client: u16,
+    /// Last client ID covered by this partition (inclusive)
+    last_client: u16,
+    /// Number of clients written in this partition
+    clients: usize,
+}
+
+/// Index of the partition owning the given client, so that each partition covers a contiguous client ID range
+fn partition_of(client_id: u16, partitions: u16) -> u16 {
+    ((client_id as u32 * partitions as u32) / (u16::MAX as u32 + 1)) as u16
+}
+
+/// First client ID (inclusive) covered by the given partition
+fn first_client_of(partition: u16, partitions: u16) -> u16 {
+    ((partition as u32 * (u16::MAX as u32 + 1)).div_ceil(partitions as u32)) as u16
+}
+
+/// Write clients in `partitions` CSV files, split by client ID range, plus a manifest listing every partition.
+/// Every partition file is written, also when empty, so that loaders can rely on the manifest being complete.
+/// Clients keep their relative order within each partition.
+pub(crate) fn write_partitions(
+    clients: Vec<&Client>,
+    format: OutputFormat,
+    partitions: u16,
+    output_dir: &Path,
+) -> Result<(), Error> {
+    let mut by_partition = vec![Vec::new(); partitions as usize];
+    clients
+        .into_iter()
+        .for_each(|client| by_partition[partition_of(client.id, partitions) as usize].push(client));
+
+    let width = (partitions - 1).to_string().len();
+    let mut manifest = WriterBuilder::new()
+        .has_headers(true)
+        .from_path(output_dir.join(MANIFEST_FILE))?;
+
+    for (partition, clients) in by_partition.into_iter().enumerate() {
+        let partition = partition as u16;
+        let file = format!("accounts_part_{partition:0width$}.csv");
+        let entry = PartitionEntry {
+            partition,
+            file: file.clone(),
+            first_client: first_client_of(partition, partitions),
+            last_client: match partition + 1 {
+                next if next == partitions => u16::MAX,
+                next => first_client_of(next, partitions) - 1,
+            },
+            clients: clients.len(),
+        };
+
+        write_clients(
+            clients.into_iter(),
+            format,
+            File::create(output_dir.join(file))?,
+        )?;
+        manifest.serialize(entry)?;
+    }
+
+    manifest.flush()?;
+
+    Ok(())
+}
+
+/// Size caps of every file of the paged output. Headers count towards the byte cap
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PageLimits {
+    /// Maximum number of accounts per file
+    pub rows: Option<NonZeroUsize>,
+    /// Maximum size of a file in bytes
+    pub bytes: Option<NonZeroU64>,
+}
+
+/// Single row of the pages manifest
+#[derive(Debug, Serialize)]
+struct PageEntry {
+    /// Page index, starting from 0
+    page: usize,
+    /// Name of the file (relative to the output directory) holding this page
+    file: String,
+    /// Client of the first account written in this page, empty if the page holds no account
+    first_client: Option<u16>,
+    /// Client of the last account written in this page, empty if the page holds no account
+    last_client: Option<u16>,
+    /// Number of clients written in this page
+    clients: usize,
+    /// Size of the file in bytes
+    bytes: usize,
+}
+
+/// Write clients in as many CSV files as needed to keep each within `limits`, plus a manifest listing every page.
+/// Every page repeats the header and clients keep their order across pages, so that concatenating the pages
+/// without their headers gives back the output written to stdout. At least one page is written, also when empty.
+pub(crate) fn write_pages(
+    clients: Vec<&Client>,
+    format: OutputFormat,
+    limits: PageLimits,
+    output_dir: &Path,
+) -> Result<(), Error> {
+    let mut rendered = Vec::new();
+    write_clients(clients.iter().copied(), format, &mut rendered)?;
+    // records never contain line breaks: every line after the header is one client
+    let mut lines = rendered.split_inclusive(|&byte| byte == b'\n');
+    let header = lines.next().unwrap_or_default();
+    let rows = clients.iter().map(|client| client.id).zip(lines);
+
+    let mut pages: Vec<Vec<(u16, &[u8])>> = vec![Vec::new()];
+    let mut page_bytes = header.len();
+    for (client, row) in rows {
+        if let Some(max_bytes) = limits.bytes {
+            if (header.len() + row.len()) as u64 > max_bytes.get() {
+                return Err(Error::OutputPageTooSmall(max_bytes.get(), client));
+            }
+        }
+        let page = pages.last().expect("at least one page");
+        let full = limits
+            .rows
+            .is_some_and(|max_rows| page.len() == max_rows.get())
+            || limits
+                .bytes
+                .is_some_and(|max_bytes| (page_bytes + row.len()) as u64 > max_bytes.get());
+        if full {
+            pages.push(Vec::new());
+            page_bytes = header.len();
+        }
+        page_bytes += row.len();
+        pages
+            .last_mut()
+            .expect("at least one page")
+            .push((client, row));
+    }
+
+    let width = (pages.len() - 1).to_string().len();
+    let mut manifest = WriterBuilder::new()
+        .has_headers(true)
+        .from_path(output_dir.join(MANIFEST_FILE))?;
+
+    for (page, rows) in pages.into_iter().enumerate() {
+        let file = format!("accounts_page_{page:0width$}.csv");
+        let mut writer = File::create(output_dir.join(&file))?;
+        let mut bytes = 0;
+        if !rows.is_empty() {
+            writer.write_all(header)?;
+            bytes += header.len();
+        }
+        for (_, row) in &rows {
+            writer.write_all(row)?;
+            bytes += row.len();
+        }
+        writer.flush()?;
+        manifest.serialize(PageEntry {
+            page,
+            file,
+            first_client: rows.first().map(|(client, _)| *client),
+            last_client: rows.last().map(|(client, _)| *client),
+            clients: rows.len(),
+            bytes,
+        })?;
+    }
+
+    manifest.flush()?;
+
+    Ok(())
+}
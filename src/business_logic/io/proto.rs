@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    io::{BufWriter, ErrorKind, Read, Write},
+};
+
+use crate::{
+    business_logic::domain::{
+        amount::{Amount, Balance},
+        Client, DisputeDetails, DisputeEffect, DisputeOutcomes, DisputeStatus, Transaction,
+        TransferLeg,
+    },
+    shared::{
+        decimal::Decimal,
+        errors::Error,
+        protobuf::{self, FieldValue, MessageReader, MessageWriter},
+    },
+};
+
+/*
+ * Accounts exported by `export-proto` as a stream of `AccountSnapshot` Protocol Buffers messages, each prefixed with
+ * its length, and read back by `import-proto`. The schema is `proto/simulator.proto`; field numbers below follow it.
+ * Amounts are integer numbers of ten-thousandths, as in snapshots. Unknown fields are skipped, so that messages
+ * written by a newer schema can still be read.
+ */
+
+/// Write clients, sorted by ID, as length-delimited `AccountSnapshot` messages
+pub(crate) fn write_accounts<W: Write>(clients: &[&Client], writer: W) -> Result<(), Error> {
+    let mut writer = BufWriter::new(writer);
+    for client in clients {
+        protobuf::write_delimited(&mut writer, encode_account(client))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read clients written by [write_accounts], or by any other program following the schema. A client exported
+/// twice is refused
+pub(crate) fn read_accounts<R: Read>(mut reader: R) -> Result<HashMap<u16, Client>, Error> {
+    let mut clients = HashMap::new();
+    while let Some(message) = protobuf::read_delimited(&mut reader).map_err(wire_error)? {
+        let client = decode_account(&message)?;
+        if clients.contains_key(&client.id) {
+            return Err(invalid(format!("client {} exported twice", client.id)));
+        }
+        clients.insert(client.id, client);
+    }
+    Ok(clients)
+}
+
+fn encode_account(client: &Client) -> MessageWriter {
+    let mut message = MessageWriter::default();
+    message.uint(1, client.id.into());
+    message.sint(2, client.available.value().to_bits());
+    message.sint(3, client.held.value().to_bits());
+    message.sint(4, client.total.value().to_bits());
+    message.bool(5, client.locked);
+
+    let outcomes = &client.dispute_outcomes;
+    let mut outcomes_message = MessageWriter::default();
+    outcomes_message.uint(1, outcomes.opened.into());
+    outcomes_message.sint(2, outcomes.opened_amount.to_bits());
+    outcomes_message.uint(3, outcomes.resolved.into());
+    outcomes_message.sint(4, outcomes.resolved_amount.to_bits());
+    outcomes_message.uint(5, outcomes.charged_back.into());
+    outcomes_message.sint(6, outcomes.charged_back_amount.to_bits());
+    message.message(6, outcomes_message);
+
+    for tx in &client.history_order {
+        if let Some(transaction) = client.transations_history.get(tx) {
+            message.message(7, encode_transaction(*tx, transaction));
+        }
+    }
+    let mut pruned = client.pruned_transactions.iter().collect::<Vec<_>>();
+    pruned.sort();
+    message.packed_uints(8, pruned.into_iter().map(|tx| u64::from(*tx)));
+    message.uint(9, client.version);
+    message.bool(10, client.erased);
+    for annotation in &client.annotations {
+        message.optional_string(11, Some(annotation));
+    }
+    message
+}
+
+fn encode_transaction(tx: u32, transaction: &Transaction) -> MessageWriter {
+    let mut message = MessageWriter::default();
+    message.uint(1, tx.into());
+    message.sint(2, transaction.amount.value().to_bits());
+    let status = match transaction.dispute_status {
+        DisputeStatus::Undisputed => 0,
+        DisputeStatus::Disputed => 1,
+        DisputeStatus::Resolved => 2,
+        DisputeStatus::ChargedBack => 3,
+    };
+    message.uint(3, status);
+    message.optional_string(4, transaction.channel.as_deref());
+    message.optional_string(5, transaction.dispute.reason.as_deref());
+    message.optional_string(6, transaction.dispute.evidence.as_deref());
+    match transaction.transfer {
+        Some(TransferLeg::To(client)) => message.optional_uint(7, Some(client.into())),
+        Some(TransferLeg::From(client)) => message.optional_uint(8, Some(client.into())),
+        None => {}
+    }
+    message.bool(9, transaction.withdrawal);
+    let effect = match transaction.dispute_effect {
+        DisputeEffect::Debit => 0,
+        DisputeEffect::Credit => 1,
+    };
+    message.uint(10, effect);
+    message
+}
+
+fn decode_account(bytes: &[u8]) -> Result<Client, Error> {
+    let mut client = Client::default();
+    let mut fields = MessageReader::new(bytes);
+    while let Some((field, value)) = fields.next_field().map_err(wire_error)? {
+        match field {
+            1 => client.id = narrow(value.uint().map_err(wire_error)?, "client")?,
+            2 => client.available = Balance::new(amount(value)?),
+            3 => client.held = Balance::new(amount(value)?),
+            4 => client.total = Balance::new(amount(value)?),
+            5 => client.locked = value.bool().map_err(wire_error)?,
+            6 => client.dispute_outcomes = decode_outcomes(value.bytes().map_err(wire_error)?)?,
+            7 => {
+                let (tx, transaction) = decode_transaction(value.bytes().map_err(wire_error)?)?;
+                if client.transations_history.insert(tx, transaction).is_some() {
+                    return Err(invalid(format!("transaction {tx} recorded twice")));
+                }
+                client.history_order.push_back(tx);
+            }
+            8 => {
+                for tx in value.uints().map_err(wire_error)? {
+                    client.pruned_transactions.insert(narrow(tx, "pruned tx")?);
+                }
+            }
+            9 => client.version = value.uint().map_err(wire_error)?,
+            10 => client.erased = value.bool().map_err(wire_error)?,
+            11 => client
+                .annotations
+                .push(value.string().map_err(wire_error)?.to_owned()),
+            _ => {}
+        }
+    }
+    Ok(client)
+}
+
+fn decode_outcomes(bytes: &[u8]) -> Result<DisputeOutcomes, Error> {
+    let mut outcomes = DisputeOutcomes::default();
+    let mut fields = MessageReader::new(bytes);
+    while let Some((field, value)) = fields.next_field().map_err(wire_error)? {
+        match field {
+            1 => outcomes.opened = narrow(value.uint().map_err(wire_error)?, "opened disputes")?,
+            2 => outcomes.opened_amount = amount(value)?,
+            3 => {
+                outcomes.resolved = narrow(value.uint().map_err(wire_error)?, "resolved disputes")?
+            }
+            4 => outcomes.resolved_amount = amount(value)?,
+            5 => {
+                outcomes.charged_back =
+                    narrow(value.uint().map_err(wire_error)?, "charged back disputes")?
+            }
+            6 => outcomes.charged_back_amount = amount(value)?,
+            _ => {}
+        }
+    }
+    Ok(outcomes)
+}
+
+fn decode_transaction(bytes: &[u8]) -> Result<(u32, Transaction), Error> {
+    let mut tx = 0;
+    let mut transaction = Transaction {
+        amount: Amount::default(),
+        dispute_status: DisputeStatus::Undisputed,
+        channel: None,
+        dispute: DisputeDetails::default(),
+        transfer: None,
+        withdrawal: false,
+        dispute_effect: DisputeEffect::Debit,
+    };
+    let mut fields = MessageReader::new(bytes);
+    while let Some((field, value)) = fields.next_field().map_err(wire_error)? {
+        let string = |value: FieldValue| value.string().map(str::to_owned).map_err(wire_error);
+        match field {
+            1 => tx = narrow(value.uint().map_err(wire_error)?, "tx")?,
+            2 => {
+                transaction.amount = Amount::new(amount(value)?)
+                    .ok_or_else(|| invalid(format!("negative amount of transaction {tx}")))?
+            }
+            3 => {
+                transaction.dispute_status = match value.uint().map_err(wire_error)? {
+                    0 => DisputeStatus::Undisputed,
+                    1 => DisputeStatus::Disputed,
+                    2 => DisputeStatus::Resolved,
+                    3 => DisputeStatus::ChargedBack,
+                    status => return Err(invalid(format!("dispute status {status}"))),
+                }
+            }
+            4 => transaction.channel = Some(string(value)?),
+            5 => transaction.dispute.reason = Some(string(value)?),
+            6 => transaction.dispute.evidence = Some(string(value)?),
+            7 => {
+                transaction.transfer = Some(TransferLeg::To(narrow(
+                    value.uint().map_err(wire_error)?,
+                    "transferred to",
+                )?))
+            }
+            8 => {
+                transaction.transfer = Some(TransferLeg::From(narrow(
+                    value.uint().map_err(wire_error)?,
+                    "transferred from",
+                )?))
+            }
+            9 => transaction.withdrawal = value.bool().map_err(wire_error)?,
+            10 => {
+                transaction.dispute_effect = match value.uint().map_err(wire_error)? {
+                    0 => DisputeEffect::Debit,
+                    1 => DisputeEffect::Credit,
+                    effect => return Err(invalid(format!("dispute effect {effect}"))),
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok((tx, transaction))
+}
+
+/// Amount of a `sint64` field, in ten-thousandths
+fn amount(value: FieldValue) -> Result<Decimal, Error> {
+    let bits = value.sint().map_err(wire_error)?;
+    Decimal::from_bits(bits).ok_or_else(|| invalid(format!("amount {bits} out of range")))
+}
+
+/// Value of an integer field narrower than its wire type
+fn narrow<T: TryFrom<u64>>(value: u64, name: &str) -> Result<T, Error> {
+    T::try_from(value).map_err(|_| invalid(format!("{name} {value} out of range")))
+}
+
+fn invalid(detail: String) -> Error {
+    Error::InvalidProtobuf(detail)
+}
+
+/// Malformed messages are invalid exports, other errors come from the reader
+fn wire_error(error: std::io::Error) -> Error {
+    match error.kind() {
+        ErrorKind::InvalidData | ErrorKind::UnexpectedEof => invalid(error.to_string()),
+        _ => Error::Io(error),
+    }
+}
@@ -0,0 +1,176 @@
+use std::{io::Write, str::FromStr};
+
+use crate::{
+    business_logic::{
+        domain::Client,
+        engine::{report::write_table, tx_index::TransactionRef},
+        io::dump::json_string,
+        policy::filter::Filter,
+    },
+    shared::{decimal::Decimal, errors::Error},
+};
+
+/// Layout of the accounts printed by the `query` subcommand
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum QueryFormat {
+    /// Human-readable aligned table
+    #[default]
+    Table,
+    /// JSON array of account objects
+    Json,
+}
+
+impl FromStr for QueryFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            _ => Err(Error::InvalidQueryFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Conditions an account must satisfy to be selected by a query. Unset conditions match every account.
+#[derive(Debug, Default)]
+pub struct AccountQuery {
+    /// Select only the given client
+    pub client: Option<u16>,
+    /// Select only locked accounts
+    pub locked: bool,
+    /// Select only accounts holding at least the given amount
+    pub min_held: Option<Decimal>,
+    /// Select only accounts satisfying the filter expression
+    pub filter: Option<Filter>,
+}
+
+impl AccountQuery {
+    pub(crate) fn matches(&self, client: &Client) -> bool {
+        self.client.is_none_or(|id| client.id == id)
+            && (!self.locked || client.locked)
+            && self
+                .min_held
+                .is_none_or(|min_held| client.held.value() >= min_held)
+            && self
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(client))
+    }
+}
+
+/// Write the given clients, already sorted, on `writer` using the requested format
+pub(crate) fn write_accounts<W>(
+    clients: &[&Client],
+    format: QueryFormat,
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    match format {
+        QueryFormat::Table => {
+            let header = [
+                "client",
+                "available",
+                "held",
+                "total",
+                "locked",
+                "version",
+                "annotations",
+            ]
+            .map(str::to_owned)
+            .to_vec();
+            let rows = std::iter::once(header)
+                .chain(clients.iter().map(|client| {
+                    vec![
+                        client.id.to_string(),
+                        client.available.to_string(),
+                        client.held.to_string(),
+                        client.total.to_string(),
+                        client.locked.to_string(),
+                        client.version.to_string(),
+                        client.annotations.join(" | "),
+                    ]
+                }))
+                .collect::<Vec<_>>();
+            write_table(&rows, writer)
+        }
+        QueryFormat::Json => {
+            let objects = clients
+                .iter()
+                .map(|client| account_json(client))
+                .collect::<Vec<_>>();
+            writeln!(writer, "[{}]", objects.join(","))?;
+            Ok(())
+        }
+    }
+}
+
+/// Account of a client as a JSON object, with its version and annotations
+pub(crate) fn account_json(client: &Client) -> String {
+    let annotations = client
+        .annotations
+        .iter()
+        .map(|note| json_string(note))
+        .collect::<Vec<_>>();
+    format!(
+        "{{\"client\":{},\"available\":{},\"held\":{},\"total\":{},\"locked\":{},\"version\":{},\"annotations\":[{}]}}",
+        client.id,
+        client.available,
+        client.held,
+        client.total,
+        client.locked,
+        client.version,
+        annotations.join(",")
+    )
+}
+
+/// Write the given transactions, with their owner and dispute state, on `writer` using the requested format
+pub(crate) fn write_transactions<W>(
+    transactions: &[TransactionRef],
+    format: QueryFormat,
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    match format {
+        QueryFormat::Table => {
+            let header = ["tx", "client", "amount", "state"]
+                .map(str::to_owned)
+                .to_vec();
+            let rows = std::iter::once(header)
+                .chain(transactions.iter().map(|transaction| {
+                    vec![
+                        transaction.tx.to_string(),
+                        transaction.client.to_string(),
+                        transaction
+                            .amount
+                            .map_or_else(String::new, |amount| amount.to_string()),
+                        transaction.state.to_string(),
+                    ]
+                }))
+                .collect::<Vec<_>>();
+            write_table(&rows, writer)
+        }
+        QueryFormat::Json => {
+            let objects = transactions
+                .iter()
+                .map(|transaction| {
+                    format!(
+                        "{{\"tx\":{},\"client\":{},\"amount\":{},\"state\":{}}}",
+                        transaction.tx,
+                        transaction.client,
+                        transaction
+                            .amount
+                            .map_or_else(|| "null".to_owned(), |amount| amount.to_string()),
+                        json_string(&transaction.state.to_string())
+                    )
+                })
+                .collect::<Vec<_>>();
+            writeln!(writer, "[{}]", objects.join(","))?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,530 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    business_logic::domain::{
+        amount::{Amount, Balance},
+        Client, DisputeDetails, DisputeEffect, DisputeOutcomes, DisputeStatus, Transaction,
+        TransferLeg,
+    },
+    shared::{
+        decimal::Decimal,
+        errors::Error,
+        retry::RetryPolicy,
+        sha256::{Digest, Sha256},
+    },
+};
+
+/// Leading bytes identifying a snapshot file
+const MAGIC: &[u8; 6] = b"TXSNAP";
+/// Version of the snapshot layout, bumped on every incompatible change
+pub(crate) const VERSION: u8 = 12;
+
+/*
+ * Snapshot layout, all numbers little endian:
+ * | magic "TXSNAP" | version [u8] | ledger count [u32] | input SHA-256 [32 bytes]... | position [u64] | clients count [u32] | clients... |
+ *
+ * The ledger lists the inputs applied to build the state. Versions 1 to 3 have no ledger.
+ * The position is the number of input records applied by an interrupted run after the inputs of the ledger, if the
+ * snapshot is a checkpoint, 0 otherwise. Versions 1 to 8 have no position.
+ *
+ * client:
+ * | id [u16] | available [amount] | held [amount] | total [amount] | locked [u8] | dispute outcomes | history count [u32] | transactions... |
+ * | pruned count [u32] | pruned tx [u32]... | account version [u64] | erased [u8] | annotations count [u32] | annotations... |
+ *
+ * Transactions are written from the oldest to the newest. Version 1 has no pruned transactions,
+ * versions 1 and 2 have no account version, versions 1 to 6 have no erased flag, versions 1 to 7 have no annotations.
+ *
+ * dispute outcomes:
+ * | opened [u32] | opened amount [amount] | resolved [u32] | resolved amount [amount] | charged back [u32] | charged back amount [amount] |
+ *
+ * transaction:
+ * | tx [u32] | amount [amount] | dispute status [u8] | channel [optional string] |
+ * | dispute reason [optional string] | dispute evidence [optional string] | transfer leg [u8] | other client [u16] |
+ * | withdrawal [u8] | dispute effect [u8] |
+ *
+ * Versions 1 to 5 have no dispute reason and evidence, versions 1 to 9 have no transfer leg, versions 1 to 10 have
+ * no withdrawal flag and dispute effect (every transaction is read as a deposit, disputed with the debit effect).
+ * Transfer leg: 0 for a transaction other than a transfer (without other client), 1 for a transfer sent to the
+ * other client, 2 for a transfer received from it.
+ * Dispute effect: 0 for funds moved from available to held (debit), 1 for a withdrawal credited back as held funds.
+ * Dispute status: 0 for undisputed, 1 for disputed, 2 for resolved, 3 for charged back. Versions 1 to 11 store an
+ * "is under dispute" flag instead, left set once the dispute is resolved or charged back: 1 is read as disputed.
+ *
+ * amount: integer number of ten-thousandths [i64]. Versions 1 to 4 store amounts as [f64]
+ *
+ * annotation: | length [u32] | UTF-8 bytes |
+ *
+ * optional string: | present [u8] | length [u32] | UTF-8 bytes | (length and bytes only if present)
+ */
+
+/// Write clients, sorted by ID, as a snapshot file whose ledger lists the digests of the applied inputs.
+/// The snapshot is written next to `path` and then renamed, so that a failed write leaves the previous file intact
+pub(crate) fn write_snapshot<'a>(
+    clients: impl Iterator<Item = &'a Client>,
+    ledger: &[Digest],
+    path: &Path,
+) -> Result<(), Error> {
+    write_checkpoint(clients, ledger, 0, path)
+}
+
+/// Same as [write_snapshot], for the state of a run interrupted after applying `position` input records
+/// following the inputs of the ledger
+pub(crate) fn write_checkpoint<'a>(
+    clients: impl Iterator<Item = &'a Client>,
+    ledger: &[Digest],
+    position: u64,
+    path: &Path,
+) -> Result<(), Error> {
+    let mut clients = clients.collect::<Vec<_>>();
+    clients.sort_by_key(|client| client.id);
+
+    let mut partial_path = path.as_os_str().to_owned();
+    partial_path.push(".partial");
+    write_clients(&clients, ledger, position, Path::new(&partial_path))?;
+    std::fs::rename(&partial_path, path)?;
+
+    Ok(())
+}
+
+fn write_clients(
+    clients: &[&Client],
+    ledger: &[Digest],
+    position: u64,
+    path: &Path,
+) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION])?;
+    writer.write_all(&(ledger.len() as u32).to_le_bytes())?;
+    ledger
+        .iter()
+        .try_for_each(|digest| writer.write_all(digest))?;
+    writer.write_all(&position.to_le_bytes())?;
+    writer.write_all(&(clients.len() as u32).to_le_bytes())?;
+    clients
+        .iter()
+        .try_for_each(|client| encode_client(client, &mut writer))?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// SHA-256 of the clients, sorted by ID, encoded as in a snapshot: balances, dispute outcomes, transactions
+/// history, pruned transactions, account version, erased flag and annotations
+pub(crate) fn state_digest<'a>(clients: impl Iterator<Item = &'a Client>) -> Result<Digest, Error> {
+    let mut clients = clients.collect::<Vec<_>>();
+    clients.sort_by_key(|client| client.id);
+
+    let mut hasher = Sha256::default();
+    hasher.write_all(&(clients.len() as u32).to_le_bytes())?;
+    clients
+        .iter()
+        .try_for_each(|client| encode_client(client, &mut hasher))?;
+    Ok(hasher.finalize())
+}
+
+/// Client encoded as in a snapshot
+pub(crate) fn encode_account(client: &Client) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    // writing on a Vec never fails
+    let _ = encode_client(client, &mut bytes);
+    bytes
+}
+
+/// Client encoded by [encode_account]
+pub(crate) fn decode_account(mut bytes: &[u8]) -> Result<Client, Error> {
+    decode_client(&mut bytes, VERSION)
+}
+
+/// Read all clients from a snapshot file
+pub(crate) fn read_snapshot(path: &Path) -> Result<HashMap<u16, Client>, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let (version, ..) = read_header(&mut reader)?;
+
+    (0..read_u32(&mut reader)?)
+        .map(|_| decode_client(&mut reader, version).map(|client| (client.id, client)))
+        .collect()
+}
+
+/// Read the digests of the inputs applied to build the state of a snapshot file
+pub(crate) fn read_ledger(path: &Path) -> Result<Vec<Digest>, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read_header(&mut reader).map(|(_, ledger, _)| ledger)
+}
+
+/// Read the number of input records applied after the inputs of the ledger, if the snapshot file is a checkpoint
+/// of an interrupted run, 0 otherwise
+pub(crate) fn read_position(path: &Path) -> Result<u64, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read_header(&mut reader).map(|(_, _, position)| position)
+}
+
+/// Read and check magic and version of a snapshot, returning the version, the ledger and the position
+fn read_header<R: Read>(reader: &mut R) -> Result<(u8, Vec<Digest>, u64), Error> {
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(Error::InvalidSnapshot("not a snapshot file".to_owned()));
+    }
+    let version = read_u8(reader)?;
+    if version == 0 || version > VERSION {
+        return Err(Error::InvalidSnapshot(format!(
+            "unsupported version {version}"
+        )));
+    }
+
+    let mut ledger = Vec::new();
+    if version >= 4 {
+        for _ in 0..read_u32(reader)? {
+            let mut digest = [0; 32];
+            reader.read_exact(&mut digest)?;
+            ledger.push(digest);
+        }
+    }
+    let position = match version >= 9 {
+        true => read_u64(reader)?,
+        false => 0,
+    };
+
+    Ok((version, ledger, position))
+}
+
+/// Move closed accounts (zero balances and no pending dispute) out of `clients`, merging them into the archive
+/// snapshot at `archive_path` (created if missing). Archived accounts can still be consulted by reading the archive.
+/// The inputs of `ledger` are added to the ledger of the archive. Archive reads and writes are retried according
+/// to `retry`, while accounts are moved only once, so that none is lost when an attempt fails.
+pub(crate) fn archive_closed_accounts(
+    clients: &mut HashMap<u16, Client>,
+    ledger: &[Digest],
+    archive_path: &Path,
+    retry: &RetryPolicy,
+) -> Result<(), Error> {
+    let (mut archive, mut archive_ledger) = match archive_path.exists() {
+        true => (
+            retry.run("read archive", || read_snapshot(archive_path))?,
+            retry.run("read archive ledger", || read_ledger(archive_path))?,
+        ),
+        false => (HashMap::new(), Vec::new()),
+    };
+    ledger.iter().for_each(|digest| {
+        if !archive_ledger.contains(digest) {
+            archive_ledger.push(*digest);
+        }
+    });
+
+    let closed = clients
+        .values()
+        .filter(|client| is_closed(client))
+        .map(|client| client.id)
+        .collect::<Vec<_>>();
+    closed.into_iter().for_each(|id| {
+        if let Some(mut client) = clients.remove(&id) {
+            // history of closed accounts is dropped, only the final state is archived
+            client.transations_history.clear();
+            client.history_order.clear();
+            client.pruned_transactions.clear();
+            archive.insert(id, client);
+        }
+    });
+
+    retry.run("write archive", || {
+        write_snapshot(archive.values(), &archive_ledger, archive_path)
+    })
+}
+
+fn is_closed(client: &Client) -> bool {
+    client.available == Balance::ZERO
+        && client.held == Balance::ZERO
+        && client.total == Balance::ZERO
+        && client.dispute_outcomes.pending() == 0
+}
+
+fn encode_client<W: Write>(client: &Client, writer: &mut W) -> Result<(), Error> {
+    writer.write_all(&client.id.to_le_bytes())?;
+    write_amount(client.available.value(), writer)?;
+    write_amount(client.held.value(), writer)?;
+    write_amount(client.total.value(), writer)?;
+    writer.write_all(&[client.locked as u8])?;
+
+    let outcomes = &client.dispute_outcomes;
+    writer.write_all(&outcomes.opened.to_le_bytes())?;
+    write_amount(outcomes.opened_amount, writer)?;
+    writer.write_all(&outcomes.resolved.to_le_bytes())?;
+    write_amount(outcomes.resolved_amount, writer)?;
+    writer.write_all(&outcomes.charged_back.to_le_bytes())?;
+    write_amount(outcomes.charged_back_amount, writer)?;
+
+    let history = client
+        .history_order
+        .iter()
+        .filter_map(|tx| {
+            client
+                .transations_history
+                .get(tx)
+                .map(|transaction| (tx, transaction))
+        })
+        .collect::<Vec<_>>();
+    writer.write_all(&(history.len() as u32).to_le_bytes())?;
+    for (tx, transaction) in history {
+        writer.write_all(&tx.to_le_bytes())?;
+        encode_transaction(transaction, writer)?;
+    }
+
+    let mut pruned = client.pruned_transactions.iter().collect::<Vec<_>>();
+    pruned.sort();
+    writer.write_all(&(pruned.len() as u32).to_le_bytes())?;
+    pruned
+        .into_iter()
+        .try_for_each(|tx| writer.write_all(&tx.to_le_bytes()))?;
+    writer.write_all(&client.version.to_le_bytes())?;
+    writer.write_all(&[client.erased as u8])?;
+    writer.write_all(&(client.annotations.len() as u32).to_le_bytes())?;
+    for annotation in &client.annotations {
+        writer.write_all(&(annotation.len() as u32).to_le_bytes())?;
+        writer.write_all(annotation.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn decode_client<R: Read>(reader: &mut R, version: u8) -> Result<Client, Error> {
+    let mut client = Client {
+        id: read_u16(reader)?,
+        available: Balance::new(read_amount(reader, version)?),
+        held: Balance::new(read_amount(reader, version)?),
+        total: Balance::new(read_amount(reader, version)?),
+        locked: read_u8(reader)? != 0,
+        dispute_outcomes: DisputeOutcomes {
+            opened: read_u32(reader)?,
+            opened_amount: read_amount(reader, version)?,
+            resolved: read_u32(reader)?,
+            resolved_amount: read_amount(reader, version)?,
+            charged_back: read_u32(reader)?,
+            charged_back_amount: read_amount(reader, version)?,
+        },
+        ..Default::default()
+    };
+
+    for _ in 0..read_u32(reader)? {
+        let tx = read_u32(reader)?;
+        let transaction = decode_transaction(reader, version, tx)?;
+        client.history_order.push_back(tx);
+        client.transations_history.insert(tx, transaction);
+    }
+
+    if version >= 2 {
+        for _ in 0..read_u32(reader)? {
+            client.pruned_transactions.insert(read_u32(reader)?);
+        }
+    }
+    if version >= 3 {
+        client.version = read_u64(reader)?;
+    }
+    if version >= 7 {
+        client.erased = read_u8(reader)? != 0;
+    }
+    if version >= 8 {
+        for _ in 0..read_u32(reader)? {
+            client.annotations.push(decode_string(reader)?);
+        }
+    }
+
+    Ok(client)
+}
+
+/// Write a transaction of the history, without its ID
+pub(crate) fn encode_transaction<W: Write>(
+    transaction: &Transaction,
+    writer: &mut W,
+) -> Result<(), Error> {
+    write_amount(transaction.amount.value(), writer)?;
+    encode_dispute_status(transaction.dispute_status, writer)?;
+    encode_optional_string(transaction.channel.as_deref(), writer)?;
+    encode_optional_string(transaction.dispute.reason.as_deref(), writer)?;
+    encode_optional_string(transaction.dispute.evidence.as_deref(), writer)?;
+    match transaction.transfer {
+        None => writer.write_all(&[0])?,
+        Some(TransferLeg::To(client)) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&client.to_le_bytes())?;
+        }
+        Some(TransferLeg::From(client)) => {
+            writer.write_all(&[2])?;
+            writer.write_all(&client.to_le_bytes())?;
+        }
+    }
+    writer.write_all(&[transaction.withdrawal as u8])?;
+    encode_dispute_effect(transaction.dispute_effect, writer)
+}
+
+/// Read transaction `tx` of the history, as written by [encode_transaction] in snapshot layout `version`
+pub(crate) fn decode_transaction<R: Read>(
+    reader: &mut R,
+    version: u8,
+    tx: u32,
+) -> Result<Transaction, Error> {
+    Ok(Transaction {
+        amount: Amount::new(read_amount(reader, version)?).ok_or_else(|| {
+            Error::InvalidSnapshot(format!("negative amount of transaction {tx}"))
+        })?,
+        dispute_status: match version >= 12 {
+            true => decode_dispute_status(reader)?,
+            false => match read_u8(reader)? {
+                0 => DisputeStatus::Undisputed,
+                _ => DisputeStatus::Disputed,
+            },
+        },
+        channel: decode_optional_string(reader)?,
+        dispute: match version >= 6 {
+            true => DisputeDetails {
+                reason: decode_optional_string(reader)?,
+                evidence: decode_optional_string(reader)?,
+            },
+            false => DisputeDetails::default(),
+        },
+        transfer: match version >= 10 {
+            true => match read_u8(reader)? {
+                0 => None,
+                1 => Some(TransferLeg::To(read_u16(reader)?)),
+                2 => Some(TransferLeg::From(read_u16(reader)?)),
+                leg => {
+                    return Err(Error::InvalidSnapshot(format!(
+                        "transfer leg {leg} of transaction {tx}"
+                    )))
+                }
+            },
+            false => None,
+        },
+        withdrawal: version >= 11 && read_u8(reader)? != 0,
+        dispute_effect: match version >= 11 {
+            true => decode_dispute_effect(reader)?,
+            false => DisputeEffect::Debit,
+        },
+    })
+}
+
+fn encode_dispute_status<W: Write>(status: DisputeStatus, writer: &mut W) -> Result<(), Error> {
+    let byte = match status {
+        DisputeStatus::Undisputed => 0,
+        DisputeStatus::Disputed => 1,
+        DisputeStatus::Resolved => 2,
+        DisputeStatus::ChargedBack => 3,
+    };
+    writer.write_all(&[byte])?;
+    Ok(())
+}
+
+fn decode_dispute_status<R: Read>(reader: &mut R) -> Result<DisputeStatus, Error> {
+    match read_u8(reader)? {
+        0 => Ok(DisputeStatus::Undisputed),
+        1 => Ok(DisputeStatus::Disputed),
+        2 => Ok(DisputeStatus::Resolved),
+        3 => Ok(DisputeStatus::ChargedBack),
+        status => Err(Error::InvalidSnapshot(format!("dispute status {status}"))),
+    }
+}
+
+pub(crate) fn encode_dispute_effect<W: Write>(
+    effect: DisputeEffect,
+    writer: &mut W,
+) -> Result<(), Error> {
+    let byte = match effect {
+        DisputeEffect::Debit => 0,
+        DisputeEffect::Credit => 1,
+    };
+    writer.write_all(&[byte])?;
+    Ok(())
+}
+
+pub(crate) fn decode_dispute_effect<R: Read>(reader: &mut R) -> Result<DisputeEffect, Error> {
+    match read_u8(reader)? {
+        0 => Ok(DisputeEffect::Debit),
+        1 => Ok(DisputeEffect::Credit),
+        effect => Err(Error::InvalidSnapshot(format!("dispute effect {effect}"))),
+    }
+}
+
+pub(crate) fn encode_optional_string<W: Write>(
+    value: Option<&str>,
+    writer: &mut W,
+) -> Result<(), Error> {
+    match value {
+        Some(value) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&(value.len() as u32).to_le_bytes())?;
+            writer.write_all(value.as_bytes())?;
+        }
+        None => writer.write_all(&[0])?,
+    }
+
+    Ok(())
+}
+
+pub(crate) fn decode_optional_string<R: Read>(reader: &mut R) -> Result<Option<String>, Error> {
+    if read_u8(reader)? == 0 {
+        return Ok(None);
+    }
+    decode_string(reader).map(Some)
+}
+
+pub(crate) fn decode_string<R: Read>(reader: &mut R) -> Result<String, Error> {
+    let length = read_u32(reader)?;
+    // the length is not trusted with an allocation before the string is read, as it may be corrupt
+    let mut bytes = Vec::new();
+    reader.take(length.into()).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != u64::from(length) {
+        return Err(Error::InvalidSnapshot(format!(
+            "string of {length} bytes cut short"
+        )));
+    }
+
+    String::from_utf8(bytes).map_err(|_| Error::InvalidSnapshot("invalid UTF-8 string".to_owned()))
+}
+
+pub(crate) fn read_u8<R: Read>(reader: &mut R) -> Result<u8, Error> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub(crate) fn read_u16<R: Read>(reader: &mut R) -> Result<u16, Error> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(crate) fn write_amount<W: Write>(amount: Decimal, writer: &mut W) -> Result<(), Error> {
+    writer.write_all(&amount.to_bits().to_le_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn read_amount<R: Read>(reader: &mut R, version: u8) -> Result<Decimal, Error> {
+    let bits = match version >= 5 {
+        true => read_u64(reader)? as i64,
+        false => Decimal::from_f64(read_f64(reader)?).to_bits(),
+    };
+    Decimal::from_bits(bits).ok_or_else(|| Error::InvalidSnapshot("amount out of range".to_owned()))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> Result<f64, Error> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
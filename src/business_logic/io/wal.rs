@@ -0,0 +1,561 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    business_logic::{
+        domain::{
+            amount::Amount, events::Event, transactions_logic::Rejection, Client, DisputeDetails,
+        },
+        engine::merge::{AccountMerger, DuplicateClients},
+        io::snapshot::{
+            self, decode_dispute_effect, decode_optional_string, encode_dispute_effect,
+            encode_optional_string, read_amount, read_u16, read_u32, read_u64, read_u8,
+            write_amount,
+        },
+    },
+    shared::{
+        errors::Error,
+        sha256::{Digest, Sha256},
+    },
+};
+
+/// Leading bytes identifying a segment of a write-ahead log
+const MAGIC: &[u8; 5] = b"TXWAL";
+/// Version of the segment layout, bumped on every incompatible change
+const VERSION: u8 = 2;
+/// Checkpoint of the accounts before the run, in the directory of the log
+const BASE_FILE: &str = "base.snap";
+
+/*
+ * Write-ahead log of a run with worker threads, a directory holding:
+ *
+ * | File                | Content                                                                                       |
+ * |---------------------|-----------------------------------------------------------------------------------------------|
+ * | `base.snap`         | checkpoint of the accounts before the run (see `snapshot.rs`), with its ledger and position   |
+ * | `shard-<index>.wal` | segment of a worker, appended with the transactions it applies, in the order they are applied |
+ *
+ * Segment layout, all numbers little endian:
+ * | magic "TXWAL" | version [u8] | shard index [u16] | shards count [u16] | frames... |
+ *
+ * frame, appended and synced to disk once every transaction of a batch is applied:
+ * | payload length [u32] | watermark [u64] | entries count [u32] | entries... | SHA-256 of the payload [32 bytes] |
+ *
+ * The payload is made of the watermark and the entries. The watermark is the sequence number of the last input
+ * record dispatched before the batch: every transaction of the shard up to it is applied. A frame cut short, or
+ * whose checksum does not match, was being written when the run was interrupted, and ends the segment.
+ *
+ * entry, for every transaction applied or rejected (which still opens the account of its client):
+ * | seq [u64] | client [u16] | applied [u8] | events count [u32] | events... |
+ *
+ * event: | kind [u8] | fields |
+ *
+ * | Kind | Event             | Fields                                                                   |
+ * |------|-------------------|--------------------------------------------------------------------------|
+ * | 0    | deposited         | tx [u32], amount [amount], channel [optional string]                     |
+ * | 1    | withdrawn         | tx [u32], amount [amount], channel [optional string]                     |
+ * | 2    | dispute implied   | tx [u32]                                                                 |
+ * | 3    | dispute opened    | tx [u32], amount [amount], reason and evidence [optional string], effect |
+ * | 4    | dispute resolved  | tx [u32], amount [amount], effect                                        |
+ * | 5    | charged back      | tx [u32], amount [amount], reason and evidence [optional string], effect |
+ * | 6    | adjusted          | signed amount [amount], reason [optional string, always present]         |
+ * | 7    | locked            |                                                                          |
+ * | 8    | pruned            | tx [u32]                                                                 |
+ * | 9    | transferred out   | tx [u32], amount [amount], to client [u16], channel [optional string]    |
+ * | 10   | transferred in    | tx [u32], amount [amount], from client [u16], channel [optional string]  |
+ * | 11   | transfer disputed | tx [u32], reason and evidence [optional string]                          |
+ * | 12   | transfer reversed | tx [u32], amount [amount]                                                |
+ * | 13   | unlocked          | tx [u32], reason [optional string], audited [u8]                         |
+ * | 14   | transfer resolved | tx [u32]                                                                 |
+ *
+ * amount, optional string and dispute effect are encoded as in snapshots. Segments of version 1, without dispute
+ * effect, are refused.
+ */
+
+/// Segment of the write-ahead log of a worker, buffering the entries of a batch until it is committed
+pub(crate) struct SegmentWriter {
+    file: File,
+    entries: Vec<u8>,
+    count: u32,
+}
+
+impl SegmentWriter {
+    /// Create the segment of worker `shard` out of `shards` in `dir`, replacing the one of a previous run
+    pub(crate) fn create(dir: &Path, shard: usize, shards: usize) -> Result<Self, Error> {
+        let mut file = File::create(segment_path(dir, shard))?;
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        file.write_all(&(shard as u16).to_le_bytes())?;
+        file.write_all(&(shards as u16).to_le_bytes())?;
+        file.sync_data()?;
+
+        Ok(Self {
+            file,
+            entries: Vec::new(),
+            count: 0,
+        })
+    }
+
+    /// Add the outcome of the transaction of input record `seq` to the current frame
+    pub(crate) fn append(
+        &mut self,
+        seq: u64,
+        client: u16,
+        result: &Result<Vec<Event>, Rejection>,
+    ) -> Result<(), Error> {
+        let events = result.as_deref().unwrap_or_default();
+        self.entries.write_all(&seq.to_le_bytes())?;
+        self.entries.write_all(&client.to_le_bytes())?;
+        self.entries.write_all(&[result.is_ok() as u8])?;
+        self.entries
+            .write_all(&(events.len() as u32).to_le_bytes())?;
+        events
+            .iter()
+            .try_for_each(|event| encode_event(event, &mut self.entries))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Append the current frame to the segment, with the given watermark, and sync it to disk
+    pub(crate) fn commit(&mut self, watermark: u64) -> Result<(), Error> {
+        let mut payload = Vec::with_capacity(12 + self.entries.len());
+        payload.write_all(&watermark.to_le_bytes())?;
+        payload.write_all(&self.count.to_le_bytes())?;
+        payload.append(&mut self.entries);
+        self.count = 0;
+
+        let mut hasher = Sha256::default();
+        hasher.update(&payload);
+        let mut frame = Vec::with_capacity(4 + payload.len() + 32);
+        frame.write_all(&(payload.len() as u32).to_le_bytes())?;
+        frame.write_all(&payload)?;
+        frame.write_all(&hasher.finalize())?;
+        self.file.write_all(&frame)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Start the write-ahead log of a run in `dir`, created if missing: save the accounts before the run, with the
+/// ledger and the position of the run, and remove the segments of a previous run
+pub(crate) fn start<'a>(
+    dir: &Path,
+    clients: impl Iterator<Item = &'a Client>,
+    ledger: &[Digest],
+    position: u64,
+) -> Result<(), Error> {
+    std::fs::create_dir_all(dir)?;
+    for path in segment_paths(dir)? {
+        std::fs::remove_file(path)?;
+    }
+    snapshot::write_checkpoint(clients, ledger, position, &dir.join(BASE_FILE))
+}
+
+/// Accounts rebuilt from a write-ahead log
+pub(crate) struct Recovered {
+    pub(crate) clients: HashMap<u16, Client>,
+    /// Ledger of the run that wrote the log
+    pub(crate) ledger: Vec<Digest>,
+    /// Number of input records applied to the accounts after the inputs of the ledger
+    pub(crate) position: u64,
+}
+
+/// Rebuild the accounts of the run that wrote the write-ahead log in `dir`, as they were after the last input
+/// record every worker is known to have handled: the accounts before the run are split among the shards, and
+/// the segments are replayed in parallel, one thread per segment
+pub(crate) fn recover(dir: &Path) -> Result<Recovered, Error> {
+    let base_file = dir.join(BASE_FILE);
+    let ledger = snapshot::read_ledger(&base_file)?;
+    let base_position = snapshot::read_position(&base_file)?;
+    let clients = snapshot::read_snapshot(&base_file)?;
+
+    let paths = segment_paths(dir)?;
+    let scans = std::thread::scope(|scope| {
+        let scans = paths
+            .iter()
+            .map(|path| scope.spawn(|| scan_segment(path)))
+            .collect::<Vec<_>>();
+        scans
+            .into_iter()
+            .map(|scan| {
+                scan.join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect::<Result<Vec<_>, Error>>()
+    })?;
+    let shards = scans.len();
+    if shards == 0 {
+        return Err(Error::InvalidWal(format!(
+            "no segment in {}",
+            dir.display()
+        )));
+    }
+    if let Some((shard, count, _)) = scans
+        .iter()
+        .enumerate()
+        .find(|(index, (shard, count, _))| *count as usize != shards || *shard as usize != *index)
+        .map(|(_, scan)| scan)
+    {
+        return Err(Error::InvalidWal(format!(
+            "segment of shard {shard} out of {count}, but {shards} segment(s) found"
+        )));
+    }
+    // a segment without any frame was interrupted before its first batch was applied
+    let position = scans
+        .iter()
+        .map(|(_, _, watermark)| watermark.unwrap_or(base_position))
+        .min()
+        .unwrap_or(base_position)
+        .max(base_position);
+
+    let mut shard_clients = (0..shards).map(|_| HashMap::new()).collect::<Vec<_>>();
+    for (id, client) in clients {
+        shard_clients[id as usize % shards].insert(id, client);
+    }
+    let clients = std::thread::scope(|scope| {
+        let replays = paths
+            .iter()
+            .zip(shard_clients)
+            .map(|(path, mut clients)| {
+                scope.spawn(move || replay_segment(path, &mut clients, position).map(|_| clients))
+            })
+            .collect::<Vec<_>>();
+        // shards own distinct clients: one found in two segments means the log is not consistent
+        let mut merger = AccountMerger::new(DuplicateClients::Error);
+        for (shard, replay) in replays.into_iter().enumerate() {
+            let clients = replay
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+            merger.add_all(clients, &format!("segment {shard}"))?;
+        }
+        Ok::<_, Error>(merger.finish().0)
+    })?;
+
+    Ok(Recovered {
+        clients,
+        ledger,
+        position,
+    })
+}
+
+fn segment_path(dir: &Path, shard: usize) -> PathBuf {
+    dir.join(format!("shard-{shard}.wal"))
+}
+
+/// Segments found in `dir`, sorted by shard index
+fn segment_paths(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut segments = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let shard = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("shard-")?.strip_suffix(".wal"))
+            .and_then(|shard| shard.parse::<usize>().ok());
+        if let Some(shard) = shard {
+            segments.push((shard, dir.join(name)));
+        }
+    }
+    segments.sort();
+    Ok(segments.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Read the header of a segment, returning its shard index and shards count
+fn read_segment_header<R: Read>(reader: &mut R) -> Result<(u16, u16), Error> {
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(Error::InvalidWal("not a segment file".to_owned()));
+    }
+    let version = read_u8(reader)?;
+    if version != VERSION {
+        return Err(Error::InvalidWal(format!("unsupported version {version}")));
+    }
+    Ok((read_u16(reader)?, read_u16(reader)?))
+}
+
+/// Shard index, shards count and watermark of the last complete frame of a segment
+fn scan_segment(path: &Path) -> Result<(u16, u16, Option<u64>), Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let (shard, shards) = read_segment_header(&mut reader)?;
+    let mut watermark = None;
+    while let Some(payload) = read_frame(&mut reader)? {
+        watermark = Some(read_u64(&mut &payload[..])?);
+    }
+    Ok((shard, shards, watermark))
+}
+
+/// Apply to `clients` the transactions of a segment up to input record `position`
+fn replay_segment(
+    path: &Path,
+    clients: &mut HashMap<u16, Client>,
+    position: u64,
+) -> Result<(), Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read_segment_header(&mut reader)?;
+    while let Some(payload) = read_frame(&mut reader)? {
+        let mut payload = &payload[..];
+        let watermark = read_u64(&mut payload)?;
+        for _ in 0..read_u32(&mut payload)? {
+            let seq = read_u64(&mut payload)?;
+            let id = read_u16(&mut payload)?;
+            let applied = read_u8(&mut payload)? != 0;
+            let events = (0..read_u32(&mut payload)?)
+                .map(|_| decode_event(&mut payload))
+                .collect::<Result<Vec<_>, Error>>()?;
+            if seq > position {
+                return Ok(());
+            }
+            let client = clients.entry(id).or_insert_with(|| Client::new(id));
+            if applied {
+                client.replay_transaction(&events).map_err(|rejection| {
+                    Error::InvalidWal(format!(
+                        "transaction of input record {seq} cannot be replayed on client {id}: {rejection}"
+                    ))
+                })?;
+            }
+        }
+        if watermark >= position {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Payload of the next frame of a segment, `None` at the end of the segment or of its complete frames
+fn read_frame<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, Error> {
+    let mut length = [0; 4];
+    let mut read = 0;
+    while read < length.len() {
+        match reader.read(&mut length[read..])? {
+            0 => return Ok(None),
+            bytes => read += bytes,
+        }
+    }
+    let length = u32::from_le_bytes(length) as u64;
+
+    // a length garbled by the interruption allocates no more than what the segment holds
+    let mut payload = Vec::new();
+    reader.take(length).read_to_end(&mut payload)?;
+    let mut checksum = Vec::new();
+    reader.take(32).read_to_end(&mut checksum)?;
+    let mut hasher = Sha256::default();
+    hasher.update(&payload);
+    match payload.len() as u64 == length && checksum[..] == hasher.finalize()[..] {
+        true => Ok(Some(payload)),
+        false => Ok(None),
+    }
+}
+
+fn encode_event<W: Write>(event: &Event, writer: &mut W) -> Result<(), Error> {
+    match event {
+        Event::Deposited {
+            tx,
+            amount,
+            channel,
+        } => {
+            writer.write_all(&[0])?;
+            encode_movement(*tx, *amount, writer)?;
+            encode_optional_string(channel.as_deref(), writer)
+        }
+        Event::Withdrawn {
+            tx,
+            amount,
+            channel,
+        } => {
+            writer.write_all(&[1])?;
+            encode_movement(*tx, *amount, writer)?;
+            encode_optional_string(channel.as_deref(), writer)
+        }
+        Event::DisputeImplied { tx } => {
+            writer.write_all(&[2])?;
+            writer.write_all(&tx.to_le_bytes())?;
+            Ok(())
+        }
+        Event::DisputeOpened {
+            tx,
+            amount,
+            details,
+            effect,
+        } => {
+            writer.write_all(&[3])?;
+            encode_movement(*tx, *amount, writer)?;
+            encode_details(details, writer)?;
+            encode_dispute_effect(*effect, writer)
+        }
+        Event::DisputeResolved { tx, amount, effect } => {
+            writer.write_all(&[4])?;
+            encode_movement(*tx, *amount, writer)?;
+            encode_dispute_effect(*effect, writer)
+        }
+        Event::ChargedBack {
+            tx,
+            amount,
+            details,
+            effect,
+        } => {
+            writer.write_all(&[5])?;
+            encode_movement(*tx, *amount, writer)?;
+            encode_details(details, writer)?;
+            encode_dispute_effect(*effect, writer)
+        }
+        Event::Adjusted { amount, reason } => {
+            writer.write_all(&[6])?;
+            write_amount(*amount, writer)?;
+            encode_optional_string(Some(reason), writer)
+        }
+        Event::Locked => {
+            writer.write_all(&[7])?;
+            Ok(())
+        }
+        Event::Pruned { tx } => {
+            writer.write_all(&[8])?;
+            writer.write_all(&tx.to_le_bytes())?;
+            Ok(())
+        }
+        Event::TransferredOut {
+            tx,
+            amount,
+            to,
+            channel,
+        } => {
+            writer.write_all(&[9])?;
+            encode_movement(*tx, *amount, writer)?;
+            writer.write_all(&to.to_le_bytes())?;
+            encode_optional_string(channel.as_deref(), writer)
+        }
+        Event::TransferredIn {
+            tx,
+            amount,
+            from,
+            channel,
+        } => {
+            writer.write_all(&[10])?;
+            encode_movement(*tx, *amount, writer)?;
+            writer.write_all(&from.to_le_bytes())?;
+            encode_optional_string(channel.as_deref(), writer)
+        }
+        Event::TransferDisputed { tx, details } => {
+            writer.write_all(&[11])?;
+            writer.write_all(&tx.to_le_bytes())?;
+            encode_details(details, writer)
+        }
+        Event::TransferResolved { tx } => {
+            writer.write_all(&[14])?;
+            writer.write_all(&tx.to_le_bytes())?;
+            Ok(())
+        }
+        Event::TransferReversed { tx, amount } => {
+            writer.write_all(&[12])?;
+            encode_movement(*tx, *amount, writer)
+        }
+        Event::Unlocked {
+            tx,
+            reason,
+            audited,
+        } => {
+            writer.write_all(&[13])?;
+            writer.write_all(&tx.to_le_bytes())?;
+            encode_optional_string(reason.as_deref(), writer)?;
+            writer.write_all(&[u8::from(*audited)])?;
+            Ok(())
+        }
+    }
+}
+
+fn decode_event<R: Read>(reader: &mut R) -> Result<Event, Error> {
+    let event = match read_u8(reader)? {
+        0 => Event::Deposited {
+            tx: read_u32(reader)?,
+            amount: decode_amount(reader)?,
+            channel: decode_optional_string(reader)?,
+        },
+        1 => Event::Withdrawn {
+            tx: read_u32(reader)?,
+            amount: decode_amount(reader)?,
+            channel: decode_optional_string(reader)?,
+        },
+        2 => Event::DisputeImplied {
+            tx: read_u32(reader)?,
+        },
+        3 => Event::DisputeOpened {
+            tx: read_u32(reader)?,
+            amount: decode_amount(reader)?,
+            details: decode_details(reader)?,
+            effect: decode_dispute_effect(reader)?,
+        },
+        4 => Event::DisputeResolved {
+            tx: read_u32(reader)?,
+            amount: decode_amount(reader)?,
+            effect: decode_dispute_effect(reader)?,
+        },
+        5 => Event::ChargedBack {
+            tx: read_u32(reader)?,
+            amount: decode_amount(reader)?,
+            details: decode_details(reader)?,
+            effect: decode_dispute_effect(reader)?,
+        },
+        6 => Event::Adjusted {
+            amount: read_amount(reader, snapshot::VERSION)?,
+            reason: decode_optional_string(reader)?.unwrap_or_default(),
+        },
+        7 => Event::Locked,
+        8 => Event::Pruned {
+            tx: read_u32(reader)?,
+        },
+        9 => Event::TransferredOut {
+            tx: read_u32(reader)?,
+            amount: decode_amount(reader)?,
+            to: read_u16(reader)?,
+            channel: decode_optional_string(reader)?,
+        },
+        10 => Event::TransferredIn {
+            tx: read_u32(reader)?,
+            amount: decode_amount(reader)?,
+            from: read_u16(reader)?,
+            channel: decode_optional_string(reader)?,
+        },
+        11 => Event::TransferDisputed {
+            tx: read_u32(reader)?,
+            details: decode_details(reader)?,
+        },
+        12 => Event::TransferReversed {
+            tx: read_u32(reader)?,
+            amount: decode_amount(reader)?,
+        },
+        13 => Event::Unlocked {
+            tx: read_u32(reader)?,
+            reason: decode_optional_string(reader)?,
+            audited: read_u8(reader)? != 0,
+        },
+        14 => Event::TransferResolved {
+            tx: read_u32(reader)?,
+        },
+        kind => return Err(Error::InvalidWal(format!("unknown event kind {kind}"))),
+    };
+    Ok(event)
+}
+
+fn encode_movement<W: Write>(tx: u32, amount: Amount, writer: &mut W) -> Result<(), Error> {
+    writer.write_all(&tx.to_le_bytes())?;
+    write_amount(amount.value(), writer)
+}
+
+fn decode_amount<R: Read>(reader: &mut R) -> Result<Amount, Error> {
+    Amount::new(read_amount(reader, snapshot::VERSION)?)
+        .ok_or_else(|| Error::InvalidWal("negative amount".to_owned()))
+}
+
+fn encode_details<W: Write>(details: &DisputeDetails, writer: &mut W) -> Result<(), Error> {
+    encode_optional_string(details.reason.as_deref(), writer)?;
+    encode_optional_string(details.evidence.as_deref(), writer)
+}
+
+fn decode_details<R: Read>(reader: &mut R) -> Result<DisputeDetails, Error> {
+    Ok(DisputeDetails {
+        reason: decode_optional_string(reader)?,
+        evidence: decode_optional_string(reader)?,
+    })
+}
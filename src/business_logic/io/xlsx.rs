@@ -0,0 +1,453 @@
+use std::{borrow::Cow, collections::HashMap, io::Read};
+
+use csv::StringRecord;
+
+use crate::shared::{errors::Error, zip::ZipArchive};
+
+/*
+ * Excel workbook (.xlsx, Office Open XML) input: the first sheet of the workbook is read as a CSV file, its first
+ * non-empty row holding the column names and every following row a record, numbered like in Excel. Cells are
+ * matched to the columns by their position, so that empty cells omitted by Excel are read as empty fields, and
+ * numbers are kept as stored in the workbook, whatever the locale and number format used to display them.
+ * Formulas are read as their cached result; styles, date formats and the other sheets are ignored.
+ */
+
+/// Path of the workbook part, and of its relationships locating the sheets, in the archive
+const WORKBOOK: &str = "xl/workbook.xml";
+const WORKBOOK_RELATIONSHIPS: &str = "xl/_rels/workbook.xml.rels";
+/// Path of the table of strings shared by the sheets, absent if no cell holds a string
+const SHARED_STRINGS: &str = "xl/sharedStrings.xml";
+/// Columns of a sheet, from `A` to `XFD`
+const MAX_COLUMN: usize = 16_384;
+
+/// Read the first sheet of a workbook as the column names and the records following them, with their row number,
+/// deserializable like CSV rows. Empty rows are skipped
+pub(crate) fn read_sheet<R: Read>(
+    mut reader: R,
+) -> Result<(StringRecord, Vec<(u64, StringRecord)>), Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let archive = ZipArchive::new(data).map_err(|error| Error::InvalidXlsx(error.to_string()))?;
+
+    let shared_strings = match read_part(&archive, SHARED_STRINGS)? {
+        Some(xml) => parse_shared_strings(&xml)?,
+        None => Vec::new(),
+    };
+    let sheet = first_sheet(&archive)?;
+    let xml = read_part(&archive, &sheet)?
+        .ok_or_else(|| Error::InvalidXlsx(format!("missing sheet {sheet}")))?;
+    let mut rows = parse_rows(&xml, &shared_strings)?
+        .into_iter()
+        .filter(|(_, cells)| cells.values().any(|value| !value.trim().is_empty()));
+
+    let Some((_, names)) = rows.next() else {
+        return Ok((StringRecord::new(), Vec::new()));
+    };
+    let mut columns = names.into_iter().collect::<Vec<_>>();
+    columns.sort_unstable();
+    let headers = columns
+        .iter()
+        .map(|(_, name)| name.trim())
+        .collect::<StringRecord>();
+    let records = rows
+        .map(|(number, cells)| {
+            let record = columns
+                .iter()
+                .map(|(column, _)| cells.get(column).map_or("", |value| value.trim()))
+                .collect::<StringRecord>();
+            (number, record)
+        })
+        .collect();
+
+    Ok((headers, records))
+}
+
+/// Content of a part of the workbook, `None` if the archive has no such part
+fn read_part(archive: &ZipArchive, path: &str) -> Result<Option<String>, Error> {
+    let Some(content) = archive
+        .read(path)
+        .map_err(|error| Error::InvalidXlsx(error.to_string()))?
+    else {
+        return Ok(None);
+    };
+    String::from_utf8(content)
+        .map(Some)
+        .map_err(|_| Error::InvalidXlsx(format!("{path} is not valid UTF-8")))
+}
+
+/// Path in the archive of the first sheet of the workbook, as listed by the workbook and located by its relationships
+fn first_sheet(archive: &ZipArchive) -> Result<String, Error> {
+    let invalid = |reason: String| Error::InvalidXlsx(reason);
+    let missing = |path: &str| invalid(format!("missing {path}"));
+
+    let workbook = read_part(archive, WORKBOOK)?.ok_or_else(|| missing(WORKBOOK))?;
+    let mut relationship = None;
+    for token in Tokens::new(&workbook) {
+        if let Token::Start {
+            name: "sheet",
+            attributes,
+            ..
+        } = token.map_err(invalid)?
+        {
+            relationship = attribute(attributes, "id").map_err(invalid)?;
+            break;
+        }
+    }
+    let relationship =
+        relationship.ok_or_else(|| invalid("the workbook has no sheet".to_owned()))?;
+
+    let relationships = read_part(archive, WORKBOOK_RELATIONSHIPS)?
+        .ok_or_else(|| missing(WORKBOOK_RELATIONSHIPS))?;
+    for token in Tokens::new(&relationships) {
+        if let Token::Start {
+            name: "Relationship",
+            attributes,
+            ..
+        } = token.map_err(invalid)?
+        {
+            if attribute(attributes, "Id").map_err(invalid)?.as_ref() != Some(&relationship) {
+                continue;
+            }
+            let target = attribute(attributes, "Target")
+                .map_err(invalid)?
+                .ok_or_else(|| invalid(format!("sheet {relationship} has no target")))?;
+            // targets are relative to the workbook part, unless absolute
+            return Ok(match target.strip_prefix('/') {
+                Some(path) => path.to_owned(),
+                None => format!("xl/{target}"),
+            });
+        }
+    }
+
+    Err(invalid(format!("sheet {relationship} not found")))
+}
+
+/// Strings of the shared strings table, in order. Rich text runs are concatenated, phonetic hints are dropped
+fn parse_shared_strings(xml: &str) -> Result<Vec<String>, Error> {
+    let mut strings = Vec::new();
+    let mut text = TextReader::default();
+    for token in Tokens::new(xml) {
+        let token = token.map_err(Error::InvalidXlsx)?;
+        match token {
+            Token::Start { name: "si", .. } => text = TextReader::default(),
+            Token::End("si") => strings.push(std::mem::take(&mut text.value)),
+            token => text.read(token),
+        }
+    }
+    Ok(strings)
+}
+
+/// Number of a sheet row and its cells by column number
+type Row = (u64, HashMap<usize, String>);
+
+/// Every row of a sheet
+fn parse_rows(xml: &str, shared_strings: &[String]) -> Result<Vec<Row>, Error> {
+    let invalid = |reason: String| Error::InvalidXlsx(reason);
+
+    let mut rows = Vec::new();
+    let mut row = (0, HashMap::new());
+    let mut cell = (0, None::<String>);
+    let mut value = None::<String>;
+    let mut in_value = false;
+    let mut text = TextReader::default();
+    for token in Tokens::new(xml) {
+        match token.map_err(invalid)? {
+            Token::Start {
+                name: "row",
+                attributes,
+                empty,
+            } => {
+                // the row and cell references may be omitted, meaning the next row or cell
+                let number = match attribute(attributes, "r").map_err(invalid)? {
+                    Some(number) => number
+                        .parse()
+                        .map_err(|_| invalid(format!("invalid row number {number}")))?,
+                    None => row.0 + 1,
+                };
+                row = (number, HashMap::new());
+                cell.0 = 0;
+                if empty {
+                    rows.push(std::mem::take(&mut row));
+                    row.0 = number;
+                }
+            }
+            Token::End("row") => {
+                let number = row.0;
+                rows.push(std::mem::take(&mut row));
+                row.0 = number;
+            }
+            Token::Start {
+                name: "c",
+                attributes,
+                empty,
+            } => {
+                let column = match attribute(attributes, "r").map_err(invalid)? {
+                    Some(reference) => column_number(&reference)
+                        .ok_or_else(|| invalid(format!("invalid cell reference {reference}")))?,
+                    None => cell.0 + 1,
+                };
+                let cell_type = attribute(attributes, "t").map_err(invalid)?;
+                cell = (column, cell_type.map(Cow::into_owned));
+                value = None;
+                text = TextReader::default();
+                if empty {
+                    row.1.insert(column, String::new());
+                }
+            }
+            Token::End("c") => {
+                let content = cell_value(cell.1.as_deref(), value.take(), &text, shared_strings)
+                    .map_err(invalid)?;
+                row.1.insert(cell.0, content);
+            }
+            Token::Start {
+                name: "v", empty, ..
+            } => {
+                value = Some(String::new());
+                in_value = !empty;
+            }
+            Token::End("v") => in_value = false,
+            Token::Text(content) if in_value => {
+                value.iter_mut().for_each(|value| value.push_str(&content))
+            }
+            token => text.read(token),
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Value of a cell as text, according to its type
+fn cell_value(
+    cell_type: Option<&str>,
+    value: Option<String>,
+    text: &TextReader,
+    shared_strings: &[String],
+) -> Result<String, String> {
+    let value = value.unwrap_or_default();
+    Ok(match cell_type {
+        Some("s") => value
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| shared_strings.get(index))
+            .ok_or_else(|| format!("invalid shared string {value}"))?
+            .clone(),
+        Some("inlineStr") => text.value.clone(),
+        Some("b") => match value.trim() {
+            "1" => "true".to_owned(),
+            _ => "false".to_owned(),
+        },
+        // numbers are stored in the shortest form reading back to the same double, possibly with an exponent
+        None | Some("n") if value.contains(['e', 'E']) => value
+            .trim()
+            .parse::<f64>()
+            .map(|number| number.to_string())
+            .map_err(|_| format!("invalid number {value}"))?,
+        // formula strings and errors (e.g. `#N/A`) are kept as they are
+        _ => value,
+    })
+}
+
+/// Column number of a cell reference, starting from 1 for `A` (e.g. `AB12` is 28)
+fn column_number(reference: &str) -> Option<usize> {
+    let letters = reference
+        .bytes()
+        .take_while(|byte| byte.is_ascii_alphabetic())
+        .try_fold(0_usize, |column, letter| {
+            let column = column * 26 + (letter.to_ascii_uppercase() - b'A') as usize + 1;
+            (column <= MAX_COLUMN).then_some(column)
+        })?;
+    (letters > 0).then_some(letters)
+}
+
+/// Text of a string item or inline string, from its `<t>` elements outside phonetic hints
+#[derive(Default)]
+struct TextReader {
+    value: String,
+    in_text: bool,
+    in_phonetic: bool,
+}
+
+impl TextReader {
+    fn read(&mut self, token: Token) {
+        match token {
+            Token::Start {
+                name: "t",
+                empty: false,
+                ..
+            } => self.in_text = true,
+            Token::End("t") => self.in_text = false,
+            Token::Start {
+                name: "rPh",
+                empty: false,
+                ..
+            } => self.in_phonetic = true,
+            Token::End("rPh") => self.in_phonetic = false,
+            Token::Text(text) if self.in_text && !self.in_phonetic => self.value.push_str(&text),
+            _ => {}
+        }
+    }
+}
+
+/// Markup of an XML document, with element names stripped of their namespace prefix
+enum Token<'a> {
+    Start {
+        name: &'a str,
+        attributes: &'a str,
+        /// Whether the element is empty (`<name/>`), with no end tag
+        empty: bool,
+    },
+    End(&'a str),
+    Text(Cow<'a, str>),
+}
+
+/// Minimal XML tokenizer, enough for the machine-written parts of a workbook: no DTD, declarations, comments
+/// and processing instructions are skipped
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(xml: &'a str) -> Self {
+        Self { rest: xml }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token<'a>>, String> {
+        loop {
+            if self.rest.is_empty() {
+                return Ok(None);
+            }
+            let Some(markup) = self.rest.strip_prefix('<') else {
+                let end = self.rest.find('<').unwrap_or(self.rest.len());
+                let (text, rest) = self.rest.split_at(end);
+                self.rest = rest;
+                return unescape(text).map(|text| Some(Token::Text(text)));
+            };
+
+            if let Some(cdata) = markup.strip_prefix("![CDATA[") {
+                let (text, rest) = cdata
+                    .split_once("]]>")
+                    .ok_or("unterminated CDATA section")?;
+                self.rest = rest;
+                return Ok(Some(Token::Text(Cow::Borrowed(text))));
+            }
+            if let Some(comment) = markup.strip_prefix("!--") {
+                self.rest = comment.split_once("-->").ok_or("unterminated comment")?.1;
+                continue;
+            }
+
+            // `>` may appear in attribute values
+            let mut quote = None;
+            let end = markup
+                .char_indices()
+                .find(|&(_, c)| match quote {
+                    Some(q) if c == q => {
+                        quote = None;
+                        false
+                    }
+                    Some(_) => false,
+                    None if c == '"' || c == '\'' => {
+                        quote = Some(c);
+                        false
+                    }
+                    None => c == '>',
+                })
+                .ok_or("unterminated tag")?
+                .0;
+            let tag = &markup[..end];
+            self.rest = &markup[end + 1..];
+
+            if tag.starts_with(['?', '!']) {
+                continue;
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                return Ok(Some(Token::End(local_name(name.trim()))));
+            }
+            let (tag, empty) = match tag.strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (tag, false),
+            };
+            let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+            return Ok(Some(Token::Start {
+                name: local_name(name),
+                attributes,
+                empty,
+            }));
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Result<Token<'a>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.next_token();
+        if token.is_err() {
+            self.rest = "";
+        }
+        token.transpose()
+    }
+}
+
+/// Name without its namespace prefix (e.g. `id` for `r:id`)
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// Value of the attribute with the given local name, if any
+fn attribute<'a>(attributes: &'a str, name: &str) -> Result<Option<Cow<'a, str>>, String> {
+    let mut rest = attributes.trim_start();
+    while !rest.is_empty() {
+        let (key, value) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("invalid attributes {attributes}"))?;
+        let value = value.trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|&c| c == '"' || c == '\'')
+            .ok_or_else(|| format!("invalid attributes {attributes}"))?;
+        let (value, next) = value[1..]
+            .split_once(quote)
+            .ok_or_else(|| format!("invalid attributes {attributes}"))?;
+        if local_name(key.trim()) == name {
+            return unescape(value).map(Some);
+        }
+        rest = next.trim_start();
+    }
+    Ok(None)
+}
+
+/// Text with its character and entity references replaced
+fn unescape(text: &str) -> Result<Cow<'_, str>, String> {
+    if !text.contains('&') {
+        return Ok(Cow::Borrowed(text));
+    }
+
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        let (entity, next) = rest[start + 1..]
+            .split_once(';')
+            .ok_or_else(|| format!("unterminated reference in {text}"))?;
+        let c = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|code| u32::from_str_radix(code, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        unescaped.push(c.ok_or_else(|| format!("unknown reference &{entity};"))?);
+        rest = next;
+    }
+    unescaped.push_str(rest);
+
+    Ok(Cow::Owned(unescaped))
+}
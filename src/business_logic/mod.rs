@@ -1,153 +1,120 @@
-use std::{collections::HashMap, fs::File, io::Write, path::PathBuf};
-
-use csv::{ReaderBuilder, WriterBuilder};
-use serde::{Deserialize, Serialize};
-
-use crate::{
-    business_logic::trait_impl::{four_decimals, from_str},
-    shared::errors::Error,
-};
-
-#[derive(Debug, Deserialize)]
-struct ClientTransaction {
-    /// Client ID, UUID
-    #[serde(rename = "client")]
-    id: u16,
-    /// Type of transaction
-    #[serde(rename = "type", deserialize_with = "from_str")]
-    transaction_type: Type,
-    /// Transaction ID
-    tx: u32,
-    /// Transaction amount. Present only for Deposit and Withdrawal
-    amount: Option<f64>,
-}
+pub mod domain;
+pub mod engine;
+pub mod io;
+pub mod policy;
+pub mod prelude;
 
-#[derive(Debug, Default, Serialize)]
-struct Client {
-    /// Client ID, UUID
-    #[serde(rename = "client")]
-    id: u16,
-    /// Available founds = total - held
-    #[serde(serialize_with = "four_decimals")]
-    available: f64,
-    /// Held founds = total - available
-    #[serde(serialize_with = "four_decimals")]
-    held: f64,
-    /// Total founds = available + held
-    #[serde(serialize_with = "four_decimals")]
-    total: f64,
-    /// Identify if client account is locked
-    locked: bool,
-    #[serde(skip)]
-    /// History of transactions of client identified by ID
-    transations_history: HashMap<u32, Transaction>,
-}
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        fs::File,
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        num::{NonZeroU64, NonZeroUsize},
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
 
-#[derive(Debug)]
-struct Transaction {
-    /// The found amount linked to this transaction
-    amount: f64,
-    /// Identify if transaction is under dispute
-    is_under_dispute: bool,
-}
+    use crate::{
+        business_logic::{
+            domain::{
+                amount::{Amount, Balance},
+                events::Event,
+                handlers,
+                transactions_logic::Rejection,
+                Client, ClientTransaction, DisputeDetails, DisputeEffect, DisputeStatus, Type,
+            },
+            engine::{
+                alerts::AlertThresholds,
+                annotate_snapshot_clients,
+                anomaly::{Anomaly, AnomalyDetector, AnomalyRate},
+                apply_batch, apply_transaction, apply_transaction_paged,
+                apply_transaction_partitioned, apply_transactions, backfill_accounts,
+                checkpoint::Checkpoint,
+                demo::DemoDataset,
+                diff_policies, dump_snapshot, erase_snapshot_client, export_snapshot_proto,
+                export_transaction, import_snapshot_proto, index_snapshot,
+                merge::{AccountMerger, DuplicateClients},
+                observer::TransactionObserver,
+                query_snapshot, query_snapshot_transaction, read_annotations, recover_wal,
+                report_transaction, run_demo, serve_http, serve_transactions,
+                serve_transactions_replaying, server,
+                spill::MemoryLimit,
+                store::ClientStore,
+                tx_index::{DisputeState, TransactionRef},
+                verify_audit_chain, verify_transaction, Account, ProcessingOptions, ReportKind,
+                TransactionEngine,
+            },
+            io::{
+                config::{self, write_config},
+                dump,
+                export::ExportFormat,
+                input::{
+                    decompressing_reader, expand_input_pattern, AmountUnits, CurrencyExponents,
+                    InputFormat,
+                },
+                normalize::{Normalizations, NormalizingReader},
+                output::{OutputFormat, PageLimits, SortKey},
+                query::{AccountQuery, QueryFormat},
+                snapshot,
+            },
+            policy::{
+                filter::Filter, ChannelRule, DisputeShortfall, EnginePolicy, NegativeAdjustments,
+                WithdrawalDisputeMode,
+            },
+        },
+        shared::{
+            decimal::Decimal,
+            errors::Error,
+            memory::{self, Accounting, Subsystem},
+            retry::RetryPolicy,
+            sha256,
+        },
+    };
 
-#[derive(Debug, Deserialize)]
-enum Type {
-    Deposit,
-    Withdrawal,
-    Dispute,
-    Resolve,
-    ChargeBack,
-}
+    fn decimal(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
 
-mod trait_impl;
-mod transactions_logic;
-
-/**
- * Having CSV input line, here data are processed as follow:
- * | type [String] | client [UUID - u16] | tx [u32] | amount [f64] |
- *
- * type can be one the following operations:
- *     - deposit:
- *         * available += amount
- *         * total += amount
- *     - withdrawal:
- *         * available -= amount
- *         * total -= amount
- *     - dispute: [Request to revert a transaction. It refers to a specific transaction ID and does not have an amount]
- *         * held += tx_hisotry[tx_id]
- *         * available -= tx_hisotry[tx_id]
- *         * set tx_id attribute is_dispute to true
- *     - resolve: [Request to resolve a dispute. It refers to a specific transaction ID and does not have an amount]
- *         * held -= tx_hisotry[tx_id]
- *         * available += tx_hisotry[tx_id]
- *         * Previous operations should be taken into account iff tx_id exists in history_tx and if tx_id is under dispute
- *     - chargeback: [Final state of a dispute. It refers to a specific transaction ID and does not have an amount]
- *         * held -= tx_hisotry[tx_id]
- *         * total -= tx_hisotry[tx_id]
- *         * Previous operations should be taken into account iff tx_id exists in history_tx and if tx_id is under dispute
- *         * This operation immediately freeze the client acount.
- *
- *  Assumptions to very:
- *  While implementing the solution I identified a few edge cases that are not fully specified. I made conservative assumptions and I’d like to confirm they align with your expectations.
- *  1. I assume accounts start with zero balance and cannot go negative. Withdrawals with insufficient available funds are ignored.
- *  2. I assume disputes can only target previous monetary transactions (deposit/withdrawal), and that disputes themselves cannot be disputed.
- *  3. After a chargeback I treat the account as frozen and ignore all subsequent transactions, considering it a terminal state.
- *  4. If a transaction with an already-seen transaction ID is received, I ignore it and keep the original transaction unchanged.
- *  5. Malformed input data are simply ignored
- *
- *
- *
- *  OUTPUT file will contain
- *  | client [UUID - u16] | available [f64 {.4}] | held [f64 {.4}] | total [f64 {.4}] | locked [bool]|
- *
- */
-pub(crate) fn apply_transaction<W>(input_file: PathBuf, writer: W) -> Result<(), Error>
-where
-    W: Write,
-{
-    let file = File::open(input_file).map_err(Error::Io)?;
-    let mut reader = ReaderBuilder::new()
-        .has_headers(true)
-        .trim(csv::Trim::All)
-        .from_reader(file);
-
-    let mut client_out = HashMap::<u16, Client>::new();
-
-    for result in reader.deserialize::<ClientTransaction>() {
-        let client_transaction = match result {
-            Ok(client_tx) => client_tx,
-            Err(_) => continue, // ignore malformed input lines
-        };
-
-        client_out
-            .entry(client_transaction.id)
-            .and_modify(|client| client.apply_transaction(&client_transaction))
-            .or_insert(Client::from(client_transaction));
-    }
-
-    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
-    client_out
-        .values()
-        .into_iter()
-        .try_for_each(|client| -> Result<(), Error> {
-            writer.serialize(client).map_err(Error::Csv)
-        })?;
-
-    writer.flush()?;
-
-    Ok(())
-}
+    /// Temporary directory of a test, unique to the test and the process, so that concurrent `cargo test` runs do
+    /// not overwrite each other's files. Removed with its content when dropped
+    struct TestDir(PathBuf);
 
-#[cfg(test)]
-mod test {
-    use std::{fs::File, io::Read, path::PathBuf};
+    impl TestDir {
+        fn new(test: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "transactions_simulator_test_{test}_{}",
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl std::ops::Deref for TestDir {
+        type Target = Path;
 
-    use crate::business_logic::apply_transaction;
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
 
     fn check_result(input_file: PathBuf, output_file: PathBuf) {
+        check_result_with(input_file, &ProcessingOptions::default(), output_file)
+    }
+
+    fn check_result_with(input_file: PathBuf, options: &ProcessingOptions, output_file: PathBuf) {
         let mut buf = Vec::new();
-        apply_transaction(input_file, &mut buf).unwrap();
+        apply_transaction(File::open(input_file).unwrap(), options, &mut buf).unwrap();
         let output = String::from_utf8(buf).unwrap();
 
         let mut expected_out = "".to_owned();
@@ -156,7 +123,16 @@ mod test {
             .read_to_string(&mut expected_out)
             .unwrap();
 
-        assert_eq!(output, expected_out)
+        // Row ordering is not significant: compare header and sorted rows
+        assert_eq!(sorted_rows(&output), sorted_rows(&expected_out))
+    }
+
+    fn sorted_rows(csv: &str) -> (Option<&str>, Vec<&str>) {
+        let mut lines = csv.lines();
+        let header = lines.next();
+        let mut rows = lines.collect::<Vec<_>>();
+        rows.sort();
+        (header, rows)
     }
 
     #[test]
@@ -230,4 +206,4124 @@ mod test {
             PathBuf::from("./tests/outputs/expected_output_fuzz_malformed.csv"),
         );
     }
+
+    #[test]
+    fn test_windows_line_endings() {
+        check_result(
+            PathBuf::from("./tests/inputs/input_27_windows.csv"),
+            PathBuf::from("./tests/outputs/expected_output_27_windows.csv"),
+        );
+
+        let (mut reader, normalizations) =
+            NormalizingReader::new(File::open("./tests/inputs/input_27_windows.csv").unwrap());
+        let mut normalized = String::new();
+        reader.read_to_string(&mut normalized).unwrap();
+        assert_eq!(
+            normalized,
+            "type,client,tx,amount\ndeposit,1,1,1.0000\ndeposit,1,2,2.0000\nwithdrawal,1,3,1.5000\n\
+             deposit,2,4,2.0000\ndispute,2,4,\n"
+        );
+        assert_eq!(
+            normalizations.counts(),
+            Normalizations {
+                crlf_line_endings: 9,
+                blank_lines: 3,
+                trailing_delimiters: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        // malformed records are skipped by default
+        let mut buf = Vec::new();
+        apply_transaction(
+            File::open("./tests/inputs/input_30_strict.csv").unwrap(),
+            &ProcessingOptions::default(),
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "client,available,held,total,locked\n1,4.0000,0.0000,4.0000,false\n"
+        );
+
+        // and abort the processing in strict mode, reporting the line in the source input, blank lines included
+        let options = ProcessingOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let result = apply_transaction(
+            File::open("./tests/inputs/input_30_strict.csv").unwrap(),
+            &options,
+            &mut Vec::new(),
+        );
+        match result {
+            Err(Error::MalformedRecord(line, _)) => assert_eq!(line, 6),
+            result => panic!("expected a malformed record error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let mut options = ProcessingOptions {
+            input_format: InputFormat::JsonLines,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        apply_transaction(
+            File::open("./tests/inputs/input_33_json_lines.jsonl").unwrap(),
+            &options,
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_33_json_lines.csv").unwrap()
+        );
+
+        // lines are numbered as in the input, empty lines included
+        options.strict = true;
+        let result = apply_transaction(
+            File::open("./tests/inputs/input_33_json_lines.jsonl").unwrap(),
+            &options,
+            &mut Vec::new(),
+        );
+        match result {
+            Err(Error::MalformedRecord(line, reason)) => {
+                assert_eq!(line, 7);
+                assert!(reason.ends_with("nested values are not supported"));
+            }
+            result => panic!("expected a malformed record error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_xlsx() {
+        // the first sheet listed by the workbook, with shared and inline strings, numbers stored with an exponent,
+        // columns written out of order and missing rows
+        let mut options = ProcessingOptions {
+            input_format: InputFormat::Xlsx,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        apply_transaction(
+            File::open("./tests/inputs/input_37_xlsx.xlsx").unwrap(),
+            &options,
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_37_xlsx.csv").unwrap()
+        );
+
+        // rows are numbered as in the sheet
+        options.strict = true;
+        let result = apply_transaction(
+            File::open("./tests/inputs/input_37_xlsx.xlsx").unwrap(),
+            &options,
+            &mut Vec::new(),
+        );
+        match result {
+            Err(Error::MalformedRecord(line, _)) => assert_eq!(line, 9),
+            result => panic!("expected a malformed record error, got {result:?}"),
+        }
+
+        // a corrupted sheet aborts the run
+        let result = apply_transaction(
+            File::open("./tests/inputs/input_37_xlsx_corrupted.xlsx").unwrap(),
+            &options,
+            &mut Vec::new(),
+        );
+        assert!(matches!(result, Err(Error::InvalidXlsx(_))), "{result:?}");
+
+        // uncompressed sizes of the central directory are not trusted: neither a huge size is allocated, nor
+        // more than a small size inflated
+        let workbook = std::fs::read("./tests/inputs/input_37_xlsx.xlsx").unwrap();
+        for size in [u32::MAX - 1, 1] {
+            let mut patched = workbook.clone();
+            let headers = (0..patched.len() - 4)
+                .filter(|&offset| patched[offset..offset + 4] == [0x50, 0x4b, 0x01, 0x02])
+                .collect::<Vec<_>>();
+            for offset in headers {
+                patched[offset + 24..offset + 28].copy_from_slice(&size.to_le_bytes());
+            }
+            let result = apply_transaction(patched.as_slice(), &options, &mut Vec::new());
+            assert!(matches!(result, Err(Error::InvalidXlsx(_))), "{result:?}");
+        }
+    }
+
+    #[test]
+    fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // the server runs until the end of the tests
+        std::thread::spawn(move || serve_transactions(listener, &ProcessingOptions::default()));
+
+        let connect = move || {
+            let stream = TcpStream::connect(address).unwrap();
+            (BufReader::new(stream.try_clone().unwrap()), stream)
+        };
+        let request = |(reader, writer): &mut (BufReader<TcpStream>, TcpStream), line: &str| {
+            writeln!(writer, "{line}").unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim_end().to_owned()
+        };
+
+        // concurrent connections apply their transactions to the same accounts
+        let senders = (0..4_u32)
+            .map(|sender| {
+                std::thread::spawn(move || {
+                    let mut connection = connect();
+                    (0..50).for_each(|i| {
+                        let response = request(
+                            &mut connection,
+                            &format!("deposit,1,{},1.0", sender * 50 + i),
+                        );
+                        assert_eq!(response, "ok");
+                    });
+                })
+            })
+            .collect::<Vec<_>>();
+        senders
+            .into_iter()
+            .for_each(|sender| sender.join().unwrap());
+
+        let mut connection = connect();
+        assert_eq!(
+            request(&mut connection, "balance 1"),
+            "account 1,200.0000,0.0000,200.0000,false"
+        );
+        assert_eq!(
+            request(&mut connection, "withdrawal,1,200,500"),
+            "rejected insufficient_funds"
+        );
+        // a header sets the columns of the next rows of the connection, JSON objects carry their own
+        assert_eq!(
+            request(&mut connection, "type,client,tx,amount,reason"),
+            "ok"
+        );
+        assert_eq!(request(&mut connection, "dispute,1,0,,fraud"), "ok");
+        assert_eq!(
+            request(
+                &mut connection,
+                r#"{"type":"deposit","client":2,"tx":201,"amount":"2.5"}"#
+            ),
+            "ok"
+        );
+        assert_eq!(
+            request(&mut connection, "refund,1,202"),
+            "error Invalid transation type refund"
+        );
+        assert_eq!(
+            request(&mut connection, "balance 3"),
+            "error unknown client 3"
+        );
+        assert_eq!(request(&mut connection, "balances"), "accounts 2");
+        let mut rows = String::new();
+        (0..2).for_each(|_| {
+            connection.0.read_line(&mut rows).unwrap();
+        });
+        assert_eq!(
+            rows,
+            "1,199.0000,1.0000,200.0000,false\n2,2.5000,0.0000,2.5000,false\n"
+        );
+
+        // a transaction expecting another version of the account, updated by another connection, is not applied
+        let (mut first, mut second) = (connect(), connect());
+        for connection in [&mut first, &mut second] {
+            assert_eq!(
+                request(connection, "type,client,tx,amount,expected_version"),
+                "ok"
+            );
+        }
+        assert_eq!(request(&mut first, "deposit,2,300,1.0,1"), "ok");
+        assert_eq!(
+            request(&mut second, "withdrawal,2,301,1.0,1"),
+            "error conflict 2"
+        );
+        assert_eq!(request(&mut second, "withdrawal,2,301,1.0,2"), "ok");
+        assert_eq!(
+            request(
+                &mut first,
+                r#"{"type":"deposit","client":2,"tx":302,"amount":"1.0","expected_version":2}"#
+            ),
+            "error conflict 3"
+        );
+        assert_eq!(
+            request(&mut first, "deposit,3,302,1.0,1"),
+            "error conflict 0"
+        );
+        assert!(request(&mut first, "deposit,3,302,1.0,x").starts_with("error "));
+        assert_eq!(request(&mut first, "balance 3"), "error unknown client 3");
+
+        // a line without end is not buffered further, and closes its connection only
+        let mut connection = connect();
+        connection.1.write_all(&[b'x'; 8 * 1024 + 1]).unwrap();
+        let mut response = String::new();
+        connection.0.read_line(&mut response).unwrap();
+        assert_eq!(response, "error request longer than 8192 bytes\n");
+        assert_eq!(connection.0.read_line(&mut response).unwrap(), 0);
+        assert_eq!(
+            request(&mut connect(), "balance 2"),
+            "account 2,2.5000,0.0000,2.5000,false"
+        );
+    }
+
+    #[test]
+    fn test_serve_accept_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+
+        // a connection aborted before being accepted is skipped, the next one is still served
+        let streams = [
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionAborted)),
+            listener.accept().map(|(stream, _)| stream),
+        ];
+        server::accept_streams(streams, |mut stream| {
+            writeln!(stream, "served")?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(client.join().unwrap(), "served\n");
+    }
+
+    #[test]
+    fn test_serve_replay() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let replay = vec![File::open("./tests/inputs/input_01_basic.csv").unwrap()];
+        // connecting right away, the connection waits for the replay to end
+        std::thread::spawn(move || {
+            serve_transactions_replaying(listener, replay, &ProcessingOptions::default())
+        });
+
+        let stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut request = |line: &str| {
+            writeln!(writer, "{line}").unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim_end().to_owned()
+        };
+
+        assert_eq!(request("balance 1"), "account 1,1.5000,0.0000,1.5000,false");
+        // live transactions refer to the replayed ones
+        assert_eq!(request("dispute,2,4,"), "ok");
+        assert_eq!(request("deposit,1,1,5.0"), "rejected duplicate_transaction");
+        assert_eq!(request("balance 2"), "account 2,0.0000,2.0000,2.0000,false");
+    }
+
+    #[test]
+    fn test_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // the server runs until the end of the tests
+        std::thread::spawn(move || serve_http(listener, &ProcessingOptions::default()));
+
+        let request = |method: &str, path: &str, body: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(
+                stream,
+                "{method} {path} HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            let (head, body) = response.split_once("\r\n\r\n").unwrap();
+            let status = head.split(' ').nth(1).unwrap().parse::<u16>().unwrap();
+            (status, body.to_owned())
+        };
+
+        let body = "type,client,tx,amount\ndeposit,1,1,5.0\nwithdrawal,1,2,9.0\n\n\
+            {\"type\":\"deposit\",\"client\":2,\"tx\":3,\"amount\":\"1.25\"}\nrefund,1,4,1.0\n";
+        assert_eq!(
+            request("POST", "/transactions", body),
+            (
+                200,
+                "[{\"result\":\"applied\"},{\"result\":\"rejected\",\"reason\":\"insufficient_funds\"},\
+                 {\"result\":\"applied\"},{\"result\":\"malformed\",\"detail\":\"Invalid transation type refund\"}]"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            request("GET", "/accounts", ""),
+            (
+                200,
+                "[{\"client\":1,\"available\":5.0000,\"held\":0.0000,\"total\":5.0000,\"locked\":false,\"version\":1,\"annotations\":[]},\
+                 {\"client\":2,\"available\":1.2500,\"held\":0.0000,\"total\":1.2500,\"locked\":false,\"version\":1,\"annotations\":[]}]"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            request("GET", "/accounts/2?fields=all", ""),
+            (
+                200,
+                "{\"client\":2,\"available\":1.2500,\"held\":0.0000,\"total\":1.2500,\"locked\":false,\"version\":1,\"annotations\":[]}"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            request("GET", "/accounts/3", ""),
+            (404, "{\"error\":\"unknown client 3\"}".to_owned())
+        );
+        assert_eq!(
+            request("POST", "/accounts/2/annotations", "verified by phone\n"),
+            (
+                200,
+                "{\"client\":2,\"available\":1.2500,\"held\":0.0000,\"total\":1.2500,\"locked\":false,\"version\":1,\"annotations\":[\"verified by phone\"]}"
+                    .to_owned()
+            )
+        );
+        // a dry run tells the outcome of every transaction against the current accounts, applying none
+        let body = "deposit,1,5,2.0\nwithdrawal,1,6,9.0\ndeposit,4,7,1.0\nrefund,1,8,1.0\n";
+        assert_eq!(
+            request("POST", "/transactions:validate", body),
+            (
+                200,
+                "[{\"result\":\"applied\",\"account\":{\"client\":1,\"available\":7.0000,\"held\":0.0000,\"total\":7.0000,\"locked\":false}},\
+                 {\"result\":\"rejected\",\"reason\":\"insufficient_funds\"},\
+                 {\"result\":\"applied\",\"account\":{\"client\":4,\"available\":1.0000,\"held\":0.0000,\"total\":1.0000,\"locked\":false}},\
+                 {\"result\":\"malformed\",\"detail\":\"Invalid transation type refund\"}]"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            request("GET", "/accounts/1", ""),
+            (
+                200,
+                "{\"client\":1,\"available\":5.0000,\"held\":0.0000,\"total\":5.0000,\"locked\":false,\"version\":1,\"annotations\":[]}"
+                    .to_owned()
+            )
+        );
+        assert_eq!(request("GET", "/accounts/4", "").0, 404);
+        // of two submitters having read version 1 of the account, the second one is answered a conflict
+        let body = |tx| format!("type,client,tx,amount,expected_version\ndeposit,1,{tx},1.0,1\n");
+        assert_eq!(
+            request("POST", "/transactions", &body(9)),
+            (200, "[{\"result\":\"applied\"}]".to_owned())
+        );
+        assert_eq!(
+            request("POST", "/transactions", &(body(10) + "deposit,1,11,1.0,\n")),
+            (
+                409,
+                "[{\"result\":\"conflict\",\"version\":2},{\"result\":\"applied\"}]".to_owned()
+            )
+        );
+        assert_eq!(
+            request("POST", "/transactions:validate", &body(10)),
+            (200, "[{\"result\":\"conflict\",\"version\":3}]".to_owned())
+        );
+        assert_eq!(
+            request("GET", "/accounts/1", ""),
+            (
+                200,
+                "{\"client\":1,\"available\":7.0000,\"held\":0.0000,\"total\":7.0000,\"locked\":false,\"version\":3,\"annotations\":[]}"
+                    .to_owned()
+            )
+        );
+        assert_eq!(request("GET", "/transactions:validate", "").0, 405);
+        assert_eq!(request("POST", "/accounts/2/annotations", " ").0, 400);
+        assert_eq!(request("POST", "/accounts/3/annotations", "note").0, 404);
+        assert_eq!(request("GET", "/accounts/2/annotations", "").0, 405);
+        assert_eq!(request("DELETE", "/accounts/1", "").0, 405);
+        assert_eq!(request("GET", "/clients", "").0, 404);
+    }
+
+    #[test]
+    fn test_annotations() {
+        let mut engine = TransactionEngine::default();
+        engine
+            .apply(ClientTransaction::new(
+                Type::Deposit,
+                1,
+                1,
+                Some(decimal("2.0")),
+            ))
+            .unwrap();
+        engine.annotate_client(1, " first contact ").unwrap();
+        assert!(matches!(
+            engine.annotate_client(1, ""),
+            Err(Error::AnnotationRefused(1, _))
+        ));
+        assert!(matches!(
+            engine.annotate_client(1, "two\nlines"),
+            Err(Error::AnnotationRefused(1, _))
+        ));
+        assert!(matches!(
+            engine.annotate_client(2, "unknown"),
+            Err(Error::AnnotationRefused(2, _))
+        ));
+
+        // notes from a file are attached to the snapshot, in file order, and restored by later runs
+        let dir = TestDir::new("annotations");
+        let snapshot_file = dir.join("state.snap");
+        let options = ProcessingOptions {
+            snapshot_file: Some(snapshot_file.clone()),
+            ..Default::default()
+        };
+        apply_transaction(
+            File::open("./tests/inputs/input_20_snapshot.csv").unwrap(),
+            &options,
+            std::io::sink(),
+        )
+        .unwrap();
+        let annotations =
+            read_annotations(Path::new("./tests/inputs/input_39_annotations.csv")).unwrap();
+        annotate_snapshot_clients(snapshot_file.clone(), &annotations).unwrap();
+        // nothing is written if a client is unknown
+        assert!(matches!(
+            annotate_snapshot_clients(
+                snapshot_file.clone(),
+                &[(1, "kept".to_owned()), (9, "lost".to_owned())]
+            ),
+            Err(Error::AnnotationRefused(9, _))
+        ));
+
+        let clients = snapshot::read_snapshot(&snapshot_file).unwrap();
+        assert_eq!(
+            clients[&1].annotations,
+            [
+                "Called on 2024-03-02, disputes confirmed by client",
+                "Escalated to fraud team"
+            ]
+        );
+        assert_eq!(
+            clients[&3].annotations,
+            ["Evidence requested from acquirer"]
+        );
+
+        let mut buf = Vec::new();
+        query_snapshot(
+            snapshot_file.clone(),
+            None,
+            &AccountQuery {
+                client: Some(3),
+                ..Default::default()
+            },
+            QueryFormat::Json,
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "[{\"client\":3,\"available\":0.0000,\"held\":4.0000,\"total\":4.0000,\"locked\":false,\"version\":2,\"annotations\":[\"Evidence requested from acquirer\"]}]\n"
+        );
+
+        let mut buf = Vec::new();
+        export_transaction(
+            "type,client,tx,amount\n".as_bytes(),
+            &ProcessingOptions {
+                restore_file: Some(snapshot_file.clone()),
+                ..Default::default()
+            },
+            ExportFormat::Camt053,
+            "01/01/1970",
+            &mut buf,
+        )
+        .unwrap();
+        let statement = String::from_utf8(buf).unwrap();
+        assert!(statement.contains(
+            "      <AddtlStmtInf>Evidence requested from acquirer</AddtlStmtInf>\n    </Stmt>"
+        ));
+
+        // notes are personal data, removed by erasure
+        erase_snapshot_client(snapshot_file.clone(), 1).unwrap();
+        assert!(snapshot::read_snapshot(&snapshot_file).unwrap()[&1]
+            .annotations
+            .is_empty());
+    }
+
+    #[test]
+    fn test_gzip_input() {
+        let plain = std::fs::read("./tests/inputs/input_34_gzip.csv").unwrap();
+        let compressed = std::fs::read("./tests/inputs/input_34_gzip.csv.gz").unwrap();
+        // a stored member with a file name followed by a compressed member
+        let members = std::fs::read("./tests/inputs/input_34_gzip_members.csv.gz").unwrap();
+        for input in [&compressed, &members, &plain] {
+            let mut content = Vec::new();
+            decompressing_reader(input.as_slice())
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            assert_eq!(content, plain);
+        }
+
+        let options = ProcessingOptions::default();
+        let mut expected = Vec::new();
+        apply_transaction(plain.as_slice(), &options, &mut expected).unwrap();
+        let mut buf = Vec::new();
+        let input = decompressing_reader(compressed.as_slice()).unwrap();
+        apply_transaction(input, &options, &mut buf).unwrap();
+        assert_eq!(buf, expected);
+
+        // a truncated file aborts the run, instead of ending it early as if the input was complete
+        let input = decompressing_reader(&compressed[..compressed.len() / 2]).unwrap();
+        let result = apply_transaction(input, &options, &mut Vec::new());
+        assert!(
+            matches!(result, Err(Error::UnreadableInput(_))),
+            "{result:?}"
+        );
+        let mut corrupted = compressed.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        let input = decompressing_reader(corrupted.as_slice()).unwrap();
+        let result = apply_transaction(input, &options, &mut Vec::new());
+        assert!(
+            matches!(result, Err(Error::UnreadableInput(_))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_zstd_input() {
+        let plain = std::fs::read("./tests/inputs/input_34_gzip.csv").unwrap();
+        let compressed = std::fs::read("./tests/inputs/input_34_gzip.csv.zst").unwrap();
+        // two frames, the second without checksum, separated by a skippable frame
+        let frames = std::fs::read("./tests/inputs/input_34_gzip_frames.csv.zst").unwrap();
+        for input in [&compressed, &frames] {
+            let mut content = Vec::new();
+            decompressing_reader(input.as_slice())
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            assert_eq!(content, plain);
+        }
+
+        let options = ProcessingOptions::default();
+        let mut expected = Vec::new();
+        apply_transaction(plain.as_slice(), &options, &mut expected).unwrap();
+        let mut buf = Vec::new();
+        let input = decompressing_reader(compressed.as_slice()).unwrap();
+        apply_transaction(input, &options, &mut buf).unwrap();
+        assert_eq!(buf, expected);
+
+        let input = decompressing_reader(&compressed[..compressed.len() / 2]).unwrap();
+        let result = apply_transaction(input, &options, &mut Vec::new());
+        assert!(
+            matches!(result, Err(Error::UnreadableInput(_))),
+            "{result:?}"
+        );
+        // the last bytes are the checksum of the content
+        let mut corrupted = compressed.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        let input = decompressing_reader(corrupted.as_slice()).unwrap();
+        let result = apply_transaction(input, &options, &mut Vec::new());
+        assert!(
+            matches!(result, Err(Error::UnreadableInput(_))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_batch() {
+        let dir = TestDir::new("batch");
+        let snapshot_file = dir.join("state.snap");
+        let options = ProcessingOptions {
+            snapshot_file: Some(snapshot_file.clone()),
+            ..Default::default()
+        };
+
+        // a missing, truncated or altered member makes the whole batch fail, before anything is saved
+        let result = apply_batch(
+            PathBuf::from("./tests/inputs/batch_32/manifest_invalid.csv"),
+            &options,
+            &mut Vec::new(),
+        );
+        match result {
+            Err(Error::InvalidBatch(problems)) => {
+                assert!(problems.contains("part_1.csv has 2 rows, expected 3"));
+                assert!(problems.contains("part_2.csv has SHA-256"));
+                assert!(problems.contains("part_3.csv cannot be read"));
+            }
+            result => panic!("expected an invalid batch error, got {result:?}"),
+        }
+        assert!(!snapshot_file.exists());
+
+        // members are applied in order as a single input, each recorded in the snapshot ledger
+        let mut buf = Vec::new();
+        apply_batch(
+            PathBuf::from("./tests/inputs/batch_32/manifest.csv"),
+            &options,
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_32_batch.csv").unwrap()
+        );
+        let ledger = snapshot::read_ledger(&snapshot_file).unwrap();
+        assert_eq!(
+            ledger,
+            [
+                sha256::digest_file(&PathBuf::from("./tests/inputs/batch_32/part_1.csv")).unwrap(),
+                sha256::digest_file(&PathBuf::from("./tests/inputs/batch_32/part_2.csv")).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiple_inputs() {
+        let dir = TestDir::new("multiple_inputs");
+        let snapshot_file = dir.join("state.snap");
+        let options = ProcessingOptions {
+            snapshot_file: Some(snapshot_file.clone()),
+            refuse_applied_inputs: true,
+            ..Default::default()
+        };
+
+        // month-split exports are listed in chronological order
+        let inputs =
+            expand_input_pattern(Path::new("./tests/inputs/exports_35/transactions-*.csv"))
+                .unwrap();
+        assert_eq!(
+            inputs,
+            ["01", "02", "03"].map(|month| {
+                PathBuf::from(format!(
+                    "./tests/inputs/exports_35/transactions-2024-{month}.csv"
+                ))
+            })
+        );
+        assert!(matches!(
+            expand_input_pattern(Path::new("./tests/inputs/exports_35/accounts-*.csv")),
+            Err(Error::NoMatchingInput(_))
+        ));
+
+        // disputes and chargebacks of a month refer to deposits of the previous ones
+        let mut buf = Vec::new();
+        apply_transactions(
+            inputs.iter().map(|input| File::open(input).unwrap()),
+            &options,
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_35_exports.csv").unwrap()
+        );
+        let ledger = snapshot::read_ledger(&snapshot_file).unwrap();
+        assert_eq!(
+            ledger,
+            inputs
+                .iter()
+                .map(|input| sha256::digest_file(input).unwrap())
+                .collect::<Vec<_>>()
+        );
+
+        // replaying one of the months again is refused
+        let result =
+            apply_transactions([File::open(&inputs[1]).unwrap()], &options, &mut Vec::new());
+        assert!(matches!(result, Err(Error::InputAlreadyApplied(_))));
+    }
+
+    #[test]
+    fn test_threads() {
+        let dir = TestDir::new("threads");
+        let run = |threads| {
+            let options = ProcessingOptions {
+                rejects_file: Some(dir.join("rejects.csv")),
+                alerts_file: Some(dir.join("alerts.csv")),
+                alert_thresholds: AlertThresholds {
+                    balance_above: Some(decimal("2000")),
+                    chargebacks_above: Some(0),
+                },
+                threads: NonZeroUsize::new(threads),
+                ..Default::default()
+            };
+            let _ = std::fs::remove_file(dir.join("alerts.csv"));
+            let mut buf = Vec::new();
+            apply_transaction(
+                File::open("./tests/inputs/input_34_gzip.csv").unwrap(),
+                &options,
+                &mut buf,
+            )
+            .unwrap();
+            let side_file = |name| std::fs::read_to_string(dir.join(name)).unwrap();
+            (buf, side_file("rejects.csv"), side_file("alerts.csv"))
+        };
+
+        // outputs and side files do not depend on how many threads apply the transactions
+        let (output, rejects, alerts) = run(0);
+        assert!(rejects.lines().count() > 1);
+        assert!(alerts.lines().count() > 1);
+        for threads in [1, 3, 8] {
+            let (threaded_output, threaded_rejects, threaded_alerts) = run(threads);
+            assert_eq!(threaded_output, output);
+            assert_eq!(threaded_rejects, rejects);
+            assert_eq!(threaded_alerts, alerts);
+        }
+
+        // the events of applied transactions are collected in input order too
+        let export = |threads| {
+            let options = ProcessingOptions {
+                threads: NonZeroUsize::new(threads),
+                ..Default::default()
+            };
+            let mut buf = Vec::new();
+            export_transaction(
+                File::open("./tests/inputs/input_34_gzip.csv").unwrap(),
+                &options,
+                ExportFormat::Qif,
+                "01/01/1970",
+                &mut buf,
+            )
+            .unwrap();
+            buf
+        };
+        assert_eq!(export(3), export(0));
+
+        // and processing stops on the same errors, from whichever stage of the pipeline
+        let options = ProcessingOptions {
+            strict: true,
+            threads: NonZeroUsize::new(2),
+            ..Default::default()
+        };
+        let result = apply_transaction(
+            File::open("./tests/inputs/input_30_strict.csv").unwrap(),
+            &options,
+            &mut Vec::new(),
+        );
+        assert!(
+            matches!(result, Err(Error::MalformedRecord(6, _))),
+            "{result:?}"
+        );
+        let compressed = std::fs::read("./tests/inputs/input_34_gzip.csv.gz").unwrap();
+        let input = decompressing_reader(&compressed[..compressed.len() / 2]).unwrap();
+        let result = apply_transaction(input, &options, &mut Vec::new());
+        assert!(
+            matches!(result, Err(Error::UnreadableInput(_))),
+            "{result:?}"
+        );
+        let options = ProcessingOptions {
+            anomaly_rate: Some(AnomalyRate {
+                max_change: Decimal::MAX,
+                window: 10,
+            }),
+            threads: NonZeroUsize::new(2),
+            ..Default::default()
+        };
+        let input = format!(
+            "type,client,tx,amount\ndeposit,1,1,{max}\ndeposit,2,2,{max}\n",
+            max = Decimal::MAX
+        );
+        let result = apply_transaction(input.as_bytes(), &options, &mut Vec::new());
+        assert!(
+            matches!(result, Err(Error::AmountOverflow(_))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let dir = TestDir::new("read_ahead");
+        let run = |read_ahead| {
+            let options = ProcessingOptions {
+                rejects_file: Some(dir.join("rejects.csv")),
+                read_ahead: NonZeroUsize::new(read_ahead),
+                ..Default::default()
+            };
+            let mut buf = Vec::new();
+            apply_transaction(
+                File::open("./tests/inputs/input_34_gzip.csv").unwrap(),
+                &options,
+                &mut buf,
+            )
+            .unwrap();
+            (
+                buf,
+                std::fs::read_to_string(dir.join("rejects.csv")).unwrap(),
+            )
+        };
+
+        // outputs do not depend on how many records are parsed ahead, however many batches they make
+        let expected = run(0);
+        for read_ahead in [1, 7, 1024] {
+            assert_eq!(run(read_ahead), expected);
+        }
+
+        // processing stops on the same errors, whether raised by the reader or while applying the records
+        let options = ProcessingOptions {
+            strict: true,
+            read_ahead: NonZeroUsize::new(2),
+            ..Default::default()
+        };
+        let result = apply_transaction(
+            File::open("./tests/inputs/input_30_strict.csv").unwrap(),
+            &options,
+            &mut Vec::new(),
+        );
+        assert!(
+            matches!(result, Err(Error::MalformedRecord(6, _))),
+            "{result:?}"
+        );
+        let compressed = std::fs::read("./tests/inputs/input_34_gzip.csv.gz").unwrap();
+        let input = decompressing_reader(&compressed[..compressed.len() / 2]).unwrap();
+        let result = apply_transaction(input, &options, &mut Vec::new());
+        assert!(
+            matches!(result, Err(Error::UnreadableInput(_))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_deposits() {
+        let dir = TestDir::new("aggregate_deposits");
+        // runs of deposits of a client, with duplicate IDs, negative and overflowing amounts, between disputes and
+        // chargebacks locking clients
+        let mut random = Lcg(272);
+        let mut input = String::from("type,client,tx,amount\n");
+        let mut tx = 0;
+        for _ in 0..300 {
+            let client = random.next(5) + 1;
+            for _ in 0..random.next(12) + 1 {
+                tx += 1;
+                let deposited = match random.next(20) {
+                    0 => random.next(tx) + 1,
+                    _ => tx,
+                };
+                let amount = match random.next(30) {
+                    0 => "-1.0".to_owned(),
+                    1 => "500000000000000.0".to_owned(),
+                    _ => format!("{}.{:04}", random.next(100), random.next(10_000)),
+                };
+                input.push_str(&format!("deposit,{client},{deposited},{amount}\n"));
+            }
+            let referenced = random.next(tx) + 1;
+            match random.next(6) {
+                0 => input.push_str(&format!("dispute,{client},{referenced},\n")),
+                1 => input.push_str(&format!("chargeback,{client},{referenced},\n")),
+                2 => input.push_str(&format!("withdrawal,{client},{},1.0\n", tx + 1_000_000)),
+                // filed by another client, routed to the owner with --route-disputes-by-tx
+                3 => input.push_str(&format!("dispute,{},{referenced},\n", client % 5 + 1)),
+                _ => {}
+            }
+        }
+
+        let run = |aggregate_deposits, history_limit, checkpoint_file: Option<&str>, routed| {
+            let options = ProcessingOptions {
+                rejects_file: Some(dir.join("rejects.csv")),
+                alerts_file: Some(dir.join("alerts.csv")),
+                alert_thresholds: AlertThresholds {
+                    balance_above: Some(decimal("500")),
+                    chargebacks_above: Some(0),
+                },
+                snapshot_file: Some(dir.join("state.snap")),
+                checkpoint: checkpoint_file.map(|file| Checkpoint {
+                    file: dir.join(file),
+                    interval: NonZeroU64::new(7).unwrap(),
+                }),
+                policy: EnginePolicy {
+                    history_limit,
+                    charge_back_undisputed: true,
+                    ..Default::default()
+                },
+                aggregate_deposits: NonZeroUsize::new(aggregate_deposits),
+                route_disputes_by_tx: routed,
+                ..Default::default()
+            };
+            let _ = std::fs::remove_file(dir.join("alerts.csv"));
+            let mut buf = Vec::new();
+            apply_transaction(input.as_bytes(), &options, &mut buf).unwrap();
+            (
+                buf,
+                std::fs::read_to_string(dir.join("rejects.csv")).unwrap(),
+                std::fs::read_to_string(dir.join("alerts.csv")).unwrap(),
+                snapshot_state(&dir.join("state.snap")),
+                checkpoint_file.map(|file| snapshot_state(&dir.join(file))),
+            )
+        };
+
+        // outputs, side outputs, snapshots and checkpoints are the same as applying deposits one by one,
+        // whatever the window, pruning and dispute routing included
+        for (history_limit, checkpoint_file) in [(None, None), (Some(5), Some("checkpoint.snap"))] {
+            for routed in [false, true] {
+                let expected = run(0, history_limit, checkpoint_file, routed);
+                for window in [1, 2, 5, 1000] {
+                    assert_eq!(
+                        run(window, history_limit, checkpoint_file, routed),
+                        expected
+                    );
+                }
+            }
+        }
+
+        // a dispute is routed to the owner of a deposit still held back
+        let options = ProcessingOptions {
+            aggregate_deposits: NonZeroUsize::new(4),
+            route_disputes_by_tx: true,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        apply_transaction(
+            "type,client,tx,amount\ndeposit,1,1,5.0\ndeposit,1,2,3.0\ndispute,2,2,\n".as_bytes(),
+            &options,
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "client,available,held,total,locked\n1,5.0000,3.0000,8.0000,false\n"
+        );
+
+        let options = ProcessingOptions {
+            aggregate_deposits: NonZeroUsize::new(8),
+            threads: NonZeroUsize::new(2),
+            ..Default::default()
+        };
+        let result = apply_transaction(input.as_bytes(), &options, &mut Vec::new());
+        assert!(
+            matches!(result, Err(Error::AggregationNotSupported(_))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_history_spill() {
+        let dir = TestDir::new("history_spill");
+        let mut input = String::from("type,client,tx,amount\n");
+        chaos_records(&mut Lcg(271), 3000)
+            .iter()
+            .for_each(|record| input.push_str(&format!("{record}\n")));
+        let run = |max_memory: Option<&str>| {
+            let options = ProcessingOptions {
+                rejects_file: Some(dir.join("rejects.csv")),
+                snapshot_file: Some(dir.join("state.snap")),
+                max_memory: max_memory.map(|limit| limit.parse().unwrap()),
+                ..Default::default()
+            };
+            let mut buf = Vec::new();
+            apply_transaction(input.as_bytes(), &options, &mut buf).unwrap();
+            (
+                buf,
+                std::fs::read_to_string(dir.join("rejects.csv")).unwrap(),
+                snapshot_state(&dir.join("state.snap")),
+            )
+        };
+
+        // disputes on spilled transactions, and duplicate IDs, are handled as if the whole history were in memory,
+        // and the snapshot gets the whole history back
+        let expected = run(None);
+        for max_memory in ["0", "2K", "64k", "1G"] {
+            assert_eq!(run(Some(max_memory)), expected);
+        }
+
+        assert_eq!(
+            "4M".parse::<MemoryLimit>().unwrap(),
+            MemoryLimit { bytes: 4 << 20 }
+        );
+        for invalid in ["", "M", "-1K", "1.5G", "1T", "99999999999G"] {
+            let result = invalid.parse::<MemoryLimit>();
+            assert!(
+                matches!(result, Err(Error::InvalidMemoryLimit(_))),
+                "{result:?}"
+            );
+        }
+
+        // the history kept by the retention policy is bounded already
+        let options = ProcessingOptions {
+            max_memory: Some(MemoryLimit { bytes: 1024 }),
+            policy: EnginePolicy {
+                history_limit: Some(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = apply_transaction(input.as_bytes(), &options, &mut Vec::new());
+        assert!(
+            matches!(result, Err(Error::SpillNotSupported(_))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_rejects() {
+        let dir = TestDir::new("rejects");
+        let rejects_file = dir.join("rejects.csv");
+        let options = ProcessingOptions {
+            rejects_file: Some(rejects_file.clone()),
+            ..Default::default()
+        };
+        apply_transaction(
+            File::open("./tests/inputs/input_31_rejects.csv").unwrap(),
+            &options,
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&rejects_file).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_31_rejects.csv").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_audit_chain() {
+        let dir = TestDir::new("audit");
+        let audit_file = dir.join("audit.csv");
+        let options = ProcessingOptions {
+            audit_file: Some(audit_file.clone()),
+            ..Default::default()
+        };
+        let run = || {
+            apply_transaction(
+                File::open("./tests/inputs/input_47_audit.csv").unwrap(),
+                &options,
+                &mut Vec::new(),
+            )
+            .unwrap()
+        };
+        // every event has a row, with the account after it, rejected transactions too, and transfers change
+        // both accounts
+        run();
+        assert_eq!(
+            std::fs::read_to_string(&audit_file).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_47_audit.csv").unwrap()
+        );
+
+        // a later run goes on with the chain of every account
+        run();
+        let mut heads = Vec::new();
+        verify_audit_chain(&audit_file, &mut heads).unwrap();
+        let heads = String::from_utf8(heads).unwrap();
+        let rows = heads
+            .lines()
+            .map(|line| line.rsplit_once(',').unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(rows, ["client,rows", "1,6", "2,8", "3,10"]);
+
+        let log = std::fs::read_to_string(&audit_file).unwrap();
+        let lines = log.lines().collect::<Vec<_>>();
+        let tampered = |lines: Vec<String>| {
+            std::fs::write(&audit_file, lines.join("\n") + "\n").unwrap();
+            verify_audit_chain(&audit_file, std::io::sink())
+        };
+        let broken_at = |result: Result<(), Error>| match result {
+            Err(Error::BrokenAuditChain(_, line, _)) => line,
+            result => panic!("{result:?}"),
+        };
+        // an edited amount
+        let mut edited = lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        edited[1] = edited[1].replace("10.0000", "100.0000");
+        assert_eq!(broken_at(tampered(edited)), 2);
+        // a removed row, found by the next row of the account
+        let mut removed = lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        removed.remove(2);
+        assert_eq!(broken_at(tampered(removed)), 5);
+        // rows of the accounts in another order, each chain being unchanged
+        let mut swapped = lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        swapped.swap(1, 2);
+        assert!(tampered(swapped).is_ok());
+
+        // a broken log is not appended to
+        let mut edited = lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        edited[7] = edited[7].replace(",resolve,", ",dispute,");
+        assert_eq!(broken_at(tampered(edited)), 8);
+        let result = apply_transaction(
+            File::open("./tests/inputs/input_47_audit.csv").unwrap(),
+            &options,
+            &mut Vec::new(),
+        );
+        assert_eq!(broken_at(result), 8);
+    }
+
+    #[test]
+    fn test_anomaly_detector() {
+        let rate = AnomalyRate {
+            max_change: "100".parse().unwrap(),
+            window: 3,
+        };
+        let mut detector = AnomalyDetector::new(rate, ["1000".parse().unwrap()]).unwrap();
+        // steady deposits and withdrawals stay below the rate, whatever the number of records
+        for seq in 1..=10 {
+            let change = if seq % 2 == 0 { "40" } else { "-30" };
+            detector.record(seq, change.parse().unwrap()).unwrap();
+        }
+        assert!(detector.anomalies().is_empty());
+
+        // a duplicated batch of deposits doubles the pace, record 12 is malformed and not applied
+        detector.record(11, "60".parse().unwrap()).unwrap();
+        detector.record(13, "60".parse().unwrap()).unwrap();
+        // the series restarts after the flagged window
+        detector.record(14, "60".parse().unwrap()).unwrap();
+        detector.record(15, "-200".parse().unwrap()).unwrap();
+
+        assert_eq!(
+            detector.anomalies(),
+            [
+                Anomaly {
+                    from_seq: 11,
+                    to_seq: 13,
+                    change: "120".parse().unwrap(),
+                },
+                Anomaly {
+                    from_seq: 14,
+                    to_seq: 15,
+                    change: "-140".parse().unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stream_input() {
+        // any reader can be the input, e.g. stdin piped from another process
+        let input = b"type,client,tx,amount\ndeposit,1,1,2.0\nwithdrawal,1,2,0.5\n";
+        let mut buf = Vec::new();
+        apply_transaction(&input[..], &ProcessingOptions::default(), &mut buf).unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n"
+        );
+    }
+
+    #[test]
+    fn test_engine() {
+        let mut engine = TransactionEngine::default();
+        engine
+            .apply(ClientTransaction::new(
+                Type::Deposit,
+                1,
+                1,
+                Some(decimal("1.0")),
+            ))
+            .unwrap();
+        engine
+            .apply(ClientTransaction::new(
+                Type::Deposit,
+                2,
+                2,
+                Some(decimal("2.0")),
+            ))
+            .unwrap();
+        assert_eq!(
+            engine.apply(ClientTransaction::new(
+                Type::Withdrawal,
+                1,
+                3,
+                Some(decimal("1.5"))
+            )),
+            Err(Rejection::InsufficientFunds)
+        );
+        engine
+            .apply(ClientTransaction::new(Type::Dispute, 2, 2, None))
+            .unwrap();
+        assert_eq!(
+            engine.apply(ClientTransaction::new(
+                Type::Adjustment,
+                1,
+                4,
+                Some(decimal("0.5"))
+            )),
+            Err(Rejection::MissingReason)
+        );
+        engine
+            .apply(
+                ClientTransaction::new(Type::Adjustment, 1, 4, Some(decimal("0.5")))
+                    .with_reason("fee-refund"),
+            )
+            .unwrap();
+
+        let account = |client, available, held, total| Account {
+            client,
+            available,
+            held,
+            total,
+            locked: false,
+        };
+        assert_eq!(
+            engine.accounts(),
+            vec![
+                account(1, decimal("1.5"), decimal("0.0"), decimal("1.5")),
+                account(2, decimal("0.0"), decimal("2.0"), decimal("2.0"))
+            ]
+        );
+        engine
+            .apply(ClientTransaction::new(Type::ChargeBack, 2, 2, None))
+            .unwrap();
+        assert_eq!(
+            engine.finalize(),
+            vec![
+                account(1, decimal("1.5"), decimal("0.0"), decimal("1.5")),
+                Account {
+                    locked: true,
+                    ..account(2, decimal("0.0"), decimal("0.0"), decimal("0.0"))
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transfers() {
+        let dir = TestDir::new("transfers");
+        let input_file = PathBuf::from("./tests/inputs/input_41_transfers.csv");
+        let output_file = PathBuf::from("./tests/outputs/expected_output_41_transfers.csv");
+        // transfers rejected on either side leave both clients untouched
+        let options = ProcessingOptions {
+            rejects_file: Some(dir.join("rejects.csv")),
+            snapshot_file: Some(dir.join("state.snap")),
+            ..Default::default()
+        };
+        check_result_with(input_file.clone(), &options, output_file.clone());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("rejects.csv")).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_41_transfers_rejects.csv")
+                .unwrap()
+        );
+        // both legs are reloaded from the spill file when disputed
+        let spilled = ProcessingOptions {
+            max_memory: Some(MemoryLimit { bytes: 0 }),
+            ..Default::default()
+        };
+        check_result_with(input_file.clone(), &spilled, output_file);
+
+        // shards only reach their own clients
+        let threads = |restore_file| ProcessingOptions {
+            threads: NonZeroUsize::new(2),
+            restore_file,
+            ..Default::default()
+        };
+        assert!(matches!(
+            apply_transaction(
+                File::open(&input_file).unwrap(),
+                &threads(None),
+                std::io::sink()
+            ),
+            Err(Error::TransfersWithThreads)
+        ));
+        assert!(matches!(
+            apply_transaction(
+                "type,client,tx,amount\ndeposit,4,1,1.0\n".as_bytes(),
+                &threads(Some(dir.join("state.snap"))),
+                std::io::sink()
+            ),
+            Err(Error::TransfersWithThreads)
+        ));
+        // refused before anything is written, whatever records precede the first transfer
+        let side_outputs = ProcessingOptions {
+            rejects_file: Some(dir.join("threads_rejects.csv")),
+            alerts_file: Some(dir.join("threads_alerts.csv")),
+            audit_file: Some(dir.join("threads_audit.csv")),
+            ..threads(None)
+        };
+        assert!(matches!(
+            apply_transaction(
+                "type,client,tx,amount,to_client\ndeposit,1,1,1.0,\nwithdrawal,2,2,1.0,\n\
+                 deposit,x,3,1.0,\ntransfer,1,4,1.0,2\n"
+                    .as_bytes(),
+                &side_outputs,
+                std::io::sink()
+            ),
+            Err(Error::TransfersWithThreads)
+        ));
+        for side_output in [
+            "threads_rejects.csv",
+            "threads_alerts.csv",
+            "threads_audit.csv",
+        ] {
+            assert!(!dir.join(side_output).exists(), "{side_output}");
+        }
+
+        let mut engine = TransactionEngine::default();
+        let transfer = |client, tx, amount, to_client| {
+            ClientTransaction::new(Type::Transfer, client, tx, Some(decimal(amount)))
+                .with_destination(to_client)
+        };
+        engine
+            .apply(ClientTransaction::new(
+                Type::Deposit,
+                1,
+                1,
+                Some(decimal("5.0")),
+            ))
+            .unwrap();
+        assert_eq!(
+            engine
+                .validate(&transfer(1, 2, "2.0", 2))
+                .unwrap()
+                .available,
+            decimal("3.0")
+        );
+        engine.apply(transfer(1, 2, "2.0", 2)).unwrap();
+        assert_eq!(
+            engine
+                .transaction(2)
+                .iter()
+                .map(|owner| owner.client)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            engine.apply(ClientTransaction::new(Type::Dispute, 2, 2, None)),
+            Err(Rejection::IncomingTransfer)
+        );
+        engine
+            .apply(ClientTransaction::new(Type::Dispute, 1, 2, None))
+            .unwrap();
+        // held by the destination
+        assert_eq!(
+            engine.validate(&transfer(2, 3, "1.0", 1)),
+            Err(Rejection::InsufficientFunds)
+        );
+        engine
+            .apply(ClientTransaction::new(Type::ChargeBack, 1, 2, None))
+            .unwrap();
+        let account = |client, total, locked| Account {
+            client,
+            available: decimal(total),
+            held: decimal("0"),
+            total: decimal(total),
+            locked,
+        };
+        assert_eq!(
+            engine.finalize(),
+            vec![account(1, "5.0", true), account(2, "0", false)]
+        );
+    }
+
+    #[test]
+    fn test_amount_types() {
+        assert_eq!(Amount::new(decimal("-0.0001")), None);
+        let amount = Amount::new(decimal("2.5")).unwrap();
+        let balance = Balance::new(decimal("1.0"));
+        assert!(!balance.covers(amount));
+        assert_eq!(
+            balance.checked_debit(amount),
+            Some(Balance::new(decimal("-1.5")))
+        );
+        assert_eq!(balance.checked_adjust(decimal("-1.0")), Some(Balance::ZERO));
+        let max = Balance::new(Decimal::MAX);
+        assert_eq!(max.checked_credit(amount), None);
+
+        // a deposit overflowing the balances is rejected, leaving the account untouched
+        let mut engine = TransactionEngine::default();
+        let deposit =
+            |tx, amount| ClientTransaction::new(Type::Deposit, 1, tx, Some(decimal(amount)));
+        engine.apply(deposit(1, "900000000000000")).unwrap();
+        assert_eq!(
+            engine.apply(deposit(2, "100000000000000")),
+            Err(Rejection::Overflow)
+        );
+        assert_eq!(engine.accounts()[0].total, decimal("900000000000000"));
+        // and so is a negative amount
+        assert_eq!(
+            engine.apply(deposit(3, "-1.0")),
+            Err(Rejection::InvalidAmount)
+        );
+    }
+
+    #[test]
+    fn test_erase_client() {
+        let mut engine = TransactionEngine::default();
+        let deposit =
+            |tx, amount| ClientTransaction::new(Type::Deposit, 1, tx, Some(decimal(amount)));
+        engine.apply(deposit(1, "2.0")).unwrap();
+        engine.apply(deposit(2, "3.0")).unwrap();
+        engine
+            .apply(ClientTransaction::new(Type::Dispute, 1, 1, None))
+            .unwrap();
+
+        // funds held by a pending dispute could no longer be released
+        assert!(matches!(
+            engine.erase_client(1),
+            Err(Error::ErasureRefused(1, _))
+        ));
+        assert!(matches!(
+            engine.erase_client(2),
+            Err(Error::ErasureRefused(2, _))
+        ));
+        engine
+            .apply(ClientTransaction::new(Type::Resolve, 1, 1, None))
+            .unwrap();
+        let accounts = engine.accounts();
+        engine.erase_client(1).unwrap();
+
+        // the tombstone keeps the balances, and rejects any later transaction
+        assert_eq!(engine.accounts(), accounts);
+        assert_eq!(
+            engine.apply(deposit(3, "1.0")),
+            Err(Rejection::ErasedAccount)
+        );
+        assert_eq!(
+            engine.apply(ClientTransaction::new(Type::Dispute, 1, 2, None)),
+            Err(Rejection::ErasedAccount)
+        );
+
+        // erasure from a snapshot keeps its ledger and the totals of the accounts
+        let dir = TestDir::new("erase");
+        let snapshot_file = dir.join("state.snap");
+        let options = ProcessingOptions {
+            snapshot_file: Some(snapshot_file.clone()),
+            ..Default::default()
+        };
+        let input = "type,client,tx,amount,reason\n\
+                     deposit,1,1,2.0,\n\
+                     deposit,2,2,5.0,\n\
+                     dispute,1,1,,FRAUD\n\
+                     chargeback,1,1,,\n\
+                     withdrawal,2,3,1.0,\n";
+        let mut expected = Vec::new();
+        apply_transaction(input.as_bytes(), &options, &mut expected).unwrap();
+        let ledger = snapshot::read_ledger(&snapshot_file).unwrap();
+        erase_snapshot_client(snapshot_file.clone(), 2).unwrap();
+        assert!(matches!(
+            erase_snapshot_client(snapshot_file.clone(), 3),
+            Err(Error::ErasureRefused(3, _))
+        ));
+
+        let clients = snapshot::read_snapshot(&snapshot_file).unwrap();
+        assert!(clients[&2].erased && clients[&2].transations_history.is_empty());
+        assert!(!clients[&1].erased && !clients[&1].transations_history.is_empty());
+        assert_eq!(snapshot::read_ledger(&snapshot_file).unwrap(), ledger);
+
+        let rejects_file = dir.join("rejects.csv");
+        let options = ProcessingOptions {
+            restore_file: Some(snapshot_file.clone()),
+            rejects_file: Some(rejects_file.clone()),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        apply_transaction(
+            "type,client,tx,amount\nwithdrawal,2,4,1.0\n".as_bytes(),
+            &options,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(output, expected);
+        assert!(std::fs::read_to_string(&rejects_file)
+            .unwrap()
+            .contains(",erased_account,"));
+    }
+
+    /// Store keeping accounts as bytes, like an embedded database would, decoding an account on every access
+    #[derive(Default)]
+    struct EncodedStore(HashMap<u16, Vec<u8>>);
+
+    impl ClientStore for EncodedStore {
+        fn get<T>(&self, client: u16, read: impl FnOnce(&Client) -> T) -> Option<T> {
+            let bytes = self.0.get(&client)?;
+            Some(read(&Client::from_bytes(bytes).unwrap()))
+        }
+
+        fn update<T>(&mut self, client: u16, update: impl FnOnce(&mut Client) -> T) -> T {
+            let mut account = self.0.get(&client).map_or_else(
+                || Client::new(client),
+                |bytes| Client::from_bytes(bytes).unwrap(),
+            );
+            let result = update(&mut account);
+            self.0.insert(client, account.to_bytes());
+            result
+        }
+
+        fn iterate(&self, mut visit: impl FnMut(&Client)) {
+            self.0
+                .values()
+                .for_each(|bytes| visit(&Client::from_bytes(bytes).unwrap()))
+        }
+    }
+
+    /// Observer recording the callbacks it receives
+    struct RecordingObserver(Arc<Mutex<Vec<String>>>);
+
+    impl TransactionObserver for RecordingObserver {
+        fn on_applied(&mut self, transaction: &ClientTransaction, account: &Account) {
+            self.0.lock().unwrap().push(format!(
+                "applied {} to {}: {}",
+                transaction.tx, account.client, account.total
+            ));
+        }
+
+        fn on_rejected(&mut self, transaction: &ClientTransaction, rejection: Rejection) {
+            self.0.lock().unwrap().push(format!(
+                "rejected {}: {}",
+                transaction.tx,
+                rejection.code()
+            ));
+        }
+
+        fn on_lock(&mut self, transaction: &ClientTransaction, account: &Account) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("locked {} by {}", account.client, transaction.tx));
+        }
+    }
+
+    #[test]
+    fn test_engine_observer() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = TransactionEngine::default();
+        engine.observe(RecordingObserver(calls.clone()));
+        let deposit = |client, tx, amount| {
+            ClientTransaction::new(Type::Deposit, client, tx, Some(decimal(amount)))
+        };
+        engine.apply(deposit(1, 1, "5.0")).unwrap();
+        engine
+            .apply(
+                ClientTransaction::new(Type::Transfer, 1, 2, Some(decimal("2.0")))
+                    .with_destination(2),
+            )
+            .unwrap();
+        assert_eq!(
+            engine.apply(ClientTransaction::new(Type::Resolve, 1, 1, None)),
+            Err(Rejection::NotDisputed)
+        );
+        engine
+            .apply(ClientTransaction::new(Type::Dispute, 1, 1, None))
+            .unwrap();
+        engine
+            .apply(ClientTransaction::new(Type::ChargeBack, 1, 1, None))
+            .unwrap();
+        assert_eq!(
+            engine.apply(deposit(1, 3, "1.0")),
+            Err(Rejection::LockedAccount)
+        );
+
+        // every observer is told every outcome, in the order they were attached
+        let later = Arc::new(Mutex::new(Vec::new()));
+        engine.observe(RecordingObserver(later.clone()));
+        engine.apply(deposit(2, 4, "1.0")).unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "applied 1 to 1: 5.0000",
+                "applied 2 to 1: 3.0000",
+                "applied 2 to 2: 2.0000",
+                "rejected 1: not_disputed",
+                "applied 1 to 1: 3.0000",
+                "applied 1 to 1: -2.0000",
+                "locked 1 by 1",
+                "rejected 3: locked_account",
+                "applied 4 to 2: 3.0000",
+            ]
+        );
+        assert_eq!(*later.lock().unwrap(), vec!["applied 4 to 2: 3.0000"]);
+        assert!(engine.accounts()[0].locked);
+    }
+
+    #[test]
+    fn test_engine_store() {
+        let policy = || EnginePolicy {
+            history_limit: Some(20),
+            ..Default::default()
+        };
+        let mut memory = TransactionEngine::new(policy());
+        let mut encoded = TransactionEngine::with_store(EncodedStore::default(), policy());
+        let mut random = Lcg(0x570e);
+        for tx in 1..=500 {
+            let client = random.next(5) as u16 + 1;
+            let referenced = random.next(u64::from(tx)) as u32 + 1;
+            let amount = Some(Decimal::from_bits(random.next(1_000_000) as i64).unwrap());
+            let kind = random.next(10);
+            let transaction = || match kind {
+                0..=3 => ClientTransaction::new(Type::Deposit, client, tx, amount),
+                4..=5 => ClientTransaction::new(Type::Withdrawal, client, tx, amount),
+                6..=7 => ClientTransaction::new(Type::Dispute, client, referenced, None)
+                    .with_reason("fraud"),
+                8 => ClientTransaction::new(Type::Resolve, client, referenced, None),
+                _ => ClientTransaction::new(Type::ChargeBack, client, referenced, None),
+            };
+            assert_eq!(
+                encoded.validate(&transaction()),
+                memory.validate(&transaction())
+            );
+            assert_eq!(encoded.apply(transaction()), memory.apply(transaction()));
+        }
+        // accounts are the same whatever the store, down to their transactions history
+        assert_eq!(encoded.accounts(), memory.accounts());
+        assert_eq!(
+            encoded.annotate_client(3, "watch").is_ok(),
+            memory.annotate_client(3, "watch").is_ok()
+        );
+        assert!(matches!(
+            encoded.erase_client(9),
+            Err(Error::ErasureRefused(9, _))
+        ));
+        let encoded = encoded.into_store();
+        for (id, client) in memory.into_store() {
+            assert_eq!(encoded.0[&id], client.to_bytes());
+        }
+    }
+
+    #[test]
+    fn test_engine_validate() {
+        let mut engine = TransactionEngine::default();
+        engine
+            .apply(ClientTransaction::new(
+                Type::Deposit,
+                1,
+                1,
+                Some(decimal("2.0")),
+            ))
+            .unwrap();
+
+        // validation reports the outcome without modifying the accounts
+        assert_eq!(
+            engine.validate(&ClientTransaction::new(Type::Dispute, 1, 1, None)),
+            Ok(Account {
+                client: 1,
+                available: decimal("0.0"),
+                held: decimal("2.0"),
+                total: decimal("2.0"),
+                locked: false,
+            })
+        );
+        assert_eq!(
+            engine.validate(&ClientTransaction::new(
+                Type::Withdrawal,
+                1,
+                2,
+                Some(decimal("3.0"))
+            )),
+            Err(Rejection::InsufficientFunds)
+        );
+        assert_eq!(
+            engine.validate(&ClientTransaction::new(
+                Type::Deposit,
+                2,
+                3,
+                Some(decimal("1.0"))
+            )),
+            Ok(Account {
+                client: 2,
+                available: decimal("1.0"),
+                held: decimal("0.0"),
+                total: decimal("1.0"),
+                locked: false,
+            })
+        );
+        assert_eq!(
+            engine.accounts(),
+            vec![Account {
+                client: 1,
+                available: decimal("2.0"),
+                held: decimal("0.0"),
+                total: decimal("2.0"),
+                locked: false,
+            }]
+        );
+
+        // the validated transaction can still be applied, with the same outcome
+        engine
+            .apply(ClientTransaction::new(Type::Dispute, 1, 1, None))
+            .unwrap();
+        assert_eq!(engine.accounts()[0].held, decimal("2.0"));
+    }
+
+    /// Pseudo-random numbers (64-bit LCG), so that chaos runs are reproducible from their seed
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: u64) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 33) % bound
+        }
+    }
+
+    /// Random mix of every transaction type over a few clients, with disputes referring to previous transactions
+    fn chaos_records(random: &mut Lcg, count: u32) -> Vec<String> {
+        (1..=count)
+            .map(|tx| {
+                let client = random.next(4) + 1;
+                let referenced = random.next(u64::from(tx)) + 1;
+                let amount = format!("{}.{:04}", random.next(100), random.next(10_000));
+                match random.next(10) {
+                    0..=3 => format!("deposit,{client},{tx},{amount}"),
+                    4..=5 => format!("withdrawal,{client},{tx},{amount}"),
+                    6..=7 => format!("dispute,{client},{referenced},"),
+                    8 => format!("resolve,{client},{referenced},"),
+                    _ => format!("chargeback,{client},{referenced},"),
+                }
+            })
+            .collect()
+    }
+
+    /// Accounts of a snapshot, ledger excluded, as dumped for debugging
+    fn snapshot_state(snapshot_file: &std::path::Path) -> String {
+        let clients = snapshot::read_snapshot(snapshot_file).unwrap();
+        let mut clients = clients.values().collect::<Vec<_>>();
+        clients.sort_by_key(|client| client.id);
+        let mut buf = Vec::new();
+        dump::write_state(&[], &clients, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    /// Chaos test of snapshot and restore: processing is interrupted at random points, the state is saved as
+    /// snapshot and restored by a new run going on with the rest of the input. The final state must be
+    /// the one of an uninterrupted run.
+    #[test]
+    fn test_snapshot_restore_chaos() {
+        let dir = TestDir::new("chaos");
+        let mut random = Lcg(0x5eed);
+        let policy = || EnginePolicy {
+            history_limit: Some(40),
+            ..Default::default()
+        };
+        let records = chaos_records(&mut random, 300);
+        let csv = |records: &[String]| format!("type,client,tx,amount\n{}\n", records.join("\n"));
+
+        let uninterrupted_file = dir.join("uninterrupted.snap");
+        let options = ProcessingOptions {
+            policy: policy(),
+            snapshot_file: Some(uninterrupted_file.clone()),
+            ..Default::default()
+        };
+        let input = csv(&records);
+        apply_transaction(input.as_bytes(), &options, &mut Vec::new()).unwrap();
+        let expected = snapshot_state(&uninterrupted_file);
+
+        let snapshot_file = dir.join("interrupted.snap");
+        for round in 0..20 {
+            let mut cuts = (0..random.next(3) + 1)
+                .map(|_| random.next(records.len() as u64) as usize)
+                .collect::<Vec<_>>();
+            cuts.sort();
+            cuts.push(records.len());
+
+            let _ = std::fs::remove_file(&snapshot_file);
+            let mut ledger = Vec::new();
+            let mut start = 0;
+            for &end in &cuts {
+                // every chunk is processed by a new run, restoring the snapshot saved by the previous one
+                let options = ProcessingOptions {
+                    policy: policy(),
+                    restore_file: (start > 0).then(|| snapshot_file.clone()),
+                    snapshot_file: Some(snapshot_file.clone()),
+                    ..Default::default()
+                };
+                let chunk = csv(&records[start..end]);
+                apply_transaction(chunk.as_bytes(), &options, &mut Vec::new()).unwrap();
+                let mut hasher = sha256::Sha256::default();
+                hasher.update(chunk.as_bytes());
+                ledger.push(hasher.finalize());
+                start = end;
+            }
+
+            assert_eq!(
+                snapshot_state(&snapshot_file),
+                expected,
+                "round {round}, interrupted after records {cuts:?}"
+            );
+            assert_eq!(snapshot::read_ledger(&snapshot_file).unwrap(), ledger);
+        }
+    }
+
+    /// A snapshot whose string length is corrupt is refused as invalid, without allocating the length read
+    #[test]
+    fn test_snapshot_corrupt_length() {
+        let dir = TestDir::new("corrupt_length");
+        let snapshot_file = dir.join("state.snap");
+        let options = ProcessingOptions {
+            snapshot_file: Some(snapshot_file.clone()),
+            ..Default::default()
+        };
+        apply_transaction(
+            "type,client,tx,amount\ndeposit,1,1,1.0\n".as_bytes(),
+            &options,
+            std::io::sink(),
+        )
+        .unwrap();
+        annotate_snapshot_clients(snapshot_file.clone(), &[(1, "checked".to_owned())]).unwrap();
+
+        let mut bytes = std::fs::read(&snapshot_file).unwrap();
+        let note = bytes
+            .windows(b"checked".len())
+            .position(|window| window == b"checked")
+            .unwrap();
+        bytes[note - 4..note].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&snapshot_file, bytes).unwrap();
+
+        assert!(matches!(
+            snapshot::read_snapshot(&snapshot_file),
+            Err(Error::InvalidSnapshot(_))
+        ));
+        let options = ProcessingOptions {
+            restore_file: Some(snapshot_file.clone()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            apply_transaction(
+                "type,client,tx,amount\n".as_bytes(),
+                &options,
+                std::io::sink()
+            ),
+            Err(Error::InvalidSnapshot(_))
+        ));
+        assert!(matches!(
+            query_snapshot(
+                snapshot_file,
+                None,
+                &AccountQuery::default(),
+                QueryFormat::Table,
+                std::io::sink()
+            ),
+            Err(Error::InvalidSnapshot(_))
+        ));
+    }
+
+    /// Input failing once `remaining` bytes are read, like a process killed or a file system going away
+    struct Interrupted<'a> {
+        data: &'a [u8],
+        remaining: usize,
+    }
+
+    impl Read for Interrupted<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.remaining == 0 {
+                return Err(std::io::ErrorKind::ConnectionAborted.into());
+            }
+            let length = buf.len().min(self.remaining).min(self.data.len());
+            buf[..length].copy_from_slice(&self.data[..length]);
+            self.data = &self.data[length..];
+            self.remaining -= length;
+            Ok(length)
+        }
+    }
+
+    /// Processing interrupted at random points is resumed from the last checkpoint with the same input.
+    /// Output and final state must be the ones of an uninterrupted run.
+    #[test]
+    fn test_checkpoint_resume() {
+        let dir = TestDir::new("checkpoint");
+        let mut random = Lcg(0xc0ffee);
+        let policy = || EnginePolicy {
+            history_limit: Some(40),
+            ..Default::default()
+        };
+        let input = format!(
+            "type,client,tx,amount\n{}\n",
+            chaos_records(&mut random, 300).join("\n")
+        );
+        let mut hasher = sha256::Sha256::default();
+        hasher.update(input.as_bytes());
+        let ledger = vec![hasher.finalize()];
+
+        let uninterrupted_file = dir.join("uninterrupted.snap");
+        let options = ProcessingOptions {
+            policy: policy(),
+            snapshot_file: Some(uninterrupted_file.clone()),
+            ..Default::default()
+        };
+        let mut expected_output = Vec::new();
+        apply_transaction(input.as_bytes(), &options, &mut expected_output).unwrap();
+        let expected = snapshot_state(&uninterrupted_file);
+
+        let checkpoint_file = dir.join("checkpoint.snap");
+        let snapshot_file = dir.join("state.snap");
+        for round in 0..20 {
+            let interval = NonZeroU64::new(random.next(50) + 1).unwrap();
+            let remaining = random.next(input.len() as u64) as usize;
+            let _ = std::fs::remove_file(&checkpoint_file);
+            let options = ProcessingOptions {
+                policy: policy(),
+                checkpoint: Some(Checkpoint {
+                    file: checkpoint_file.clone(),
+                    interval,
+                }),
+                ..Default::default()
+            };
+            let interrupted = Interrupted {
+                data: input.as_bytes(),
+                remaining,
+            };
+            assert!(matches!(
+                apply_transaction(interrupted, &options, std::io::sink()),
+                Err(Error::UnreadableInput(_))
+            ));
+            if !checkpoint_file.exists() {
+                continue;
+            }
+            let position = snapshot::read_position(&checkpoint_file).unwrap();
+            assert_eq!(position % interval.get(), 0);
+
+            let options = ProcessingOptions {
+                policy: policy(),
+                resume_file: Some(checkpoint_file.clone()),
+                snapshot_file: Some(snapshot_file.clone()),
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            apply_transaction(input.as_bytes(), &options, &mut output).unwrap();
+            assert_eq!(
+                (snapshot_state(&snapshot_file), output),
+                (expected.clone(), expected_output.clone()),
+                "round {round}, interrupted after {remaining} bytes, checkpoint after record {position}"
+            );
+            assert_eq!(snapshot::read_ledger(&snapshot_file).unwrap(), ledger);
+            assert_eq!(snapshot::read_position(&snapshot_file).unwrap(), 0);
+        }
+
+        // a checkpoint cannot be restored, nor resumed with an input shorter than the records it applied
+        let options = ProcessingOptions {
+            checkpoint: Some(Checkpoint {
+                file: checkpoint_file.clone(),
+                interval: NonZeroU64::new(100).unwrap(),
+            }),
+            ..Default::default()
+        };
+        apply_transaction(input.as_bytes(), &options, std::io::sink()).unwrap();
+        let options = ProcessingOptions {
+            restore_file: Some(checkpoint_file.clone()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            apply_transaction(input.as_bytes(), &options, std::io::sink()),
+            Err(Error::InvalidSnapshot(_))
+        ));
+        let options = ProcessingOptions {
+            resume_file: Some(checkpoint_file.clone()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            apply_transaction(
+                "type,client,tx,amount\n".as_bytes(),
+                &options,
+                std::io::sink()
+            ),
+            Err(Error::ResumePastInput(300, 0))
+        ));
+        assert!(matches!(
+            apply_transaction(
+                input.as_bytes(),
+                &ProcessingOptions {
+                    checkpoint: Some(Checkpoint {
+                        file: checkpoint_file.clone(),
+                        interval: NonZeroU64::new(100).unwrap(),
+                    }),
+                    threads: NonZeroUsize::new(2),
+                    ..Default::default()
+                },
+                std::io::sink()
+            ),
+            Err(Error::CheckpointWithThreads)
+        ));
+    }
+
+    /// A run with worker threads killed at random points, leaving the segments of its write-ahead log cut at different
+    /// lengths, is rebuilt from the log and resumed with the same input. Output and final state must be the ones of
+    /// an uninterrupted run.
+    #[test]
+    fn test_wal_recovery() {
+        let dir = TestDir::new("wal");
+        let wal_dir = dir.join("wal");
+        let mut random = Lcg(0x3a1);
+        let options = |wal_dir: Option<&std::path::Path>, resume_file: Option<&std::path::Path>| {
+            ProcessingOptions {
+                policy: EnginePolicy {
+                    history_limit: Some(40),
+                    ..Default::default()
+                },
+                threads: NonZeroUsize::new(3),
+                wal_dir: wal_dir.map(ToOwned::to_owned),
+                resume_file: resume_file.map(ToOwned::to_owned),
+                snapshot_file: Some(dir.join("state.snap")),
+                ..Default::default()
+            }
+        };
+        let input = format!(
+            "type,client,tx,amount\n{}\n",
+            chaos_records(&mut random, 6000).join("\n")
+        );
+
+        let mut expected_output = Vec::new();
+        apply_transaction(input.as_bytes(), &options(None, None), &mut expected_output).unwrap();
+        let expected = snapshot_state(&dir.join("state.snap"));
+
+        let checkpoint_file = dir.join("recovered.snap");
+        let mut positions = Vec::new();
+        for round in 0..8 {
+            apply_transaction(
+                input.as_bytes(),
+                &options(Some(&wal_dir), None),
+                std::io::sink(),
+            )
+            .unwrap();
+            // every worker was killed at a point of its own, possibly in the middle of a frame
+            for shard in 0..3 {
+                let segment = wal_dir.join(format!("shard-{shard}.wal"));
+                let length = std::fs::metadata(&segment).unwrap().len();
+                let kept = match round {
+                    0 => length,
+                    _ => random.next(length + 1),
+                };
+                let file = File::options().write(true).open(&segment).unwrap();
+                file.set_len(kept.max(9)).unwrap();
+            }
+            let position = recover_wal(&wal_dir, &checkpoint_file).unwrap();
+            assert_eq!(snapshot::read_position(&checkpoint_file).unwrap(), position);
+            positions.push(position);
+
+            let mut output = Vec::new();
+            apply_transaction(
+                input.as_bytes(),
+                &options(None, Some(&checkpoint_file)),
+                &mut output,
+            )
+            .unwrap();
+            assert_eq!(
+                (snapshot_state(&dir.join("state.snap")), output),
+                (expected.clone(), expected_output.clone()),
+                "round {round}, recovered after input record {position}"
+            );
+        }
+        // the uninterrupted log recovers every transaction, the others stopped on their way
+        assert!(positions[0] > 5000, "{positions:?}");
+        assert!(positions[1..]
+            .iter()
+            .any(|position| *position < positions[0]));
+
+        // a log missing a segment cannot be recovered, and only worker threads write a log
+        std::fs::remove_file(wal_dir.join("shard-1.wal")).unwrap();
+        assert!(matches!(
+            recover_wal(&wal_dir, &checkpoint_file),
+            Err(Error::InvalidWal(_))
+        ));
+        let options = ProcessingOptions {
+            wal_dir: Some(wal_dir.clone()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            apply_transaction(input.as_bytes(), &options, std::io::sink()),
+            Err(Error::WalWithoutThreads)
+        ));
+    }
+
+    /// Random mix of transactions over two clients with amounts at the edges of the representable range,
+    /// adjustments of either sign included
+    fn extreme_records(random: &mut Lcg, count: u32) -> Vec<String> {
+        let amounts = [
+            Decimal::MAX.to_string(),
+            "461168601842738.7904".to_owned(),
+            "922337203685477.5806".to_owned(),
+            "0.0001".to_owned(),
+            "0".to_owned(),
+        ];
+        (1..=count)
+            .map(|tx| {
+                let client = random.next(2) + 1;
+                let referenced = random.next(u64::from(tx)) + 1;
+                let amount = &amounts[random.next(amounts.len() as u64) as usize];
+                match random.next(12) {
+                    0..=3 => format!("deposit,{client},{tx},{amount}"),
+                    4..=5 => format!("withdrawal,{client},{tx},{amount}"),
+                    6..=7 => format!("dispute,{client},{referenced},"),
+                    8 => format!("resolve,{client},{referenced},"),
+                    9 => format!("chargeback,{client},{referenced},"),
+                    10 => format!("adjustment,{client},{tx},{amount},BOUNDARY"),
+                    _ => format!("adjustment,{client},{tx},-{amount},BOUNDARY"),
+                }
+            })
+            .collect()
+    }
+
+    /// Transactions that would take a balance or a total out of range are rejected as overflows, and the outputs
+    /// aggregating several accounts refuse sums out of range: nothing wraps around or saturates, which test builds
+    /// would also catch as a panic
+    #[test]
+    fn test_overflow() {
+        let dir = TestDir::new("overflow");
+        let mut random = Lcg(0x0f10);
+        let records = extreme_records(&mut random, 2000);
+        let input = format!("type,client,tx,amount,reason\n{}\n", records.join("\n"));
+        let options = ProcessingOptions {
+            policy: EnginePolicy {
+                negative_adjustments: NegativeAdjustments::All,
+                adjust_locked_accounts: true,
+                ..Default::default()
+            },
+            rejects_file: Some(dir.join("rejects.csv")),
+            ..Default::default()
+        };
+
+        let mut buf = Vec::new();
+        apply_transaction(input.as_bytes(), &options, &mut buf).unwrap();
+        let rejects = std::fs::read_to_string(dir.join("rejects.csv")).unwrap();
+        assert!(rejects.lines().any(|line| line.contains(",overflow,")));
+        // every balance is in range and consistent with the others
+        for line in String::from_utf8(buf).unwrap().lines().skip(1) {
+            let fields = line.split(',').collect::<Vec<_>>();
+            assert_eq!(
+                decimal(fields[1]).checked_add(decimal(fields[2])),
+                Some(decimal(fields[3])),
+                "{line}"
+            );
+        }
+        // outputs of the events applied run through the same amounts
+        let exported = export_transaction(
+            input.as_bytes(),
+            &options,
+            ExportFormat::Qif,
+            "2024-01-01",
+            Vec::new(),
+        );
+        assert!(exported.is_ok());
+
+        // two accounts holding the largest amount each, under dispute
+        let input = format!(
+            "type,client,tx,amount\ndeposit,1,1,{max}\ndeposit,2,2,{max}\ndispute,1,1,\ndispute,2,2,\n",
+            max = Decimal::MAX
+        );
+        let options = ProcessingOptions::default();
+        for kind in [ReportKind::Cohorts(None), ReportKind::DisputeOutcomes] {
+            assert!(matches!(
+                report_transaction(input.as_bytes(), &options, kind, Vec::new()),
+                Err(Error::AmountOverflow(_))
+            ));
+        }
+        let options = ProcessingOptions {
+            anomaly_rate: Some(AnomalyRate {
+                max_change: Decimal::MAX,
+                window: 10,
+            }),
+            ..Default::default()
+        };
+        assert!(matches!(
+            apply_transaction(input.as_bytes(), &options, Vec::new()),
+            Err(Error::AmountOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_storage_retry() {
+        let retry = RetryPolicy {
+            attempts: 3,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        };
+        let transient = || Error::Io(std::io::Error::from(std::io::ErrorKind::TimedOut));
+
+        // transient failures are retried while attempts are left
+        let mut failures = 2;
+        let result = retry.run("write snapshot", || match failures {
+            0 => Ok("written"),
+            _ => {
+                failures -= 1;
+                Err(transient())
+            }
+        });
+        assert_eq!(result.unwrap(), "written");
+
+        // then the operation fails with a fatal error
+        let mut attempts = 0;
+        let result = retry.run("write snapshot", || -> Result<(), Error> {
+            attempts += 1;
+            Err(transient())
+        });
+        assert!(matches!(
+            result,
+            Err(Error::StorageUnavailable(operation, 3, _)) if operation == "write snapshot"
+        ));
+        assert_eq!(attempts, 3);
+
+        // other failures are not retried
+        let mut attempts = 0;
+        let result = retry.run("read snapshot", || -> Result<(), Error> {
+            attempts += 1;
+            Err(Error::Io(std::io::Error::from(
+                std::io::ErrorKind::NotFound,
+            )))
+        });
+        assert!(matches!(result, Err(Error::Io(_))));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_partitions() {
+        let output_dir = TestDir::new("partitions");
+        apply_transaction_partitioned(
+            [File::open("./tests/inputs/input_09_partitions.csv").unwrap()],
+            &ProcessingOptions::default(),
+            4,
+            output_dir.to_path_buf(),
+        )
+        .unwrap();
+
+        let expected_dir = PathBuf::from("./tests/outputs/expected_output_09_partitions");
+        for entry in std::fs::read_dir(&expected_dir).unwrap() {
+            let file_name = entry.unwrap().file_name();
+            assert_eq!(
+                std::fs::read_to_string(output_dir.join(&file_name)).unwrap(),
+                std::fs::read_to_string(expected_dir.join(&file_name)).unwrap(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_pages() {
+        let output_dir = TestDir::new("pages");
+        let input_file = "./tests/inputs/input_09_partitions.csv";
+        let paged = |rows, bytes| {
+            apply_transaction_paged(
+                [File::open(input_file).unwrap()],
+                &ProcessingOptions::default(),
+                PageLimits {
+                    rows: NonZeroUsize::new(rows),
+                    bytes: NonZeroU64::new(bytes),
+                },
+                output_dir.to_path_buf(),
+            )
+        };
+        paged(3, 100).unwrap();
+
+        let expected_dir = PathBuf::from("./tests/outputs/expected_output_09_pages");
+        for entry in std::fs::read_dir(&expected_dir).unwrap() {
+            let file_name = entry.unwrap().file_name();
+            assert_eq!(
+                std::fs::read_to_string(output_dir.join(&file_name)).unwrap(),
+                std::fs::read_to_string(expected_dir.join(&file_name)).unwrap(),
+            );
+        }
+
+        // pages without their repeated headers give back the whole output
+        paged(3, 0).unwrap();
+        let mut buf = Vec::new();
+        apply_transaction(
+            File::open(input_file).unwrap(),
+            &ProcessingOptions::default(),
+            &mut buf,
+        )
+        .unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        let first_page = std::fs::read_to_string(output_dir.join("accounts_page_0.csv")).unwrap();
+        let second_page = std::fs::read_to_string(output_dir.join("accounts_page_1.csv")).unwrap();
+        assert_eq!(first_page.lines().count(), 4);
+        assert_eq!(first_page + second_page.split_once('\n').unwrap().1, output);
+
+        // a single account must fit in a page together with the header
+        assert!(matches!(
+            paged(0, 65),
+            Err(Error::OutputPageTooSmall(65, 20000))
+        ));
+    }
+
+    #[test]
+    fn test_verify_match() {
+        let mut buf = Vec::new();
+        verify_transaction(
+            File::open("./tests/inputs/input_01_basic.csv").unwrap(),
+            &ProcessingOptions::default(),
+            PathBuf::from("./tests/outputs/expected_output_01_basic.csv"),
+            decimal("0.0001"),
+            &mut buf,
+        )
+        .unwrap();
+
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_verify_mismatch() {
+        let mut buf = Vec::new();
+        let result = verify_transaction(
+            File::open("./tests/inputs/input_01_basic.csv").unwrap(),
+            &ProcessingOptions::default(),
+            PathBuf::from("./tests/outputs/expected_output_10_verify_mismatch.csv"),
+            decimal("0.0001"),
+            &mut buf,
+        );
+
+        assert!(matches!(result, Err(Error::VerificationFailed(3))));
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "client 1: available expected 1.4000, got 1.5000\n\
+             client 1: locked expected true, got false\n\
+             client 3: missing from output\n"
+        );
+    }
+
+    #[test]
+    fn test_enrichment() {
+        let mut options = ProcessingOptions {
+            policy: EnginePolicy {
+                rejected_attributes: vec![("channel".to_owned(), "atm".to_owned())],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        options
+            .enrichment
+            .load(&PathBuf::from("./tests/inputs/enrichment_11_client.csv"))
+            .unwrap();
+        options
+            .enrichment
+            .load(&PathBuf::from("./tests/inputs/enrichment_11_tx.csv"))
+            .unwrap();
+
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_11_enrichment.csv"),
+            &options,
+            PathBuf::from("./tests/outputs/expected_output_11_enrichment.csv"),
+        );
+    }
+
+    #[test]
+    fn test_channel_rules() {
+        let mut options = ProcessingOptions {
+            policy: EnginePolicy {
+                channel_rules: HashMap::from([(Type::Dispute, vec!["online".to_owned()])]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        options
+            .enrichment
+            .load(&PathBuf::from("./tests/inputs/enrichment_12_channels.csv"))
+            .unwrap();
+
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_12_channel_rules.csv"),
+            &options,
+            PathBuf::from("./tests/outputs/expected_output_12_channel_rules.csv"),
+        );
+    }
+
+    /// Output of the v1 format must stay byte-identical to the one of the first release,
+    /// except for row ordering: same header, same line terminator, same number formatting
+    #[test]
+    fn test_v1_compatibility() {
+        let fixtures = [
+            "01_basic",
+            "02_insufficient_funds",
+            "03_dispute_resolve",
+            "04_chargeback",
+            "05_invalid_dispute",
+            "06_duplicate_tx",
+            "07_whitespace_precision",
+            "08_dispute_on_dispute",
+        ];
+
+        for fixture in fixtures {
+            let mut buf = Vec::new();
+            apply_transaction(
+                File::open(format!("./tests/inputs/input_{fixture}.csv")).unwrap(),
+                &ProcessingOptions::default(),
+                &mut buf,
+            )
+            .unwrap();
+            let expected =
+                std::fs::read(format!("./tests/outputs/expected_output_{fixture}.csv")).unwrap();
+
+            let split_rows = |bytes: &[u8]| {
+                let mut rows = bytes
+                    .split_inclusive(|byte| *byte == b'\n')
+                    .map(<[u8]>::to_vec)
+                    .collect::<Vec<_>>();
+                rows[1..].sort();
+                rows
+            };
+            assert_eq!(split_rows(&buf), split_rows(&expected), "fixture {fixture}");
+        }
+    }
+
+    #[test]
+    fn test_cohort_report() {
+        let mut buf = Vec::new();
+        report_transaction(
+            File::open("./tests/inputs/input_13_cohorts.csv").unwrap(),
+            &ProcessingOptions::default(),
+            ReportKind::Cohorts(Some(PathBuf::from("./tests/inputs/cohorts_13.csv"))),
+            &mut buf,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_13_cohorts.txt").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_risk_score() {
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_14_risk_score.csv"),
+            &ProcessingOptions {
+                output_format: OutputFormat::Extended,
+                ..Default::default()
+            },
+            PathBuf::from("./tests/outputs/expected_output_14_risk_score.csv"),
+        );
+    }
+
+    #[test]
+    fn test_dispute_outcomes_report() {
+        let mut buf = Vec::new();
+        report_transaction(
+            File::open("./tests/inputs/input_13_cohorts.csv").unwrap(),
+            &ProcessingOptions::default(),
+            ReportKind::DisputeOutcomes,
+            &mut buf,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_13_dispute_outcomes.txt")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_activity_report() {
+        let mut buf = Vec::new();
+        report_transaction(
+            File::open("./tests/inputs/input_36_activity.csv").unwrap(),
+            &ProcessingOptions::default(),
+            ReportKind::Activity,
+            &mut buf,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_36_activity.txt").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_minor_units() {
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_15_minor_units.csv"),
+            &ProcessingOptions {
+                amount_units: AmountUnits::Minor(CurrencyExponents {
+                    default: 2,
+                    by_currency: HashMap::from([("JPY".to_owned(), 0), ("BHD".to_owned(), 3)]),
+                }),
+                ..Default::default()
+            },
+            PathBuf::from("./tests/outputs/expected_output_15_minor_units.csv"),
+        );
+    }
+
+    #[test]
+    fn test_deterministic() {
+        // accounts are sorted by client ID by default
+        let options = ProcessingOptions::default();
+
+        for _ in 0..10 {
+            let mut buf = Vec::new();
+            apply_transaction(
+                File::open("./tests/inputs/input_13_cohorts.csv").unwrap(),
+                &options,
+                &mut buf,
+            )
+            .unwrap();
+
+            assert_eq!(
+                buf,
+                std::fs::read("./tests/outputs/expected_output_16_deterministic.csv").unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_sort_by() {
+        let options = ProcessingOptions {
+            sort_by: "total".parse().unwrap(),
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        apply_transaction(
+            File::open("./tests/inputs/input_13_cohorts.csv").unwrap(),
+            &options,
+            &mut buf,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_28_sort_by_total.csv")
+                .unwrap()
+        );
+        assert!(matches!(
+            "risk".parse::<SortKey>(),
+            Err(Error::InvalidSortKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_backfill() {
+        let mut buf = Vec::new();
+        backfill_accounts(
+            PathBuf::from("./tests/outputs/expected_output_01_basic.csv"),
+            PathBuf::from("./tests/inputs/corrections_17.csv"),
+            &ProcessingOptions::default(),
+            &mut buf,
+        )
+        .unwrap();
+        let expected =
+            std::fs::read_to_string("./tests/outputs/expected_output_17_backfill.csv").unwrap();
+
+        assert_eq!(
+            sorted_rows(&String::from_utf8(buf).unwrap()),
+            sorted_rows(&expected)
+        );
+    }
+
+    #[test]
+    fn test_backfill_missing_reason() {
+        let result = backfill_accounts(
+            PathBuf::from("./tests/outputs/expected_output_01_basic.csv"),
+            PathBuf::from("./tests/inputs/corrections_17_missing_reason.csv"),
+            &ProcessingOptions::default(),
+            Vec::new(),
+        );
+
+        assert!(matches!(result, Err(Error::MissingReasonCode(3))));
+    }
+
+    #[test]
+    fn test_adjustment() {
+        check_result(
+            PathBuf::from("./tests/inputs/input_18_adjustment.csv"),
+            PathBuf::from("./tests/outputs/expected_output_18_adjustment.csv"),
+        );
+    }
+
+    #[test]
+    fn test_adjustment_permissive_policy() {
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_18_adjustment.csv"),
+            &ProcessingOptions {
+                policy: EnginePolicy {
+                    negative_adjustments: NegativeAdjustments::All,
+                    adjust_locked_accounts: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PathBuf::from("./tests/outputs/expected_output_18_adjustment_permissive.csv"),
+        );
+    }
+
+    #[test]
+    fn test_decimal() {
+        let format = |value: &str| decimal(value).to_string();
+        assert_eq!(format("1"), "1.0000");
+        assert_eq!(format(" +.5 "), "0.5000");
+        assert_eq!(format("-0.00005"), "-0.0001");
+        assert_eq!(format("2.23456"), "2.2346");
+        assert_eq!(format("0.33454"), "0.3345");
+        assert_eq!(format("-12.3"), "-12.3000");
+        assert_eq!(format("922337203685477.5807"), "922337203685477.5807");
+        for invalid in ["", "-", ".", "1e3", "1.2.3", "0x10", "922337203685477.5808"] {
+            assert!(invalid.parse::<Decimal>().is_err(), "{invalid}");
+        }
+
+        assert_eq!(
+            decimal("0.1")
+                .checked_add(decimal("0.2"))
+                .and_then(|sum| sum.checked_sub(decimal("0.3"))),
+            Some(Decimal::ZERO)
+        );
+        assert_eq!(Decimal::MAX.checked_add(decimal("0.0001")), None);
+        assert_eq!((-Decimal::MAX).checked_sub(decimal("0.0001")), None);
+        assert_eq!((-Decimal::MAX).abs(), Decimal::MAX);
+        assert_eq!(Decimal::from_minor_units(1050, 2), Some(decimal("10.5")));
+        assert_eq!(Decimal::from_minor_units(-7, 0), Some(decimal("-7")));
+        assert_eq!(
+            Decimal::from_minor_units(123456, 6),
+            Some(decimal("0.1235"))
+        );
+        assert_eq!(Decimal::from_minor_units(i64::MAX, 2), None);
+        assert_eq!(Decimal::from_f64(1.00005), decimal("1.0001"));
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        check_result(
+            PathBuf::from("./tests/inputs/input_19_fixed_point.csv"),
+            PathBuf::from("./tests/outputs/expected_output_19_fixed_point.csv"),
+        );
+    }
+
+    #[test]
+    fn test_snapshot_compaction() {
+        let dir = TestDir::new("snapshot");
+        let snapshot_file = dir.join("state.snap");
+        let archive_file = dir.join("archive.snap");
+        let options = ProcessingOptions {
+            snapshot_file: Some(snapshot_file.clone()),
+            archive_file: Some(archive_file.clone()),
+            ..Default::default()
+        };
+
+        let mut buf = Vec::new();
+        apply_transaction(
+            File::open("./tests/inputs/input_20_snapshot.csv").unwrap(),
+            &options,
+            &mut buf,
+        )
+        .unwrap();
+
+        // output still lists every account, closed ones included
+        assert_eq!(
+            sorted_rows(&String::from_utf8(buf).unwrap()),
+            sorted_rows(
+                &std::fs::read_to_string("./tests/outputs/expected_output_20_snapshot.csv")
+                    .unwrap()
+            )
+        );
+
+        let state = snapshot::read_snapshot(&snapshot_file).unwrap();
+        let mut ids = state.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![1, 3]);
+        assert_eq!(state[&1].transations_history.len(), 2);
+        assert_eq!(state[&1].transations_history[&1].channel, None);
+        assert_eq!(
+            state[&3].transations_history[&5].dispute_status,
+            DisputeStatus::Disputed
+        );
+        assert_eq!(state[&3].dispute_outcomes.pending(), 1);
+        assert_eq!(state[&3].held.value(), decimal("4.0"));
+
+        let archive = snapshot::read_snapshot(&archive_file).unwrap();
+        assert_eq!(archive.keys().copied().collect::<Vec<_>>(), vec![2]);
+        assert!(archive[&2].transations_history.is_empty());
+    }
+
+    #[test]
+    fn test_transaction_index() {
+        let dir = TestDir::new("tx_index");
+        let snapshot_file = dir.join("state.snap");
+        let policy = || EnginePolicy {
+            history_limit: Some(2),
+            ..Default::default()
+        };
+        let options = ProcessingOptions {
+            policy: policy(),
+            snapshot_file: Some(snapshot_file.clone()),
+            ..Default::default()
+        };
+        apply_transaction(
+            File::open("./tests/inputs/input_40_tx_index.csv").unwrap(),
+            &options,
+            std::io::sink(),
+        )
+        .unwrap();
+
+        // tx 3 is owned by two clients, disputed by one of them
+        let query = |tx, format| {
+            let mut buf = Vec::new();
+            query_snapshot_transaction(snapshot_file.clone(), None, tx, format, &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            query(3, QueryFormat::Table),
+            std::fs::read_to_string("./tests/outputs/expected_output_40_tx_index.txt").unwrap()
+        );
+        assert_eq!(
+            query(1, QueryFormat::Json),
+            "[{\"tx\":1,\"client\":1,\"amount\":null,\"state\":\"pruned\"}]\n"
+        );
+        assert_eq!(query(9, QueryFormat::Json), "[]\n");
+        let index = index_snapshot(&snapshot_file, None).unwrap();
+        assert_eq!(index.len(), 4);
+        assert_eq!(
+            index.get(4),
+            [TransactionRef {
+                tx: 4,
+                client: 2,
+                amount: Some(decimal("1.0")),
+                state: DisputeState::Undisputed,
+            }]
+        );
+
+        // the engine keeps its index up to date as transactions are applied
+        let mut engine = TransactionEngine::new(policy());
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path("./tests/inputs/input_40_tx_index.csv")
+            .unwrap();
+        for transaction in reader.deserialize::<ClientTransaction>() {
+            engine.apply(transaction.unwrap()).unwrap();
+        }
+        for tx in 1..=4 {
+            assert_eq!(engine.transaction(tx), index.get(tx));
+        }
+        assert!(engine.transaction(9).is_empty());
+    }
+
+    #[test]
+    fn test_query() {
+        let dir = TestDir::new("query");
+        let snapshot_file = dir.join("state.snap");
+        let archive_file = dir.join("archive.snap");
+        let options = ProcessingOptions {
+            snapshot_file: Some(snapshot_file.clone()),
+            archive_file: Some(archive_file.clone()),
+            ..Default::default()
+        };
+        apply_transaction(
+            File::open("./tests/inputs/input_20_snapshot.csv").unwrap(),
+            &options,
+            std::io::sink(),
+        )
+        .unwrap();
+
+        let query = |archive_file: Option<PathBuf>, query: AccountQuery, format| {
+            let mut buf = Vec::new();
+            query_snapshot(
+                snapshot_file.clone(),
+                archive_file,
+                &query,
+                format,
+                &mut buf,
+            )
+            .unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+
+        assert_eq!(
+            query(None, AccountQuery::default(), QueryFormat::Table),
+            std::fs::read_to_string("./tests/outputs/expected_output_21_query.txt").unwrap()
+        );
+        assert_eq!(
+            query(
+                Some(archive_file.clone()),
+                AccountQuery {
+                    client: Some(2),
+                    ..Default::default()
+                },
+                QueryFormat::Json
+            ),
+            "[{\"client\":2,\"available\":0.0000,\"held\":0.0000,\"total\":0.0000,\"locked\":false,\"version\":2,\"annotations\":[]}]\n"
+        );
+        assert_eq!(
+            query(
+                Some(archive_file),
+                AccountQuery {
+                    min_held: Some(decimal("1.0")),
+                    ..Default::default()
+                },
+                QueryFormat::Json
+            ),
+            "[{\"client\":3,\"available\":0.0000,\"held\":4.0000,\"total\":4.0000,\"locked\":false,\"version\":2,\"annotations\":[]}]\n"
+        );
+        assert_eq!(
+            query(
+                None,
+                AccountQuery {
+                    locked: true,
+                    ..Default::default()
+                },
+                QueryFormat::Json
+            ),
+            "[]\n"
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = |expression: &str| {
+            let options = ProcessingOptions {
+                filter: Some(expression.parse::<Filter>().unwrap()),
+                ..Default::default()
+            };
+            let mut buf = Vec::new();
+            apply_transaction(
+                File::open("./tests/inputs/input_20_snapshot.csv").unwrap(),
+                &options,
+                &mut buf,
+            )
+            .unwrap();
+            String::from_utf8(buf)
+                .unwrap()
+                .lines()
+                .skip(1)
+                .map(|row| row.split(',').next().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(filter("held > 0 && !locked"), vec!["3"]);
+        assert_eq!(
+            filter("available >= 7.5 || (held>0&&total<=4)"),
+            vec!["1", "3"]
+        );
+        assert_eq!(filter("!(client == 1) && locked == false"), vec!["2", "3"]);
+        assert_eq!(filter("!(total != 0 || locked)"), vec!["2"]);
+
+        // balances are compared exactly, beyond the precision of a floating point number
+        let options = ProcessingOptions {
+            filter: Some("total > 900000000000000".parse().unwrap()),
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        apply_transaction(
+            "type,client,tx,amount\ndeposit,1,1,900000000000000.0001\ndeposit,2,2,900000000000000\n"
+                .as_bytes(),
+            &options,
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "client,available,held,total,locked\n1,900000000000000.0001,0.0000,900000000000000.0001,false\n"
+        );
+
+        for invalid in [
+            "",
+            "held >",
+            "held",
+            "locked > 1",
+            "held == true",
+            "balance > 0",
+            "(held > 0",
+            "held > 0 &&",
+            "held > 0 locked",
+            "held > 0 & locked",
+            "held > 0.00001",
+            "total > 1.2.3",
+        ] {
+            assert!(
+                matches!(invalid.parse::<Filter>(), Err(Error::InvalidFilter(..))),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_dump_state() {
+        let dir = TestDir::new("dump_state");
+        let snapshot_file = dir.join("state.snap");
+        let options = ProcessingOptions {
+            snapshot_file: Some(snapshot_file.clone()),
+            policy: EnginePolicy {
+                history_limit: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        apply_transaction(
+            File::open("./tests/inputs/input_20_snapshot.csv").unwrap(),
+            &options,
+            std::io::sink(),
+        )
+        .unwrap();
+
+        let mut buf = Vec::new();
+        dump_snapshot(snapshot_file, &mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_27_dump_state.json").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_proto_export() {
+        let dir = TestDir::new("proto_export");
+        let snapshot_file = dir.join("state.snap");
+        let imported_file = dir.join("imported.snap");
+        let dump = |snapshot_file: &Path| {
+            let mut buf = Vec::new();
+            dump_snapshot(snapshot_file.to_path_buf(), &mut buf).unwrap();
+            // imported snapshots start a new ledger
+            let dump = String::from_utf8(buf).unwrap();
+            dump.lines().skip(2).collect::<Vec<_>>().join("\n")
+        };
+
+        // pruned transactions, then every dispute status and transfers
+        for (input, history_limit) in [
+            ("./tests/inputs/input_20_snapshot.csv", Some(1)),
+            ("./tests/inputs/input_48_dispute_lifecycle.csv", None),
+        ] {
+            let options = ProcessingOptions {
+                snapshot_file: Some(snapshot_file.clone()),
+                policy: EnginePolicy {
+                    history_limit,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            std::fs::remove_file(&snapshot_file).ok();
+            apply_transaction(File::open(input).unwrap(), &options, std::io::sink()).unwrap();
+
+            let mut exported = Vec::new();
+            export_snapshot_proto(&snapshot_file, None, &mut exported).unwrap();
+            let accounts = import_snapshot_proto(exported.as_slice(), &imported_file).unwrap();
+            assert_eq!(
+                accounts,
+                snapshot::read_snapshot(&snapshot_file).unwrap().len()
+            );
+            assert_eq!(dump(&imported_file), dump(&snapshot_file));
+
+            let truncated = &exported[..exported.len() - 1];
+            assert!(matches!(
+                import_snapshot_proto(truncated, &imported_file),
+                Err(Error::InvalidProtobuf(_))
+            ));
+            let twice = [exported.as_slice(), exported.as_slice()].concat();
+            assert!(matches!(
+                import_snapshot_proto(twice.as_slice(), &imported_file),
+                Err(Error::InvalidProtobuf(_))
+            ));
+        }
+
+        // client 7, available and total 1.5, locked, with an unknown field 15 skipped
+        let message = [
+            0x0e, 0x08, 0x07, 0x10, 0xb0, 0xea, 0x01, 0x20, 0xb0, 0xea, 0x01, 0x78, 0x01, 0x28,
+            0x01,
+        ];
+        assert_eq!(
+            import_snapshot_proto(&message[..], &imported_file).unwrap(),
+            1
+        );
+        let client = &snapshot::read_snapshot(&imported_file).unwrap()[&7];
+        assert_eq!(client.available.value(), decimal("1.5"));
+        assert!(client.locked);
+        let mut exported = Vec::new();
+        export_snapshot_proto(&imported_file, None, &mut exported).unwrap();
+        assert_eq!(
+            exported,
+            [
+                0x0e, 0x08, 0x07, 0x10, 0xb0, 0xea, 0x01, 0x20, 0xb0, 0xea, 0x01, 0x28, 0x01, 0x32,
+                0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn test_demo() {
+        let mut buf = Vec::new();
+        run_demo(None, &ProcessingOptions::default(), &mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_28_demo.txt").unwrap()
+        );
+
+        assert_eq!(
+            "dispute-heavy".parse::<DemoDataset>().unwrap(),
+            DemoDataset::DisputeHeavy
+        );
+        assert!(matches!(
+            "disputes".parse::<DemoDataset>(),
+            Err(Error::InvalidDemoDataset(_))
+        ));
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let policy = EnginePolicy {
+            history_limit: Some(2),
+            ..Default::default()
+        };
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_22_history_limit.csv"),
+            &ProcessingOptions {
+                policy,
+                ..Default::default()
+            },
+            PathBuf::from("./tests/outputs/expected_output_22_history_limit.csv"),
+        );
+
+        let policy = EnginePolicy {
+            history_limit: Some(1),
+            ..Default::default()
+        };
+        let transaction = |transaction_type, tx, amount| ClientTransaction {
+            id: 1,
+            transaction_type,
+            tx,
+            amount,
+            reason: None,
+            evidence: None,
+            to_client: None,
+            attributes: Default::default(),
+        };
+        let mut client = Client::new(1);
+        client
+            .apply_transaction(
+                &transaction(Type::Deposit, 1, Some(decimal("1.0"))),
+                &policy,
+            )
+            .unwrap();
+        client
+            .apply_transaction(
+                &transaction(Type::Deposit, 2, Some(decimal("1.0"))),
+                &policy,
+            )
+            .unwrap();
+
+        // pruned references are rejected with a distinct reason from unknown ones
+        assert_eq!(
+            client.apply_transaction(&transaction(Type::Dispute, 1, None), &policy),
+            Err(Rejection::PrunedTransaction)
+        );
+        assert_eq!(
+            client.apply_transaction(&transaction(Type::Dispute, 3, None), &policy),
+            Err(Rejection::UnknownTransaction)
+        );
+        assert_eq!(
+            client.apply_transaction(
+                &transaction(Type::Deposit, 1, Some(decimal("1.0"))),
+                &policy
+            ),
+            Err(Rejection::DuplicateTransaction)
+        );
+        assert_eq!(client.transations_history.len(), 1);
+        assert_eq!(client.total.value(), decimal("2.0"));
+        // rejected transactions do not bump the account version
+        assert_eq!(client.version, 2);
+    }
+
+    #[test]
+    fn test_resolve_without_dispute() {
+        let policy = EnginePolicy::default();
+        let mut client = Client::new(1);
+        client
+            .apply_transaction(
+                &ClientTransaction::new(Type::Deposit, 1, 1, Some(decimal("3.0"))),
+                &policy,
+            )
+            .unwrap();
+
+        for transaction_type in [Type::Resolve, Type::ChargeBack] {
+            assert_eq!(
+                client.apply_transaction(
+                    &ClientTransaction::new(transaction_type, 1, 1, None),
+                    &policy
+                ),
+                Err(Rejection::NotDisputed)
+            );
+        }
+        assert_eq!(
+            client.apply_transaction(&ClientTransaction::new(Type::Resolve, 1, 2, None), &policy),
+            Err(Rejection::UnknownTransaction)
+        );
+        assert_eq!(client.available.value(), decimal("3.0"));
+        assert_eq!(client.held.value(), Decimal::ZERO);
+        assert_eq!(client.total.value(), decimal("3.0"));
+        assert!(!client.locked);
+        assert_eq!(client.version, 1);
+    }
+
+    #[test]
+    fn test_dispute_lifecycle() {
+        let dir = TestDir::new("dispute_lifecycle");
+        // resolved and charged back disputes are final: a later resolve, dispute or chargeback of the transaction
+        // moves no funds, unlocked accounts and transfers included
+        let options = ProcessingOptions {
+            policy: EnginePolicy {
+                allow_unlock: true,
+                ..Default::default()
+            },
+            rejects_file: Some(dir.join("rejects.csv")),
+            snapshot_file: Some(dir.join("state.snap")),
+            ..Default::default()
+        };
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_48_dispute_lifecycle.csv"),
+            &options,
+            PathBuf::from("./tests/outputs/expected_output_48_dispute_lifecycle.csv"),
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("rejects.csv")).unwrap(),
+            std::fs::read_to_string(
+                "./tests/outputs/expected_output_48_dispute_lifecycle_rejects.csv"
+            )
+            .unwrap()
+        );
+
+        // the status of every dispute is kept by snapshots, on both sides of a transfer
+        let state = snapshot::read_snapshot(&dir.join("state.snap")).unwrap();
+        let status = |client: u16, tx: u32| state[&client].transations_history[&tx].dispute_status;
+        assert_eq!(status(1, 1), DisputeStatus::ChargedBack);
+        assert_eq!(status(1, 2), DisputeStatus::Undisputed);
+        assert_eq!(status(2, 4), DisputeStatus::Resolved);
+        assert_eq!(status(3, 6), DisputeStatus::Resolved);
+        assert_eq!(status(4, 6), DisputeStatus::Resolved);
+        assert_eq!(status(3, 7), DisputeStatus::ChargedBack);
+        assert_eq!(status(4, 7), DisputeStatus::ChargedBack);
+
+        // disputes resolved on the spot by policy are final too
+        let policy = EnginePolicy {
+            auto_resolve_reasons: vec!["friendly_fraud".to_owned()],
+            ..Default::default()
+        };
+        let mut client = Client::new(1);
+        client
+            .apply_transaction(
+                &ClientTransaction::new(Type::Deposit, 1, 1, Some(decimal("3.0"))),
+                &policy,
+            )
+            .unwrap();
+        client
+            .apply_transaction(
+                &ClientTransaction::new(Type::Dispute, 1, 1, None).with_reason("friendly_fraud"),
+                &policy,
+            )
+            .unwrap();
+        assert_eq!(
+            client.apply_transaction(&ClientTransaction::new(Type::Resolve, 1, 1, None), &policy),
+            Err(Rejection::AlreadyResolved)
+        );
+        assert_eq!(client.available.value(), decimal("3.0"));
+        assert_eq!(client.held.value(), Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_charge_back_undisputed() {
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_38_undisputed_chargeback.csv"),
+            &ProcessingOptions::default(),
+            PathBuf::from("./tests/outputs/expected_output_38_undisputed_chargeback_ignored.csv"),
+        );
+        let policy = EnginePolicy {
+            charge_back_undisputed: true,
+            ..Default::default()
+        };
+        // deposits and withdrawals alike are charged back, unknown transactions are still ignored
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_38_undisputed_chargeback.csv"),
+            &ProcessingOptions {
+                policy,
+                ..Default::default()
+            },
+            PathBuf::from("./tests/outputs/expected_output_38_undisputed_chargeback.csv"),
+        );
+
+        // the dispute opened by the chargeback is recorded, with the details of the chargeback
+        let policy = EnginePolicy {
+            charge_back_undisputed: true,
+            ..Default::default()
+        };
+        let mut client = Client::new(1);
+        client
+            .apply_transaction(
+                &ClientTransaction::new(Type::Deposit, 1, 1, Some(decimal("3.0"))),
+                &policy,
+            )
+            .unwrap();
+        let events = client
+            .apply_transaction(
+                &ClientTransaction::new(Type::ChargeBack, 1, 1, None).with_reason("fraud"),
+                &policy,
+            )
+            .unwrap();
+        let details = DisputeDetails {
+            reason: Some("fraud".to_owned()),
+            evidence: None,
+        };
+        let amount = Amount::new(decimal("3.0")).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                Event::DisputeImplied { tx: 1 },
+                Event::DisputeOpened {
+                    tx: 1,
+                    amount,
+                    details: details.clone(),
+                    effect: DisputeEffect::Debit,
+                },
+                Event::ChargedBack {
+                    tx: 1,
+                    amount,
+                    details,
+                    effect: DisputeEffect::Debit,
+                },
+                Event::Locked,
+            ]
+        );
+        assert_eq!(client.total.value(), Decimal::ZERO);
+        assert!(client.locked);
+        assert_eq!(client.dispute_outcomes.charged_back, 1);
+        assert_eq!(client.dispute_outcomes.pending(), 0);
+    }
+
+    #[test]
+    fn test_unlock() {
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_42_unlock.csv"),
+            &ProcessingOptions::default(),
+            PathBuf::from("./tests/outputs/expected_output_42_unlock_ignored.csv"),
+        );
+        let unlocking = |audit_unlocks| EnginePolicy {
+            allow_unlock: true,
+            audit_unlocks,
+            ..Default::default()
+        };
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_42_unlock.csv"),
+            &ProcessingOptions {
+                policy: unlocking(false),
+                ..Default::default()
+            },
+            PathBuf::from("./tests/outputs/expected_output_42_unlock.csv"),
+        );
+
+        let charged_back = |policy: &EnginePolicy| {
+            let mut client = Client::new(1);
+            for transaction in [
+                ClientTransaction::new(Type::Deposit, 1, 1, Some(decimal("3.0"))),
+                ClientTransaction::new(Type::Dispute, 1, 1, None),
+                ClientTransaction::new(Type::ChargeBack, 1, 1, None),
+            ] {
+                client.apply_transaction(&transaction, policy).unwrap();
+            }
+            client
+        };
+        let unlock = ClientTransaction::new(Type::Unlock, 1, 2, None).with_reason("ticket-42");
+        assert_eq!(
+            charged_back(&EnginePolicy::default())
+                .apply_transaction(&unlock, &EnginePolicy::default()),
+            Err(Rejection::RejectedByPolicy)
+        );
+
+        // unlocks are only recorded as notes when audited
+        let policy = unlocking(false);
+        let mut client = charged_back(&policy);
+        assert_eq!(
+            client.apply_transaction(&unlock, &policy),
+            Ok(vec![Event::Unlocked {
+                tx: 2,
+                reason: Some("ticket-42".to_owned()),
+                audited: false,
+            }])
+        );
+        assert!(!client.locked);
+        assert!(client.annotations.is_empty());
+        assert_eq!(
+            client.apply_transaction(&ClientTransaction::new(Type::Unlock, 1, 3, None), &policy),
+            Err(Rejection::NotLocked)
+        );
+
+        let audited = unlocking(true);
+        let mut client = charged_back(&audited);
+        client.apply_transaction(&unlock, &audited).unwrap();
+        assert!(!client.locked);
+        assert_eq!(client.annotations, vec!["unlocked by tx 2: ticket-42"]);
+    }
+
+    #[test]
+    fn test_withdrawal_dispute_modes() {
+        let input_file = PathBuf::from("./tests/inputs/input_43_withdrawal_disputes.csv");
+        let with_mode = |withdrawal_dispute_mode| ProcessingOptions {
+            policy: EnginePolicy {
+                withdrawal_dispute_mode,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for (mode, output_file) in [
+            (WithdrawalDisputeMode::Debit, "debit"),
+            (WithdrawalDisputeMode::Credit, "credit"),
+            (WithdrawalDisputeMode::Reject, "reject"),
+        ] {
+            check_result_with(
+                input_file.clone(),
+                &with_mode(mode),
+                PathBuf::from(format!(
+                    "./tests/outputs/expected_output_43_withdrawal_disputes_{output_file}.csv"
+                )),
+            );
+        }
+        assert!(matches!(
+            "refund".parse::<WithdrawalDisputeMode>(),
+            Err(Error::InvalidWithdrawalDisputeMode(_))
+        ));
+
+        // disputes are undone as they were opened, whatever the mode of the run resolving them
+        let dir = TestDir::new("withdrawal_disputes");
+        let snapshot_file = dir.join("state.snap");
+        let opened = ProcessingOptions {
+            snapshot_file: Some(snapshot_file.clone()),
+            ..with_mode(WithdrawalDisputeMode::Credit)
+        };
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,5.0\n\
+                     withdrawal,1,2,2.0\n\
+                     dispute,1,2,\n";
+        apply_transaction(input.as_bytes(), &opened, std::io::sink()).unwrap();
+        let resolved = ProcessingOptions {
+            restore_file: Some(snapshot_file.clone()),
+            ..with_mode(WithdrawalDisputeMode::Reject)
+        };
+        let mut output = Vec::new();
+        apply_transaction(
+            "type,client,tx,amount\nchargeback,1,2,\n".as_bytes(),
+            &resolved,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n1,5.0000,0.0000,5.0000,true\n"
+        );
+    }
+
+    #[test]
+    fn test_overdraft() {
+        let input_file = PathBuf::from("./tests/inputs/input_44_overdraft.csv");
+        let with_policy = |overdraft_limit: Option<&str>, dispute_shortfall| ProcessingOptions {
+            policy: EnginePolicy {
+                overdraft_limit: overdraft_limit.map(decimal),
+                dispute_shortfall,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for (overdraft_limit, dispute_shortfall, output_file) in [
+            (None, DisputeShortfall::Allow, "overdraft"),
+            (Some("10"), DisputeShortfall::Allow, "overdraft_limit"),
+            (None, DisputeShortfall::Reject, "overdraft_shortfall_reject"),
+            (
+                Some("10"),
+                DisputeShortfall::Reject,
+                "overdraft_limit_shortfall_reject",
+            ),
+        ] {
+            check_result_with(
+                input_file.clone(),
+                &with_policy(overdraft_limit, dispute_shortfall),
+                PathBuf::from(format!(
+                    "./tests/outputs/expected_output_44_{output_file}.csv"
+                )),
+            );
+        }
+        assert!(matches!(
+            "cap".parse::<DisputeShortfall>(),
+            Err(Error::InvalidDisputeShortfall(_))
+        ));
+
+        // a dispute refused for shortfall is rejected for insufficient funds
+        let policy = with_policy(None, DisputeShortfall::Reject).policy;
+        let mut client = Client::new(1);
+        for transaction in [
+            ClientTransaction::new(Type::Deposit, 1, 1, Some(decimal("2.0"))),
+            ClientTransaction::new(Type::Withdrawal, 1, 2, Some(decimal("1.0"))),
+        ] {
+            client.apply_transaction(&transaction, &policy).unwrap();
+        }
+        assert_eq!(
+            client.apply_transaction(&ClientTransaction::new(Type::Dispute, 1, 1, None), &policy),
+            Err(Rejection::InsufficientFunds)
+        );
+    }
+
+    #[test]
+    fn test_transaction_handlers() {
+        let policy = EnginePolicy::default();
+        let adjusting_locked = EnginePolicy {
+            adjust_locked_accounts: true,
+            ..Default::default()
+        };
+        // only unlocks and adjustments check their amount themselves, and may apply to locked accounts
+        for transaction_type in [
+            Type::Deposit,
+            Type::Withdrawal,
+            Type::Dispute,
+            Type::Resolve,
+            Type::ChargeBack,
+            Type::Transfer,
+        ] {
+            let handler = handlers::handler(transaction_type);
+            assert!(handler.takes_amount(), "{transaction_type:?}");
+            assert!(
+                !handler.applies_to_locked(&adjusting_locked),
+                "{transaction_type:?}"
+            );
+        }
+        let unlock = handlers::handler(Type::Unlock);
+        assert!(!unlock.takes_amount() && unlock.applies_to_locked(&policy));
+        let adjustment = handlers::handler(Type::Adjustment);
+        assert!(!adjustment.takes_amount());
+        assert!(!adjustment.applies_to_locked(&policy));
+        assert!(adjustment.applies_to_locked(&adjusting_locked));
+
+        // handlers produce the events of a transaction without modifying the account
+        let mut client = Client::new(1);
+        let deposit = ClientTransaction::new(Type::Deposit, 1, 1, Some(decimal("2.0")));
+        client.apply_transaction(&deposit, &policy).unwrap();
+        let dispute = ClientTransaction::new(Type::Dispute, 1, 1, None);
+        let events = handlers::handler(Type::Dispute)
+            .events(&client, &dispute, None, &policy)
+            .unwrap();
+        assert!(
+            matches!(
+                events.as_slice(),
+                [Event::DisputeOpened {
+                    tx: 1,
+                    effect: DisputeEffect::Debit,
+                    ..
+                }]
+            ),
+            "{events:?}"
+        );
+        assert_eq!(
+            client.transations_history[&1].dispute_status,
+            DisputeStatus::Undisputed
+        );
+        assert_eq!(
+            handlers::handler(Type::Withdrawal).events(
+                &client,
+                &ClientTransaction::new(Type::Withdrawal, 1, 2, Some(decimal("3.0"))),
+                Amount::new(decimal("3.0")),
+                &policy,
+            ),
+            Err(Rejection::InsufficientFunds)
+        );
+        assert_eq!(
+            handlers::handler(Type::Transfer).events(&client, &deposit, None, &policy),
+            Err(Rejection::InvalidTransfer)
+        );
+    }
+
+    #[test]
+    fn test_chargeback_then_deposit() {
+        let policy = EnginePolicy::default();
+        let mut client = Client::new(1);
+        for transaction in [
+            ClientTransaction::new(Type::Deposit, 1, 1, Some(decimal("2.0"))),
+            ClientTransaction::new(Type::Deposit, 1, 2, Some(decimal("1.0"))),
+            ClientTransaction::new(Type::Dispute, 1, 1, None),
+        ] {
+            client.apply_transaction(&transaction, &policy).unwrap();
+        }
+        assert_eq!(
+            client.apply_transaction(
+                &ClientTransaction::new(Type::ChargeBack, 1, 1, None),
+                &policy
+            ),
+            Ok(vec![
+                Event::ChargedBack {
+                    tx: 1,
+                    amount: Amount::new(decimal("2.0")).unwrap(),
+                    details: Default::default(),
+                    effect: DisputeEffect::Debit,
+                },
+                Event::Locked
+            ])
+        );
+
+        // every following transaction is ignored, adjustments included unless allowed by policy
+        for transaction in [
+            ClientTransaction::new(Type::Deposit, 1, 3, Some(decimal("5.0"))),
+            ClientTransaction::new(Type::Withdrawal, 1, 4, Some(decimal("1.0"))),
+            ClientTransaction::new(Type::Dispute, 1, 2, None),
+            ClientTransaction::new(Type::Adjustment, 1, 5, Some(decimal("1.0")))
+                .with_reason("refund"),
+        ] {
+            assert_eq!(
+                client.apply_transaction(&transaction, &policy),
+                Err(Rejection::LockedAccount)
+            );
+        }
+        assert_eq!(client.available.value(), decimal("1.0"));
+        assert_eq!(client.held.value(), Decimal::ZERO);
+        assert_eq!(client.total.value(), decimal("1.0"));
+        assert!(client.locked);
+
+        let policy = EnginePolicy {
+            adjust_locked_accounts: true,
+            ..Default::default()
+        };
+        client
+            .apply_transaction(
+                &ClientTransaction::new(Type::Adjustment, 1, 5, Some(decimal("1.0")))
+                    .with_reason("refund"),
+                &policy,
+            )
+            .unwrap();
+        assert_eq!(client.total.value(), decimal("2.0"));
+        assert!(client.locked);
+    }
+
+    #[test]
+    fn test_dispute_after_resolve() {
+        let policy = EnginePolicy::default();
+        let mut client = Client::new(1);
+        for transaction in [
+            ClientTransaction::new(Type::Deposit, 1, 1, Some(decimal("2.0"))),
+            ClientTransaction::new(Type::Dispute, 1, 1, None),
+            ClientTransaction::new(Type::Resolve, 1, 1, None),
+        ] {
+            client.apply_transaction(&transaction, &policy).unwrap();
+        }
+        assert_eq!(client.available.value(), decimal("2.0"));
+        assert_eq!(client.held.value(), Decimal::ZERO);
+
+        // a resolved dispute cannot be reopened
+        assert!(client
+            .apply_transaction(&ClientTransaction::new(Type::Dispute, 1, 1, None), &policy)
+            .is_err());
+        assert_eq!(client.available.value(), decimal("2.0"));
+        assert_eq!(client.held.value(), Decimal::ZERO);
+        assert_eq!(client.total.value(), decimal("2.0"));
+        assert_eq!(client.dispute_outcomes.opened, 1);
+        assert_eq!(client.dispute_outcomes.resolved, 1);
+    }
+
+    #[test]
+    fn test_balance_boundaries() {
+        let policy = EnginePolicy::default();
+        let mut client = Client::new(1);
+        client
+            .apply_transaction(
+                &ClientTransaction::new(Type::Deposit, 1, 1, Some(decimal("2.0"))),
+                &policy,
+            )
+            .unwrap();
+
+        // negative amounts are only valid for adjustments
+        assert_eq!(
+            client.apply_transaction(
+                &ClientTransaction::new(Type::Deposit, 1, 2, Some(decimal("-1.0"))),
+                &policy
+            ),
+            Err(Rejection::InvalidAmount)
+        );
+        assert_eq!(
+            client.apply_transaction(
+                &ClientTransaction::new(Type::Withdrawal, 1, 2, None),
+                &policy
+            ),
+            Err(Rejection::InvalidAmount)
+        );
+        assert_eq!(
+            client.apply_transaction(
+                &ClientTransaction::new(Type::Withdrawal, 1, 2, Some(decimal("2.0001"))),
+                &policy
+            ),
+            Err(Rejection::InsufficientFunds)
+        );
+        // withdrawing or adjusting down to exactly zero is allowed
+        client
+            .apply_transaction(
+                &ClientTransaction::new(Type::Withdrawal, 1, 2, Some(decimal("1.0"))),
+                &policy,
+            )
+            .unwrap();
+        assert_eq!(
+            client.apply_transaction(
+                &ClientTransaction::new(Type::Adjustment, 1, 3, Some(decimal("-1.0001")))
+                    .with_reason("fee"),
+                &policy
+            ),
+            Err(Rejection::NegativeBalance)
+        );
+        client
+            .apply_transaction(
+                &ClientTransaction::new(Type::Adjustment, 1, 3, Some(decimal("-1.0")))
+                    .with_reason("fee"),
+                &policy,
+            )
+            .unwrap();
+        assert_eq!(client.available.value(), Decimal::ZERO);
+        assert_eq!(client.total.value(), Decimal::ZERO);
+        assert_eq!(client.version, 3);
+    }
+
+    #[test]
+    fn test_input_ledger() {
+        let digest = |data: &[u8]| {
+            let mut hasher = sha256::Sha256::default();
+            // split updates to exercise buffering across blocks
+            data.chunks(7).for_each(|chunk| hasher.update(chunk));
+            sha256::to_hex(&hasher.finalize())
+        };
+        assert_eq!(
+            digest(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+
+        let dir = TestDir::new("ledger");
+        let snapshot_file = dir.join("state.snap");
+        let input_file = PathBuf::from("./tests/inputs/input_20_snapshot.csv");
+        let options = ProcessingOptions {
+            snapshot_file: Some(snapshot_file.clone()),
+            refuse_applied_inputs: true,
+            ..Default::default()
+        };
+
+        apply_transaction(File::open(&input_file).unwrap(), &options, std::io::sink()).unwrap();
+        let input_digest = sha256::digest_file(&input_file).unwrap();
+        assert_eq!(
+            snapshot::read_ledger(&snapshot_file).unwrap(),
+            vec![input_digest]
+        );
+
+        assert!(matches!(
+            apply_transaction(File::open(&input_file).unwrap(), &options, std::io::sink()),
+            Err(Error::InputAlreadyApplied(hash)) if hash == sha256::to_hex(&input_digest)
+        ));
+    }
+
+    #[test]
+    fn test_alerts() {
+        let dir = TestDir::new("alerts");
+        let alerts_file = dir.join("alerts.csv");
+        let options = ProcessingOptions {
+            alerts_file: Some(alerts_file.clone()),
+            alert_thresholds: AlertThresholds {
+                balance_above: Some(decimal("5.0")),
+                chargebacks_above: Some(0),
+            },
+            ..Default::default()
+        };
+
+        // alerts are appended, the header is written only once
+        for _ in 0..2 {
+            apply_transaction(
+                File::open("./tests/inputs/input_23_alerts.csv").unwrap(),
+                &options,
+                std::io::sink(),
+            )
+            .unwrap();
+        }
+
+        let expected =
+            std::fs::read_to_string("./tests/outputs/expected_output_23_alerts.csv").unwrap();
+        let (header, rows) = expected.split_once('\n').unwrap();
+        assert_eq!(
+            std::fs::read_to_string(alerts_file).unwrap(),
+            format!("{header}\n{rows}{rows}")
+        );
+    }
+
+    #[test]
+    fn test_export_camt053() {
+        let mut buf = Vec::new();
+        export_transaction(
+            File::open("./tests/inputs/input_24_export.csv").unwrap(),
+            &ProcessingOptions::default(),
+            ExportFormat::Camt053,
+            "01/01/1970",
+            &mut buf,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_24_camt053.xml").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_export_qif() {
+        let mut buf = Vec::new();
+        export_transaction(
+            File::open("./tests/inputs/input_24_export.csv").unwrap(),
+            &ProcessingOptions::default(),
+            ExportFormat::Qif,
+            "10/16/2026",
+            &mut buf,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_24_qif.qif").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_dispute_details() {
+        let options = ProcessingOptions {
+            policy: EnginePolicy {
+                auto_resolve_reasons: vec!["friendly-fraud".to_owned()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_29_dispute_details.csv"),
+            &options,
+            PathBuf::from("./tests/outputs/expected_output_29_dispute_details.csv"),
+        );
+
+        let mut buf = Vec::new();
+        export_transaction(
+            File::open("./tests/inputs/input_29_dispute_details.csv").unwrap(),
+            &options,
+            ExportFormat::Qif,
+            "10/16/2026",
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_29_dispute_details.qif")
+                .unwrap()
+        );
+
+        // details are kept on the dispute state across snapshots
+        let dir = TestDir::new("dispute_details");
+        let snapshot_file = dir.join("state.snap");
+        apply_transaction(
+            File::open("./tests/inputs/input_29_dispute_details.csv").unwrap(),
+            &ProcessingOptions {
+                snapshot_file: Some(snapshot_file.clone()),
+                ..Default::default()
+            },
+            std::io::sink(),
+        )
+        .unwrap();
+        let clients = snapshot::read_snapshot(&snapshot_file).unwrap();
+        let dispute =
+            |client: u16, tx: u32| clients[&client].transations_history[&tx].dispute.clone();
+        assert_eq!(dispute(1, 1).reason.as_deref(), Some("fraud"));
+        assert_eq!(dispute(1, 1).evidence.as_deref(), Some("CASE-17"));
+        assert_eq!(dispute(2, 3).reason.as_deref(), Some("friendly-fraud"));
+        assert_eq!(dispute(3, 4).reason, None);
+        assert_eq!(dispute(3, 4).evidence.as_deref(), Some("DOC-9"));
+    }
+
+    #[test]
+    fn test_iso8583_input() {
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_25_iso8583.txt"),
+            &ProcessingOptions {
+                input_format: InputFormat::Iso8583(CurrencyExponents {
+                    default: 2,
+                    by_currency: HashMap::from([("392".to_owned(), 0)]),
+                }),
+                ..Default::default()
+            },
+            PathBuf::from("./tests/outputs/expected_output_25_iso8583.csv"),
+        );
+    }
+
+    #[test]
+    fn test_print_config() {
+        let mut options = ProcessingOptions {
+            amount_units: AmountUnits::Minor(CurrencyExponents {
+                default: 2,
+                by_currency: HashMap::from([("JPY".to_owned(), 0), ("BHD".to_owned(), 3)]),
+            }),
+            policy: EnginePolicy {
+                rejected_attributes: vec![("channel".to_owned(), "a\"tm".to_owned())],
+                channel_rules: ["dispute=online", "deposit=online,card_present"]
+                    .into_iter()
+                    .map(|rule| rule.parse::<ChannelRule>().unwrap())
+                    .map(|rule| (rule.transaction_type, rule.channels))
+                    .collect(),
+                negative_adjustments: "3,1".parse().unwrap(),
+                history_limit: Some(100),
+                ..Default::default()
+            },
+            filter: Some("held > 0 && !locked".parse().unwrap()),
+            snapshot_file: Some(PathBuf::from("state.snap")),
+            anomaly_rate: Some(AnomalyRate {
+                max_change: "5000".parse().unwrap(),
+                window: 100,
+            }),
+            threads: NonZeroUsize::new(4),
+            ..Default::default()
+        };
+        options
+            .enrichment
+            .load(&PathBuf::from("./tests/inputs/enrichment_11_client.csv"))
+            .unwrap();
+
+        let mut buf = Vec::new();
+        write_config(&options, &mut buf).unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_config.toml").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_certify() {
+        let dir = TestDir::new("certify");
+        let certificate_file = dir.join("certificate.json");
+        let certify = |seed_file: PathBuf, threads| {
+            let options = ProcessingOptions {
+                seed_file: Some(seed_file),
+                certificate_file: Some(certificate_file.clone()),
+                threads,
+                ..Default::default()
+            };
+            let result = apply_transaction(
+                File::open("./tests/inputs/input_26_resume.csv").unwrap(),
+                &options,
+                std::io::sink(),
+            );
+            let certificate = std::fs::read_to_string(&certificate_file).unwrap();
+            (result, certificate, config::config_digest(&options))
+        };
+
+        // held funds of resumed accounts have no dispute history, and are not pending disputes
+        let seed_file = PathBuf::from("./tests/outputs/expected_output_20_snapshot.csv");
+        let (result, certificate, config_digest) = certify(seed_file.clone(), None);
+        result.unwrap();
+        assert!(certificate.contains("  \"passed\": true,\n"));
+        assert!(certificate.contains(&format!(
+            "  \"config_sha256\": \"{}\",\n",
+            sha256::to_hex(&config_digest)
+        )));
+        let (body, signature) = certificate.split_at(certificate.find("  \"sha256\"").unwrap());
+        let mut hasher = sha256::Sha256::default();
+        hasher.update(body.as_bytes());
+        assert_eq!(
+            signature,
+            format!(
+                "  \"sha256\": \"{}\"\n}}\n",
+                sha256::to_hex(&hasher.finalize())
+            )
+        );
+        // the certified state does not depend on the number of threads
+        let state_digest = |certificate: &str| {
+            certificate
+                .lines()
+                .find(|line| line.contains("state_sha256"))
+                .unwrap()
+                .to_owned()
+        };
+        assert_eq!(
+            state_digest(&certify(seed_file, NonZeroUsize::new(3)).1),
+            state_digest(&certificate)
+        );
+
+        // the certificate lists the failures, and the run fails
+        let seed_file = dir.join("seed.csv");
+        std::fs::write(
+            &seed_file,
+            "client,available,held,total,locked\n1,1.0,0.0,5.0,false\n",
+        )
+        .unwrap();
+        let (result, certificate, _) = certify(seed_file, None);
+        assert!(matches!(result, Err(Error::CertificationFailed(1, _))));
+        assert!(certificate.contains(
+            "{\"name\": \"account_identity\", \"passed\": false, \"failures\": 1, \"first_failures\": [\"client 1: available 2.0000 + held 0.0000 = 2.0000, but total is 6.0000\"]}"
+        ));
+        assert!(certificate.contains("  \"passed\": false,\n"));
+    }
+
+    #[test]
+    fn test_policy_diff() {
+        let dir = TestDir::new("policy_diff");
+        let certify = |name: &str, policy| {
+            let certificate_file = dir.join(name);
+            let options = ProcessingOptions {
+                certificate_file: Some(certificate_file.clone()),
+                policy,
+                ..Default::default()
+            };
+            apply_transaction(
+                File::open("./tests/inputs/input_38_undisputed_chargeback.csv").unwrap(),
+                &options,
+                std::io::sink(),
+            )
+            .unwrap();
+            certificate_file
+        };
+        let before = certify("before.json", EnginePolicy::default());
+        let after = certify(
+            "after.json",
+            EnginePolicy {
+                history_limit: Some(1),
+                charge_back_undisputed: true,
+                ..Default::default()
+            },
+        );
+
+        let mut buf = Vec::new();
+        diff_policies(&before, &after, &mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            std::fs::read_to_string("./tests/outputs/expected_output_42_policy_diff.txt").unwrap()
+        );
+        let mut buf = Vec::new();
+        diff_policies(&before, &before, &mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "Policy settings\nidentical\n\nOutput differences\nnone\n"
+        );
+
+        // edited certificates are refused
+        let edited = std::fs::read_to_string(&after)
+            .unwrap()
+            .replace("\"locked\": 1", "\"locked\": 0");
+        std::fs::write(&after, edited).unwrap();
+        assert!(matches!(
+            diff_policies(&before, &after, std::io::sink()),
+            Err(Error::InvalidRunReport(..))
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_clients() {
+        let input_file = PathBuf::from("./tests/inputs/input_01_basic.csv");
+        let with_duplicates = |duplicate_clients| ProcessingOptions {
+            seed_file: Some(PathBuf::from(
+                "./tests/inputs/seed_45_duplicate_clients.csv",
+            )),
+            duplicate_clients,
+            ..Default::default()
+        };
+        let result = apply_transaction(
+            File::open(&input_file).unwrap(),
+            &with_duplicates(DuplicateClients::Error),
+            std::io::sink(),
+        );
+        assert!(
+            matches!(&result, Err(Error::DuplicateClient(1, rows)) if rows == "row 1 and row 3"),
+            "{result:?}"
+        );
+        for (duplicate_clients, output_file) in [
+            (DuplicateClients::Merge, "merge"),
+            (DuplicateClients::PreferLatest, "prefer_latest"),
+        ] {
+            check_result_with(
+                input_file.clone(),
+                &with_duplicates(duplicate_clients),
+                PathBuf::from(format!(
+                    "./tests/outputs/expected_output_45_duplicate_clients_{output_file}.csv"
+                )),
+            );
+        }
+        assert!(matches!(
+            "first".parse::<DuplicateClients>(),
+            Err(Error::InvalidDuplicateClients(_))
+        ));
+
+        // merged entries join their histories, but never two transactions with the same ID
+        let policy = EnginePolicy::default();
+        let entry = |txs: &[u32]| {
+            let mut client = Client::new(1);
+            for tx in txs {
+                let deposit = ClientTransaction::new(Type::Deposit, 1, *tx, Some(decimal("1.0")));
+                client.apply_transaction(&deposit, &policy).unwrap();
+            }
+            client
+        };
+        let mut merger = AccountMerger::new(DuplicateClients::Merge);
+        merger.add(entry(&[1, 2]), "shard 0").unwrap();
+        merger.add(entry(&[3]), "shard 1").unwrap();
+        let result = merger.add(entry(&[2]), "shard 2");
+        assert!(
+            matches!(&result, Err(Error::DuplicateClient(1, detail)) if detail.contains("transaction 2")),
+            "{result:?}"
+        );
+        let (clients, report) = merger.finish();
+        assert_eq!(clients[&1].total, Balance::new(decimal("3.0")));
+        assert_eq!(clients[&1].version, 3);
+        assert_eq!(clients[&1].history_order, [1, 2, 3]);
+        assert_eq!(report.to_string(), "1 client(s) (1) merged");
+    }
+
+    #[test]
+    fn test_route_disputes_by_tx() {
+        let input_file = PathBuf::from("./tests/inputs/input_46_routed_disputes.csv");
+        let dir = TestDir::new("routed_disputes");
+        let rejects_file = dir.join("rejects.csv");
+        let options = ProcessingOptions {
+            route_disputes_by_tx: true,
+            rejects_file: Some(rejects_file.clone()),
+            ..Default::default()
+        };
+        // disputes are applied to the owner of their transaction, unless its ID is used by several clients
+        check_result_with(
+            input_file.clone(),
+            &options,
+            PathBuf::from("./tests/outputs/expected_output_46_routed_disputes.csv"),
+        );
+        assert_eq!(
+            std::fs::read_to_string(&rejects_file).unwrap(),
+            std::fs::read_to_string(
+                "./tests/outputs/expected_output_46_routed_disputes_rejects.csv"
+            )
+            .unwrap()
+        );
+
+        // without routing, disputes filed by another client refer to unknown transactions
+        let mut output = Vec::new();
+        apply_transaction(
+            File::open(&input_file).unwrap(),
+            &ProcessingOptions::default(),
+            &mut output,
+        )
+        .unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("2,5.0000,0.0000,5.0000,false"));
+
+        let result = apply_transaction(
+            File::open(&input_file).unwrap(),
+            &ProcessingOptions {
+                route_disputes_by_tx: true,
+                threads: NonZeroUsize::new(2),
+                ..Default::default()
+            },
+            std::io::sink(),
+        );
+        assert!(
+            matches!(result, Err(Error::RoutingNotSupported(_))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_resume_from_output() {
+        check_result_with(
+            PathBuf::from("./tests/inputs/input_26_resume.csv"),
+            &ProcessingOptions {
+                seed_file: Some(PathBuf::from(
+                    "./tests/outputs/expected_output_20_snapshot.csv",
+                )),
+                ..Default::default()
+            },
+            PathBuf::from("./tests/outputs/expected_output_26_resume.csv"),
+        );
+    }
+
+    #[test]
+    fn test_memory_accounting() {
+        let accounting = Accounting::new();
+        accounting.allocated(Subsystem::ParseBuffers, 4096);
+        accounting.allocated(Subsystem::Histories, 2048);
+        accounting.freed(Subsystem::ParseBuffers, 4096);
+        accounting.allocated(Subsystem::Histories, 3000);
+        accounting.allocated(Subsystem::ClientMap, 100);
+        accounting.freed(Subsystem::Histories, 2048);
+
+        // peaks are kept once the memory is freed, subsystems peaking at different times
+        assert_eq!(
+            accounting.report().to_string(),
+            "Memory: peak 6 KiB, 4 KiB still allocated\n  \
+             client map: peak 1 KiB, 1 KiB still allocated\n  \
+             histories: peak 5 KiB, 3 KiB still allocated\n  \
+             parse buffers: peak 4 KiB, 0 KiB still allocated\n  \
+             other: peak 0 KiB, 0 KiB still allocated"
+        );
+
+        // tracking leaves what it runs unchanged
+        assert_eq!(memory::track(Subsystem::ClientMap, || 1 + 1), 2);
+    }
 }
@@ -0,0 +1,335 @@
+use std::str::FromStr;
+
+use crate::{
+    business_logic::domain::Client,
+    shared::{
+        decimal::{Decimal, DECIMALS},
+        errors::Error,
+    },
+};
+
+/*
+ * Filter expression grammar, `||` binding looser than `&&`:
+ * expression := and ( "||" and )*
+ * and        := unary ( "&&" unary )*
+ * unary      := "!" unary | "(" expression ")" | field ( operator value )?
+ * field      := "client" | "available" | "held" | "total" | "locked" | "risk_score"
+ * operator   := ">" | ">=" | "<" | "<=" | "==" | "!="
+ * value      := number | "true" | "false"
+ *
+ * A field alone is a boolean condition, allowed only for `locked`. Client IDs and balances are compared exactly, as
+ * fixed-point numbers, so their values have at most 4 decimal places; the risk score is a floating point number.
+ */
+
+/// Predicate over accounts parsed from a filter expression, e.g. `held > 0 && !locked`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    /// Expression as written by the user
+    source: String,
+    expression: Expression,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Client,
+    Available,
+    Held,
+    Total,
+    Locked,
+    RiskScore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    /// Client ID or balance
+    Number(Decimal),
+    /// Risk score
+    Score(f64),
+    Boolean(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Or(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Compare(Field, Operator, Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    /// Number as written, read according to the field it is compared to
+    Number(String),
+    Operator(Operator),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl Filter {
+    /// Check whether the account satisfies the filter
+    pub(crate) fn matches(&self, client: &Client) -> bool {
+        self.expression.evaluate(client)
+    }
+
+    /// Expression the filter was parsed from
+    pub(crate) fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| Error::InvalidFilter(s.to_owned(), reason);
+
+        let tokens = tokenize(s).map_err(invalid)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let expression = parser.expression().map_err(invalid)?;
+        match parser.tokens.get(parser.next) {
+            None => Ok(Self {
+                source: s.to_owned(),
+                expression,
+            }),
+            Some(token) => Err(invalid(format!("unexpected {token:?}"))),
+        }
+    }
+}
+
+impl Field {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "client" => Ok(Self::Client),
+            "available" => Ok(Self::Available),
+            "held" => Ok(Self::Held),
+            "total" => Ok(Self::Total),
+            "locked" => Ok(Self::Locked),
+            "risk_score" => Ok(Self::RiskScore),
+            _ => Err(format!("unknown field `{name}`")),
+        }
+    }
+
+    fn value(&self, client: &Client) -> Value {
+        match self {
+            // any client ID fits
+            Field::Client => {
+                Value::Number(Decimal::from_minor_units(client.id.into(), 0).unwrap_or_default())
+            }
+            Field::Available => Value::Number(client.available.value()),
+            Field::Held => Value::Number(client.held.value()),
+            Field::Total => Value::Number(client.total.value()),
+            Field::Locked => Value::Boolean(client.locked),
+            Field::RiskScore => Value::Score(client.risk_score),
+        }
+    }
+}
+
+impl Operator {
+    fn compare<T: PartialOrd>(&self, actual: T, value: T) -> bool {
+        match self {
+            Operator::Greater => actual > value,
+            Operator::GreaterOrEqual => actual >= value,
+            Operator::Less => actual < value,
+            Operator::LessOrEqual => actual <= value,
+            Operator::Equal => actual == value,
+            Operator::NotEqual => actual != value,
+        }
+    }
+}
+
+impl Expression {
+    fn evaluate(&self, client: &Client) -> bool {
+        match self {
+            Expression::Or(left, right) => left.evaluate(client) || right.evaluate(client),
+            Expression::And(left, right) => left.evaluate(client) && right.evaluate(client),
+            Expression::Not(expression) => !expression.evaluate(client),
+            Expression::Compare(field, operator, value) => {
+                match (field.value(client), value) {
+                    (Value::Number(actual), Value::Number(value)) => {
+                        operator.compare(actual, *value)
+                    }
+                    (Value::Score(actual), Value::Score(value)) => operator.compare(actual, *value),
+                    (Value::Boolean(actual), Value::Boolean(value)) => match operator {
+                        Operator::Equal => actual == *value,
+                        _ => actual != *value,
+                    },
+                    // mismatching types are rejected while parsing
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (token, length) = match (c, next) {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('>', Some('=')) => (Token::Operator(Operator::GreaterOrEqual), 2),
+            ('<', Some('=')) => (Token::Operator(Operator::LessOrEqual), 2),
+            ('=', Some('=')) => (Token::Operator(Operator::Equal), 2),
+            ('!', Some('=')) => (Token::Operator(Operator::NotEqual), 2),
+            ('>', _) => (Token::Operator(Operator::Greater), 1),
+            ('<', _) => (Token::Operator(Operator::Less), 1),
+            ('!', _) => (Token::Not, 1),
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let length = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    .count();
+                (
+                    Token::Identifier(chars[i..i + length].iter().collect()),
+                    length,
+                )
+            }
+            _ if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let length = 1 + chars[i + 1..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit() || **c == '.')
+                    .count();
+                (Token::Number(chars[i..i + length].iter().collect()), length)
+            }
+            _ => return Err(format!("unexpected character `{c}`")),
+        };
+        tokens.push(token);
+        i += length;
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the tokens of a filter expression
+struct Parser {
+    tokens: Vec<Token>,
+    /// Index of the next token to consume
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn consume(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn expression(&mut self) -> Result<Expression, String> {
+        let mut expression = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next += 1;
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<Expression, String> {
+        let mut expression = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next += 1;
+            expression = Expression::And(Box::new(expression), Box::new(self.unary()?));
+        }
+        Ok(expression)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        match self.consume() {
+            Some(Token::Not) => Ok(Expression::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expression = self.expression()?;
+                match self.consume() {
+                    Some(Token::Close) => Ok(expression),
+                    _ => Err("missing `)`".to_owned()),
+                }
+            }
+            Some(Token::Identifier(name)) => self.comparison(Field::parse(&name)?),
+            Some(token) => Err(format!("unexpected {token:?}")),
+            None => Err("unexpected end of expression".to_owned()),
+        }
+    }
+
+    fn comparison(&mut self, field: Field) -> Result<Expression, String> {
+        let operator = match self.peek() {
+            Some(Token::Operator(operator)) => *operator,
+            // a field alone is a boolean condition
+            _ if field == Field::Locked => {
+                return Ok(Expression::Compare(
+                    field,
+                    Operator::Equal,
+                    Value::Boolean(true),
+                ))
+            }
+            _ => return Err(format!("missing comparison for {field:?}")),
+        };
+        self.next += 1;
+
+        let value = match self.consume() {
+            Some(Token::Number(number)) if field == Field::RiskScore => Value::Score(
+                number
+                    .parse()
+                    .map_err(|_| format!("invalid number `{number}`"))?,
+            ),
+            Some(Token::Number(number)) => Value::Number(parse_number(&number)?),
+            Some(Token::Identifier(name)) if name == "true" => Value::Boolean(true),
+            Some(Token::Identifier(name)) if name == "false" => Value::Boolean(false),
+            _ => return Err(format!("missing value for {field:?}")),
+        };
+        match (field, value, operator) {
+            (Field::Locked, Value::Boolean(_), Operator::Equal | Operator::NotEqual) => {}
+            (Field::Locked, _, _) => {
+                return Err("locked can only be compared to true or false with == or !=".to_owned())
+            }
+            (_, Value::Boolean(_), _) => {
+                return Err(format!("{field:?} must be compared to a number"))
+            }
+            _ => {}
+        }
+
+        Ok(Expression::Compare(field, operator, value))
+    }
+}
+
+/// Client ID or balance compared to a field, refused if it has more decimal places than balances, which would make
+/// the comparison inexact
+fn parse_number(number: &str) -> Result<Decimal, String> {
+    let decimals = number
+        .split_once('.')
+        .map_or(0, |(_, fraction)| fraction.trim_end_matches('0').len());
+    if decimals > DECIMALS as usize {
+        return Err(format!(
+            "invalid number `{number}`, more than {DECIMALS} decimal places"
+        ));
+    }
+    number
+        .parse()
+        .map_err(|_| format!("invalid number `{number}`"))
+}
@@ -0,0 +1,232 @@
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use crate::{
+    business_logic::domain::{
+        amount::{Amount, Balance},
+        Client, ClientTransaction, DisputeDetails, DisputeEffect, Transaction, Type,
+    },
+    shared::{decimal::Decimal, errors::Error},
+};
+
+pub mod filter;
+pub mod risk;
+
+/// Name of the enrichment attribute identifying the channel of a transaction (e.g. card-present, online)
+pub(crate) const CHANNEL_ATTRIBUTE: &str = "channel";
+
+/// Business rules applied to every transaction before it reaches the client account
+#[derive(Debug, Default)]
+pub struct EnginePolicy {
+    /// Transactions carrying any of these (attribute, value) pairs are ignored
+    pub rejected_attributes: Vec<(String, String)>,
+    /// Channels allowed for each transaction type. Types without an entry are allowed on every channel
+    pub channel_rules: HashMap<Type, Vec<String>>,
+    /// Clients whose available funds may go negative because of an adjustment
+    pub negative_adjustments: NegativeAdjustments,
+    /// Whether adjustments are applied also to locked accounts
+    pub adjust_locked_accounts: bool,
+    /// Maximum number of deposits and withdrawals kept in the history of each client, the oldest being pruned first.
+    /// Transactions under dispute are always kept. Unlimited if not set
+    pub history_limit: Option<usize>,
+    /// Dispute reason codes (e.g. friendly fraud) whose disputes are resolved as soon as they are opened
+    pub auto_resolve_reasons: Vec<String>,
+    /// Whether a chargeback of a transaction not under dispute opens the dispute and charges it back in one step,
+    /// instead of being ignored
+    pub charge_back_undisputed: bool,
+    /// Whether unlock transactions are accepted, lifting the lock of an account charged back. Ignored otherwise
+    pub allow_unlock: bool,
+    /// Whether accepted unlocks are recorded as operator notes of the account, shown in statements and queries
+    pub audit_unlocks: bool,
+    /// How the funds of a withdrawal move while it is under dispute
+    pub withdrawal_dispute_mode: WithdrawalDisputeMode,
+    /// Amount by which withdrawals and transfers may drive available funds below zero. No overdraft if not set
+    pub overdraft_limit: Option<Decimal>,
+    /// What happens to a dispute holding more than the available funds, overdraft included
+    pub dispute_shortfall: DisputeShortfall,
+}
+
+/// Treatment of disputes holding more funds than available, overdraft included, parsed from `allow` or `reject`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DisputeShortfall {
+    /// Funds are held anyway, driving available funds negative (e.g. deposit already withdrawn)
+    #[default]
+    Allow,
+    /// The dispute is ignored
+    Reject,
+}
+
+impl FromStr for DisputeShortfall {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "allow" => Ok(Self::Allow),
+            "reject" => Ok(Self::Reject),
+            _ => Err(Error::InvalidDisputeShortfall(s.to_owned())),
+        }
+    }
+}
+
+/// Treatment of disputes of withdrawals, whose funds already left the account, parsed from `debit`, `credit` or
+/// `reject`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum WithdrawalDisputeMode {
+    /// Held as for deposits: the amount is moved from available to held funds, and removed again if charged back
+    #[default]
+    Debit,
+    /// Credited back as held funds: total and held funds grow by the amount, released to available funds if
+    /// charged back and removed again if resolved
+    Credit,
+    /// Disputes of withdrawals are ignored
+    Reject,
+}
+
+impl FromStr for WithdrawalDisputeMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "debit" => Ok(Self::Debit),
+            "credit" => Ok(Self::Credit),
+            "reject" => Ok(Self::Reject),
+            _ => Err(Error::InvalidWithdrawalDisputeMode(s.to_owned())),
+        }
+    }
+}
+
+/// Clients allowed to go negative because of an adjustment, parsed from `none`, `all` or `client[,client...]`
+#[derive(Debug, Default)]
+pub enum NegativeAdjustments {
+    #[default]
+    None,
+    All,
+    Clients(HashSet<u16>),
+}
+
+impl NegativeAdjustments {
+    pub(crate) fn allows(&self, client: u16) -> bool {
+        match self {
+            NegativeAdjustments::None => false,
+            NegativeAdjustments::All => true,
+            NegativeAdjustments::Clients(clients) => clients.contains(&client),
+        }
+    }
+}
+
+impl FromStr for NegativeAdjustments {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "none" => Ok(Self::None),
+            "all" => Ok(Self::All),
+            clients => clients
+                .split(',')
+                .map(|client| client.trim().parse())
+                .collect::<Result<_, _>>()
+                .map(Self::Clients)
+                .map_err(|_| Error::InvalidNegativeAdjustments(s.to_owned())),
+        }
+    }
+}
+
+/// Channels allowed for a transaction type, parsed from `type=channel[,channel...]`
+#[derive(Debug)]
+pub struct ChannelRule {
+    pub transaction_type: Type,
+    pub channels: Vec<String>,
+}
+
+impl FromStr for ChannelRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (transaction_type, channels) = s
+            .split_once('=')
+            .ok_or_else(|| Error::InvalidChannelRule(s.to_owned()))?;
+
+        Ok(Self {
+            transaction_type: transaction_type.trim().parse()?,
+            channels: channels
+                .split(',')
+                .map(|channel| channel.trim().to_owned())
+                .filter(|channel| !channel.is_empty())
+                .collect(),
+        })
+    }
+}
+
+impl EnginePolicy {
+    /// Check whether the transaction is allowed by the policy rules, given the current state of the client account
+    pub(crate) fn allows(&self, transaction: &ClientTransaction, client: &Client) -> bool {
+        !self.rejected_attributes.iter().any(|(name, value)| {
+            transaction
+                .attributes
+                .get(name)
+                .is_some_and(|attribute| attribute == value)
+        }) && (transaction.transaction_type != Type::Unlock || self.allow_unlock)
+            && self.allows_channel(transaction, client)
+    }
+
+    /// Whether a dispute opened with the given details is resolved right away
+    pub(crate) fn auto_resolves(&self, details: &DisputeDetails) -> bool {
+        details
+            .reason
+            .as_ref()
+            .is_some_and(|reason| self.auto_resolve_reasons.contains(reason))
+    }
+
+    /// How the funds of a transaction move once disputed, `None` if its disputes are ignored
+    pub(crate) fn dispute_effect(&self, transaction: &Transaction) -> Option<DisputeEffect> {
+        match (transaction.withdrawal, self.withdrawal_dispute_mode) {
+            (false, _) | (true, WithdrawalDisputeMode::Debit) => Some(DisputeEffect::Debit),
+            (true, WithdrawalDisputeMode::Credit) => Some(DisputeEffect::Credit),
+            (true, WithdrawalDisputeMode::Reject) => None,
+        }
+    }
+
+    /// Whether `available` funds, extended by the overdraft limit, are enough to take `amount` from them
+    pub(crate) fn covers(&self, available: Balance, amount: Amount) -> bool {
+        match self.overdraft_limit {
+            Some(limit) => available
+                .value()
+                .checked_add(limit)
+                .is_none_or(|funds| funds >= amount.value()),
+            None => available.covers(amount),
+        }
+    }
+
+    /// Whether a dispute opened with `effect` may hold `amount` out of `available` funds
+    pub(crate) fn allows_dispute(
+        &self,
+        available: Balance,
+        amount: Amount,
+        effect: DisputeEffect,
+    ) -> bool {
+        // disputes crediting the funds back never take them from the available ones
+        effect == DisputeEffect::Credit
+            || self.dispute_shortfall == DisputeShortfall::Allow
+            || self.covers(available, amount)
+    }
+
+    /// Dispute, resolve and chargeback are checked against the channel of the transaction they refer to,
+    /// any other type against its own channel. Transactions with unknown channel are rejected by channel rules.
+    fn allows_channel(&self, transaction: &ClientTransaction, client: &Client) -> bool {
+        let Some(channels) = self.channel_rules.get(&transaction.transaction_type) else {
+            return true;
+        };
+
+        let channel = match transaction.transaction_type {
+            Type::Dispute | Type::Resolve | Type::ChargeBack => client
+                .transations_history
+                .get(&transaction.tx)
+                .and_then(|tx| tx.channel.as_ref()),
+            _ => transaction.attributes.get(CHANNEL_ATTRIBUTE),
+        };
+
+        channel.is_some_and(|channel| channels.contains(channel))
+    }
+}
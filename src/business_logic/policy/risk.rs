@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+use crate::business_logic::domain::Client;
+
+/// Weights of the risk factors summed up into the client risk score
+#[derive(Debug, Clone, Copy)]
+pub struct RiskWeights {
+    /// Weight of each chargeback on the client transactions
+    pub chargeback: f64,
+    /// Weight of each dispute still open (neither resolved nor charged back)
+    pub open_dispute: f64,
+}
+
+impl Default for RiskWeights {
+    fn default() -> Self {
+        Self {
+            chargeback: 1.0,
+            open_dispute: 0.5,
+        }
+    }
+}
+
+/// Scoring pass: compute the risk score of every client from its dispute history
+pub(crate) fn score_clients(clients: &mut HashMap<u16, Client>, weights: &RiskWeights) {
+    clients.values_mut().for_each(|client| {
+        let outcomes = &client.dispute_outcomes;
+        client.risk_score = weights.chargeback * outcomes.charged_back as f64
+            + weights.open_dispute * outcomes.pending() as f64;
+    });
+}
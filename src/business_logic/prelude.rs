@@ -0,0 +1,15 @@
+//! Types needed to embed the payments engine, importable at once with `use transactions_simulator::prelude::*`
+
+pub use crate::{
+    business_logic::{
+        domain::{Client, ClientTransaction, Rejection, Type},
+        engine::{
+            observer::TransactionObserver,
+            store::ClientStore,
+            tx_index::{DisputeState, TransactionRef},
+            Account, TransactionEngine,
+        },
+        policy::EnginePolicy,
+    },
+    shared::decimal::Decimal,
+};
@@ -0,0 +1,21 @@
+//! Toy payments engine: applies deposits, withdrawals, disputes, resolves and chargebacks, in order,
+//! to the accounts of their clients.
+//!
+//! Other programs can embed the engine through [TransactionEngine], without going through the CLI:
+//!
+//! ```
+//! use transactions_simulator::prelude::*;
+//!
+//! let amount = |value: &str| value.parse::<Decimal>().ok();
+//! let mut engine = TransactionEngine::default();
+//! engine.apply(ClientTransaction::new(Type::Deposit, 1, 1, amount("2.0"))).unwrap();
+//! engine.apply(ClientTransaction::new(Type::Withdrawal, 1, 2, amount("0.5"))).unwrap();
+//!
+//! let accounts = engine.finalize();
+//! assert_eq!(accounts[0].available.to_string(), "1.5000");
+//! ```
+
+pub mod business_logic;
+pub mod shared;
+
+pub use business_logic::prelude::{self, *};
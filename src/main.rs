@@ -1,21 +1,777 @@
-use std::path::PathBuf;
+use std::{
+    fs::File,
+    io::Read,
+    net::TcpListener,
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    time::Duration,
+};
 
-use structopt::StructOpt;
+use structopt::{
+    clap::{self, ErrorKind},
+    StructOpt,
+};
 
-use crate::{business_logic::apply_transaction, shared::errors::Error};
-
-mod business_logic;
-mod shared;
+use transactions_simulator::{
+    business_logic::{
+        engine::{
+            alerts::AlertThresholds, annotate_snapshot_clients, anomaly::AnomalyRate, apply_batch,
+            apply_transaction_paged, apply_transaction_partitioned, apply_transactions,
+            backfill_accounts, checkpoint::Checkpoint, demo::DemoDataset, diff_policies,
+            dump_snapshot, erase_snapshot_client, export_snapshot_proto, export_transaction,
+            import_snapshot_proto, merge::DuplicateClients, query_snapshot,
+            query_snapshot_transaction, read_annotations, recover_wal, report_transaction,
+            run_demo, serve_http, serve_transactions, serve_transactions_replaying,
+            spill::MemoryLimit, verify_audit_chain, verify_transaction, ProcessingOptions,
+            ReportKind,
+        },
+        io::{
+            config::write_config,
+            export::ExportFormat,
+            input::{
+                decompressing_reader, expand_input_pattern, AmountUnits, CurrencyExponent,
+                CurrencyExponents, InputFormat,
+            },
+            output::{OutputFormat, PageLimits, SortKey},
+            query::{AccountQuery, QueryFormat},
+        },
+        policy::{
+            filter::Filter, risk::RiskWeights, ChannelRule, DisputeShortfall, EnginePolicy,
+            NegativeAdjustments, WithdrawalDisputeMode,
+        },
+    },
+    shared::{decimal::Decimal, errors::Error, retry::RetryPolicy},
+};
 
 #[derive(Debug, StructOpt)]
 struct Args {
-    /// Input file
-    #[structopt(parse(from_os_str))]
-    input: PathBuf,
+    /// Input files, or `-` to read from stdin, required unless a subcommand, --manifest or --http is given.
+    /// Several inputs are applied in order into the same state, as a single stream. File names can contain
+    /// `*` and `?` wildcards (e.g. `'transactions-*.csv'`), expanded to the matching files sorted by name
+    #[structopt(name = "input", parse(from_os_str))]
+    inputs: Vec<PathBuf>,
+    /// Batch manifest (`file,rows,sha256` CSV file) listing the input files to process in order as one atomic batch,
+    /// refused as a whole if any file is missing or does not match its expected number of rows and checksum
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["input", "partition-output", "max-output-rows", "max-output-bytes"])]
+    manifest: Option<PathBuf>,
+    /// Serve an HTTP API on the given address (e.g. `127.0.0.1:8080`) instead of processing input files:
+    /// `POST /transactions` applies the CSV rows or JSON Lines of the body, `GET /accounts` and
+    /// `GET /accounts/{client}` return the current balances. Starts from --restore or --resume-from-output, if given
+    #[structopt(long, conflicts_with_all = &["input", "manifest", "partition-output", "max-output-rows", "max-output-bytes"])]
+    http: Option<String>,
+    /// Split the output into N files partitioned by client id range, plus a manifest, instead of writing to stdout
+    #[structopt(long)]
+    partition_output: Option<u16>,
+    /// Split the output into numbered files of at most N accounts each, plus a manifest, instead of writing to stdout.
+    /// Every file repeats the header
+    #[structopt(long, conflicts_with = "partition-output")]
+    max_output_rows: Option<NonZeroUsize>,
+    /// Split the output into numbered files of at most N bytes each, header included, plus a manifest, instead of
+    /// writing to stdout. Can be combined with --max-output-rows
+    #[structopt(long, conflicts_with = "partition-output")]
+    max_output_bytes: Option<NonZeroU64>,
+    /// Apply transactions with N worker threads, each owning the clients whose ID modulo N is its index, while the
+    /// main thread reads the input. Outputs are the same whatever the number of threads
+    #[structopt(long)]
+    threads: Option<NonZeroUsize>,
+    /// Parse the next N input records on a reader thread while the previous ones are applied, without --threads
+    #[structopt(long, conflicts_with = "threads")]
+    read_ahead: Option<NonZeroUsize>,
+    /// What to do with a client found more than once when combining account sets (the shards of --threads, the
+    /// rows of --resume-from-output): `error` fails, `merge` sums the entries into one account, `prefer-latest`
+    /// keeps the last one. Duplicates resolved are reported on stderr
+    #[structopt(long, default_value = "error")]
+    duplicate_clients: DuplicateClients,
+    /// Directory where the worker threads log the transactions they apply, so that an interrupted run can be
+    /// rebuilt with the `recover` subcommand and go on with --resume
+    #[structopt(long, parse(from_os_str), requires = "threads")]
+    wal: Option<PathBuf>,
+    /// Memory the transactions history may take (e.g. `512M`), beyond which the oldest transactions not under
+    /// dispute are spilled to a temporary file, and reloaded when a dispute refers to them
+    #[structopt(long, conflicts_with_all = &["threads", "checkpoint", "history-limit"])]
+    max_memory: Option<MemoryLimit>,
+    /// Apply runs of up to N consecutive deposits of the same client with a single update of its balances,
+    /// for deposit-dominated inputs. Outputs are the same as without it
+    #[structopt(long, conflicts_with_all = &["threads", "max-memory"])]
+    aggregate_deposits: Option<NonZeroUsize>,
+    /// Apply disputes, resolves and chargebacks filed by a client other than the owner of the transaction they
+    /// refer to, to the owner, listing them with the `client_mismatch` reason in the --rejects file
+    #[structopt(long, conflicts_with_all = &["threads", "max-memory"])]
+    route_disputes_by_tx: bool,
+    /// Output format: `v1` (guaranteed byte-identical to the first release, except for row ordering)
+    /// or `extended` (v1 columns followed by `risk_score`)
+    #[structopt(long, default_value = "v1")]
+    output_format: OutputFormat,
+    /// Weight of each chargeback in the risk score of a client
+    #[structopt(long, default_value = "1.0")]
+    risk_weight_chargeback: f64,
+    /// Weight of each open dispute in the risk score of a client
+    #[structopt(long, default_value = "0.5")]
+    risk_weight_open_dispute: f64,
+    /// Input format: `csv`, `json` (JSON Lines, one object per line with the CSV columns as members),
+    /// `iso8583` (simplified ISO 8583 message dump, amounts in minor units converted by the currency
+    /// exponent table using field 49) or `xlsx` (first sheet of an Excel workbook, with the CSV columns as header row)
+    #[structopt(long, alias = "format", default_value = "csv", possible_values = &["csv", "json", "iso8583", "xlsx"])]
+    input_format: String,
+    /// Read amounts as integers in minor units (e.g. cents) instead of decimals. The number of decimal digits
+    /// of each amount is taken from the currency exponent table, using the optional `currency` input column
+    #[structopt(long)]
+    minor_units: bool,
+    /// Currency exponent used by --minor-units and ISO 8583 input for rows without currency or with an unlisted currency
+    #[structopt(long, default_value = "2")]
+    minor_unit_exponent: u32,
+    /// Exponent of a currency used by --minor-units and ISO 8583 input, in the form `CURRENCY=exponent` (e.g. `JPY=0`). Can be repeated.
+    #[structopt(long, number_of_values = 1)]
+    currency_exponent: Vec<CurrencyExponent>,
+    /// Column the accounts are sorted by, then by client id: `client`, `available`, `held`, `total` or `locked`.
+    /// Output is byte-identical across runs and platforms whatever the column
+    #[structopt(long, default_value = "client")]
+    sort_by: SortKey,
+    /// Deprecated: accounts are always written sorted, see --sort-by
+    #[structopt(long, hidden = true)]
+    deterministic: bool,
+    /// Save the final state of the accounts, transactions history included, as binary snapshot in the given file
+    #[structopt(long, parse(from_os_str))]
+    snapshot: Option<PathBuf>,
+    /// Move closed accounts (zero balances, no pending dispute) from the snapshot into the given archive snapshot,
+    /// merged with the accounts already archived there
+    #[structopt(long, parse(from_os_str), requires = "snapshot")]
+    archive: Option<PathBuf>,
+    /// Refuse to process the input if its SHA-256 is already in the ledger of the existing --snapshot file,
+    /// preventing accidental double ingestion
+    #[structopt(long, requires = "snapshot")]
+    refuse_applied_inputs: bool,
+    /// Maximum number of attempts of snapshot and archive file operations failing with a transient I/O error
+    /// (e.g. a busy network file system), the first one included
+    #[structopt(long, default_value = "3")]
+    storage_attempts: u32,
+    /// Delay in milliseconds before retrying a failed storage operation, doubled on every retry up to
+    /// --storage-max-backoff-ms
+    #[structopt(long, default_value = "100")]
+    storage_backoff_ms: u64,
+    /// Maximum delay in milliseconds between two attempts of a storage operation
+    #[structopt(long, default_value = "2000")]
+    storage_max_backoff_ms: u64,
+    /// Write only the accounts satisfying the given filter expression, e.g. `held > 0 && !locked`
+    #[structopt(long)]
+    filter: Option<Filter>,
+    /// Abort with the line number of the first malformed input record, instead of skipping malformed records
+    #[structopt(long)]
+    strict: bool,
+    /// Write every ignored input record (`seq,line,type,client,tx,reason,detail`) to the given CSV file,
+    /// with a machine-readable reason code such as `insufficient_funds` or `malformed_record`
+    #[structopt(long, parse(from_os_str))]
+    rejects: Option<PathBuf>,
+    /// Append to the given CSV file a row for every event of every account changed by a transaction (deposited,
+    /// funds held, resolved, charged back, account locked...) and for every rejected transaction, with the input
+    /// line, the reason of rejections and the balances after the event, chaining the rows of each account by
+    /// their SHA-256, so that later changes to the file can be detected by `verify-chain`. Also written by
+    /// `serve` and --http
+    #[structopt(long, parse(from_os_str))]
+    audit: Option<PathBuf>,
+    /// Check the invariants of the final accounts (balance identities, reconciliation with the funds moved by
+    /// transactions, dispute consistency) and write a certificate of the run to the given JSON file, with the
+    /// SHA-256 of the configuration and of the final state. Fails if any check fails
+    #[structopt(long, parse(from_os_str), conflicts_with = "http")]
+    certify: Option<PathBuf>,
+    /// Append an alert (`seq,client,tx,alert,value`) to the given CSV file whenever a client crosses one of the
+    /// alert thresholds, as soon as the triggering transaction is applied
+    #[structopt(long, parse(from_os_str))]
+    alerts: Option<PathBuf>,
+    /// Alert when the total funds of a client rise above the given amount
+    #[structopt(long, requires = "alerts")]
+    alert_balance_above: Option<Decimal>,
+    /// Alert when the chargebacks of a client exceed the given number
+    #[structopt(long, requires = "alerts")]
+    alert_chargebacks_above: Option<u32>,
+    /// Flag as anomaly every window of records where the total funds of all clients change by more than
+    /// the given amount, e.g. because of corrupted data or a duplicated batch
+    #[structopt(long)]
+    anomaly_max_change: Option<Decimal>,
+    /// Number of consecutive input records of the anomaly detection window, used with --anomaly-max-change
+    #[structopt(long, default_value = "100")]
+    anomaly_window: u64,
+    /// Start from the accounts of a previous output file (v1 format) instead of empty accounts.
+    /// Transactions history is not restored: disputes on transactions processed before are rejected
+    #[structopt(long, parse(from_os_str))]
+    resume_from_output: Option<PathBuf>,
+    /// Start from the full state of a snapshot file, as written by --snapshot, transactions history included,
+    /// so that processing interrupted after a snapshot can go on with the rest of the input
+    #[structopt(long, parse(from_os_str), conflicts_with = "resume-from-output")]
+    restore: Option<PathBuf>,
+    /// Save the state of the accounts, transactions history included, to the given checkpoint file every
+    /// --checkpoint-every input records, so that a long run interrupted can go on with --resume
+    #[structopt(long, parse(from_os_str), conflicts_with = "threads")]
+    checkpoint: Option<PathBuf>,
+    /// Number of input records between two checkpoints
+    #[structopt(long, default_value = "100000")]
+    checkpoint_every: NonZeroU64,
+    /// Go on with a run interrupted after writing the given --checkpoint file: start from its state and skip the
+    /// input records it applied. The same inputs must be given, in the same order
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["restore", "resume-from-output"])]
+    resume: Option<PathBuf>,
+    /// Directory where partitioned or paged output files are written
+    #[structopt(long, parse(from_os_str), default_value = ".")]
+    output_dir: PathBuf,
+    /// Lookup CSV file whose attributes are merged into each transaction before it is applied.
+    /// The first column (`tx` or `client`) is the key, every other column is an attribute. Can be repeated.
+    #[structopt(long, parse(from_os_str), number_of_values = 1)]
+    enrich: Vec<PathBuf>,
+    /// Ignore transactions having the given attribute value, in the form `name=value`. Can be repeated.
+    #[structopt(long, parse(try_from_str = parse_attribute), number_of_values = 1)]
+    reject_attribute: Vec<(String, String)>,
+    /// Allow a transaction type only on the given channels, in the form `type=channel[,channel...]`.
+    /// Dispute, resolve and chargeback are checked against the channel of the disputed transaction. Can be repeated.
+    #[structopt(long, number_of_values = 1)]
+    channel_rule: Vec<ChannelRule>,
+    /// Clients whose available funds may go negative because of an adjustment: `none`, `all` or `client[,client...]`
+    #[structopt(long, default_value = "none")]
+    negative_adjustments: NegativeAdjustments,
+    /// Apply adjustments also to locked accounts
+    #[structopt(long)]
+    adjust_locked_accounts: bool,
+    /// Keep at most N deposits and withdrawals in the history of each client, pruning the oldest ones.
+    /// Transactions under dispute are always kept; pruned transactions can no longer be disputed
+    #[structopt(long)]
+    history_limit: Option<usize>,
+    /// Dispute reason code (e.g. `friendly-fraud`) whose disputes are resolved as soon as they are opened,
+    /// without holding funds. Can be repeated.
+    #[structopt(long, number_of_values = 1)]
+    auto_resolve_reason: Vec<String>,
+    /// Apply chargebacks of transactions not under dispute, as found in some acquirer feeds, by opening
+    /// the dispute and charging it back in one step, instead of ignoring them
+    #[structopt(long)]
+    charge_back_undisputed: bool,
+    /// Accept `unlock` transactions, lifting the lock of an account charged back. They are ignored otherwise
+    #[structopt(long)]
+    allow_unlock: bool,
+    /// Record every accepted unlock as an operator note of the account, shown in statements and queries
+    #[structopt(long, requires = "allow-unlock")]
+    audit_unlocks: bool,
+    /// How disputes of withdrawals move the funds: `debit` holds the amount out of the available funds, as for
+    /// deposits, `credit` credits the withdrawn amount back as held funds, `reject` ignores them
+    #[structopt(long, default_value = "debit")]
+    withdrawal_dispute_mode: WithdrawalDisputeMode,
+    /// Allow withdrawals and transfers to drive the available funds down to minus the given amount, instead of
+    /// stopping at zero
+    #[structopt(long, parse(try_from_str = parse_overdraft_limit))]
+    overdraft_limit: Option<Decimal>,
+    /// What happens to a dispute holding more than the available funds, overdraft included: `allow` holds them
+    /// anyway, driving the available funds negative, `reject` ignores the dispute
+    #[structopt(long, default_value = "allow")]
+    dispute_shortfall: DisputeShortfall,
+    /// Print the effective configuration, resolved from defaults and flags, as TOML followed by its SHA-256,
+    /// then exit without processing anything
+    #[structopt(long)]
+    print_config: bool,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Process the input file and compare the resulting accounts against an expected output.
+    /// Rows are matched by client id and monetary values are compared with the given tolerance.
+    /// Exits with an error, after printing the differences, on mismatch.
+    Verify {
+        /// Input file, or `-` to read from stdin
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Expected output CSV file
+        #[structopt(long, parse(from_os_str))]
+        expected: PathBuf,
+        /// Maximum absolute difference allowed between expected and actual monetary values
+        #[structopt(long, default_value = "0.0001")]
+        tolerance: Decimal,
+    },
+    /// Process the input file and print a human-readable report of the resulting accounts, aggregated by cohort
+    Report {
+        /// Input file, or `-` to read from stdin
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// CSV file (`client,cohort`) tagging clients into cohorts. Untagged clients are reported together
+        #[structopt(long, parse(from_os_str), conflicts_with = "dispute-outcomes")]
+        cohorts: Option<PathBuf>,
+        /// Report how disputes ended (resolved, charged back, pending) per client and overall, instead of cohorts
+        #[structopt(long)]
+        dispute_outcomes: bool,
+        /// Report the activity breakdown (transactions per client percentiles, most disputed clients, busiest
+        /// transaction ID ranges) instead of cohorts, to check how skewed a dataset is
+        #[structopt(long, conflicts_with_all = &["cohorts", "dispute-outcomes"])]
+        activity: bool,
+    },
+    /// Apply signed balance corrections to the accounts of a previous output file and print the adjusted accounts
+    Backfill {
+        /// Accounts file, in the v1 output format
+        #[structopt(parse(from_os_str))]
+        accounts: PathBuf,
+        /// Corrections CSV file (`client,amount,reason`). Every correction must have a reason code
+        #[structopt(long, parse(from_os_str))]
+        corrections: PathBuf,
+    },
+    /// Process the input file and print the statement of every client, listing the movements of its funds,
+    /// in an interchange format
+    Export {
+        /// Input file, or `-` to read from stdin
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Export format: `camt053` (ISO 20022 camt.053 XML, simplified profile) or `qif` (Quicken Interchange Format)
+        #[structopt(long, default_value = "camt053")]
+        format: ExportFormat,
+        /// Date of every QIF entry, as `MM/DD/YYYY`, since input transactions carry no timestamp
+        #[structopt(long, default_value = "01/01/1970")]
+        date: String,
+    },
+    /// Print the accounts of a snapshot matching all the given conditions, or the owners of a transaction,
+    /// without processing any input
+    Query {
+        /// Snapshot file, as written by --snapshot
+        #[structopt(long, parse(from_os_str))]
+        snapshot: PathBuf,
+        /// Archive snapshot file, as written by --archive, whose accounts are queried too
+        #[structopt(long, parse(from_os_str))]
+        archive: Option<PathBuf>,
+        /// Select only the given client
+        #[structopt(long)]
+        client: Option<u16>,
+        /// Select only locked accounts
+        #[structopt(long)]
+        locked: bool,
+        /// Select only accounts holding at least the given amount
+        #[structopt(long)]
+        min_held: Option<Decimal>,
+        /// Select only accounts satisfying the given filter expression, e.g. `held > 0 && !locked`
+        #[structopt(long)]
+        filter: Option<Filter>,
+        /// Print the clients owning the given transaction and its dispute state, instead of accounts
+        #[structopt(long, conflicts_with_all = &["client", "locked", "min-held", "filter"])]
+        tx: Option<u32>,
+        /// Output format: `table` or `json`
+        #[structopt(long, default_value = "table")]
+        format: QueryFormat,
+    },
+    /// Print the full internal state of a snapshot as JSON for debugging, including the transactions history
+    /// with dispute states and the pending disputes, which are not part of the output schema
+    DumpState {
+        /// Snapshot file, as written by --snapshot or --archive
+        #[structopt(long, parse(from_os_str))]
+        snapshot: PathBuf,
+    },
+    /// Write the accounts of a snapshot, with their transactions history, to stdout as length-delimited
+    /// `AccountSnapshot` Protocol Buffers messages, following the schema `proto/simulator.proto`
+    ExportProto {
+        /// Snapshot file, as written by --snapshot
+        #[structopt(long, parse(from_os_str))]
+        snapshot: PathBuf,
+        /// Archive snapshot file, as written by --archive, whose accounts are exported too
+        #[structopt(long, parse(from_os_str))]
+        archive: Option<PathBuf>,
+    },
+    /// Read accounts written by `export-proto`, or by any program following `proto/simulator.proto`, and write
+    /// them to a new snapshot, restorable by --snapshot
+    ImportProto {
+        /// File of length-delimited `AccountSnapshot` messages
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Snapshot file to write
+        #[structopt(long, parse(from_os_str))]
+        snapshot: PathBuf,
+    },
+    /// Erase the personal data of a client from a snapshot, rewritten in place: the transactions history and
+    /// dispute details are removed, while the account is kept as a tombstone with the same balances, so that
+    /// totals do not change, and any later transaction of the client is rejected
+    EraseClient {
+        /// Snapshot file, as written by --snapshot
+        #[structopt(long, parse(from_os_str))]
+        snapshot: PathBuf,
+        /// Client to erase
+        #[structopt(long)]
+        client: u16,
+    },
+    /// Attach operator notes to clients of a snapshot, rewritten in place: the notes are kept by later runs
+    /// restoring the snapshot and shown by `query`, `dump-state` and statements
+    Annotate {
+        /// Snapshot file, as written by --snapshot
+        #[structopt(long, parse(from_os_str))]
+        snapshot: PathBuf,
+        /// Client to annotate with --note
+        #[structopt(long, requires = "note", required_unless = "file")]
+        client: Option<u16>,
+        /// Note attached to --client
+        #[structopt(long, requires = "client")]
+        note: Option<String>,
+        /// `client,note` CSV file of notes to attach, in file order
+        #[structopt(long, parse(from_os_str), conflicts_with = "client")]
+        file: Option<PathBuf>,
+    },
+    /// Compare the run certificates of two runs, as written by --certify: print the policy settings that
+    /// differ, and the outputs that differ (accounts, totals, locked accounts, rejections by reason, final
+    /// state), each with the differing settings that can explain it
+    PolicyDiff {
+        /// Certificate of the first run
+        #[structopt(parse(from_os_str))]
+        before: PathBuf,
+        /// Certificate of the second run
+        #[structopt(parse(from_os_str))]
+        after: PathBuf,
+    },
+    /// Rebuild the accounts of a run with --threads interrupted while writing a --wal directory, replaying the
+    /// segments of the worker threads in parallel, and save them as a checkpoint to go on with --resume
+    Recover {
+        /// Write-ahead log directory, as written by --wal
+        #[structopt(long, parse(from_os_str))]
+        wal: PathBuf,
+        /// Checkpoint file written
+        #[structopt(long, parse(from_os_str))]
+        checkpoint: PathBuf,
+    },
+    /// Check the hash chain of every account of an audit log written by --audit, failing on the first row edited,
+    /// removed or inserted afterwards, and print the number of rows and the last SHA-256 of every account, to
+    /// be archived and compared with a later check
+    VerifyChain {
+        /// Audit log, as written by --audit
+        #[structopt(parse(from_os_str))]
+        audit: PathBuf,
+    },
+    /// Listen on a TCP address for transactions sent as CSV rows or JSON objects, one per line, by any number of
+    /// concurrent connections, and apply them as they arrive to accounts that can be queried at any time
+    /// (`balance <client>`, `balances`). Starts from --restore or --resume-from-output, if given
+    Serve {
+        /// Address to listen on
+        #[structopt(long, default_value = "127.0.0.1:7878")]
+        listen: String,
+        /// Input files replayed on the accounts, in the given order, before accepting connections, e.g. to prime
+        /// a shadow environment with historical traffic. Connections made meanwhile wait for the replay to end
+        #[structopt(long, parse(from_os_str))]
+        replay: Vec<PathBuf>,
+    },
+    /// Run bundled example datasets through the engine, explaining the state change made by every transaction,
+    /// or why it was ignored. Useful to learn the dispute semantics
+    Demo {
+        /// Dataset to run: `basic`, `dispute-heavy` or `adversarial`. Every dataset is run if not given
+        dataset: Option<DemoDataset>,
+    },
+}
+
+fn parse_overdraft_limit(s: &str) -> Result<Decimal, String> {
+    match s.parse::<Decimal>().map_err(|error| error.to_string())? {
+        limit if limit.is_negative() => Err(format!("negative overdraft limit {s}")),
+        limit => Ok(limit),
+    }
 }
 
+fn parse_attribute(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+        .ok_or_else(|| format!("expected `name=value`, found `{s}`"))
+}
+
+#[cfg(feature = "memory-profile")]
+use transactions_simulator::shared::memory;
+
+#[cfg(feature = "memory-profile")]
+#[global_allocator]
+static ALLOCATOR: memory::TrackingAllocator = memory::TrackingAllocator;
+
 fn main() -> Result<(), Error> {
+    let result = run();
+    #[cfg(feature = "memory-profile")]
+    eprintln!("{}", memory::report());
+    result
+}
+
+fn run() -> Result<(), Error> {
     let args = Args::from_args();
 
-    apply_transaction(args.input, std::io::stdout())
+    let exponents = CurrencyExponents {
+        default: args.minor_unit_exponent,
+        by_currency: args
+            .currency_exponent
+            .into_iter()
+            .map(|exponent| (exponent.currency, exponent.exponent))
+            .collect(),
+    };
+    let mut options = ProcessingOptions {
+        input_format: match args.input_format.as_str() {
+            "iso8583" => InputFormat::Iso8583(exponents.clone()),
+            "json" => InputFormat::JsonLines,
+            "xlsx" => InputFormat::Xlsx,
+            _ => InputFormat::Csv,
+        },
+        seed_file: args.resume_from_output,
+        restore_file: args.restore,
+        resume_file: args.resume,
+        checkpoint: args.checkpoint.map(|file| Checkpoint {
+            file,
+            interval: args.checkpoint_every,
+        }),
+        amount_units: match args.minor_units {
+            true => AmountUnits::Minor(exponents),
+            false => AmountUnits::Decimal,
+        },
+        policy: EnginePolicy {
+            rejected_attributes: args.reject_attribute,
+            channel_rules: args
+                .channel_rule
+                .into_iter()
+                .map(|rule| (rule.transaction_type, rule.channels))
+                .collect(),
+            negative_adjustments: args.negative_adjustments,
+            adjust_locked_accounts: args.adjust_locked_accounts,
+            history_limit: args.history_limit,
+            auto_resolve_reasons: args.auto_resolve_reason,
+            charge_back_undisputed: args.charge_back_undisputed,
+            allow_unlock: args.allow_unlock,
+            audit_unlocks: args.audit_unlocks,
+            withdrawal_dispute_mode: args.withdrawal_dispute_mode,
+            overdraft_limit: args.overdraft_limit,
+            dispute_shortfall: args.dispute_shortfall,
+        },
+        output_format: args.output_format,
+        sort_by: args.sort_by,
+        snapshot_file: args.snapshot,
+        archive_file: args.archive,
+        refuse_applied_inputs: args.refuse_applied_inputs,
+        storage_retry: RetryPolicy {
+            attempts: args.storage_attempts.max(1),
+            initial_backoff: Duration::from_millis(args.storage_backoff_ms),
+            max_backoff: Duration::from_millis(args.storage_max_backoff_ms),
+        },
+        alerts_file: args.alerts,
+        alert_thresholds: AlertThresholds {
+            balance_above: args.alert_balance_above,
+            chargebacks_above: args.alert_chargebacks_above,
+        },
+        filter: args.filter,
+        strict: args.strict,
+        rejects_file: args.rejects,
+        audit_file: args.audit,
+        certificate_file: args.certify,
+        anomaly_rate: args.anomaly_max_change.map(|max_change| AnomalyRate {
+            max_change,
+            window: args.anomaly_window,
+        }),
+        threads: args.threads,
+        read_ahead: args.read_ahead,
+        duplicate_clients: args.duplicate_clients,
+        wal_dir: args.wal,
+        max_memory: args.max_memory,
+        aggregate_deposits: args.aggregate_deposits,
+        route_disputes_by_tx: args.route_disputes_by_tx,
+        risk_weights: RiskWeights {
+            chargeback: args.risk_weight_chargeback,
+            open_dispute: args.risk_weight_open_dispute,
+        },
+        ..Default::default()
+    };
+    args.enrich
+        .iter()
+        .try_for_each(|path| options.enrichment.load(path))?;
+
+    if let Some(seed_file) = &options.seed_file {
+        eprintln!(
+            "Warning: accounts resumed from {} have no transactions history, \
+             disputes, resolves and chargebacks on previous transactions will be rejected",
+            seed_file.display()
+        );
+    }
+
+    if args.deterministic {
+        eprintln!("Warning: --deterministic is deprecated, accounts are always written sorted (see --sort-by)");
+    }
+
+    if args.print_config {
+        return write_config(&options, std::io::stdout());
+    }
+
+    if let (None, Some(address)) = (&args.command, &args.http) {
+        let listener = TcpListener::bind(address).map_err(Error::Io)?;
+        eprintln!(
+            "Serving HTTP on {}",
+            listener.local_addr().map_err(Error::Io)?
+        );
+        return serve_http(listener, &options);
+    }
+
+    if let (None, Some(manifest)) = (&args.command, args.manifest) {
+        return apply_batch(manifest, &options, std::io::stdout());
+    }
+
+    let inputs = args
+        .inputs
+        .iter()
+        .map(|pattern| expand_input_pattern(pattern))
+        .collect::<Result<Vec<_>, _>>()?
+        .concat();
+
+    match (args.command, inputs) {
+        (
+            Some(Command::Verify {
+                input,
+                expected,
+                tolerance,
+            }),
+            _,
+        ) => verify_transaction(
+            open_input(input)?,
+            &options,
+            expected,
+            tolerance,
+            std::io::stdout(),
+        ),
+        (
+            Some(Command::Report {
+                input,
+                cohorts,
+                dispute_outcomes,
+                activity,
+            }),
+            _,
+        ) => {
+            let kind = match (dispute_outcomes, activity) {
+                (true, _) => ReportKind::DisputeOutcomes,
+                (_, true) => ReportKind::Activity,
+                _ => ReportKind::Cohorts(cohorts),
+            };
+            report_transaction(open_input(input)?, &options, kind, std::io::stdout())
+        }
+        (
+            Some(Command::Backfill {
+                accounts,
+                corrections,
+            }),
+            _,
+        ) => backfill_accounts(accounts, corrections, &options, std::io::stdout()),
+        (
+            Some(Command::Export {
+                input,
+                format,
+                date,
+            }),
+            _,
+        ) => export_transaction(
+            open_input(input)?,
+            &options,
+            format,
+            &date,
+            std::io::stdout(),
+        ),
+        (
+            Some(Command::Query {
+                snapshot,
+                archive,
+                client,
+                locked,
+                min_held,
+                filter,
+                tx,
+                format,
+            }),
+            _,
+        ) => match tx {
+            Some(tx) => {
+                query_snapshot_transaction(snapshot, archive, tx, format, std::io::stdout())
+            }
+            None => query_snapshot(
+                snapshot,
+                archive,
+                &AccountQuery {
+                    client,
+                    locked,
+                    min_held,
+                    filter,
+                },
+                format,
+                std::io::stdout(),
+            ),
+        },
+        (Some(Command::DumpState { snapshot }), _) => dump_snapshot(snapshot, std::io::stdout()),
+        (Some(Command::ExportProto { snapshot, archive }), _) => {
+            export_snapshot_proto(&snapshot, archive.as_deref(), std::io::stdout())
+        }
+        (Some(Command::ImportProto { input, snapshot }), _) => {
+            let accounts = import_snapshot_proto(File::open(input).map_err(Error::Io)?, &snapshot)?;
+            eprintln!("Imported {accounts} account(s)");
+            Ok(())
+        }
+        (Some(Command::EraseClient { snapshot, client }), _) => {
+            erase_snapshot_client(snapshot, client)
+        }
+        (
+            Some(Command::Annotate {
+                snapshot,
+                client,
+                note,
+                file,
+            }),
+            _,
+        ) => {
+            let annotations = match (file, client, note) {
+                (Some(file), _, _) => read_annotations(&file)?,
+                (None, Some(client), Some(note)) => vec![(client, note)],
+                _ => Vec::new(),
+            };
+            annotate_snapshot_clients(snapshot, &annotations)
+        }
+        (Some(Command::PolicyDiff { before, after }), _) => {
+            diff_policies(&before, &after, std::io::stdout())
+        }
+        (Some(Command::Recover { wal, checkpoint }), _) => {
+            let position = recover_wal(&wal, &checkpoint)?;
+            eprintln!(
+                "Recovered the accounts after input record {position}, go on with --resume {}",
+                checkpoint.display()
+            );
+            Ok(())
+        }
+        (Some(Command::VerifyChain { audit }), _) => verify_audit_chain(&audit, std::io::stdout()),
+        (Some(Command::Demo { dataset }), _) => run_demo(dataset, &options, std::io::stdout()),
+        (Some(Command::Serve { listen, replay }), _) => {
+            let listener = TcpListener::bind(&listen).map_err(Error::Io)?;
+            eprintln!("Listening on {}", listener.local_addr().map_err(Error::Io)?);
+            match replay.is_empty() {
+                true => serve_transactions(listener, &options),
+                false => {
+                    let replay = replay
+                        .into_iter()
+                        .map(open_input)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    serve_transactions_replaying(listener, replay, &options)
+                }
+            }
+        }
+        (None, inputs) if !inputs.is_empty() => {
+            let inputs = inputs
+                .into_iter()
+                .map(open_input)
+                .collect::<Result<Vec<_>, _>>()?;
+            let limits = PageLimits {
+                rows: args.max_output_rows,
+                bytes: args.max_output_bytes,
+            };
+            match args.partition_output {
+                Some(partitions) => {
+                    apply_transaction_partitioned(inputs, &options, partitions, args.output_dir)
+                }
+                None if limits != PageLimits::default() => {
+                    apply_transaction_paged(inputs, &options, limits, args.output_dir)
+                }
+                None => apply_transactions(inputs, &options, std::io::stdout()),
+            }
+        }
+        (None, _) => clap::Error::with_description(
+            "The following required arguments were not provided: <input>",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit(),
+    }
+}
+
+/// Open the input file, or stdin if the path is `-`, so that transactions can be piped from another process.
+/// Compressed input is decompressed while read. Stdin is not locked, so that it can be read by another thread
+fn open_input(path: PathBuf) -> Result<Box<dyn Read + Send>, Error> {
+    match path.as_os_str() == "-" {
+        true => decompressing_reader(std::io::stdin()),
+        false => decompressing_reader(File::open(path).map_err(Error::Io)?),
+    }
 }
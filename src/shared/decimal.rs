@@ -0,0 +1,172 @@
+use std::{fmt::Display, ops::Neg, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::shared::errors::Error;
+
+/// Number of decimal places kept by [Decimal]
+pub const DECIMALS: u32 = 4;
+/// Units of the smallest representable amount in one unit
+const SCALE: i64 = 10_i64.pow(DECIMALS);
+
+/// Fixed-point decimal number with four decimal places, stored as an integer number of ten-thousandths,
+/// so that sums and differences of amounts are exact.
+/// Values with more decimal places are rounded half away from zero when parsed.
+/// The range is symmetric, from `-MAX` to `MAX`, so that negating a number or taking its absolute value
+/// never overflows: numbers out of range are refused when parsed, and checked operations return `None`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Decimal(i64);
+
+impl Decimal {
+    pub const ZERO: Self = Self(0);
+    /// Largest number, about 922 trillion
+    pub const MAX: Self = Self(i64::MAX);
+
+    /// Number of ten-thousandths, if in range
+    fn checked(units: i64) -> Option<Self> {
+        (units != i64::MIN).then_some(Self(units))
+    }
+
+    /// Number from an integer amount of minor units with the given number of decimal places
+    /// (e.g. `1050` with exponent `2` is `10.50`). Returns `None` if the number does not fit.
+    pub fn from_minor_units(units: i64, exponent: u32) -> Option<Self> {
+        match exponent <= DECIMALS {
+            true => units
+                .checked_mul(10_i64.pow(DECIMALS - exponent))
+                .and_then(Self::checked),
+            false => {
+                let divisor = 10_i128.checked_pow(exponent - DECIMALS)?;
+                Self::checked(round_div(units as i128, divisor).try_into().ok()?)
+            }
+        }
+    }
+
+    /// Nearest number to a floating point value, used to read values stored as `f64` by earlier versions
+    pub fn from_f64(value: f64) -> Self {
+        Self((value * SCALE as f64).round() as i64)
+    }
+
+    /// Closest floating point value, for statistics only (e.g. rates and scores)
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / SCALE as f64
+    }
+
+    /// Integer number of ten-thousandths, as stored in snapshots
+    pub fn to_bits(self) -> i64 {
+        self.0
+    }
+
+    /// Number from an integer number of ten-thousandths, as stored in snapshots, `None` if out of range
+    pub fn from_bits(bits: i64) -> Option<Self> {
+        Self::checked(bits)
+    }
+
+    pub fn abs(self) -> Self {
+        Self(self.0.abs())
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// Sum of two numbers, `None` if it does not fit
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).and_then(Self::checked)
+    }
+
+    /// Difference of two numbers, `None` if it does not fit
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).and_then(Self::checked)
+    }
+}
+
+/// Integer division rounding half away from zero
+fn round_div(dividend: i128, divisor: i128) -> i128 {
+    let quotient = dividend / divisor;
+    let remainder = dividend % divisor;
+    match remainder.abs() * 2 >= divisor {
+        true => quotient + dividend.signum(),
+        false => quotient,
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidDecimal(s.to_owned());
+
+        let trimmed = s.trim();
+        let (negative, digits) = match trimmed.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if integer.is_empty() && fraction.is_empty()
+            || !integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        // integer part, the kept decimal places and the first discarded one, as a single integer
+        let kept = DECIMALS as usize + 1;
+        let mut fraction = fraction.as_bytes().to_vec();
+        fraction.resize(fraction.len().max(kept), b'0');
+        let mut units = 0_i128;
+        for digit in integer.bytes().chain(fraction[..kept].iter().copied()) {
+            units = units
+                .checked_mul(10)
+                .and_then(|units| units.checked_add((digit - b'0') as i128))
+                .ok_or_else(invalid)?;
+        }
+        let mut units = round_div(units, 10);
+        if negative {
+            units = -units;
+        }
+
+        units
+            .try_into()
+            .ok()
+            .and_then(Self::checked)
+            .ok_or_else(invalid)
+    }
+}
+
+impl Display for Decimal {
+    /// Always four decimal places, e.g. `-1.5000`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let scale = SCALE as u64;
+        let width = DECIMALS as usize;
+        f.pad(&format!(
+            "{sign}{}.{:0width$}",
+            units / scale,
+            units % scale
+        ))
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
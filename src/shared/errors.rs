@@ -1,9 +1,109 @@
 use thiserror::Error;
 
 #[derive(Debug, Error)]
-pub(crate) enum Error {
+pub enum Error {
     #[error("Invalid transation type {0}")]
     InvalidTransactionType(String),
+    #[error("Number of output partitions must be greater than zero")]
+    InvalidPartitionCount,
+    #[error(
+        "Output files of at most {0} bytes cannot hold the header and the account of client {1}"
+    )]
+    OutputPageTooSmall(u64, u16),
+    #[error("Output does not match expected accounts: {0} difference(s) found")]
+    VerificationFailed(usize),
+    #[error("Invalid enrichment file {0}: {1}")]
+    InvalidEnrichmentFile(String, String),
+    #[error("Invalid channel rule {0}, expected `type=channel[,channel...]`")]
+    InvalidChannelRule(String),
+    #[error("Invalid output format {0}")]
+    InvalidOutputFormat(String),
+    #[error(
+        "Invalid sort column {0}, expected `client`, `available`, `held`, `total` or `locked`"
+    )]
+    InvalidSortKey(String),
+    #[error("Invalid currency exponent {0}, expected `CURRENCY=exponent`")]
+    InvalidCurrencyExponent(String),
+    #[error("Missing reason code for correction at line {0}")]
+    MissingReasonCode(usize),
+    #[error("Correction at line {0} cannot be applied: {1}")]
+    RejectedCorrection(usize, String),
+    #[error("Invalid negative adjustments {0}, expected `none`, `all` or `client[,client...]`")]
+    InvalidNegativeAdjustments(String),
+    #[error("Invalid withdrawal dispute mode {0}, expected `debit`, `credit` or `reject`")]
+    InvalidWithdrawalDisputeMode(String),
+    #[error("Invalid dispute shortfall {0}, expected `allow` or `reject`")]
+    InvalidDisputeShortfall(String),
+    #[error(
+        "Invalid duplicate clients treatment {0}, expected `error`, `merge` or `prefer-latest`"
+    )]
+    InvalidDuplicateClients(String),
+    #[error("Client {0} found more than once, in {1}")]
+    DuplicateClient(u16, String),
+    #[error("Invalid decimal amount {0}")]
+    InvalidDecimal(String),
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    #[error("Invalid query format {0}, expected `table` or `json`")]
+    InvalidQueryFormat(String),
+    #[error("Invalid export format {0}")]
+    InvalidExportFormat(String),
+    #[error("Invalid ISO 8583 message {0}: {1}")]
+    InvalidIso8583Message(String, String),
+    #[error("Invalid JSON record {0}: {1}")]
+    InvalidJsonRecord(String, String),
+    #[error("Invalid xlsx workbook: {0}")]
+    InvalidXlsx(String),
+    #[error("Invalid filter {0}: {1}")]
+    InvalidFilter(String, String),
+    #[error("Input cannot be read: {0}")]
+    UnreadableInput(std::io::Error),
+    #[error("Malformed record at line {0}: {1}")]
+    MalformedRecord(u64, String),
+    #[error("No input file matches {0}")]
+    NoMatchingInput(String),
+    #[error("Invalid batch: {0}")]
+    InvalidBatch(String),
+    #[error("Input with SHA-256 {0} already applied to the snapshot state")]
+    InputAlreadyApplied(String),
+    #[error("Invalid demo dataset {0}, expected `basic`, `dispute-heavy` or `adversarial`")]
+    InvalidDemoDataset(String),
+    #[error("Storage operation {0} failed after {1} attempt(s): {2}")]
+    StorageUnavailable(String, u32, std::io::Error),
+    #[error("Client {0} cannot be erased: {1}")]
+    ErasureRefused(u16, String),
+    #[error("Checkpoints are not supported with worker threads")]
+    CheckpointWithThreads,
+    #[error("Checkpoint taken after input record {0}, but the input has only {1} records")]
+    ResumePastInput(u64, u64),
+    #[error("Invalid write-ahead log: {0}")]
+    InvalidWal(String),
+    #[error("Invalid protobuf export: {0}")]
+    InvalidProtobuf(String),
+    #[error("The write-ahead log is only written with worker threads")]
+    WalWithoutThreads,
+    #[error(
+        "Invalid memory limit {0}, expected a number of bytes with an optional K, M or G suffix"
+    )]
+    InvalidMemoryLimit(String),
+    #[error("The transactions history cannot be spilled with {0}")]
+    SpillNotSupported(&'static str),
+    #[error("Deposits cannot be pre-aggregated with {0}")]
+    AggregationNotSupported(&'static str),
+    #[error("Disputes cannot be routed by transaction ID with {0}")]
+    RoutingNotSupported(&'static str),
+    #[error("Transfers between clients are not supported with worker threads")]
+    TransfersWithThreads,
+    #[error("{0} invariant check(s) failed, see the certificate {1}")]
+    CertificationFailed(usize, String),
+    #[error("Audit log {0} broken at line {1}: {2}")]
+    BrokenAuditChain(String, u64, String),
+    #[error("Invalid run report {0}: {1}")]
+    InvalidRunReport(String, String),
+    #[error("Client {0} cannot be annotated: {1}")]
+    AnnotationRefused(u16, String),
+    #[error("{0} exceeds the range of representable amounts")]
+    AmountOverflow(String),
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
@@ -0,0 +1,430 @@
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+
+/// Leading bytes of a gzip member (RFC 1952)
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Size of the DEFLATE sliding window, the farthest a back-reference can reach
+const WINDOW_SIZE: usize = 32 * 1024;
+
+/// Base lengths and extra bits of the length symbols 257 to 285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances and extra bits of the distance symbols 0 to 29
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which the code lengths of the code length alphabet are sent in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Canonical Huffman code, as number of codes of each length and symbols ordered by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        lengths
+            .iter()
+            .for_each(|&length| counts[length as usize] += 1);
+        counts[0] = 0;
+
+        let mut offsets = [0; 16];
+        (1..15).for_each(|length| offsets[length + 1] = offsets[length] + counts[length]);
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Self { counts, symbols }
+    }
+
+    /// Codes of the fixed Huffman blocks
+    fn fixed() -> (Self, Self) {
+        let mut lengths = [0; 288];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+
+        (Self::new(&lengths), Self::new(&[5; 30]))
+    }
+}
+
+/// Position of the decoder in the gzip stream
+enum State {
+    /// Expecting the header of a member, or the end of the input after at least one member
+    Header,
+    /// Expecting the header of a DEFLATE block, or the member trailer after the final block
+    Block,
+    /// Copying the given number of bytes of a stored block
+    Stored(u16),
+    /// Decoding a compressed block with its literal/length and distance codes
+    Compressed(Huffman, Huffman),
+    Done,
+}
+
+/// Streaming gzip decoder (RFC 1951 and 1952), dependency-free. Concatenated members are decoded as a single
+/// stream, and the CRC-32 and size of every member are checked, so that a corrupted or truncated input
+/// is reported as an error instead of being silently decoded into garbage.
+pub struct GzipReader<R> {
+    reader: BufReader<R>,
+    /// Whether the stream is raw DEFLATE, without gzip header and trailer
+    raw: bool,
+    /// Bits read but not consumed yet, least significant first, and their number (always less than 8 between reads)
+    bits: u64,
+    bit_count: u32,
+    state: State,
+    final_block: bool,
+    members: u64,
+    /// Last decoded bytes, referenced by back-references, as a ring buffer
+    window: Vec<u8>,
+    window_position: usize,
+    /// Decoded bytes not handed out yet, and position of the next byte to hand out
+    output: Vec<u8>,
+    position: usize,
+    /// CRC-32 and size of the member decoded so far
+    crc: u32,
+    size: u64,
+}
+
+impl<R: Read> GzipReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            raw: false,
+            bits: 0,
+            bit_count: 0,
+            state: State::Header,
+            final_block: false,
+            members: 0,
+            window: vec![0; WINDOW_SIZE],
+            window_position: 0,
+            output: Vec::with_capacity(WINDOW_SIZE),
+            position: 0,
+            crc: !0,
+            size: 0,
+        }
+    }
+
+    /// Decoder of a raw DEFLATE stream (RFC 1951), such as a zip archive entry, ending after its final block.
+    /// The stream has no checksum of its own, it is up to the container to check the decoded bytes
+    pub fn raw(reader: R) -> Self {
+        Self {
+            raw: true,
+            state: State::Block,
+            ..Self::new(reader)
+        }
+    }
+
+    /// Decode the next part of the stream, filling the output with up to a window of bytes
+    fn step(&mut self) -> Result<(), Error> {
+        match std::mem::replace(&mut self.state, State::Done) {
+            State::Header => {
+                if self.members > 0 && self.reader.fill_buf()?.is_empty() {
+                    return Ok(());
+                }
+                self.read_header()?;
+                self.members += 1;
+                self.state = State::Block;
+            }
+            State::Block if self.final_block && self.raw => {}
+            State::Block if self.final_block => {
+                self.read_trailer()?;
+                self.final_block = false;
+                self.state = State::Header;
+            }
+            State::Block => {
+                self.final_block = self.read_bits(1)? == 1;
+                self.state = match self.read_bits(2)? {
+                    0 => {
+                        self.align();
+                        let length = self.read_bits(16)? as u16;
+                        if self.read_bits(16)? as u16 != !length {
+                            return Err(invalid("stored block length mismatch"));
+                        }
+                        State::Stored(length)
+                    }
+                    1 => {
+                        let (literals, distances) = Huffman::fixed();
+                        State::Compressed(literals, distances)
+                    }
+                    2 => self.read_dynamic_codes()?,
+                    _ => return Err(invalid("invalid block type")),
+                };
+            }
+            State::Stored(remaining) => {
+                let length = remaining.min(WINDOW_SIZE as u16);
+                for _ in 0..length {
+                    let byte = self.read_bits(8)? as u8;
+                    self.emit(byte);
+                }
+                self.state = match remaining - length {
+                    0 => State::Block,
+                    remaining => State::Stored(remaining),
+                };
+            }
+            State::Compressed(literals, distances) => {
+                let end_of_block = self.inflate(&literals, &distances)?;
+                self.state = match end_of_block {
+                    true => State::Block,
+                    false => State::Compressed(literals, distances),
+                };
+            }
+            State::Done => {}
+        }
+
+        Ok(())
+    }
+
+    fn read_header(&mut self) -> Result<(), Error> {
+        let mut header = [0; 10];
+        for byte in header.iter_mut() {
+            *byte = self.read_bits(8)? as u8;
+        }
+        if header[..2] != GZIP_MAGIC {
+            return Err(invalid("not a gzip stream"));
+        }
+        if header[2] != 8 {
+            return Err(invalid("unsupported compression method"));
+        }
+
+        let flags = header[3];
+        if flags & 0x04 != 0 {
+            // extra field
+            let length = self.read_bits(16)?;
+            for _ in 0..length {
+                self.read_bits(8)?;
+            }
+        }
+        for flag in [0x08, 0x10] {
+            // file name and comment, zero terminated
+            if flags & flag != 0 {
+                while self.read_bits(8)? != 0 {}
+            }
+        }
+        if flags & 0x02 != 0 {
+            // header CRC
+            self.read_bits(16)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_trailer(&mut self) -> Result<(), Error> {
+        self.align();
+        let crc = self.read_bits(32)? as u32;
+        let size = self.read_bits(32)? as u32;
+        if crc != !self.crc || size != self.size as u32 {
+            return Err(invalid("corrupted data, checksum mismatch"));
+        }
+        self.crc = !0;
+        self.size = 0;
+
+        Ok(())
+    }
+
+    /// Read the literal/length and distance codes of a dynamic block
+    fn read_dynamic_codes(&mut self) -> Result<State, Error> {
+        let literal_count = self.read_bits(5)? as usize + 257;
+        let distance_count = self.read_bits(5)? as usize + 1;
+        let code_length_count = self.read_bits(4)? as usize + 4;
+        if literal_count > 286 || distance_count > 30 {
+            return Err(invalid("too many codes"));
+        }
+
+        let mut code_lengths = [0; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[symbol] = self.read_bits(3)? as u8;
+        }
+        let code_lengths = Huffman::new(&code_lengths);
+
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+        while lengths.len() < literal_count + distance_count {
+            let (length, repeat) = match self.decode(&code_lengths)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => {
+                    let previous = *lengths
+                        .last()
+                        .ok_or_else(|| invalid("repeated length without previous length"))?;
+                    (previous, 3 + self.read_bits(2)?)
+                }
+                17 => (0, 3 + self.read_bits(3)?),
+                _ => (0, 11 + self.read_bits(7)?),
+            };
+            if lengths.len() + repeat as usize > literal_count + distance_count {
+                return Err(invalid("too many code lengths"));
+            }
+            lengths.extend(std::iter::repeat_n(length, repeat as usize));
+        }
+        if lengths[256] == 0 {
+            return Err(invalid("missing end of block code"));
+        }
+
+        Ok(State::Compressed(
+            Huffman::new(&lengths[..literal_count]),
+            Huffman::new(&lengths[literal_count..]),
+        ))
+    }
+
+    /// Decode symbols of a compressed block until a window of bytes is produced,
+    /// returning whether the end of the block was reached
+    fn inflate(&mut self, literals: &Huffman, distances: &Huffman) -> Result<bool, Error> {
+        while self.output.len() < WINDOW_SIZE {
+            let symbol = self.decode(literals)? as usize;
+            match symbol {
+                0..=255 => self.emit(symbol as u8),
+                256 => return Ok(true),
+                _ => {
+                    let index = symbol - 257;
+                    if index >= LENGTH_BASE.len() {
+                        return Err(invalid("invalid length symbol"));
+                    }
+                    let length = LENGTH_BASE[index] as usize
+                        + self.read_bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                    let index = self.decode(distances)? as usize;
+                    if index >= DISTANCE_BASE.len() {
+                        return Err(invalid("invalid distance symbol"));
+                    }
+                    let distance = DISTANCE_BASE[index] as usize
+                        + self.read_bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                    if distance as u64 > self.size {
+                        return Err(invalid("distance too far back"));
+                    }
+
+                    for _ in 0..length {
+                        let byte = self.window
+                            [(self.window_position + WINDOW_SIZE - distance) % WINDOW_SIZE];
+                        self.emit(byte);
+                    }
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Decode a symbol, reading its code bit by bit
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16, Error> {
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &huffman.counts[1..] {
+            code |= self.read_bits(1)? as i32;
+            let count = count as i32;
+            if code - count < first {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(invalid("invalid Huffman code"))
+    }
+
+    fn emit(&mut self, byte: u8) {
+        self.window[self.window_position] = byte;
+        self.window_position = (self.window_position + 1) % WINDOW_SIZE;
+        self.output.push(byte);
+        self.crc = CRC32_TABLE[((self.crc ^ byte as u32) & 0xff) as usize] ^ (self.crc >> 8);
+        self.size += 1;
+    }
+
+    /// Read `count` bits (up to 32), least significant first
+    fn read_bits(&mut self, count: u32) -> Result<u64, Error> {
+        while self.bit_count < count {
+            let byte = match self.reader.fill_buf()?.first() {
+                Some(&byte) => byte,
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "truncated compressed stream",
+                    ))
+                }
+            };
+            self.reader.consume(1);
+            self.bits |= (byte as u64) << self.bit_count;
+            self.bit_count += 8;
+        }
+
+        let value = self.bits & ((1 << count) - 1);
+        self.bits >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    /// Drop the bits left in the current byte
+    fn align(&mut self) {
+        self.bits = 0;
+        self.bit_count = 0;
+    }
+}
+
+impl<R: Read> Read for GzipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.output.len() {
+            if matches!(self.state, State::Done) {
+                return Ok(0);
+            }
+            self.output.clear();
+            self.position = 0;
+            self.step()?;
+        }
+
+        let remaining = &self.output[self.position..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.position += len;
+
+        Ok(len)
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid compressed stream: {reason}"),
+    )
+}
+
+/// CRC-32 of `bytes`, as stored by gzip and zip
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// CRC-32 (IEEE 802.3, reflected polynomial) of every byte value
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+}
@@ -0,0 +1,224 @@
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(feature = "memory-profile")]
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+/// Part of the simulator the memory allocated while it runs is accounted to, when allocations are tracked
+/// (`memory-profile` feature)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subsystem {
+    /// Entries of the in-memory client map
+    ClientMap,
+    /// Transactions history of the clients, with everything allocated to apply a transaction
+    Histories,
+    /// Readers of the inputs and records parsed from them, until they are applied
+    ParseBuffers,
+    /// Any other allocation
+    Other,
+}
+
+const SUBSYSTEMS: [Subsystem; 4] = [
+    Subsystem::ClientMap,
+    Subsystem::Histories,
+    Subsystem::ParseBuffers,
+    Subsystem::Other,
+];
+
+impl Subsystem {
+    fn name(&self) -> &'static str {
+        match self {
+            Subsystem::ClientMap => "client map",
+            Subsystem::Histories => "histories",
+            Subsystem::ParseBuffers => "parse buffers",
+            Subsystem::Other => "other",
+        }
+    }
+}
+
+#[cfg(feature = "memory-profile")]
+thread_local! {
+    /// Subsystem the allocations of the thread are accounted to
+    static CURRENT: Cell<Subsystem> = const { Cell::new(Subsystem::Other) };
+}
+
+/// Run `f`, accounting the memory it allocates on the calling thread to `subsystem`. Does nothing more than running
+/// `f` unless allocations are tracked
+pub fn track<T>(subsystem: Subsystem, f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "memory-profile")]
+    {
+        let previous = CURRENT.try_with(|current| current.replace(subsystem)).ok();
+        let result = f();
+        if let Some(previous) = previous {
+            let _ = CURRENT.try_with(|current| current.set(previous));
+        }
+        result
+    }
+    #[cfg(not(feature = "memory-profile"))]
+    {
+        let _ = subsystem;
+        f()
+    }
+}
+
+/// Bytes allocated and not freed yet, and the most ever allocated at once
+#[derive(Debug)]
+struct Usage {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl Usage {
+    const fn new() -> Self {
+        Self {
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    fn add(&self, size: usize) {
+        let current = self.current.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak.fetch_max(current, Ordering::Relaxed);
+    }
+
+    fn sub(&self, size: usize) {
+        self.current.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+/// Memory allocated by the whole run and by every subsystem, updated by the tracking allocator
+#[derive(Debug)]
+pub struct Accounting {
+    total: Usage,
+    by_subsystem: [Usage; SUBSYSTEMS.len()],
+}
+
+impl Default for Accounting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Accounting {
+    pub const fn new() -> Self {
+        Self {
+            total: Usage::new(),
+            by_subsystem: [Usage::new(), Usage::new(), Usage::new(), Usage::new()],
+        }
+    }
+
+    /// Account `size` bytes allocated by `subsystem`
+    pub fn allocated(&self, subsystem: Subsystem, size: usize) {
+        self.total.add(size);
+        self.by_subsystem[subsystem as usize].add(size);
+    }
+
+    /// Account `size` bytes allocated by `subsystem` as freed
+    pub fn freed(&self, subsystem: Subsystem, size: usize) {
+        self.total.sub(size);
+        self.by_subsystem[subsystem as usize].sub(size);
+    }
+
+    pub fn report(&self) -> MemoryReport {
+        let usage = |usage: &Usage| {
+            (
+                usage.peak.load(Ordering::Relaxed),
+                usage.current.load(Ordering::Relaxed),
+            )
+        };
+        MemoryReport {
+            total: usage(&self.total),
+            by_subsystem: SUBSYSTEMS
+                .iter()
+                .map(|subsystem| (*subsystem, usage(&self.by_subsystem[*subsystem as usize])))
+                .collect(),
+        }
+    }
+}
+
+/// Peak and still allocated bytes of the run and of every subsystem. The peaks of the subsystems are reached at
+/// different times, so they may add up to more than the peak of the run
+#[derive(Debug, PartialEq)]
+pub struct MemoryReport {
+    total: (usize, usize),
+    by_subsystem: Vec<(Subsystem, (usize, usize))>,
+}
+
+impl Display for MemoryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let usage = |(peak, current): (usize, usize)| {
+            format!(
+                "peak {}, {} still allocated",
+                kibibytes(peak),
+                kibibytes(current)
+            )
+        };
+        write!(f, "Memory: {}", usage(self.total))?;
+        for (subsystem, subsystem_usage) in &self.by_subsystem {
+            write!(f, "\n  {}: {}", subsystem.name(), usage(*subsystem_usage))?;
+        }
+        Ok(())
+    }
+}
+
+fn kibibytes(bytes: usize) -> String {
+    format!("{} KiB", bytes.div_ceil(1 << 10))
+}
+
+/// Memory allocated by the run, when allocations are tracked
+#[cfg(feature = "memory-profile")]
+static ACCOUNTING: Accounting = Accounting::new();
+
+/// Peak and still allocated memory of the run so far, by subsystem
+#[cfg(feature = "memory-profile")]
+pub fn report() -> MemoryReport {
+    ACCOUNTING.report()
+}
+
+/// Allocator accounting every allocation to the subsystem of the allocating thread (see [track]), to be installed
+/// as the global allocator of the binary. Every block is prefixed with the subsystem it was allocated by, so that
+/// freeing it is accounted to the same subsystem whichever thread frees it
+#[cfg(feature = "memory-profile")]
+pub struct TrackingAllocator;
+
+#[cfg(feature = "memory-profile")]
+impl TrackingAllocator {
+    /// Layout of a block prefixed with its subsystem, and where the block starts. The prefix takes a whole
+    /// alignment so that the block stays aligned
+    fn prefixed(layout: Layout) -> Option<(Layout, usize)> {
+        let offset = layout.align();
+        let size = layout.size().checked_add(offset)?;
+        Some((Layout::from_size_align(size, layout.align()).ok()?, offset))
+    }
+}
+
+#[cfg(feature = "memory-profile")]
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some((prefixed, offset)) = Self::prefixed(layout) else {
+            return std::ptr::null_mut();
+        };
+        let base = System.alloc(prefixed);
+        if base.is_null() {
+            return base;
+        }
+        let subsystem = CURRENT.try_with(Cell::get).unwrap_or(Subsystem::Other);
+        ACCOUNTING.allocated(subsystem, layout.size());
+        let block = base.add(offset);
+        block.sub(1).write(subsystem as u8);
+        block
+    }
+
+    unsafe fn dealloc(&self, block: *mut u8, layout: Layout) {
+        let subsystem = SUBSYSTEMS[block.sub(1).read() as usize];
+        ACCOUNTING.freed(subsystem, layout.size());
+        // the prefixed layout was valid when the block was allocated
+        let (prefixed, offset) = Self::prefixed(layout).unwrap_or_else(|| unreachable!());
+        System.dealloc(block.sub(offset), prefixed);
+    }
+}
@@ -1 +1,9 @@
-pub(crate) mod errors;
+pub mod decimal;
+pub mod errors;
+pub mod gzip;
+pub mod memory;
+pub mod protobuf;
+pub mod retry;
+pub mod sha256;
+pub mod zip;
+pub mod zstd;
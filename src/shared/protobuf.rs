@@ -0,0 +1,249 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+/// Wire types of the fields of a message
+const VARINT: u8 = 0;
+const I64: u8 = 1;
+const LEN: u8 = 2;
+const I32: u8 = 5;
+
+/// Protocol Buffers message (proto3 wire format) written field by field, dependency-free. Scalar fields equal to
+/// their default value are left out, as proto3 encoders do, except the `optional_` ones, whose presence is
+/// significant (proto3 `optional` fields and members of a `oneof`)
+#[derive(Debug, Default)]
+pub struct MessageWriter {
+    bytes: Vec<u8>,
+}
+
+impl MessageWriter {
+    /// Unsigned integer field (`uint32`, `uint64`), or enum field
+    pub fn uint(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.optional_uint(field, Some(value));
+        }
+    }
+
+    /// Unsigned integer field written even if zero, if present
+    pub fn optional_uint(&mut self, field: u32, value: Option<u64>) {
+        if let Some(value) = value {
+            self.key(field, VARINT);
+            write_varint(&mut self.bytes, value);
+        }
+    }
+
+    /// Signed integer field (`sint32`, `sint64`), zigzag encoded so that small negative values stay short
+    pub fn sint(&mut self, field: u32, value: i64) {
+        self.uint(field, ((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    pub fn bool(&mut self, field: u32, value: bool) {
+        self.uint(field, u64::from(value));
+    }
+
+    /// String field, written even if empty, if present. Repeated string fields write every value this way
+    pub fn optional_string(&mut self, field: u32, value: Option<&str>) {
+        if let Some(value) = value {
+            self.bytes_field(field, value.as_bytes());
+        }
+    }
+
+    /// Message field, written even if empty. Repeated message fields write every message this way
+    pub fn message(&mut self, field: u32, message: MessageWriter) {
+        self.bytes_field(field, &message.bytes);
+    }
+
+    /// Repeated unsigned integer field, packed as a single field, left out if empty
+    pub fn packed_uints(&mut self, field: u32, values: impl IntoIterator<Item = u64>) {
+        let mut packed = Vec::new();
+        values
+            .into_iter()
+            .for_each(|value| write_varint(&mut packed, value));
+        if !packed.is_empty() {
+            self.bytes_field(field, &packed);
+        }
+    }
+
+    /// Encoded message
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    fn bytes_field(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, LEN);
+        write_varint(&mut self.bytes, bytes.len() as u64);
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        write_varint(
+            &mut self.bytes,
+            (u64::from(field) << 3) | u64::from(wire_type),
+        );
+    }
+}
+
+/// Value of a field read from a message, as told by its wire type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> FieldValue<'a> {
+    /// Unsigned integer or enum value
+    pub fn uint(self) -> Result<u64, Error> {
+        match self {
+            FieldValue::Varint(value) => Ok(value),
+            _ => Err(invalid("integer field of another wire type")),
+        }
+    }
+
+    /// Zigzag encoded signed integer value
+    pub fn sint(self) -> Result<i64, Error> {
+        let value = self.uint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    pub fn bool(self) -> Result<bool, Error> {
+        Ok(self.uint()? != 0)
+    }
+
+    pub fn string(self) -> Result<&'a str, Error> {
+        std::str::from_utf8(self.bytes()?).map_err(|_| invalid("string not in UTF-8"))
+    }
+
+    /// Encoded message, or packed repeated values
+    pub fn bytes(self) -> Result<&'a [u8], Error> {
+        match self {
+            FieldValue::Bytes(bytes) => Ok(bytes),
+            _ => Err(invalid("length-delimited field of another wire type")),
+        }
+    }
+
+    /// Values of a repeated unsigned integer field, whether packed or not
+    pub fn uints(self) -> Result<Vec<u64>, Error> {
+        match self {
+            FieldValue::Varint(value) => Ok(vec![value]),
+            FieldValue::Bytes(mut bytes) => {
+                let mut values = Vec::new();
+                while !bytes.is_empty() {
+                    values.push(read_varint(&mut bytes)?);
+                }
+                Ok(values)
+            }
+            _ => Err(invalid("integer field of another wire type")),
+        }
+    }
+}
+
+/// Fields of a message, in the order they were written. Fields of unknown numbers are read like any other, for
+/// the caller to skip them
+pub struct MessageReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> MessageReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Next field, with its number, `None` at the end of the message
+    pub fn next_field(&mut self) -> Result<Option<(u32, FieldValue<'a>)>, Error> {
+        if self.bytes.is_empty() {
+            return Ok(None);
+        }
+        let key = read_varint(&mut self.bytes)?;
+        let field = u32::try_from(key >> 3)
+            .ok()
+            .filter(|field| *field != 0)
+            .ok_or_else(|| invalid("invalid field number"))?;
+        let value = match (key & 7) as u8 {
+            VARINT => FieldValue::Varint(read_varint(&mut self.bytes)?),
+            I64 => FieldValue::Fixed64(u64::from_le_bytes(
+                self.take(8)?.try_into().unwrap_or_default(),
+            )),
+            LEN => {
+                let length = usize::try_from(read_varint(&mut self.bytes)?)
+                    .map_err(|_| invalid("field longer than the message"))?;
+                FieldValue::Bytes(self.take(length)?)
+            }
+            I32 => FieldValue::Fixed32(u32::from_le_bytes(
+                self.take(4)?.try_into().unwrap_or_default(),
+            )),
+            wire_type => return Err(invalid(&format!("unsupported wire type {wire_type}"))),
+        };
+        Ok(Some((field, value)))
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        if length > self.bytes.len() {
+            return Err(invalid("field longer than the message"));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+}
+
+/// Write a message prefixed with its length, as a varint, so that several messages can follow each other in a
+/// stream (`writeDelimitedTo` in Java)
+pub fn write_delimited<W: Write>(writer: &mut W, message: MessageWriter) -> Result<(), Error> {
+    let mut length = Vec::new();
+    write_varint(&mut length, message.bytes.len() as u64);
+    writer.write_all(&length)?;
+    writer.write_all(&message.bytes)
+}
+
+/// Read a message written by [write_delimited], `None` at the end of the stream. A stream ending within a message
+/// is an error
+pub fn read_delimited<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, Error> {
+    let mut length = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        if reader.read(&mut byte)? == 0 {
+            return match shift {
+                0 => Ok(None),
+                _ => Err(Error::from(ErrorKind::UnexpectedEof)),
+            };
+        }
+        length |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            // the length is not trusted with an allocation before the message is read
+            let mut message = Vec::new();
+            reader.take(length).read_to_end(&mut message)?;
+            if message.len() as u64 != length {
+                return Err(Error::from(ErrorKind::UnexpectedEof));
+            }
+            return Ok(Some(message));
+        }
+    }
+    Err(invalid("varint longer than 10 bytes"))
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, Error> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes
+            .split_first()
+            .ok_or_else(|| invalid("varint cut short"))?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint longer than 10 bytes"))
+}
+
+fn invalid(detail: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, detail.to_owned())
+}
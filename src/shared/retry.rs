@@ -0,0 +1,65 @@
+use std::{io::ErrorKind, thread, time::Duration};
+
+use crate::shared::errors::Error;
+
+/// How storage operations (snapshot and archive files) failing with a transient I/O error are retried:
+/// the delay between attempts starts from `initial_backoff` and doubles on every retry, up to `max_backoff`.
+/// Once `attempts` are used up the operation fails with [Error::StorageUnavailable]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts of every operation, the first one included
+    pub attempts: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Maximum delay between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Run `operation`, retrying it while it fails with a transient I/O error and attempts are left.
+    /// Other errors are returned right away, as retrying them would fail the same way
+    pub(crate) fn run<T>(
+        &self,
+        name: &str,
+        mut operation: impl FnMut() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match operation() {
+                Err(Error::Io(error)) if is_transient(&error) => {
+                    if attempt >= self.attempts {
+                        return Err(Error::StorageUnavailable(name.to_owned(), attempt, error));
+                    }
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Errors which may not happen again on a later attempt, e.g. a busy or slow network file system
+fn is_transient(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::ResourceBusy
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+    )
+}
@@ -0,0 +1,144 @@
+use std::io::{Error, ErrorKind, Read};
+
+use crate::shared::gzip::{crc32, GzipReader};
+
+/// Signatures of the end of central directory record, of a central directory header and of a local file header
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+/// Fixed sizes of the end of central directory record, of a central directory header and of a local file header
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+const CENTRAL_DIRECTORY_HEADER_SIZE: usize = 46;
+const LOCAL_FILE_HEADER_SIZE: usize = 30;
+/// Compression methods supported
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// File stored in an archive, as described by the central directory
+struct Entry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+/// Zip archive (PKWARE APPNOTE) read in memory, dependency-free, so that the files packed in container formats
+/// such as xlsx can be extracted. Stored and deflated files are supported, and their CRC-32 is checked;
+/// encrypted files and zip64 archives (over 4 GiB or 65535 files) are refused.
+pub struct ZipArchive {
+    data: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+impl ZipArchive {
+    /// Archive from its content, listing its files from the central directory
+    pub fn new(data: Vec<u8>) -> Result<Self, Error> {
+        // the end of central directory record is followed by a comment of up to 64 KiB
+        let end = (END_OF_CENTRAL_DIRECTORY_SIZE..=data.len())
+            .rev()
+            .take(u16::MAX as usize + 1)
+            .map(|end| end - END_OF_CENTRAL_DIRECTORY_SIZE)
+            .find(|&offset| read_u32(&data, offset) == Some(END_OF_CENTRAL_DIRECTORY))
+            .ok_or_else(|| invalid("end of central directory not found"))?;
+        let field = |offset| read_u16(&data, end + offset).unwrap_or_default();
+        let count = field(10);
+        let directory_offset = read_u32(&data, end + 16).unwrap_or_default();
+        if count == u16::MAX || directory_offset == u32::MAX {
+            return Err(invalid("zip64 archives are not supported"));
+        }
+
+        let mut entries = Vec::with_capacity(count as usize);
+        let mut offset = directory_offset as usize;
+        for _ in 0..count {
+            let header = data
+                .get(offset..offset + CENTRAL_DIRECTORY_HEADER_SIZE)
+                .filter(|header| read_u32(header, 0) == Some(CENTRAL_DIRECTORY_HEADER))
+                .ok_or_else(|| invalid("truncated central directory"))?;
+            let field = |offset| read_u16(header, offset).unwrap_or_default();
+            let long_field = |offset| read_u32(header, offset).unwrap_or_default() as usize;
+            if field(8) & 0x01 != 0 {
+                return Err(invalid("encrypted files are not supported"));
+            }
+            let name_length = field(28) as usize;
+            let name_start = offset + CENTRAL_DIRECTORY_HEADER_SIZE;
+            let name = data
+                .get(name_start..name_start + name_length)
+                .ok_or_else(|| invalid("truncated central directory"))?;
+            entries.push(Entry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: field(10),
+                crc: long_field(16) as u32,
+                compressed_size: long_field(20),
+                size: long_field(24),
+                header_offset: long_field(42),
+            });
+            offset = name_start + name_length + field(30) as usize + field(32) as usize;
+        }
+
+        Ok(Self { data, entries })
+    }
+
+    /// Content of the file named `name`, `None` if the archive has no such file
+    pub fn read(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        let Some(entry) = self.entries.iter().find(|entry| entry.name == name) else {
+            return Ok(None);
+        };
+
+        // the local header repeats the name and may have a different extra field
+        let header = self
+            .data
+            .get(entry.header_offset..entry.header_offset + LOCAL_FILE_HEADER_SIZE)
+            .filter(|header| read_u32(header, 0) == Some(LOCAL_FILE_HEADER))
+            .ok_or_else(|| invalid("invalid local file header"))?;
+        let start = entry.header_offset
+            + LOCAL_FILE_HEADER_SIZE
+            + read_u16(header, 26).unwrap_or_default() as usize
+            + read_u16(header, 28).unwrap_or_default() as usize;
+        let compressed = self
+            .data
+            .get(start..start + entry.compressed_size)
+            .ok_or_else(|| invalid("truncated file data"))?;
+
+        let content = match entry.method {
+            STORED => compressed.to_vec(),
+            DEFLATED => {
+                // the size is not trusted with an allocation, nor the compressed data with inflating more than it
+                let mut content = Vec::new();
+                GzipReader::raw(compressed)
+                    .take(entry.size as u64 + 1)
+                    .read_to_end(&mut content)
+                    .map_err(|error| invalid(&format!("corrupted file {name}: {error}")))?;
+                content
+            }
+            method => return Err(invalid(&format!("unsupported compression method {method}"))),
+        };
+        if content.len() != entry.size || crc32(&content) != entry.crc {
+            return Err(invalid(&format!(
+                "corrupted file {name}, checksum mismatch"
+            )));
+        }
+
+        Ok(Some(content))
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid zip archive: {reason}"),
+    )
+}
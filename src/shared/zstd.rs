@@ -0,0 +1,961 @@
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+
+/// Leading bytes of a Zstandard frame (RFC 8878)
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Largest window accepted, as by the reference decoder without `--memory`
+const MAX_WINDOW_SIZE: u64 = 1 << 27;
+/// Largest size of a block, compressed or decompressed
+const MAX_BLOCK_SIZE: usize = 128 * 1024;
+/// Longest Huffman code of the literals
+const MAX_HUFFMAN_BITS: u32 = 11;
+
+/// Baselines and extra bits of the literal length codes 0 to 35
+const LITERAL_LENGTH_BASE: [u32; 36] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 22, 24, 28, 32, 40, 48, 64,
+    128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536,
+];
+const LITERAL_LENGTH_BITS: [u32; 36] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3, 3, 4, 6, 7, 8, 9, 10, 11,
+    12, 13, 14, 15, 16,
+];
+/// Baselines and extra bits of the match length codes 0 to 52
+const MATCH_LENGTH_BASE: [u32; 53] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27,
+    28, 29, 30, 31, 32, 33, 34, 35, 37, 39, 41, 43, 47, 51, 59, 67, 83, 99, 131, 259, 515, 1027,
+    2051, 4099, 8195, 16387, 32771, 65539,
+];
+const MATCH_LENGTH_BITS: [u32; 53] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    1, 1, 1, 1, 2, 2, 3, 3, 4, 4, 5, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+];
+/// Predefined distributions of the literal length, match length and offset codes, with their accuracy log
+const LITERAL_LENGTH_DEFAULT: ([i32; 36], u32) = (
+    [
+        4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1,
+        1, 1, -1, -1, -1, -1,
+    ],
+    6,
+);
+const MATCH_LENGTH_DEFAULT: ([i32; 53], u32) = (
+    [
+        1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+    ],
+    6,
+);
+const OFFSET_DEFAULT: ([i32; 29], u32) = (
+    [
+        1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+    ],
+    5,
+);
+
+/// Bits of a forward bitstream, least significant first, as FSE table descriptions
+struct ForwardBits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl ForwardBits<'_> {
+    fn read(&mut self, count: u32) -> Result<u32, Error> {
+        let mut value = 0;
+        for bit in 0..count {
+            let byte = self
+                .data
+                .get(self.position / 8)
+                .ok_or_else(|| invalid("truncated table description"))?;
+            value |= ((*byte as u32 >> (self.position % 8)) & 1) << bit;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// Bits of a backward bitstream, as Huffman and FSE coded streams: read from the end, most significant first,
+/// starting after the highest set bit of the last byte. Bits read past the start are zeros, so that the last
+/// codes can be looked up with a full table index
+struct BackwardBits<'a> {
+    data: &'a [u8],
+    /// Number of bits left to read, negative once more bits than available were read
+    position: isize,
+}
+
+impl<'a> BackwardBits<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, Error> {
+        match data.last() {
+            Some(&last) if last != 0 => Ok(Self {
+                data,
+                position: (data.len() * 8) as isize - (last.leading_zeros() as isize + 1),
+            }),
+            _ => Err(invalid("missing end of bitstream marker")),
+        }
+    }
+
+    /// Next `count` bits (up to 32), without consuming them
+    fn peek(&self, count: u32) -> u64 {
+        let low = self.position - count as isize;
+        let (start, end) = (low.max(0) as usize, self.position.max(0) as usize);
+        if end <= start {
+            return 0;
+        }
+        let value = (start / 8..=(end - 1) / 8)
+            .enumerate()
+            .fold(0_u64, |value, (shift, index)| {
+                value | (self.data[index] as u64) << (8 * shift)
+            });
+        let value = (value >> (start % 8)) & ((1 << (end - start)) - 1);
+        value << (start as isize - low)
+    }
+
+    fn read(&mut self, count: u32) -> u64 {
+        let value = self.peek(count);
+        self.position -= count as isize;
+        value
+    }
+
+    /// Whether the bits read are exactly the ones of the stream
+    fn consumed(&self) -> bool {
+        self.position == 0
+    }
+}
+
+/// State of a finite state entropy decoding table: symbol decoded, and how to compute the next state
+#[derive(Clone, Copy)]
+struct FseEntry {
+    symbol: u8,
+    bits: u8,
+    base: u16,
+}
+
+/// Finite state entropy decoding table, with `1 << accuracy_log` states
+#[derive(Clone)]
+struct FseTable {
+    accuracy_log: u32,
+    entries: Vec<FseEntry>,
+}
+
+impl FseTable {
+    /// Table of the normalized counts of the symbols, -1 standing for a count lower than 1
+    fn new(counts: &[i32], accuracy_log: u32) -> Result<Self, Error> {
+        let size = 1 << accuracy_log;
+        let mut symbols = vec![0_u8; size];
+        let mut next_states = vec![0_usize; counts.len()];
+        // symbols of count lower than 1 take the last states, a state each
+        let mut high = size;
+        for (symbol, &count) in counts.iter().enumerate() {
+            if count == -1 {
+                high -= 1;
+                symbols[high] = symbol as u8;
+                next_states[symbol] = 1;
+            }
+        }
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mut position = 0;
+        for (symbol, &count) in counts.iter().enumerate() {
+            if count <= 0 {
+                continue;
+            }
+            next_states[symbol] = count as usize;
+            for _ in 0..count {
+                symbols[position] = symbol as u8;
+                position = (position + step) & (size - 1);
+                while position >= high {
+                    position = (position + step) & (size - 1);
+                }
+            }
+        }
+        if position != 0 {
+            return Err(invalid("invalid FSE table"));
+        }
+
+        let entries = symbols
+            .iter()
+            .map(|&symbol| {
+                let next_state = next_states[symbol as usize];
+                next_states[symbol as usize] += 1;
+                let bits = accuracy_log - highest_bit(next_state as u32);
+                FseEntry {
+                    symbol,
+                    bits: bits as u8,
+                    base: ((next_state << bits) - size) as u16,
+                }
+            })
+            .collect();
+        Ok(Self {
+            accuracy_log,
+            entries,
+        })
+    }
+
+    /// Table of a single symbol, taking no bits
+    fn rle(symbol: u8) -> Self {
+        Self {
+            accuracy_log: 0,
+            entries: vec![FseEntry {
+                symbol,
+                bits: 0,
+                base: 0,
+            }],
+        }
+    }
+
+    /// Table of its description at the start of `data`, and the number of bytes of the description
+    fn read(data: &[u8], max_accuracy_log: u32, max_symbol: usize) -> Result<(Self, usize), Error> {
+        let mut bits = ForwardBits { data, position: 0 };
+        let accuracy_log = bits.read(4)? + 5;
+        if accuracy_log > max_accuracy_log {
+            return Err(invalid("FSE accuracy too large"));
+        }
+
+        let mut remaining = 1_i32 << accuracy_log;
+        let mut counts = Vec::new();
+        while remaining > 0 && counts.len() <= max_symbol {
+            let count_bits = highest_bit(remaining as u32 + 1) + 1;
+            let lower_mask = (1 << (count_bits - 1)) - 1;
+            let threshold = (1 << count_bits) - 1 - (remaining + 1);
+            let mut value = bits.read(count_bits)? as i32;
+            if value & lower_mask < threshold {
+                // small values take a bit less
+                bits.position -= 1;
+                value &= lower_mask;
+            } else if value > lower_mask {
+                value -= threshold;
+            }
+            let count = value - 1;
+            remaining -= count.abs();
+            counts.push(count);
+            if count == 0 {
+                loop {
+                    let repeat = bits.read(2)?;
+                    counts.extend(std::iter::repeat_n(0, repeat as usize));
+                    if repeat != 3 {
+                        break;
+                    }
+                }
+            }
+        }
+        if remaining != 0 || counts.len() > max_symbol + 1 {
+            return Err(invalid("invalid FSE table description"));
+        }
+
+        Ok((Self::new(&counts, accuracy_log)?, bits.position.div_ceil(8)))
+    }
+
+    fn initial_state(&self, bits: &mut BackwardBits) -> usize {
+        bits.read(self.accuracy_log) as usize
+    }
+
+    fn symbol(&self, state: usize) -> u8 {
+        self.entries[state].symbol
+    }
+
+    fn next_state(&self, state: usize, bits: &mut BackwardBits) -> usize {
+        let entry = self.entries[state];
+        entry.base as usize + bits.read(entry.bits as u32) as usize
+    }
+}
+
+/// Huffman decoding table of the literals, indexed by the next `max_bits` bits: symbol and length of its code
+struct HuffmanTable {
+    max_bits: u32,
+    entries: Vec<(u8, u8)>,
+}
+
+impl HuffmanTable {
+    /// Table of its description at the start of `data`, and the number of bytes of the description
+    fn read(data: &[u8]) -> Result<(Self, usize), Error> {
+        let header = *data
+            .first()
+            .ok_or_else(|| invalid("missing Huffman table"))? as usize;
+        let (weights, size) = match header {
+            0..=127 => {
+                let compressed = data
+                    .get(1..1 + header)
+                    .ok_or_else(|| invalid("truncated Huffman table"))?;
+                (Self::decode_weights(compressed)?, 1 + header)
+            }
+            _ => {
+                let count = header - 127;
+                let packed = data
+                    .get(1..1 + count.div_ceil(2))
+                    .ok_or_else(|| invalid("truncated Huffman table"))?;
+                let weights = (0..count)
+                    .map(|index| match index % 2 {
+                        0 => packed[index / 2] >> 4,
+                        _ => packed[index / 2] & 0xf,
+                    })
+                    .collect();
+                (weights, 1 + count.div_ceil(2))
+            }
+        };
+        Ok((Self::new(weights)?, size))
+    }
+
+    /// Weights of the symbols, compressed by FSE as two interleaved states
+    fn decode_weights(compressed: &[u8]) -> Result<Vec<u8>, Error> {
+        let (table, size) = FseTable::read(compressed, 6, 255)?;
+        let mut bits = BackwardBits::new(&compressed[size..])?;
+        let mut states = [
+            table.initial_state(&mut bits),
+            table.initial_state(&mut bits),
+        ];
+        let mut weights = Vec::new();
+        for turn in [0, 1].into_iter().cycle() {
+            weights.push(table.symbol(states[turn]));
+            states[turn] = table.next_state(states[turn], &mut bits);
+            if bits.position < 0 {
+                // the stream ends with the symbol of the other state
+                weights.push(table.symbol(states[1 - turn]));
+                break;
+            }
+            if weights.len() > 255 {
+                return Err(invalid("too many Huffman weights"));
+            }
+        }
+        Ok(weights)
+    }
+
+    /// Table of the weights of the symbols but the last one, whose weight is implied
+    fn new(mut weights: Vec<u8>) -> Result<Self, Error> {
+        if weights.len() > 255
+            || weights
+                .iter()
+                .any(|&weight| weight > MAX_HUFFMAN_BITS as u8)
+        {
+            return Err(invalid("invalid Huffman weights"));
+        }
+        let total = weights
+            .iter()
+            .filter(|&&weight| weight > 0)
+            .map(|&weight| 1_u32 << (weight - 1))
+            .sum::<u32>();
+        if total == 0 {
+            return Err(invalid("invalid Huffman weights"));
+        }
+        let max_bits = highest_bit(total) + 1;
+        let left = (1 << max_bits) - total;
+        if max_bits > MAX_HUFFMAN_BITS || !left.is_power_of_two() {
+            return Err(invalid("invalid Huffman weights"));
+        }
+        weights.push(left.trailing_zeros() as u8 + 1);
+
+        // codes are given by increasing weight, then symbol, starting from 0
+        let mut entries = Vec::with_capacity(1 << max_bits);
+        for weight in 1..=max_bits as u8 {
+            for (symbol, _) in weights.iter().enumerate().filter(|(_, &w)| w == weight) {
+                let code = (symbol as u8, max_bits as u8 + 1 - weight);
+                entries.extend(std::iter::repeat_n(code, 1 << (weight - 1)));
+            }
+        }
+        Ok(Self { max_bits, entries })
+    }
+
+    /// Decode `count` literals of the `streams` (1 or 4) Huffman coded streams of `data`
+    fn decode(&self, data: &[u8], count: usize, streams: usize) -> Result<Vec<u8>, Error> {
+        let mut literals = Vec::with_capacity(count);
+        if streams == 1 {
+            self.decode_stream(data, count, &mut literals)?;
+            return Ok(literals);
+        }
+
+        let jump_table = data.get(..6).ok_or_else(|| invalid("truncated literals"))?;
+        // sizes of the first three streams, the last one taking the rest of the data
+        let sizes = [0, 2, 4].map(|at| u16::from_le_bytes([jump_table[at], jump_table[at + 1]]));
+        let stream_count = count.div_ceil(4);
+        let mut start = 6;
+        for size in sizes.map(Some).into_iter().chain([None]) {
+            let (end, count) = match size {
+                Some(size) => (start + size as usize, stream_count),
+                None => (
+                    data.len(),
+                    count
+                        .checked_sub(3 * stream_count)
+                        .ok_or_else(|| invalid("invalid literals size"))?,
+                ),
+            };
+            let stream = data
+                .get(start..end)
+                .ok_or_else(|| invalid("truncated literals"))?;
+            self.decode_stream(stream, count, &mut literals)?;
+            start = end;
+        }
+        Ok(literals)
+    }
+
+    fn decode_stream(
+        &self,
+        data: &[u8],
+        count: usize,
+        literals: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        let mut bits = BackwardBits::new(data)?;
+        for _ in 0..count {
+            let (symbol, length) = self.entries[bits.peek(self.max_bits) as usize];
+            literals.push(symbol);
+            bits.position -= length as isize;
+        }
+        if !bits.consumed() {
+            return Err(invalid("corrupted literals"));
+        }
+        Ok(())
+    }
+}
+
+/// Literals to copy, then match to copy from the decoded bytes, of a compressed block
+struct Sequence {
+    literal_length: usize,
+    /// Offset value, standing for one of the repeated offsets up to 3
+    offset: u64,
+    match_length: usize,
+}
+
+/// Frame being decoded
+struct Frame {
+    window_size: usize,
+    content_size: Option<u64>,
+    checksum: Option<Xxh64>,
+    last_block: bool,
+    /// Bytes decoded from the frame so far
+    size: u64,
+    /// Tables and offsets repeated from the previous blocks of the frame
+    huffman: Option<HuffmanTable>,
+    literal_lengths: Option<FseTable>,
+    offsets: Option<FseTable>,
+    match_lengths: Option<FseTable>,
+    repeated_offsets: [u64; 3],
+}
+
+impl Frame {
+    /// Decode a compressed block, appending its bytes to `output`, which ends with the bytes of the window
+    fn decode_block(&mut self, block: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        let (literals, size) = self.read_literals(block)?;
+        let sequences = self.read_sequences(&block[size..])?;
+
+        let start = output.len();
+        let mut literals = literals.as_slice();
+        for sequence in sequences {
+            let copied = literals
+                .get(..sequence.literal_length)
+                .ok_or_else(|| invalid("literals overrun"))?;
+            output.extend_from_slice(copied);
+            literals = &literals[sequence.literal_length..];
+
+            let offset = self.match_offset(&sequence);
+            let decoded = self.size + (output.len() - start) as u64;
+            if offset == 0 || offset > decoded || offset > self.window_size as u64 {
+                return Err(invalid("distance too far back"));
+            }
+            if output.len() - start + sequence.match_length > MAX_BLOCK_SIZE {
+                return Err(invalid("block too large"));
+            }
+            let from = output.len() - offset as usize;
+            if offset as usize >= sequence.match_length {
+                output.extend_from_within(from..from + sequence.match_length);
+            } else {
+                for index in from..from + sequence.match_length {
+                    output.push(output[index]);
+                }
+            }
+        }
+        output.extend_from_slice(literals);
+        if output.len() - start > MAX_BLOCK_SIZE {
+            return Err(invalid("block too large"));
+        }
+        Ok(())
+    }
+
+    /// Literals section at the start of a compressed block, and its size
+    fn read_literals(&mut self, block: &[u8]) -> Result<(Vec<u8>, usize), Error> {
+        let byte = |index: usize| {
+            block
+                .get(index)
+                .map(|&byte| byte as usize)
+                .ok_or_else(|| invalid("truncated literals"))
+        };
+        let literals_type = byte(0)? & 3;
+        let size_format = (byte(0)? >> 2) & 3;
+        if literals_type < 2 {
+            let (size, header) = match size_format {
+                0 | 2 => (byte(0)? >> 3, 1),
+                1 => ((byte(0)? >> 4) + (byte(1)? << 4), 2),
+                _ => ((byte(0)? >> 4) + (byte(1)? << 4) + (byte(2)? << 12), 3),
+            };
+            if size > MAX_BLOCK_SIZE {
+                return Err(invalid("block too large"));
+            }
+            return match literals_type {
+                0 => block
+                    .get(header..header + size)
+                    .map(|literals| (literals.to_vec(), header + size))
+                    .ok_or_else(|| invalid("truncated literals")),
+                _ => Ok((vec![byte(header)? as u8; size], header + 1)),
+            };
+        }
+
+        let (header, size_bits, streams) = match size_format {
+            0 => (3, 10, 1),
+            1 => (3, 10, 4),
+            2 => (4, 14, 4),
+            _ => (5, 18, 4),
+        };
+        let mut sizes = 0_u64;
+        for index in 0..header {
+            sizes |= (byte(index)? as u64) << (8 * index);
+        }
+        let regenerated_size = ((sizes >> 4) & ((1 << size_bits) - 1)) as usize;
+        let compressed_size = ((sizes >> (4 + size_bits)) & ((1 << size_bits) - 1)) as usize;
+        if regenerated_size > MAX_BLOCK_SIZE {
+            return Err(invalid("block too large"));
+        }
+        let mut compressed = block
+            .get(header..header + compressed_size)
+            .ok_or_else(|| invalid("truncated literals"))?;
+        if literals_type == 2 {
+            let (huffman, size) = HuffmanTable::read(compressed)?;
+            self.huffman = Some(huffman);
+            compressed = &compressed[size..];
+        }
+        let huffman = self
+            .huffman
+            .as_ref()
+            .ok_or_else(|| invalid("missing Huffman table"))?;
+        let literals = huffman.decode(compressed, regenerated_size, streams)?;
+        Ok((literals, header + compressed_size))
+    }
+
+    /// Sequences section of a compressed block, following its literals
+    fn read_sequences(&mut self, section: &[u8]) -> Result<Vec<Sequence>, Error> {
+        let byte = |index: usize| {
+            section
+                .get(index)
+                .map(|&byte| byte as usize)
+                .ok_or_else(|| invalid("truncated sequences"))
+        };
+        let (count, header) = match byte(0)? {
+            0 => return Ok(Vec::new()),
+            count @ 1..=127 => (count, 1),
+            128..=254 => (((byte(0)? - 128) << 8) + byte(1)?, 2),
+            _ => (byte(1)? + (byte(2)? << 8) + 0x7f00, 3),
+        };
+        let modes = byte(header)?;
+        if modes & 3 != 0 {
+            return Err(invalid("reserved sequences modes"));
+        }
+        let mut data = &section[header + 1..];
+        let literal_lengths = Self::sequence_table(
+            modes >> 6,
+            &mut data,
+            self.literal_lengths.take(),
+            (&LITERAL_LENGTH_DEFAULT.0, LITERAL_LENGTH_DEFAULT.1),
+            9,
+            35,
+        )?;
+        let offsets = Self::sequence_table(
+            (modes >> 4) & 3,
+            &mut data,
+            self.offsets.take(),
+            (&OFFSET_DEFAULT.0, OFFSET_DEFAULT.1),
+            8,
+            31,
+        )?;
+        let match_lengths = Self::sequence_table(
+            (modes >> 2) & 3,
+            &mut data,
+            self.match_lengths.take(),
+            (&MATCH_LENGTH_DEFAULT.0, MATCH_LENGTH_DEFAULT.1),
+            9,
+            52,
+        )?;
+
+        let mut bits = BackwardBits::new(data)?;
+        let mut literal_length_state = literal_lengths.initial_state(&mut bits);
+        let mut offset_state = offsets.initial_state(&mut bits);
+        let mut match_length_state = match_lengths.initial_state(&mut bits);
+        let mut sequences = Vec::with_capacity(count);
+        for index in 0..count {
+            let literal_length_code = literal_lengths.symbol(literal_length_state) as usize;
+            let offset_code = offsets.symbol(offset_state) as u32;
+            let match_length_code = match_lengths.symbol(match_length_state) as usize;
+            let offset = (1 << offset_code) + bits.read(offset_code);
+            let match_length = MATCH_LENGTH_BASE[match_length_code] as u64
+                + bits.read(MATCH_LENGTH_BITS[match_length_code]);
+            let literal_length = LITERAL_LENGTH_BASE[literal_length_code] as u64
+                + bits.read(LITERAL_LENGTH_BITS[literal_length_code]);
+            sequences.push(Sequence {
+                literal_length: literal_length as usize,
+                offset,
+                match_length: match_length as usize,
+            });
+            if index + 1 < count {
+                literal_length_state = literal_lengths.next_state(literal_length_state, &mut bits);
+                match_length_state = match_lengths.next_state(match_length_state, &mut bits);
+                offset_state = offsets.next_state(offset_state, &mut bits);
+            }
+        }
+        if !bits.consumed() {
+            return Err(invalid("corrupted sequences"));
+        }
+
+        self.literal_lengths = Some(literal_lengths);
+        self.offsets = Some(offsets);
+        self.match_lengths = Some(match_lengths);
+        Ok(sequences)
+    }
+
+    /// Decoding table of a sequence code, according to its mode, reading its description (if any) from `data`
+    fn sequence_table(
+        mode: usize,
+        data: &mut &[u8],
+        previous: Option<FseTable>,
+        (counts, accuracy_log): (&[i32], u32),
+        max_accuracy_log: u32,
+        max_symbol: usize,
+    ) -> Result<FseTable, Error> {
+        match mode {
+            0 => FseTable::new(counts, accuracy_log),
+            1 => {
+                let (&symbol, rest) = data
+                    .split_first()
+                    .ok_or_else(|| invalid("truncated sequences"))?;
+                if symbol as usize > max_symbol {
+                    return Err(invalid("invalid sequence code"));
+                }
+                *data = rest;
+                Ok(FseTable::rle(symbol))
+            }
+            2 => {
+                let (table, size) = FseTable::read(data, max_accuracy_log, max_symbol)?;
+                *data = &data[size..];
+                Ok(table)
+            }
+            _ => previous.ok_or_else(|| invalid("missing sequence table")),
+        }
+    }
+
+    /// Offset of the match of a sequence, updating the repeated offsets
+    fn match_offset(&mut self, sequence: &Sequence) -> u64 {
+        let repeated = &mut self.repeated_offsets;
+        if sequence.offset > 3 {
+            *repeated = [sequence.offset - 3, repeated[0], repeated[1]];
+            return repeated[0];
+        }
+        let index = sequence.offset as usize - 1 + (sequence.literal_length == 0) as usize;
+        let offset = match index {
+            0 => return repeated[0],
+            3 => repeated[0].wrapping_sub(1),
+            _ => repeated[index],
+        };
+        if index > 1 {
+            repeated[2] = repeated[1];
+        }
+        repeated[1] = repeated[0];
+        repeated[0] = offset;
+        offset
+    }
+}
+
+/// Streaming Zstandard decoder (RFC 8878), dependency-free. Concatenated frames are decoded as a single stream and
+/// skippable frames are ignored. The content size and checksum of the frames giving them are checked, so that a
+/// corrupted or truncated input is reported as an error instead of being silently decoded into garbage.
+/// Frames using a dictionary, or a window larger than 128 MiB, are refused.
+pub struct ZstdReader<R> {
+    reader: BufReader<R>,
+    frames: u64,
+    /// Frame being decoded, `None` between frames
+    frame: Option<Frame>,
+    done: bool,
+    /// Decoded bytes: the window of the frame, followed by the bytes not handed out yet, from `position`
+    output: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> ZstdReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            frames: 0,
+            frame: None,
+            done: false,
+            output: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Decode the next part of the stream: a frame header, a block or the end of a frame
+    fn step(&mut self) -> Result<(), Error> {
+        let Some(mut frame) = self.frame.take() else {
+            if self.frames > 0 && self.reader.fill_buf()?.is_empty() {
+                self.done = true;
+                return Ok(());
+            }
+            self.frames += 1;
+            let magic = self.read_bytes(4)?;
+            if magic[1..] == [0x2a, 0x4d, 0x18] && magic[0] & 0xf0 == 0x50 {
+                // skippable frame, such as metadata
+                let size = u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap());
+                let skipped = std::io::copy(
+                    &mut self.reader.by_ref().take(size.into()),
+                    &mut std::io::sink(),
+                )?;
+                if skipped < size.into() {
+                    return Err(truncated());
+                }
+                return Ok(());
+            }
+            if magic != ZSTD_MAGIC {
+                return Err(invalid("not a Zstandard stream"));
+            }
+            self.frame = Some(self.read_frame_header()?);
+            // matches do not reach the previous frames
+            self.output.clear();
+            self.position = 0;
+            return Ok(());
+        };
+
+        if frame.last_block {
+            if let Some(checksum) = &frame.checksum {
+                let expected = u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap());
+                if expected != checksum.digest() as u32 {
+                    return Err(invalid("corrupted data, checksum mismatch"));
+                }
+            }
+            if frame.content_size.is_some_and(|size| size != frame.size) {
+                return Err(invalid("frame content size mismatch"));
+            }
+            return Ok(());
+        }
+
+        // the window is kept, and trimmed once the bytes handed out take more than twice its size
+        if self.output.len() > 2 * frame.window_size.max(MAX_BLOCK_SIZE) {
+            self.output.drain(..self.output.len() - frame.window_size);
+            self.position = self.output.len();
+        }
+        let header = self.read_bytes(3)?;
+        let header = u32::from_le_bytes([header[0], header[1], header[2], 0]);
+        frame.last_block = header & 1 == 1;
+        let size = (header >> 3) as usize;
+        if size > frame.window_size.min(MAX_BLOCK_SIZE) {
+            return Err(invalid("block too large"));
+        }
+        let start = self.output.len();
+        match (header >> 1) & 3 {
+            0 => {
+                let block = self.read_bytes(size)?;
+                self.output.extend_from_slice(&block);
+            }
+            1 => {
+                let byte = self.read_bytes(1)?[0];
+                self.output.resize(start + size, byte);
+            }
+            2 => {
+                let block = self.read_bytes(size)?;
+                frame.decode_block(&block, &mut self.output)?;
+            }
+            _ => return Err(invalid("invalid block type")),
+        }
+        if let Some(checksum) = &mut frame.checksum {
+            checksum.update(&self.output[start..]);
+        }
+        frame.size += (self.output.len() - start) as u64;
+        self.frame = Some(frame);
+
+        Ok(())
+    }
+
+    fn read_frame_header(&mut self) -> Result<Frame, Error> {
+        let descriptor = self.read_bytes(1)?[0];
+        let single_segment = descriptor & 0x20 != 0;
+        if descriptor & 0x08 != 0 {
+            return Err(invalid("reserved frame header bit set"));
+        }
+        let window_size = match single_segment {
+            true => None,
+            false => {
+                let window = self.read_bytes(1)?[0];
+                let base = 1_u64 << (10 + (window >> 3));
+                Some(base + (base / 8) * (window & 7) as u64)
+            }
+        };
+        let dictionary_size = [0, 1, 2, 4][(descriptor & 3) as usize];
+        if self
+            .read_bytes(dictionary_size)?
+            .iter()
+            .any(|&byte| byte != 0)
+        {
+            return Err(invalid("dictionaries are not supported"));
+        }
+        let content_size = match (descriptor >> 6, single_segment) {
+            (0, false) => None,
+            (0, true) => Some(self.read_bytes(1)?[0] as u64),
+            (1, _) => {
+                Some(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()) as u64 + 256)
+            }
+            (2, _) => Some(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()) as u64),
+            _ => Some(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap())),
+        };
+        let window_size = window_size.or(content_size).unwrap_or_default();
+        if window_size > MAX_WINDOW_SIZE {
+            return Err(invalid("window too large"));
+        }
+
+        Ok(Frame {
+            window_size: window_size as usize,
+            content_size,
+            checksum: (descriptor & 0x04 != 0).then(Xxh64::new),
+            last_block: false,
+            size: 0,
+            huffman: None,
+            literal_lengths: None,
+            offsets: None,
+            match_lengths: None,
+            repeated_offsets: [1, 4, 8],
+        })
+    }
+
+    fn read_bytes(&mut self, count: usize) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::with_capacity(count);
+        self.reader
+            .by_ref()
+            .take(count as u64)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() < count {
+            return Err(truncated());
+        }
+        Ok(bytes)
+    }
+}
+
+impl<R: Read> Read for ZstdReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.output.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.step()?;
+        }
+
+        let remaining = &self.output[self.position..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.position += len;
+
+        Ok(len)
+    }
+}
+
+/// Index of the highest set bit of a non-zero value
+fn highest_bit(value: u32) -> u32 {
+    31 - value.leading_zeros()
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid compressed stream: {reason}"),
+    )
+}
+
+fn truncated() -> Error {
+    Error::new(ErrorKind::UnexpectedEof, "truncated compressed stream")
+}
+
+const PRIME64_1: u64 = 0x9e37_79b1_85eb_ca87;
+const PRIME64_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const PRIME64_3: u64 = 0x1656_67b1_9e37_79f9;
+const PRIME64_4: u64 = 0x85eb_ca77_c2b2_ae63;
+const PRIME64_5: u64 = 0x27d4_eb2f_1656_67c5;
+
+/// XXH64 hash with seed 0, whose lowest 32 bits are the checksum of a frame
+struct Xxh64 {
+    accumulators: [u64; 4],
+    /// Bytes of the stripe of 32 bytes being filled
+    stripe: Vec<u8>,
+    length: u64,
+}
+
+impl Xxh64 {
+    fn new() -> Self {
+        Self {
+            accumulators: [
+                PRIME64_1.wrapping_add(PRIME64_2),
+                PRIME64_2,
+                0,
+                0_u64.wrapping_sub(PRIME64_1),
+            ],
+            stripe: Vec::with_capacity(32),
+            length: 0,
+        }
+    }
+
+    fn update(&mut self, mut bytes: &[u8]) {
+        self.length += bytes.len() as u64;
+        while !bytes.is_empty() {
+            let taken = bytes.len().min(32 - self.stripe.len());
+            self.stripe.extend_from_slice(&bytes[..taken]);
+            bytes = &bytes[taken..];
+            if self.stripe.len() == 32 {
+                for (accumulator, lane) in self.accumulators.iter_mut().zip(self.stripe.chunks(8)) {
+                    *accumulator =
+                        xxh64_round(*accumulator, u64::from_le_bytes(lane.try_into().unwrap()));
+                }
+                self.stripe.clear();
+            }
+        }
+    }
+
+    fn digest(&self) -> u64 {
+        let [v1, v2, v3, v4] = self.accumulators;
+        let mut hash = match self.length >= 32 {
+            true => [v1, v2, v3, v4].into_iter().fold(
+                v1.rotate_left(1)
+                    .wrapping_add(v2.rotate_left(7))
+                    .wrapping_add(v3.rotate_left(12))
+                    .wrapping_add(v4.rotate_left(18)),
+                |hash, accumulator| {
+                    (hash ^ xxh64_round(0, accumulator))
+                        .wrapping_mul(PRIME64_1)
+                        .wrapping_add(PRIME64_4)
+                },
+            ),
+            false => PRIME64_5,
+        };
+        hash = hash.wrapping_add(self.length);
+
+        let mut rest = self.stripe.as_slice();
+        while rest.len() >= 8 {
+            let lane = u64::from_le_bytes(rest[..8].try_into().unwrap());
+            hash = (hash ^ xxh64_round(0, lane))
+                .rotate_left(27)
+                .wrapping_mul(PRIME64_1)
+                .wrapping_add(PRIME64_4);
+            rest = &rest[8..];
+        }
+        if rest.len() >= 4 {
+            let lane = u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64;
+            hash = (hash ^ lane.wrapping_mul(PRIME64_1))
+                .rotate_left(23)
+                .wrapping_mul(PRIME64_2)
+                .wrapping_add(PRIME64_3);
+            rest = &rest[4..];
+        }
+        for &byte in rest {
+            hash = (hash ^ (byte as u64).wrapping_mul(PRIME64_5))
+                .rotate_left(11)
+                .wrapping_mul(PRIME64_1);
+        }
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME64_2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME64_3);
+        hash ^ (hash >> 32)
+    }
+}
+
+fn xxh64_round(accumulator: u64, lane: u64) -> u64 {
+    accumulator
+        .wrapping_add(lane.wrapping_mul(PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME64_1)
+}